@@ -0,0 +1,407 @@
+//! Resignation and draw adjudication, configured and applied like cutechess's `-resign`
+//! and `-draw`, so that games in the match runner and in datagen end at the same points
+//! that cutechess-cli would end them, and so that a GUI can be told when we would resign
+//! or offer a draw.
+//!
+//! The rules are written as cutechess writes them, and mean the same things:
+//!
+//! - `-resign movecount=N score=S`: a player loses once it has scored each of its last
+//!   `N` moves at `-S` or worse. With `twosided=true`, its opponent must also have scored
+//!   each of its own last `N` moves at `S` or better.
+//! - `-draw movenumber=M movecount=N score=S`: the game is drawn once the last `2N` plies,
+//!   by either player, were all scored within `S` of zero, and at least `M` full moves
+//!   have been played. Scores before move `M` count towards the `2N` plies.
+//!
+//! Draws are checked before resignations, a score is always from the point of view of
+//! the player making the move, and a move count of zero turns a rule off. Keys left out
+//! of a rule take the values that `OpenBench` passes to cutechess.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+
+use crate::chess::{
+    board::{Board, DrawType, GameOutcome, WinType},
+    piece::Colour,
+};
+
+/// Resign once our score has been at or below `-score` for `moves` consecutive moves,
+/// and, if `two_sided`, the opponent's has been at or above `score` for as long.
+/// A move count of zero never resigns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResignRule {
+    pub moves: usize,
+    pub score: i32,
+    pub two_sided: bool,
+}
+
+/// Draw once the scores of both sides have been within `score` of zero for `moves`
+/// consecutive moves each, from full-move `move_number` on. A move count of zero never
+/// draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DrawRule {
+    pub move_number: usize,
+    pub moves: usize,
+    pub score: i32,
+}
+
+impl ResignRule {
+    pub const OFF: Self = Self {
+        moves: 0,
+        score: 0,
+        two_sided: false,
+    };
+}
+
+impl DrawRule {
+    pub const OFF: Self = Self {
+        move_number: 0,
+        moves: 0,
+        score: 0,
+    };
+}
+
+/// Split a rule written like `movecount=3 score=400` into its keys and values, or give
+/// nothing for `off`.
+fn parse_rule<'a>(s: &'a str, keys: &[&str]) -> anyhow::Result<Option<Vec<(&'a str, &'a str)>>> {
+    if s.trim() == "off" {
+        return Ok(None);
+    }
+    s.split_ascii_whitespace()
+        .map(|part| {
+            let (key, value) = part
+                .split_once('=')
+                .with_context(|| format!("expected key=value, got \"{part}\""))?;
+            if !keys.contains(&key) {
+                bail!("unknown key \"{key}\", expected one of {}", keys.join(", "));
+            }
+            Ok((key, value))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// Parse the value of a numeric key, which must not be negative.
+fn parse_number<T: TryFrom<u64>>(key: &str, value: &str) -> anyhow::Result<T> {
+    value
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .with_context(|| format!("invalid value for {key}: \"{value}\""))
+}
+
+impl FromStr for ResignRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(pairs) = parse_rule(s, &["movecount", "score", "twosided"])? else {
+            return Ok(Self::OFF);
+        };
+        let mut rule = Self {
+            moves: 3,
+            score: 1000,
+            two_sided: false,
+        };
+        for (key, value) in pairs {
+            match key {
+                "movecount" => rule.moves = parse_number(key, value)?,
+                "score" => rule.score = parse_number(key, value)?,
+                _ => {
+                    rule.two_sided = value
+                        .parse()
+                        .with_context(|| format!("invalid value for {key}: \"{value}\""))?;
+                }
+            }
+        }
+        Ok(rule)
+    }
+}
+
+impl FromStr for DrawRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(pairs) = parse_rule(s, &["movenumber", "movecount", "score"])? else {
+            return Ok(Self::OFF);
+        };
+        let mut rule = Self {
+            move_number: 40,
+            moves: 8,
+            score: 10,
+        };
+        for (key, value) in pairs {
+            match key {
+                "movenumber" => rule.move_number = parse_number(key, value)?,
+                "movecount" => rule.moves = parse_number(key, value)?,
+                _ => rule.score = parse_number(key, value)?,
+            }
+        }
+        Ok(rule)
+    }
+}
+
+impl Display for ResignRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.moves == 0 {
+            return write!(f, "off");
+        }
+        write!(f, "movecount={} score={}", self.moves, self.score)?;
+        if self.two_sided {
+            write!(f, " twosided=true")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for DrawRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.moves == 0 {
+            return write!(f, "off");
+        }
+        write!(
+            f,
+            "movenumber={} movecount={} score={}",
+            self.move_number, self.moves, self.score
+        )
+    }
+}
+
+/// What a player would like to do instead of playing on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    Resign,
+    OfferDraw,
+}
+
+/// Tracks the scores that each side gives for its moves over the course of a game.
+#[derive(Clone, Debug)]
+pub struct Adjudicator {
+    pub resign: ResignRule,
+    pub draw: DrawRule,
+    /// How many consecutive moves each side has scored badly enough to resign.
+    resign_streak: [usize; 2],
+    /// How many consecutive moves each side has scored well enough for its opponent to
+    /// resign, for two-sided resignation.
+    win_streak: [usize; 2],
+    /// How many consecutive moves each side has scored close enough to a draw.
+    draw_streak: [usize; 2],
+    /// How many consecutive plies, by either side, have been scored close enough to a draw.
+    drawish_plies: usize,
+}
+
+impl Default for Adjudicator {
+    fn default() -> Self {
+        Self::new(ResignRule::OFF, DrawRule::OFF)
+    }
+}
+
+impl Adjudicator {
+    pub const fn new(resign: ResignRule, draw: DrawRule) -> Self {
+        Self {
+            resign,
+            draw,
+            resign_streak: [0; 2],
+            win_streak: [0; 2],
+            draw_streak: [0; 2],
+            drawish_plies: 0,
+        }
+    }
+
+    /// Forget the scores of the last game.
+    pub const fn new_game(&mut self) {
+        self.resign_streak = [0; 2];
+        self.win_streak = [0; 2];
+        self.draw_streak = [0; 2];
+        self.drawish_plies = 0;
+    }
+
+    /// Whether the game will have reached the draw rule's move number once the side to
+    /// move in `board` has moved.
+    const fn past_draw_move_number(&self, board: &Board) -> bool {
+        board.ply().div_ceil(2) >= self.draw.move_number
+    }
+
+    /// Record that the side to move in `board` scored its move as `score`, from its own
+    /// perspective, and find whether it now wants to resign or offer a draw. A player
+    /// only sees its own scores, so it resigns as if the rule were one-sided, and offers
+    /// a draw when its own scores would allow one.
+    pub const fn record(&mut self, board: &Board, score: i32) -> Option<Signal> {
+        let side = board.turn().index();
+        self.resign_streak[side] = if score <= -self.resign.score {
+            self.resign_streak[side] + 1
+        } else {
+            0
+        };
+        self.win_streak[side] = if score >= self.resign.score {
+            self.win_streak[side] + 1
+        } else {
+            0
+        };
+        (self.draw_streak[side], self.drawish_plies) = if score.abs() <= self.draw.score {
+            (self.draw_streak[side] + 1, self.drawish_plies + 1)
+        } else {
+            (0, 0)
+        };
+        if self.resign.moves > 0 && self.resign_streak[side] >= self.resign.moves {
+            Some(Signal::Resign)
+        } else if self.draw.moves > 0
+            && self.draw_streak[side] >= self.draw.moves
+            && self.past_draw_move_number(board)
+        {
+            Some(Signal::OfferDraw)
+        } else {
+            None
+        }
+    }
+
+    /// Record a move's score as in `record`, and find whether the game is now over by
+    /// adjudication. Only the match runner and datagen see the scores of both sides.
+    #[cfg_attr(not(feature = "datagen"), allow(dead_code))]
+    pub const fn adjudicate(&mut self, board: &Board, score: i32) -> Option<GameOutcome> {
+        self.record(board, score);
+        if self.draw.moves > 0
+            && self.drawish_plies >= 2 * self.draw.moves
+            && self.past_draw_move_number(board)
+        {
+            return Some(GameOutcome::Draw(DrawType::Adjudication));
+        }
+        let us = board.turn();
+        let resigns = self.resign.moves > 0
+            && self.resign_streak[us.index()] >= self.resign.moves
+            && (!self.resign.two_sided || self.win_streak[us.flip().index()] >= self.resign.moves);
+        if !resigns {
+            return None;
+        }
+        Some(match us {
+            Colour::White => GameOutcome::BlackWin(WinType::Adjudication),
+            Colour::Black => GameOutcome::WhiteWin(WinType::Adjudication),
+        })
+    }
+}
+
+mod tests {
+    #[test]
+    fn resignation_and_draw_offers() {
+        use super::{Adjudicator, DrawRule, ResignRule, Signal};
+        use crate::chess::board::Board;
+
+        let resign = "movecount=2 score=500".parse::<ResignRule>().unwrap();
+        assert_eq!(
+            resign,
+            ResignRule {
+                moves: 2,
+                score: 500,
+                two_sided: false,
+            }
+        );
+        let two_sided = "movecount=2 score=500 twosided=true"
+            .parse::<ResignRule>()
+            .unwrap();
+        assert_eq!(two_sided.to_string(), "movecount=2 score=500 twosided=true");
+        let draw = "movenumber=1 movecount=2 score=10"
+            .parse::<DrawRule>()
+            .unwrap();
+        assert_eq!("off".parse::<DrawRule>().unwrap(), DrawRule::OFF);
+        assert!("movecount=2 margin=10".parse::<DrawRule>().is_err());
+        assert!("movecount=-2".parse::<ResignRule>().is_err());
+        assert!("twosided=yes".parse::<ResignRule>().is_err());
+
+        let mut board = Board::default();
+        let mut adjudicator = Adjudicator::new(resign, draw);
+        let mut play = |san, score| {
+            let signal = adjudicator.record(&board, score);
+            let m = board.parse_san(san).unwrap();
+            board.make_move_simple(m);
+            signal
+        };
+        assert_eq!(play("e4", 0), None);
+        assert_eq!(play("e5", 5), None);
+        assert_eq!(play("Nf3", -5), Some(Signal::OfferDraw));
+        assert_eq!(play("Nc6", 0), Some(Signal::OfferDraw));
+        // white's score collapses, so its offer lapses before it resigns.
+        assert_eq!(play("Bc4", -600), None);
+        assert_eq!(play("Nf6", 0), Some(Signal::OfferDraw));
+        assert_eq!(play("d3", -600), Some(Signal::Resign));
+    }
+
+    #[test]
+    fn cutechess_adjudication() {
+        use super::Adjudicator;
+        use crate::chess::board::{Board, DrawType, GameOutcome, WinType};
+
+        // feed each ply's score, from the mover's point of view, to a fresh adjudicator,
+        // and find the ply on which the game ends.
+        let adjudicate = |resign: &str, draw: &str, scores: &[i32]| {
+            let mut adjudicator = Adjudicator::new(resign.parse().unwrap(), draw.parse().unwrap());
+            let mut board = Board::default();
+            for (ply, &score) in scores.iter().enumerate() {
+                if let Some(outcome) = adjudicator.adjudicate(&board, score) {
+                    return Some((ply, outcome));
+                }
+                let san = ["Nf3", "Nf6", "Ng1", "Ng8"][ply % 4];
+                let m = board.parse_san(san).unwrap();
+                board.make_move_simple(m);
+            }
+            None
+        };
+        let draw = GameOutcome::Draw(DrawType::Adjudication);
+        let white_wins = GameOutcome::WhiteWin(WinType::Adjudication);
+        let black_wins = GameOutcome::BlackWin(WinType::Adjudication);
+
+        // four quiet plies are enough by the second move, but the game can't be drawn
+        // before the third.
+        let quiet = [0; 8];
+        assert_eq!(
+            adjudicate("off", "movenumber=3 movecount=2 score=10", &quiet),
+            Some((5, draw))
+        );
+        // a score from either side restarts the count.
+        assert_eq!(
+            adjudicate(
+                "off",
+                "movenumber=0 movecount=2 score=10",
+                &[0, 0, 0, -50, 0, 0, 0, 10]
+            ),
+            Some((7, draw))
+        );
+        assert_eq!(
+            adjudicate(
+                "off",
+                "movenumber=0 movecount=2 score=10",
+                &[0, 0, 0, -50, 0, 0, 11]
+            ),
+            None
+        );
+
+        // one-sided resignation ignores the winner's scores.
+        let collapse = [-600, 0, -600];
+        assert_eq!(
+            adjudicate("movecount=2 score=500", "off", &collapse),
+            Some((2, black_wins))
+        );
+        // two-sided resignation needs the winner to agree for as long.
+        let two_sided = "movecount=2 score=500 twosided=true";
+        assert_eq!(adjudicate(two_sided, "off", &collapse), None);
+        assert_eq!(
+            adjudicate(two_sided, "off", &[-600, 600, -600, 600, -600]),
+            Some((4, black_wins))
+        );
+        assert_eq!(
+            adjudicate(two_sided, "off", &[600, -600, 600, -600]),
+            Some((3, white_wins))
+        );
+
+        // draws are found before resignations.
+        assert_eq!(
+            adjudicate(
+                "movecount=1 score=0",
+                "movenumber=0 movecount=1 score=10",
+                &[5, 0]
+            ),
+            Some((1, draw))
+        );
+    }
+}
@@ -0,0 +1,408 @@
+//! Bulk analysis of a file of positions, with one single-threaded search per position
+//! and as many positions in flight as there are threads.
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+use crate::{
+    cache::{AnalysisCache, CachedAnalysis},
+    chess::{board::Board, chessmove::Move, piece::Colour, CHESS960},
+    epd::Epd,
+    evaluation::{is_mate_score, MATE_SCORE},
+    nnue::network::NNUEParams,
+    search::{parameters::Config, LMTable},
+    searchinfo::SearchInfo,
+    threadlocal::ThreadData,
+    timemgmt::SearchLimit,
+    transpositiontable::TT,
+    uci::{format_score, win_rate_model, NORMALISE_TO_PAWN_VALUE},
+    util::MEGABYTE,
+};
+
+pub struct AnalyseOptions {
+    /// Path to the input file, one position per line.
+    pub input: PathBuf,
+    /// Where to write the results. A `.csv` extension selects CSV, `.epd` selects EPD
+    /// with analysis operations filled in, and anything else JSON lines.
+    pub output: Option<PathBuf>,
+    /// The limit for each search.
+    pub limit: SearchLimit,
+    /// Number of positions to analyse concurrently.
+    pub threads: usize,
+    /// Hash table size for each search thread, in megabytes.
+    pub hash_mb: usize,
+    /// Path to a persistent analysis cache, and the size to create it with in megabytes.
+    pub cache: Option<(PathBuf, usize)>,
+}
+
+/// The result of analysing a single position.
+pub struct Analysis {
+    pub fen: String,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: usize,
+    pub pv: Vec<Move>,
+    /// Win, draw and loss probabilities in per mille, for the side to move.
+    pub wdl: (i32, i32, i32),
+    /// How long the search took, or zero if the result was cached.
+    pub time: Duration,
+}
+
+impl Analysis {
+    fn pv_string(&self) -> String {
+        let chess960 = CHESS960.load(Ordering::Relaxed);
+        self.pv
+            .iter()
+            .map(|m| m.display(chess960).to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn best_move_string(&self) -> String {
+        self.best_move.map_or_else(String::new, |m| {
+            m.display(CHESS960.load(Ordering::Relaxed)).to_string()
+        })
+    }
+
+    fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let (w, d, l) = self.wdl;
+        writeln!(
+            out,
+            "{},{},{},{},{},{w},{d},{l}",
+            self.fen,
+            self.best_move_string(),
+            format_score(self.score),
+            self.depth,
+            self.pv_string()
+        )
+    }
+
+    /// Write `epd` back out with the `acd`, `acs`, `ce` and `pv` operations filled in.
+    fn write_epd(&self, epd: &Epd, out: &mut impl Write) -> anyhow::Result<()> {
+        let mut board = Board::default();
+        board.set_from_fen(&self.fen)?;
+        // scores are from white's perspective, but `ce` is from the side to move's.
+        let score = if board.turn() == Colour::White {
+            self.score
+        } else {
+            -self.score
+        };
+        let ce = if is_mate_score(score) {
+            // by convention, mates count down from the largest centipawn score.
+            let plies = MATE_SCORE - score.abs();
+            (32767 - plies) * score.signum()
+        } else {
+            score * 100 / NORMALISE_TO_PAWN_VALUE
+        };
+        let mut pv = Vec::with_capacity(self.pv.len());
+        for &m in &self.pv {
+            pv.push(board.san(m).with_context(|| "illegal move in PV")?);
+            board.make_move_simple(m);
+        }
+        let mut epd = epd.clone();
+        epd.set("acd", vec![self.depth.to_string()]);
+        epd.set("acs", vec![self.time.as_secs().to_string()]);
+        epd.set("ce", vec![ce.to_string()]);
+        epd.set("pv", pv);
+        writeln!(out, "{epd}")?;
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let (w, d, l) = self.wdl;
+        let mut best_move = String::new();
+        if self.best_move.is_some() {
+            write!(best_move, "\"{}\"", self.best_move_string()).unwrap();
+        } else {
+            best_move.push_str("null");
+        }
+        writeln!(
+            out,
+            "{{\"fen\": \"{}\", \"bestmove\": {best_move}, \"score\": \"{}\", \"depth\": {}, \"pv\": \"{}\", \"wdl\": [{w}, {d}, {l}]}}",
+            self.fen,
+            format_score(self.score),
+            self.depth,
+            self.pv_string()
+        )
+    }
+}
+
+/// Searches positions one at a time on a single thread, with its own hash table.
+struct Worker<'a> {
+    info: SearchInfo<'a>,
+    tt: &'a TT,
+    thread_data: Vec<ThreadData<'a>>,
+    cache: Option<&'a Mutex<AnalysisCache>>,
+    cache_hits: &'a AtomicUsize,
+}
+
+impl Worker<'_> {
+    fn analyse(&mut self, fen: &str, limit: &SearchLimit) -> anyhow::Result<Analysis> {
+        let mut board = Board::default();
+        board
+            .set_from_fen(fen)
+            .with_context(|| format!("Failed to parse FEN {fen}"))?;
+        let (turn, ply) = (board.turn(), board.ply());
+        if board.legal_moves().is_empty() {
+            // scores are from white's perspective, so it's white who is mated here.
+            let score = match (board.in_check(), turn) {
+                (false, _) => 0,
+                (true, Colour::White) => -MATE_SCORE,
+                (true, Colour::Black) => MATE_SCORE,
+            };
+            return Ok(Analysis {
+                fen: fen.to_string(),
+                best_move: None,
+                score,
+                depth: 0,
+                pv: Vec::new(),
+                wdl: wdl(score, turn, ply),
+                time: Duration::ZERO,
+            });
+        }
+
+        if let Some(cached) = self.probe_cache(&board, limit) {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Analysis {
+                fen: fen.to_string(),
+                best_move: Some(cached.best_move),
+                score: cached.score,
+                depth: cached.depth,
+                pv: vec![cached.best_move],
+                wdl: wdl(cached.score, turn, ply),
+                time: Duration::ZERO,
+            });
+        }
+
+        // each position is analysed from scratch, as if in a new game.
+        self.tt.clear(1);
+        self.thread_data
+            .iter_mut()
+            .for_each(ThreadData::clear_tables);
+        self.info.time_manager.set_limit(limit.clone());
+        self.info.time_manager.start();
+        let start = Instant::now();
+        let (score, best_move) =
+            board.search_position(&mut self.info, &mut self.thread_data, self.tt.view());
+        let t = &self.thread_data[0];
+        if let (Some(cache), Some(best_move)) = (self.cache, best_move) {
+            cache
+                .lock()
+                .map_err(|_| anyhow::anyhow!("analysis cache lock poisoned"))?
+                .store(
+                    board.zobrist_key(),
+                    CachedAnalysis {
+                        best_move,
+                        score: score.get(),
+                        depth: t.completed,
+                        nodes: self.info.nodes.get_global(),
+                        time_ms: u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+                    },
+                );
+        }
+        Ok(Analysis {
+            fen: fen.to_string(),
+            best_move,
+            score: score.get(),
+            depth: t.completed,
+            pv: t.pv().moves().to_vec(),
+            wdl: wdl(score.get(), turn, ply),
+            time: start.elapsed(),
+        })
+    }
+}
+
+impl Worker<'_> {
+    /// Find a cached result that is good enough to stand in for a search with `limit`:
+    /// one that searched at least as deep, as many nodes, or for as long as `limit` asks.
+    fn probe_cache(&self, board: &Board, limit: &SearchLimit) -> Option<CachedAnalysis> {
+        let cached = self.cache?.lock().ok()?.probe(board.zobrist_key())?;
+        let deep_enough = match limit {
+            SearchLimit::Depth(depth) => {
+                usize::try_from(*depth).is_ok_and(|depth| cached.depth >= depth)
+            }
+            SearchLimit::Nodes(nodes) => cached.nodes >= *nodes,
+            SearchLimit::Time(ms) => cached.time_ms >= *ms,
+            _ => false,
+        };
+        // guard against hash collisions.
+        let legal = board.legal_moves().contains(&cached.best_move);
+        (deep_enough && legal).then_some(cached)
+    }
+}
+
+/// The win, draw and loss probabilities for the side to move, given a `score` from
+/// white's perspective.
+fn wdl(score: i32, turn: Colour, ply: usize) -> (i32, i32, i32) {
+    let score = if turn == Colour::White { score } else { -score };
+    let (w, l) = win_rate_model(score, ply);
+    (w, 1000 - w - l, l)
+}
+
+/// Analyse `fens` across `threads` threads, returning the results in input order.
+pub fn analyse_fens(
+    fens: &[String],
+    limit: &SearchLimit,
+    threads: usize,
+    hash_mb: usize,
+    conf: &Config,
+    nnue_params: &NNUEParams,
+    cache: Option<&Mutex<AnalysisCache>>,
+) -> anyhow::Result<Vec<Analysis>> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let cache_hits = AtomicUsize::new(0);
+    let start = Instant::now();
+    let mut results = std::thread::scope(|s| {
+        let handles = (0..threads.clamp(1, fens.len().max(1)))
+            .map(|_| {
+                s.spawn(|| -> anyhow::Result<Vec<(usize, Analysis)>> {
+                    let stopped = AtomicBool::new(false);
+                    let nodes = AtomicU64::new(0);
+                    let mut tt = TT::new();
+                    tt.resize(hash_mb * MEGABYTE);
+                    let mut worker = Worker {
+                        info: SearchInfo {
+                            print_to_stdout: false,
+                            conf: conf.clone(),
+                            lm_table: LMTable::new(conf),
+                            ..SearchInfo::new(&stopped, &nodes)
+                        },
+                        tt: &tt,
+                        thread_data: vec![ThreadData::new(
+                            0,
+                            &Board::default(),
+                            tt.view(),
+                            nnue_params,
+                        )],
+                        cache,
+                        cache_hits: &cache_hits,
+                    };
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(fen) = fens.get(index) else {
+                            return Ok(results);
+                        };
+                        results.push((index, worker.analyse(fen, limit)?));
+                        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if done.is_multiple_of(100) || done == fens.len() {
+                            eprintln!(
+                                "analysed {done}/{} positions in {:.1}s",
+                                fens.len(),
+                                start.elapsed().as_secs_f64()
+                            );
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut results = Vec::with_capacity(fens.len());
+        for handle in handles {
+            results.extend(
+                handle
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("analysis thread panicked"))??,
+            );
+        }
+        anyhow::Ok(results)
+    })?;
+    if cache.is_some() {
+        eprintln!(
+            "took {} of {} results from the analysis cache",
+            cache_hits.load(Ordering::Relaxed),
+            fens.len()
+        );
+    }
+    results.sort_unstable_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(_, analysis)| analysis).collect())
+}
+
+pub fn run_analysefile(options: &AnalyseOptions, conf: &Config) -> anyhow::Result<()> {
+    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let text = std::fs::read_to_string(&options.input)
+        .with_context(|| format!("Failed to read {}", options.input.display()))?;
+    let epds = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(Epd::parse)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let fens = epds.iter().map(|epd| epd.fen.clone()).collect::<Vec<_>>();
+
+    let cache = options
+        .cache
+        .as_ref()
+        .map(|(path, size_mb)| AnalysisCache::open(path, *size_mb).map(Mutex::new))
+        .transpose()?;
+
+    let results = analyse_fens(
+        &fens,
+        &options.limit,
+        options.threads,
+        options.hash_mb,
+        conf,
+        nnue_params,
+        cache.as_ref(),
+    )?;
+
+    if let Some(cache) = cache {
+        cache
+            .into_inner()
+            .map_err(|_| anyhow::anyhow!("analysis cache lock poisoned"))?
+            .flush()?;
+    }
+
+    write_results(&results, &epds, options.output.as_deref())
+}
+
+fn write_results(results: &[Analysis], epds: &[Epd], output: Option<&Path>) -> anyhow::Result<()> {
+    let extension = output.and_then(Path::extension);
+    let csv = extension.is_some_and(|ext| ext == "csv");
+    let epd = extension.is_some_and(|ext| ext == "epd");
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).with_context(|| {
+                format!("Failed to create {}", path.display())
+            })?))
+        }
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    if csv {
+        writeln!(writer, "fen,bestmove,score,depth,pv,win,draw,loss")?;
+    }
+    for (analysis, record) in results.iter().zip(epds) {
+        if csv {
+            analysis.write_csv(&mut writer)?;
+        } else if epd {
+            analysis.write_epd(record, &mut writer)?;
+        } else {
+            analysis.write_json(&mut writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn wdl_is_for_the_side_to_move() {
+        use super::wdl;
+        use crate::chess::piece::Colour;
+
+        // white is better, so black to move is likely to lose.
+        let (w, _, l) = wdl(300, Colour::White, 0);
+        assert!(w > l);
+        assert_eq!(wdl(300, Colour::Black, 0), (l, 1000 - w - l, w));
+    }
+}
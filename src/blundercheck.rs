@@ -0,0 +1,261 @@
+//! Checking whole games for blunders: every position of every game is analysed, and a
+//! move is reported when it gives away more than a threshold compared to the best move,
+//! along with the line that refutes it.
+
+use std::{fmt::Write as _, path::PathBuf};
+
+use anyhow::Context;
+
+use crate::{
+    analysefile::{analyse_fens, Analysis},
+    chess::{board::Board, chessmove::Move, piece::Colour},
+    evaluation::{is_game_theoretic_score, MINIMUM_TB_WIN_SCORE},
+    nnue::network::NNUEParams,
+    notation::{move_number, pgn_score, read_pgn},
+    search::parameters::Config,
+    timemgmt::SearchLimit,
+    uci::NORMALISE_TO_PAWN_VALUE,
+};
+
+pub struct BlunderCheckOptions {
+    /// Path to the PGN file of games to check.
+    pub input: PathBuf,
+    /// The smallest loss, in centipawns, for a move to count as a blunder.
+    pub threshold: i32,
+    /// The limit for the search of each position.
+    pub limit: SearchLimit,
+    /// Number of positions to analyse concurrently.
+    pub threads: usize,
+    /// Hash table size for each search thread, in megabytes.
+    pub hash_mb: usize,
+}
+
+/// A move that lost more than the threshold.
+struct Blunder {
+    ply: usize,
+    played: Move,
+    best: Option<Move>,
+    /// The white-relative scores before and after the move.
+    before: i32,
+    after: i32,
+    /// How much the move lost, from the mover's perspective.
+    loss: i32,
+    /// The best reply to the move, and the line that follows.
+    refutation: Vec<Move>,
+}
+
+/// A game as its positions, from the first to the one after the last move, and the moves between them.
+struct ReplayedGame {
+    positions: Vec<Board>,
+    moves: Vec<Move>,
+}
+
+/// Bring mate and tablebase scores down to the edge of the ordinary scores, so that the
+/// loss of a move that misses a mate is measured as a very large but comparable number.
+const fn clamp_score(score: i32) -> i32 {
+    if is_game_theoretic_score(score) {
+        MINIMUM_TB_WIN_SCORE * score.signum()
+    } else {
+        score
+    }
+}
+
+/// Find the moves in `game` that lost at least `threshold` in our internal units, given
+/// `analyses` of each of its positions.
+fn find_blunders(game: &ReplayedGame, analyses: &[Analysis], threshold: i32) -> Vec<Blunder> {
+    let mut blunders = Vec::new();
+    for (ply, (&played, pair)) in game.moves.iter().zip(analyses.windows(2)).enumerate() {
+        let [before, after] = pair else {
+            continue;
+        };
+        if before.best_move == Some(played) {
+            continue;
+        }
+        let drop = clamp_score(before.score) - clamp_score(after.score);
+        let loss = if game.positions[ply].turn() == Colour::White {
+            drop
+        } else {
+            -drop
+        };
+        if loss >= threshold {
+            blunders.push(Blunder {
+                ply,
+                played,
+                best: before.best_move,
+                before: before.score,
+                after: after.score,
+                loss,
+                refutation: after.pv.clone(),
+            });
+        }
+    }
+    blunders
+}
+
+/// Write `moves` from `board` in SAN, with move numbers.
+fn line_san(board: &Board, moves: &[Move]) -> String {
+    let mut board = board.clone();
+    let mut words = Vec::with_capacity(moves.len());
+    for (i, &m) in moves.iter().enumerate() {
+        let Some(san) = board.san(m) else {
+            break;
+        };
+        let ply = board.ply();
+        if ply.is_multiple_of(2) || i == 0 {
+            words.push(format!("{} {san}", move_number(ply)));
+        } else {
+            words.push(san);
+        }
+        board.make_move_simple(m);
+    }
+    words.join(" ")
+}
+
+fn describe(game: &ReplayedGame, blunder: &Blunder) -> String {
+    let board = &game.positions[blunder.ply];
+    let played = line_san(board, &[blunder.played]);
+    let mut description =
+        if is_game_theoretic_score(blunder.before) || is_game_theoretic_score(blunder.after) {
+            format!("{played} is a blunder")
+        } else {
+            let loss = f64::from(blunder.loss * 100 / NORMALISE_TO_PAWN_VALUE) / 100.0;
+            format!("{played} loses {loss:.2}")
+        };
+    write!(
+        description,
+        " ({} -> {})",
+        pgn_score(blunder.before),
+        pgn_score(blunder.after)
+    )
+    .unwrap();
+    if let Some(best) = blunder.best {
+        write!(description, "; best was {}", line_san(board, &[best])).unwrap();
+    }
+    if !blunder.refutation.is_empty() {
+        write!(
+            description,
+            "; refutation {}",
+            line_san(&game.positions[blunder.ply + 1], &blunder.refutation)
+        )
+        .unwrap();
+    }
+    description
+}
+
+pub fn run_blundercheck(options: &BlunderCheckOptions, conf: &Config) -> anyhow::Result<()> {
+    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let text = std::fs::read_to_string(&options.input)
+        .with_context(|| format!("Failed to read {}", options.input.display()))?;
+
+    let mut games = Vec::new();
+    for (index, pgn) in read_pgn(&text).into_iter().enumerate() {
+        let mut board = Board::default();
+        if let Some(fen) = &pgn.fen {
+            board
+                .set_from_fen(fen)
+                .with_context(|| format!("Invalid FEN tag in game {}: {fen}", index + 1))?;
+        }
+        let mut game = ReplayedGame {
+            positions: vec![board.clone()],
+            moves: Vec::with_capacity(pgn.moves.len()),
+        };
+        for (ply, san) in pgn.moves.iter().enumerate() {
+            let m = board
+                .parse_san(san)
+                .with_context(|| format!("In game {} at ply {}", index + 1, ply + 1))?;
+            board.make_move_simple(m);
+            game.moves.push(m);
+            game.positions.push(board.clone());
+        }
+        games.push(game);
+    }
+
+    let fens = games
+        .iter()
+        .flat_map(|game| game.positions.iter().map(ToString::to_string))
+        .collect::<Vec<_>>();
+    let mut analyses = analyse_fens(
+        &fens,
+        &options.limit,
+        options.threads,
+        options.hash_mb,
+        conf,
+        nnue_params,
+        None,
+    )?
+    .into_iter();
+
+    let threshold = options.threshold * NORMALISE_TO_PAWN_VALUE / 100;
+    let mut moves_checked = 0;
+    let mut blunder_count = 0;
+    for (index, game) in games.iter().enumerate() {
+        let analyses = analyses
+            .by_ref()
+            .take(game.positions.len())
+            .collect::<Vec<_>>();
+        moves_checked += game.moves.len();
+        for blunder in find_blunders(game, &analyses, threshold) {
+            println!("game {}: {}", index + 1, describe(game, &blunder));
+            blunder_count += 1;
+        }
+    }
+    println!(
+        "{blunder_count} blunders in {moves_checked} moves across {} games",
+        games.len()
+    );
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn blunders() {
+        use std::time::Duration;
+
+        use super::{find_blunders, ReplayedGame};
+        use crate::{analysefile::Analysis, chess::board::Board, evaluation::mate_in};
+
+        // 1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6?? 4. Qxf7#
+        let mut board = Board::default();
+        let mut game = ReplayedGame {
+            positions: vec![board.clone()],
+            moves: Vec::new(),
+        };
+        for san in ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"] {
+            let m = board.parse_san(san).unwrap();
+            board.make_move_simple(m);
+            game.moves.push(m);
+            game.positions.push(board.clone());
+        }
+        let analysed = |board: &Board, score, best_move| Analysis {
+            fen: board.to_string(),
+            best_move,
+            score,
+            depth: 10,
+            pv: best_move.into_iter().collect(),
+            wdl: (0, 1000, 0),
+            time: Duration::ZERO,
+        };
+        let g6 = game.positions[5].parse_san("g6").unwrap();
+        let qxf7 = game.moves[6];
+        let results = [
+            (20, Some(game.moves[0])),
+            (20, Some(game.moves[1])),
+            (10, Some(game.moves[2])),
+            (30, Some(game.moves[3])),
+            (30, Some(game.moves[4])),
+            (30, Some(g6)),
+            (mate_in(1), Some(qxf7)),
+            (mate_in(0), None),
+        ]
+        .iter()
+        .zip(&game.positions)
+        .map(|(&(score, best_move), board)| analysed(board, score, best_move))
+        .collect::<Vec<_>>();
+
+        let blunders = find_blunders(&game, &results, 150);
+        assert_eq!(blunders.len(), 1);
+        assert_eq!(blunders[0].ply, 5);
+        assert_eq!(blunders[0].best, Some(g6));
+        assert_eq!(blunders[0].refutation, [qxf7]);
+    }
+}
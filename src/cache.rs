@@ -0,0 +1,196 @@
+//! A persistent analysis cache: a memory-mapped, fixed-size table of search results keyed
+//! by zobrist hash, so that repeated batch analysis can skip positions it has already searched.
+//! The cache is tied to the network and engine version that filled it, and is wiped if
+//! opened by any other.
+
+use std::{fs::OpenOptions, hash::Hasher, path::Path};
+
+use anyhow::{bail, Context};
+use memmap2::MmapMut;
+
+use crate::{chess::chessmove::Move, nnue::network::nnue_checksum, util::MEGABYTE};
+
+const MAGIC: &[u8; 8] = b"VIRICACH";
+/// Bumped whenever the layout of the file changes.
+const FORMAT_VERSION: u64 = 2;
+const HEADER_SIZE: usize = 32;
+/// Key (8), move (2), score (2), depth (1), occupied (1), two bytes of padding,
+/// nodes (8), and milliseconds (8).
+const ENTRY_SIZE: usize = 32;
+
+/// A search result held in the cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CachedAnalysis {
+    pub best_move: Move,
+    pub score: i32,
+    pub depth: usize,
+    /// How many nodes the search that produced this result used.
+    pub nodes: u64,
+    /// How long the search that produced this result took, in milliseconds.
+    pub time_ms: u64,
+}
+
+pub struct AnalysisCache {
+    map: MmapMut,
+    entries: usize,
+}
+
+/// Identifies the engine build that wrote a cache, so that results from another version are discarded.
+fn version_hash() -> u64 {
+    let mut hasher = fxhash::FxHasher::default();
+    hasher.write(crate::VERSION.as_bytes());
+    hasher.finish()
+}
+
+fn header() -> [u8; HEADER_SIZE] {
+    let mut header = [0; HEADER_SIZE];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..16].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header[16..24].copy_from_slice(&nnue_checksum().to_le_bytes());
+    header[24..32].copy_from_slice(&version_hash().to_le_bytes());
+    header
+}
+
+impl AnalysisCache {
+    /// Open the cache at `path`, creating it with `size_mb` megabytes of entries if it
+    /// does not exist. An existing cache keeps its size.
+    pub fn open(path: &Path, size_mb: usize) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open analysis cache {}", path.display()))?;
+        let mut len = usize::try_from(file.metadata()?.len())?;
+        let fresh = len == 0;
+        if fresh {
+            len = HEADER_SIZE + (size_mb * MEGABYTE / ENTRY_SIZE).max(1) * ENTRY_SIZE;
+            file.set_len(u64::try_from(len)?)?;
+        }
+        if len < HEADER_SIZE + ENTRY_SIZE || !(len - HEADER_SIZE).is_multiple_of(ENTRY_SIZE) {
+            bail!("{} is not an analysis cache", path.display());
+        }
+        // SAFETY: the cache file is only expected to be modified through this mapping.
+        // Concurrent writers in other processes can at worst corrupt entries, which
+        // are validated for legality before use.
+        let map = unsafe { MmapMut::map_mut(&file) }
+            .with_context(|| format!("Failed to map analysis cache {}", path.display()))?;
+        let mut cache = Self {
+            map,
+            entries: (len - HEADER_SIZE) / ENTRY_SIZE,
+        };
+        let expected = header();
+        if cache.map[..HEADER_SIZE] != expected {
+            if cache.map[..MAGIC.len()] != *MAGIC && !fresh {
+                bail!("{} is not an analysis cache", path.display());
+            }
+            if !fresh {
+                eprintln!(
+                    "Analysis cache {} was written by a different network or engine version, clearing it",
+                    path.display()
+                );
+            }
+            cache.map.fill(0);
+            cache.map[..HEADER_SIZE].copy_from_slice(&expected);
+        }
+        Ok(cache)
+    }
+
+    fn entry(&self, key: u64) -> &[u8] {
+        #![allow(clippy::cast_possible_truncation)]
+        let index = (key % self.entries as u64) as usize;
+        let start = HEADER_SIZE + index * ENTRY_SIZE;
+        &self.map[start..start + ENTRY_SIZE]
+    }
+
+    fn entry_mut(&mut self, key: u64) -> &mut [u8] {
+        #![allow(clippy::cast_possible_truncation)]
+        let index = (key % self.entries as u64) as usize;
+        let start = HEADER_SIZE + index * ENTRY_SIZE;
+        &mut self.map[start..start + ENTRY_SIZE]
+    }
+
+    /// Look up the stored result for a position. The move is not checked for legality.
+    pub fn probe(&self, key: u64) -> Option<CachedAnalysis> {
+        let entry = self.entry(key);
+        if entry[13] == 0 || entry[..8] != key.to_le_bytes() {
+            return None;
+        }
+        Some(CachedAnalysis {
+            best_move: Move::from_raw(u16::from_le_bytes([entry[8], entry[9]]))?,
+            score: i32::from(i16::from_le_bytes([entry[10], entry[11]])),
+            depth: usize::from(entry[12]),
+            nodes: u64::from_le_bytes(entry[16..24].try_into().ok()?),
+            time_ms: u64::from_le_bytes(entry[24..32].try_into().ok()?),
+        })
+    }
+
+    /// Store a result, unless it would displace a deeper result for the same position.
+    pub fn store(&mut self, key: u64, analysis: CachedAnalysis) {
+        let (Ok(score), Ok(depth)) = (i16::try_from(analysis.score), u8::try_from(analysis.depth))
+        else {
+            return;
+        };
+        if self
+            .probe(key)
+            .is_some_and(|old| old.depth > analysis.depth)
+        {
+            return;
+        }
+        let entry = self.entry_mut(key);
+        entry[..8].copy_from_slice(&key.to_le_bytes());
+        entry[8..10].copy_from_slice(&analysis.best_move.inner().to_le_bytes());
+        entry[10..12].copy_from_slice(&score.to_le_bytes());
+        entry[12] = depth;
+        entry[13] = 1;
+        entry[16..24].copy_from_slice(&analysis.nodes.to_le_bytes());
+        entry[24..32].copy_from_slice(&analysis.time_ms.to_le_bytes());
+    }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.map
+            .flush()
+            .with_context(|| "Failed to flush analysis cache")
+    }
+}
+
+mod tests {
+    #[test]
+    fn cache_persists_and_invalidates() {
+        use super::{AnalysisCache, CachedAnalysis, HEADER_SIZE};
+        use crate::chess::{chessmove::Move, types::Square};
+
+        let path = std::env::temp_dir().join(format!("viri-cache-test-{}.bin", std::process::id()));
+        let analysis = CachedAnalysis {
+            best_move: Move::new(Square::E2, Square::E4),
+            score: -35,
+            depth: 20,
+            nodes: 1_000_000,
+            time_ms: 500,
+        };
+        let shallower = CachedAnalysis {
+            depth: 10,
+            ..analysis
+        };
+        {
+            let mut cache = AnalysisCache::open(&path, 1).unwrap();
+            assert_eq!(cache.probe(12345), None);
+            cache.store(12345, analysis);
+            cache.store(12345, shallower);
+            cache.flush().unwrap();
+        }
+        let cache = AnalysisCache::open(&path, 1).unwrap();
+        assert_eq!(cache.probe(12345), Some(analysis));
+        std::mem::drop(cache);
+
+        // a cache from another network is wiped on opening.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[HEADER_SIZE - 1] ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+        let cache = AnalysisCache::open(&path, 1).unwrap();
+        assert_eq!(cache.probe(12345), None);
+        std::mem::drop(cache);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! Classification of openings by their ECO code, keyed by the Zobrist hash of the
+//! position that each one reaches, so that transpositions are classified alike.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use crate::chess::board::Board;
+
+/// The ECO code, name, and moves from the starting position of each opening, one per line.
+/// Where two lines reach the same position, the first one is kept.
+const TABLE: &str = include_str!("eco.tsv");
+
+/// An opening from the ECO classification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opening {
+    pub code: &'static str,
+    pub name: &'static str,
+}
+
+static OPENINGS: LazyLock<HashMap<u64, Opening>> = LazyLock::new(|| {
+    let mut openings = HashMap::new();
+    for line in TABLE.lines() {
+        let mut fields = line.split('\t');
+        let (Some(code), Some(name), Some(moves)) = (fields.next(), fields.next(), fields.next())
+        else {
+            panic!("malformed ECO table line: {line:?}");
+        };
+        let mut board = Board::default();
+        for san in moves.split_ascii_whitespace() {
+            let m = board
+                .parse_san(san)
+                .unwrap_or_else(|e| panic!("bad move {san} in ECO table line {line:?}: {e}"));
+            board.make_move_simple(m);
+        }
+        openings
+            .entry(board.zobrist_key())
+            .or_insert(Opening { code, name });
+    }
+    openings
+});
+
+impl Board {
+    /// The opening that the game has reached: the classification of the latest position
+    /// in the game that has one.
+    pub fn eco(&self) -> Option<Opening> {
+        std::iter::once(self.key)
+            .chain(self.history.iter().rev().map(|undo| undo.key))
+            .find_map(|key| OPENINGS.get(&key).copied())
+    }
+}
+
+mod tests {
+    #[test]
+    fn classification() {
+        use crate::chess::board::Board;
+
+        let opening_after = |moves: &str| {
+            let mut board = Board::default();
+            for san in moves.split_ascii_whitespace() {
+                let m = board.parse_san(san).unwrap();
+                board.make_move_simple(m);
+            }
+            board.eco().map(|opening| opening.code)
+        };
+
+        assert_eq!(opening_after(""), None);
+        assert_eq!(opening_after("e4 c5"), Some("B20"));
+        // reached by transposition, and then left for a position with no entry.
+        assert_eq!(opening_after("Nf3 c5 e4 d6"), Some("B50"));
+        assert_eq!(opening_after("Nf3 c5 e4 d6 Nc3"), Some("B50"));
+        // every line in the table is legal, and reaches a position of its own.
+        assert_eq!(
+            super::OPENINGS.len(),
+            super::TABLE.lines().count(),
+            "two lines of the ECO table reach the same position"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Storage for the states that `make_move` saves, so that moves can be unmade.
+//!
+//! By default this is a `Vec`, which can hold a game of any length. With the `copy-make`
+//! feature, it is instead a stack allocated up front when the board is created, large
+//! enough that saving a state almost never has to grow the allocation. A longer game
+//! still works: the stack doubles in size when it runs out of room. The `makebench` UCI command measures make/unmake throughput
+//! under either choice.
+
+#[cfg(feature = "copy-make")]
+use std::ops::Deref;
+
+use crate::chess::types::Undo;
+
+/// The plies of game history that the stack holds before it first has to grow,
+/// including the moves made during search.
+#[cfg(feature = "copy-make")]
+pub const HISTORY_CAPACITY: usize = 1024;
+
+/// A preallocated stack of saved states. The states live on the heap, as they are far
+/// too large to keep inline in a `Board` that is moved around on thread stacks.
+#[cfg(feature = "copy-make")]
+#[derive(Debug)]
+pub struct History {
+    states: Box<[Undo]>,
+    len: usize,
+}
+
+#[cfg(feature = "copy-make")]
+impl History {
+    pub fn new() -> Self {
+        Self {
+            states: vec![Undo::default(); HISTORY_CAPACITY].into_boxed_slice(),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, state: Undo) {
+        if self.len == self.states.len() {
+            self.grow(self.len * 2);
+        }
+        self.states[self.len] = state;
+        self.len += 1;
+    }
+
+    /// Move the states into a larger allocation that holds `capacity` of them.
+    #[cold]
+    fn grow(&mut self, capacity: usize) {
+        let mut states = vec![Undo::default(); capacity].into_boxed_slice();
+        states[..self.len].copy_from_slice(self);
+        self.states = states;
+    }
+
+    pub fn pop(&mut self) -> Option<Undo> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.states[self.len])
+    }
+
+    pub const fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Make sure that `additional` more states can be pushed without growing the stack.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len + additional;
+        if needed > self.states.len() {
+            self.grow(needed.max(self.states.len() * 2));
+        }
+    }
+}
+
+#[cfg(feature = "copy-make")]
+impl Clone for History {
+    fn clone(&self) -> Self {
+        Self {
+            states: self.states.clone(),
+            len: self.len,
+        }
+    }
+}
+
+#[cfg(feature = "copy-make")]
+impl PartialEq for History {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+#[cfg(feature = "copy-make")]
+impl Eq for History {}
+
+#[cfg(feature = "copy-make")]
+impl Deref for History {
+    type Target = [Undo];
+
+    fn deref(&self) -> &[Undo] {
+        &self.states[..self.len]
+    }
+}
+
+#[cfg(not(feature = "copy-make"))]
+pub type History = Vec<Undo>;
+
+/// A description of the history storage in use, for benchmark output.
+#[cfg(feature = "copy-make")]
+pub const HISTORY_KIND: &str = "preallocated stack";
+#[cfg(not(feature = "copy-make"))]
+pub const HISTORY_KIND: &str = "vec";
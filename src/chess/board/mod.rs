@@ -1,4 +1,7 @@
+pub mod eco;
+pub mod history;
 pub mod movegen;
+pub mod render;
 pub mod validation;
 
 use std::{
@@ -7,20 +10,23 @@ use std::{
 };
 
 use anyhow::{bail, Context};
+use arrayvec::ArrayString;
 
+use history::History;
 use movegen::RAY_BETWEEN;
 #[cfg(feature = "datagen")]
-use rand::{prelude::SliceRandom, rngs::ThreadRng};
+use rand::{prelude::SliceRandom, Rng};
 
 use crate::{
     chess::{
         board::movegen::{
-            bishop_attacks, king_attacks, knight_attacks, pawn_attacks, rook_attacks, MoveList,
+            bishop_attacks, king_attacks, knight_attacks, pawn_attacks, queen_lines, rook_attacks,
+            MoveList,
         },
         chessmove::Move,
         piece::{Black, Col, Colour, Piece, PieceType, White},
         squareset::SquareSet,
-        types::{CastlingRights, CheckState, File, Rank, Square, Undo},
+        types::{CastlingRights, CheckState, Direction, File, Rank, Square, Undo},
         CHESS960,
     },
     cuckoo,
@@ -32,6 +38,10 @@ use crate::{
 
 use crate::chess::piecelayout::{PieceLayout, Threats};
 
+/// The longest a compact FEN can be: 71 characters of piece placement
+/// (eight per rank, and seven slashes), then `" w KQkq e3"`.
+pub const COMPACT_FEN_CAPACITY: usize = 81;
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct Board {
     /// The square-sets of all the pieces on the board.
@@ -64,7 +74,7 @@ pub struct Board {
     threats: Threats,
 
     height: usize,
-    history: Vec<Undo>,
+    history: History,
 }
 
 impl Debug for Board {
@@ -105,7 +115,7 @@ impl Board {
             major_key: 0,
             threats: Threats::default(),
             castle_perm: CastlingRights::NONE,
-            history: Vec::new(),
+            history: History::new(),
         };
         out.reset();
         out
@@ -155,7 +165,6 @@ impl Board {
         self.major_key
     }
 
-    #[cfg(debug_assertions)]
     pub const fn all_keys(&self) -> (u64, u64, [u64; 2], u64, u64) {
         (
             self.key,
@@ -179,6 +188,37 @@ impl Board {
         &self.threats
     }
 
+    /// The pieces of either colour that attack `sq`, with sliders blocked by `occupied`.
+    /// Pass something other than the board's occupancy to look through pieces, as in
+    /// static exchange evaluation.
+    pub fn attackers_to(&self, sq: Square, occupied: SquareSet) -> SquareSet {
+        self.pieces.all_attackers_to_sq(sq, occupied)
+    }
+
+    /// The pieces of `colour` that are pinned to their own king by an enemy slider.
+    pub const fn pinned(&self, colour: Colour) -> SquareSet {
+        self.threats.pinned[colour as usize]
+    }
+
+    /// The enemy pieces giving check to the side to move.
+    pub const fn checkers(&self) -> SquareSet {
+        self.threats.checkers
+    }
+
+    /// Every square attacked by a piece of `colour`, whether or not moving there is legal.
+    /// Sliders are blocked by every piece, so squares behind the enemy king are not included.
+    #[allow(dead_code)]
+    pub fn threats_by(&self, colour: Colour) -> SquareSet {
+        if colour != self.side {
+            return self.threats.all;
+        }
+        let sliders = self.threats.sliders[colour];
+        match colour {
+            Colour::White => self.attacks_with_sliders::<White>(sliders),
+            Colour::Black => self.attacks_with_sliders::<Black>(sliders),
+        }
+    }
+
     pub fn king_sq(&self, side: Colour) -> Square {
         debug_assert!(side == Colour::White || side == Colour::Black);
         debug_assert_eq!(self.pieces.king::<White>().count(), 1);
@@ -196,13 +236,19 @@ impl Board {
     }
 
     pub const fn in_check(&self) -> bool {
-        self.threats.checkers.non_empty()
+        self.checkers().non_empty()
     }
 
     pub fn zero_height(&mut self) {
         self.height = 0;
     }
 
+    /// Make room for a search's worth of moves on top of the game so far,
+    /// so that making moves during search never has to grow the history.
+    pub fn reserve_search_history(&mut self) {
+        self.history.reserve(crate::util::MAX_PLY);
+    }
+
     pub const fn height(&self) -> usize {
         self.height
     }
@@ -258,7 +304,6 @@ impl Board {
         (key, pawn_key, non_pawn_key, minor_key, major_key)
     }
 
-    #[cfg(feature = "datagen")]
     pub fn regenerate_zobrist(&mut self) {
         (
             self.key,
@@ -269,7 +314,6 @@ impl Board {
         ) = self.generate_pos_keys();
     }
 
-    #[cfg(feature = "datagen")]
     pub fn regenerate_threats(&mut self) {
         self.threats = self.generate_threats(self.side.flip());
     }
@@ -283,53 +327,136 @@ impl Board {
     }
 
     pub fn generate_threats_from<C: Col>(&self) -> Threats {
-        let mut threats = SquareSet::EMPTY;
-        let mut checkers = SquareSet::EMPTY;
+        let mut sliders = [SquareSet::EMPTY; 2];
+        sliders[C::COLOUR] = self.slider_attacks::<C>();
+        sliders[C::Opposite::COLOUR] = self.slider_attacks::<C::Opposite>();
+        let mut pinned = [SquareSet::EMPTY; 2];
+        pinned[C::COLOUR] = self.pinned_pieces::<C>();
+        pinned[C::Opposite::COLOUR] = self.pinned_pieces::<C::Opposite>();
 
-        let their_pawns = self.pieces.pawns::<C>();
-        let their_knights = self.pieces.knights::<C>();
-        let their_diags = self.pieces.diags::<C>();
-        let their_orthos = self.pieces.orthos::<C>();
-        let their_king = self.king_sq(C::COLOUR);
-        let blockers = self.pieces.occupied();
+        Threats {
+            all: self.attacks_with_sliders::<C>(sliders[C::COLOUR]),
+            /* pawn: pawn_threats, minor: minor_threats, rook: rook_threats, */
+            checkers: self.checkers_of::<C::Opposite>(),
+            pinned,
+            sliders,
+        }
+    }
+
+    /// Update the threats after `C` has made the move `m`, given the threats
+    /// from before the move. Moves that shift more than one piece, and king moves,
+    /// fall back to generating the threats from scratch.
+    fn update_threats_from<C: Col>(
+        &self,
+        prev: &Threats,
+        m: Move,
+        piece: Piece,
+        captured: Option<Piece>,
+    ) -> Threats {
+        let is_slider = |piece: Piece| {
+            matches!(
+                piece.piece_type(),
+                PieceType::Bishop | PieceType::Rook | PieceType::Queen
+            )
+        };
+        if m.is_castle() || m.is_ep() || m.is_promo() || piece.piece_type() == PieceType::King {
+            return self.generate_threats_from::<C>();
+        }
 
-        // compute threats
-        threats |= pawn_attacks::<C>(their_pawns);
+        let changed = m.from().as_set() | m.to().as_set();
 
-        for sq in their_knights {
-            threats |= knight_attacks(sq);
+        // slider attacks only change if a slider moves or is captured,
+        // or if one of the squares a slider can see changes occupancy.
+        let mut sliders = prev.sliders;
+        if is_slider(piece) || (changed & sliders[C::COLOUR]).non_empty() {
+            sliders[C::COLOUR] = self.slider_attacks::<C>();
         }
-        for sq in their_diags {
-            threats |= bishop_attacks(sq, blockers);
-        }
-        for sq in their_orthos {
-            threats |= rook_attacks(sq, blockers);
+        if captured.is_some_and(is_slider) || (changed & sliders[C::Opposite::COLOUR]).non_empty() {
+            sliders[C::Opposite::COLOUR] = self.slider_attacks::<C::Opposite>();
         }
 
-        threats |= king_attacks(their_king);
-
-        // compute checkers
-        let our_king = self.king_sq(C::Opposite::COLOUR);
-        let king_bb = our_king.as_set();
-        let backwards_from_king = pawn_attacks::<C::Opposite>(king_bb);
-        checkers |= backwards_from_king & their_pawns;
-
-        let knight_attacks = knight_attacks(our_king);
-
-        checkers |= knight_attacks & their_knights;
+        // pins and discovered checks can only appear along the lines out from a king.
+        let mut pinned = prev.pinned;
+        let our_king = self.king_sq(C::COLOUR);
+        if (changed & queen_lines(our_king)).non_empty() {
+            pinned[C::COLOUR] = self.pinned_pieces::<C>();
+        }
+        let their_king = self.king_sq(C::Opposite::COLOUR);
+        let checkers = if (changed & queen_lines(their_king)).non_empty() {
+            pinned[C::Opposite::COLOUR] = self.pinned_pieces::<C::Opposite>();
+            self.checkers_of::<C::Opposite>()
+        } else {
+            // no line to their king was opened, so only the moved piece can give check.
+            // as it didn't move onto one of those lines, only a knight or a pawn can.
+            let direct = match piece.piece_type() {
+                PieceType::Pawn => pawn_attacks::<C>(m.to().as_set()),
+                PieceType::Knight => knight_attacks(m.to()),
+                _ => SquareSet::EMPTY,
+            };
+            if direct.contains_square(their_king) {
+                m.to().as_set()
+            } else {
+                SquareSet::EMPTY
+            }
+        };
 
-        let diag_attacks = bishop_attacks(our_king, blockers);
+        Threats {
+            all: self.attacks_with_sliders::<C>(sliders[C::COLOUR]),
+            checkers,
+            pinned,
+            sliders,
+        }
+    }
 
-        checkers |= diag_attacks & their_diags;
+    /// All the squares attacked by `C`, given the squares attacked by its sliders.
+    fn attacks_with_sliders<C: Col>(&self, sliders: SquareSet) -> SquareSet {
+        let mut threats = sliders;
+        threats |= pawn_attacks::<C>(self.pieces.pawns::<C>());
+        for sq in self.pieces.knights::<C>() {
+            threats |= knight_attacks(sq);
+        }
+        threats | king_attacks(self.king_sq(C::COLOUR))
+    }
 
-        let ortho_attacks = rook_attacks(our_king, blockers);
+    /// The squares attacked by the bishops, rooks, and queens of `C`.
+    fn slider_attacks<C: Col>(&self) -> SquareSet {
+        let blockers = self.pieces.occupied();
+        let mut threats = SquareSet::EMPTY;
+        for sq in self.pieces.diags::<C>() {
+            threats |= bishop_attacks(sq, blockers);
+        }
+        for sq in self.pieces.orthos::<C>() {
+            threats |= rook_attacks(sq, blockers);
+        }
+        threats
+    }
 
-        checkers |= ortho_attacks & their_orthos;
+    /// The enemy pieces giving check to the king of `C`.
+    fn checkers_of<C: Col>(&self) -> SquareSet {
+        let our_king = self.king_sq(C::COLOUR);
+        let blockers = self.pieces.occupied();
+        (pawn_attacks::<C>(our_king.as_set()) & self.pieces.pawns::<C::Opposite>())
+            | (knight_attacks(our_king) & self.pieces.knights::<C::Opposite>())
+            | (bishop_attacks(our_king, blockers) & self.pieces.diags::<C::Opposite>())
+            | (rook_attacks(our_king, blockers) & self.pieces.orthos::<C::Opposite>())
+    }
 
-        Threats {
-            all: threats,
-            /* pawn: pawn_threats, minor: minor_threats, rook: rook_threats, */ checkers,
+    /// The pieces of `C` that are all that stands between their king and an enemy slider.
+    fn pinned_pieces<C: Col>(&self) -> SquareSet {
+        let our_king = self.king_sq(C::COLOUR);
+        let their_pieces = self.pieces.our_pieces::<C::Opposite>();
+        let blockers = self.pieces.occupied();
+        // look through our pieces for sliders aimed at our king.
+        let snipers = (bishop_attacks(our_king, their_pieces) & self.pieces.diags::<C::Opposite>())
+            | (rook_attacks(our_king, their_pieces) & self.pieces.orthos::<C::Opposite>());
+        let mut pinned = SquareSet::EMPTY;
+        for sniper in snipers {
+            let between = RAY_BETWEEN[our_king][sniper] & blockers;
+            if between.count() == 1 {
+                pinned |= between & self.pieces.our_pieces::<C>();
+            }
         }
+        pinned
     }
 
     pub fn reset(&mut self) {
@@ -559,6 +686,8 @@ impl Board {
 
         let mut rank = Rank::Eight;
         let mut file = File::A;
+        // the number of squares on the current rank that the FEN has described.
+        let mut filled = 0;
 
         self.reset();
 
@@ -590,8 +719,11 @@ impl Board {
                     count = c - b'0';
                 }
                 b'/' => {
-                    rank = rank.sub(1).unwrap();
+                    rank = rank
+                        .sub(1)
+                        .with_context(|| format!("FEN string has more than eight ranks: {fen}"))?;
                     file = File::A;
+                    filled = 0;
                     continue;
                 }
                 c => {
@@ -602,6 +734,10 @@ impl Board {
                 }
             }
 
+            filled += count;
+            if filled > 8 {
+                bail!("FEN string has more than eight squares on a rank: {fen}");
+            }
             for _ in 0..count {
                 let sq = Square::from_rank_file(rank, file);
                 if let Some(piece) = piece {
@@ -612,6 +748,17 @@ impl Board {
             }
         }
 
+        // the castling rights and the threats both need the kings.
+        for colour in Colour::all() {
+            let kings = self
+                .pieces
+                .piece_bb(Piece::new(colour, PieceType::King))
+                .count();
+            if kings != 1 {
+                bail!("FEN string has {kings} {colour:?} kings, not one: {fen}");
+            }
+        }
+
         let mut info_parts = info_part[1..].split(|&c| c == b' ');
 
         self.set_side(info_parts.next())?;
@@ -642,13 +789,72 @@ impl Board {
             .expect("for some reason, STARTING_FEN is now broken.");
     }
 
-    #[cfg(test)]
     pub fn from_fen(fen: &str) -> anyhow::Result<Self> {
         let mut out = Self::new();
         out.set_from_fen(fen)?;
         Ok(out)
     }
 
+    /// A copy of this position with every piece moved to `map` of its square, the colours
+    /// swapped if `swap` is set, and `side` to move. Castling rights and the game history
+    /// are not carried over.
+    fn remapped(&self, map: impl Fn(Square) -> Square, swap: bool, side: Colour) -> Self {
+        let mut out = Self::new();
+        for sq in Square::all() {
+            if let Some(piece) = self.piece_at(sq) {
+                let colour = if swap {
+                    piece.colour().flip()
+                } else {
+                    piece.colour()
+                };
+                out.add_piece(map(sq), Piece::new(colour, piece.piece_type()));
+            }
+        }
+        out.side = side;
+        out.ep_sq = self.ep_sq.map(&map);
+        out.fifty_move_counter = self.fifty_move_counter;
+        // keep the move number, whoever is now to move.
+        out.ply = (self.ply & !1) | usize::from(side == Colour::Black);
+        out
+    }
+
+    fn finish_remap(mut self) -> Self {
+        self.finish_edit();
+        self
+    }
+
+    /// The same position with the other side to move. Fails if that would leave the side
+    /// not to move in check. En passant is lost, as it was only ever ours to take.
+    pub fn flip_side(&self) -> anyhow::Result<Self> {
+        if self.in_check() {
+            bail!("can't flip the side to move while in check");
+        }
+        let mut out = self.clone();
+        out.set_side_to_move(self.side.flip());
+        Ok(out)
+    }
+
+    /// The position mirrored top to bottom with the colours swapped, which should
+    /// evaluate exactly as this one does.
+    pub fn mirror_vertical(&self) -> Self {
+        let mut out = self.remapped(Square::flip_rank, true, self.side.flip());
+        let rights = self.castle_perm;
+        out.castle_perm = CastlingRights {
+            wk: rights.bk.map(Square::flip_rank),
+            wq: rights.bq.map(Square::flip_rank),
+            bk: rights.wk.map(Square::flip_rank),
+            bq: rights.wq.map(Square::flip_rank),
+        };
+        out.finish_remap()
+    }
+
+    /// The position mirrored left to right. The kings end up on the wrong side of their
+    /// rooks to castle, so castling rights are dropped.
+    pub fn mirror_horizontal(&self) -> Self {
+        self.remapped(Square::flip_file, false, self.side)
+            .finish_remap()
+    }
+
     #[cfg(test)]
     pub fn from_frc_idx(scharnagl: usize) -> Self {
         let mut out = Self::new();
@@ -715,7 +921,12 @@ impl Board {
                 for &c in shredder_castling {
                     match c {
                         c if c.is_ascii_uppercase() => {
-                            let file = File::from_index(c - b'A').unwrap();
+                            let file = File::from_index(c - b'A').with_context(|| {
+                                format!(
+                                    "FEN string is invalid, got castling rights on file {}",
+                                    c as char
+                                )
+                            })?;
                             let king_file = white_king.file();
                             if file == king_file {
                                 bail!(format!("FEN string is invalid, white king is on file {:?}, but got castling rights on that file - got \"{}\"", king_file, std::str::from_utf8(shredder_castling).unwrap_or("<invalid utf8>")));
@@ -730,7 +941,12 @@ impl Board {
                             }
                         }
                         c if c.is_ascii_lowercase() => {
-                            let file = File::from_index(c - b'a').unwrap();
+                            let file = File::from_index(c - b'a').with_context(|| {
+                                format!(
+                                    "FEN string is invalid, got castling rights on file {}",
+                                    c as char
+                                )
+                            })?;
                             let king_file = black_king.file();
                             if file == king_file {
                                 bail!(format!("FEN string is invalid, black king is on file {:?}, but got castling rights on that file - got \"{}\"", king_file, std::str::from_utf8(shredder_castling).unwrap_or("<invalid utf8>")));
@@ -766,17 +982,15 @@ impl Board {
                         std::str::from_utf8(ep_sq).unwrap_or("<invalid utf8>")
                     ));
                 }
-                let file = ep_sq[0] - b'a';
-                let rank = ep_sq[1] - b'1';
-                let file = File::from_index(file);
-                let rank = Rank::from_index(rank);
-                if !(file.is_some() && rank.is_some()) {
+                let file = File::from_index(ep_sq[0].wrapping_sub(b'a'));
+                let rank = Rank::from_index(ep_sq[1].wrapping_sub(b'1'));
+                let (Some(file), Some(rank)) = (file, rank) else {
                     bail!(format!(
                         "FEN string is invalid, expected en passant part to be of the form 'a1', got \"{}\"",
                         std::str::from_utf8(ep_sq).unwrap_or("<invalid utf8>")
                     ));
-                }
-                self.ep_sq = Some(Square::from_rank_file(rank.unwrap(), file.unwrap()));
+                };
+                self.ep_sq = Some(Square::from_rank_file(rank, file));
             }
         }
 
@@ -787,15 +1001,18 @@ impl Board {
         match halfmove_part {
             None => bail!("FEN string is invalid, expected halfmove clock part.".to_string()),
             Some(halfmove_clock) => {
-                self.fifty_move_counter = std::str::from_utf8(halfmove_clock)
+                let clock = std::str::from_utf8(halfmove_clock)
                     .with_context(|| "FEN string is invalid, expected halfmove clock part to be valid UTF-8")?
-                    .parse::<u8>()
+                    .parse::<u32>()
                     .with_context(|| {
                         format!(
                             "FEN string is invalid, expected halfmove clock part to be a number, got \"{}\"",
                             std::str::from_utf8(halfmove_clock).unwrap_or("<invalid utf8>")
                         )
                     })?;
+                // a clock past 100 is already a fifty-move draw, and counting further
+                // would overflow, so such positions are read as if on move 100.
+                self.fifty_move_counter = u8::try_from(clock.min(100))?;
             }
         }
 
@@ -814,7 +1031,8 @@ impl Board {
                     .with_context(|| {
                         "FEN string is invalid, expected fullmove number part to be a number"
                     })?;
-                self.ply = (fullmove_number - 1) * 2;
+                // some programs write a fullmove number of zero, meaning the first move.
+                self.ply = fullmove_number.saturating_sub(1) * 2;
                 if self.side == Colour::Black {
                     self.ply += 1;
                 }
@@ -824,6 +1042,72 @@ impl Board {
         Ok(())
     }
 
+    /// Write the FEN of this position into `out`, without allocating.
+    pub fn write_fen_into(&self, out: &mut impl Write) -> fmt::Result {
+        self.write_compact_fen_into(out)?;
+        write!(out, " {} {}", self.fifty_move_counter, self.ply / 2 + 1)
+    }
+
+    /// The FEN of this position without the move counters, so that the same position
+    /// reached at different points in a game has the same key.
+    #[allow(dead_code)]
+    pub fn compact_fen(&self) -> ArrayString<COMPACT_FEN_CAPACITY> {
+        let mut fen = ArrayString::new();
+        self.write_compact_fen_into(&mut fen)
+            .expect("compact FENs always fit in the buffer");
+        fen
+    }
+
+    fn write_compact_fen_into(&self, out: &mut impl Write) -> fmt::Result {
+        for rank in Rank::all().rev() {
+            let mut empty = 0;
+            for file in File::all() {
+                if let Some(piece) = self.piece_at(Square::from_rank_file(rank, file)) {
+                    if empty != 0 {
+                        out.write_char(char::from(b'0' + empty))?;
+                        empty = 0;
+                    }
+                    out.write_char(piece.char())?;
+                } else {
+                    empty += 1;
+                }
+            }
+            if empty != 0 {
+                out.write_char(char::from(b'0' + empty))?;
+            }
+            if rank != Rank::One {
+                out.write_char('/')?;
+            }
+        }
+
+        out.write_str(if self.side == Colour::White {
+            " w "
+        } else {
+            " b "
+        })?;
+        if self.castle_perm == CastlingRights::NONE {
+            out.write_char('-')?;
+        } else {
+            for (_, ch) in [
+                self.castle_perm.wk,
+                self.castle_perm.wq,
+                self.castle_perm.bk,
+                self.castle_perm.bq,
+            ]
+            .into_iter()
+            .zip("KQkq".chars())
+            .filter(|(m, _)| m.is_some())
+            {
+                out.write_char(ch)?;
+            }
+        }
+        if let Some(ep_sq) = self.ep_sq {
+            write!(out, " {ep_sq}")
+        } else {
+            out.write_str(" -")
+        }
+    }
+
     /// Determines if `sq` is attacked by `side`
     pub fn sq_attacked(&self, sq: Square, side: Colour) -> bool {
         if side == Colour::White {
@@ -1039,6 +1323,50 @@ impl Board {
             && !self.any_attacked(king_path | m.from().as_set(), self.side.flip())
     }
 
+    /// Checks whether a pseudo-legal move leaves our king safe, without making the move.
+    pub fn is_legal(&self, m: Move) -> bool {
+        let from = m.from();
+        let to = m.to();
+        let king_sq = self.king_sq(self.side);
+        let their_pieces = self.pieces.occupied_co(self.side.flip());
+        let occupied = self.pieces.occupied();
+
+        if m.is_castle() {
+            // the path is checked when castling is generated, so only the destination is left.
+            let king_dst = if to > from { Square::G1 } else { Square::C1 }.relative_to(self.side);
+            let rook_dst = if to > from { Square::F1 } else { Square::D1 }.relative_to(self.side);
+            let occupied = occupied
+                .remove(from.as_set() | to.as_set())
+                .add_square(king_dst)
+                .add_square(rook_dst);
+            return (self.pieces.all_attackers_to_sq(king_dst, occupied) & their_pieces).is_empty();
+        }
+
+        if from == king_sq {
+            // the king can't hide from a slider by standing in its way.
+            let occupied = occupied.remove_square(from);
+            return (self.pieces.all_attackers_to_sq(to, occupied)
+                & their_pieces.remove_square(to))
+            .is_empty();
+        }
+
+        if m.is_ep() {
+            // two pieces leave the same rank at once, so there are no shortcuts.
+            let captured = Square::from_rank_file(from.rank(), to.file());
+            let occupied = occupied
+                .remove(from.as_set() | captured.as_set())
+                .add_square(to);
+            let attackers = self.pieces.all_attackers_to_sq(king_sq, occupied)
+                & their_pieces.remove_square(captured);
+            return attackers.is_empty();
+        }
+
+        // a pinned piece may only move along the line of the pin.
+        !self.pinned(self.side).contains_square(from)
+            || RAY_BETWEEN[king_sq][to].contains_square(from)
+            || RAY_BETWEEN[king_sq][from].contains_square(to)
+    }
+
     pub fn any_attacked(&self, squares: SquareSet, by: Colour) -> bool {
         if by == self.side.flip() {
             (squares & self.threats.all).non_empty()
@@ -1057,6 +1385,69 @@ impl Board {
         *self.piece_at_mut(sq) = Some(piece);
     }
 
+    /// Takes the piece off `sq`, along with any castling right that depended on it,
+    /// and the en passant square. Keys and threats are regenerated.
+    pub fn remove_piece(&mut self, sq: Square) {
+        let Some(piece) = self.piece_at(sq) else {
+            return;
+        };
+        self.pieces.clear_piece_at(sq, piece);
+        *self.piece_at_mut(sq) = None;
+        self.castle_perm.remove(sq);
+        self.ep_sq = None;
+        self.finish_edit();
+    }
+
+    /// Puts `piece` on `sq` in place of whatever was there, dropping any castling right
+    /// that depended on the old piece, and the en passant square. Keys and threats are
+    /// regenerated. Pawns can't go on the back ranks, and each side can only have one
+    /// king, but a position built up this way should still be checked with
+    /// [`Self::validate`] before it's used.
+    pub fn put_piece(&mut self, sq: Square, piece: Piece) -> anyhow::Result<()> {
+        if piece.piece_type() == PieceType::Pawn && matches!(sq.rank(), Rank::One | Rank::Eight) {
+            bail!("can't put a pawn on the back rank, at {sq}");
+        }
+        if piece.piece_type() == PieceType::King
+            && self.piece_at(sq) != Some(piece)
+            && self.pieces.piece_bb(piece).non_empty()
+        {
+            bail!("{:?} already has a king", piece.colour());
+        }
+        if let Some(old) = self.piece_at(sq) {
+            self.pieces.clear_piece_at(sq, old);
+            self.castle_perm.remove(sq);
+        }
+        self.add_piece(sq, piece);
+        self.ep_sq = None;
+        self.finish_edit();
+        Ok(())
+    }
+
+    /// Hands the move to `side`, keeping the move number. If that changes who is to move,
+    /// the en passant square is dropped, and keys and threats are regenerated.
+    pub fn set_side_to_move(&mut self, side: Colour) {
+        if side == self.side {
+            return;
+        }
+        self.side = side;
+        self.ply = (self.ply & !1) | usize::from(side == Colour::Black);
+        self.ep_sq = None;
+        self.finish_edit();
+    }
+
+    /// Bring everything derived from the pieces up to date after an edit. The game
+    /// history no longer leads to the position, so it is forgotten. Threats can't be
+    /// worked out without both kings, so they're left empty until the kings are back.
+    fn finish_edit(&mut self) {
+        self.history.clear();
+        self.regenerate_zobrist();
+        if self.pieces.king::<White>().count() == 1 && self.pieces.king::<Black>().count() == 1 {
+            self.regenerate_threats();
+        } else {
+            self.threats = Threats::default();
+        }
+    }
+
     /// Gets the piece that will be moved by the given move.
     pub fn moved_piece(&self, m: Move) -> Option<Piece> {
         let idx = m.from();
@@ -1152,12 +1543,10 @@ impl Board {
         }
 
         if m.is_ep() {
-            let clear_at = if side == Colour::White {
-                to.sub(8)
-            } else {
-                to.add(8)
-            }
-            .unwrap();
+            // the captured pawn is one step behind the square the capture lands on.
+            let clear_at = to
+                .try_offset(Direction::forward(side.flip()))
+                .expect("en passant captures land on the third or sixth rank");
             let to_clear = Piece::new(side.flip(), PieceType::Pawn);
             self.pieces.clear_piece_at(clear_at, to_clear);
             update_buffer.clear_piece(clear_at, to_clear);
@@ -1216,11 +1605,10 @@ impl Board {
                     & self.pieces.occupied_co(side.flip())
                     != SquareSet::EMPTY
             {
+                self.ep_sq = from.try_offset(Direction::forward(side));
                 if side == Colour::White {
-                    self.ep_sq = from.add(8);
                     debug_assert!(self.ep_sq.unwrap().rank() == Rank::Three);
                 } else {
-                    self.ep_sq = from.sub(8);
                     debug_assert!(self.ep_sq.unwrap().rank() == Rank::Six);
                 }
             }
@@ -1358,7 +1746,17 @@ impl Board {
         self.ply += 1;
         self.height += 1;
 
-        self.threats = self.generate_threats(self.side.flip());
+        self.threats = if side == Colour::White {
+            self.update_threats_from::<White>(&saved_state.threats, m, piece, captured)
+        } else {
+            self.update_threats_from::<Black>(&saved_state.threats, m, piece, captured)
+        };
+        debug_assert_eq!(
+            self.threats,
+            self.generate_threats(side),
+            "incremental threat update diverged after {} in {self}",
+            m.display(false)
+        );
 
         self.history.push(saved_state);
 
@@ -1500,6 +1898,12 @@ impl Board {
     }
 
     pub fn make_move(&mut self, m: Move, t: &mut ThreadData) -> bool {
+        // moves from the TT may belong to another position that shares the key, so
+        // they must be checked before they're played.
+        debug_assert!(
+            self.is_pseudo_legal(m),
+            "tried to make {m:?} in {self}, but it isn't pseudo-legal"
+        );
         self.make_move_nnue(m, t)
     }
 
@@ -1608,6 +2012,56 @@ impl Board {
         res
     }
 
+    /// Parses a move in standard algebraic notation, tolerating check and annotation
+    /// suffixes and disambiguation that is unnecessary or that differs from ours.
+    pub fn parse_san(&mut self, san: &str) -> anyhow::Result<Move> {
+        fn normalise(san: &str) -> String {
+            san.trim_end_matches(['+', '#', '!', '?'])
+                .trim_end_matches("e.p.")
+                .replace('0', "O")
+        }
+        fn strip_disambiguation(san: &str) -> String {
+            match san.as_bytes() {
+                [piece @ (b'N' | b'B' | b'R' | b'Q' | b'K'), .., _, _] => {
+                    let capture = if san.contains('x') { "x" } else { "" };
+                    format!("{}{capture}{}", *piece as char, &san[san.len() - 2..])
+                }
+                _ => san.to_string(),
+            }
+        }
+
+        let target = normalise(san);
+        let legal_moves = self.legal_moves();
+        let sans = legal_moves
+            .iter()
+            .map(|&m| Some((m, normalise(&self.san(m)?))))
+            .collect::<Option<Vec<_>>>()
+            .with_context(|| format!("failed to generate SAN in {self}"))?;
+        if let Some(&(m, _)) = sans.iter().find(|(_, s)| *s == target) {
+            return Ok(m);
+        }
+        let relaxed = strip_disambiguation(&target);
+        // any disambiguation that was given must still agree with the move.
+        let disambiguation = if relaxed == target {
+            ""
+        } else {
+            target[1..target.len() - 2].trim_end_matches('x')
+        };
+        let mut candidates = sans
+            .iter()
+            .filter(|(_, s)| strip_disambiguation(s) == relaxed)
+            .map(|&(m, _)| m)
+            .filter(|m| {
+                let from = m.from().to_string();
+                disambiguation.chars().all(|c| from.contains(c))
+            });
+        match (candidates.next(), candidates.next()) {
+            (Some(m), None) => Ok(m),
+            (Some(_), Some(_)) => bail!("ambiguous move {san} in {self}"),
+            (None, _) => bail!("illegal or unrecognised move {san} in {self}"),
+        }
+    }
+
     pub fn san(&mut self, m: Move) -> Option<String> {
         let check_char = match self.gives(m) {
             CheckState::None => "",
@@ -1681,20 +2135,14 @@ impl Board {
         let gives_check = self.in_check();
         if gives_check {
             let mut ml = MoveList::new();
-            self.generate_moves(&mut ml);
-            for &m in ml.iter_moves() {
-                if !self.make_move_simple(m) {
-                    continue;
-                }
-                // we found a legal move, so m does not give checkmate.
-                self.unmake_move_base();
-                self.unmake_move_base();
-                return CheckState::Check;
-            }
-            // we didn't return, so there were no legal moves,
-            // so m gives checkmate.
+            self.generate_legal_moves(&mut ml);
             self.unmake_move_base();
-            return CheckState::Checkmate;
+            // if there are no legal replies, m gives checkmate.
+            return if ml.is_empty() {
+                CheckState::Checkmate
+            } else {
+                CheckState::Check
+            };
         }
         self.unmake_move_base();
         CheckState::None
@@ -1702,6 +2150,12 @@ impl Board {
 
     /// Has the current position occurred before in the current game?
     pub fn is_repetition(&self) -> bool {
+        self.has_repeated(true)
+    }
+
+    /// Has the current position occurred twice before, or once before if
+    /// `twofold_in_tree` is set and the earlier occurrence is inside the search tree?
+    fn has_repeated(&self, twofold_in_tree: bool) -> bool {
         let mut counter = 0;
         // distance to the last irreversible move
         let moves_since_zeroing = self.fifty_move_counter() as usize;
@@ -1717,7 +2171,7 @@ impl Board {
         {
             if u.key == self.key {
                 // in-tree, can twofold:
-                if dist_back < self.height {
+                if twofold_in_tree && dist_back < self.height {
                     return true;
                 }
                 // partially materialised, proper threefold:
@@ -1732,7 +2186,19 @@ impl Board {
 
     /// Should we consider the current position a draw?
     pub fn is_draw(&self) -> bool {
-        (self.fifty_move_counter >= 100 || self.is_repetition()) && self.height != 0
+        (self.fifty_move_counter >= 100 || self.is_repetition() || self.is_insufficient_material())
+            && self.height != 0
+    }
+
+    /// Could a draw be claimed in the current position under the rules of the game,
+    /// by threefold repetition or the fifty-move rule? Unlike [`Self::is_draw`], this
+    /// ignores the search tree.
+    pub fn is_claimable_draw(&self) -> bool {
+        if self.fifty_move_counter >= 100 {
+            // checkmate on the hundredth ply takes precedence.
+            return !(self.in_check() && self.legal_moves().is_empty());
+        }
+        self.has_repeated(false)
     }
 
     pub fn pv_san(&mut self, pv: &PVariation) -> Result<String, fmt::Error> {
@@ -1749,24 +2215,16 @@ impl Board {
         Ok(out)
     }
 
-    pub fn legal_moves(&mut self) -> Vec<Move> {
+    pub fn legal_moves(&self) -> Vec<Move> {
         let mut move_list = MoveList::new();
-        self.generate_moves(&mut move_list);
-        let mut legal_moves = Vec::new();
-        for &m in move_list.iter_moves() {
-            if self.make_move_simple(m) {
-                self.unmake_move_base();
-                legal_moves.push(m);
-            }
-        }
-        legal_moves
+        self.generate_legal_moves(&mut move_list);
+        move_list.iter_moves().copied().collect()
     }
 
     pub const fn fifty_move_counter(&self) -> u8 {
         self.fifty_move_counter
     }
 
-    #[cfg(any(feature = "datagen", test))]
     pub fn has_insufficient_material<C: Col>(&self) -> bool {
         if (self.pieces.pawns::<C>() | self.pieces.rooks::<C>() | self.pieces.queens::<C>())
             .non_empty()
@@ -1796,7 +2254,6 @@ impl Board {
         true
     }
 
-    #[cfg(feature = "datagen")]
     pub const fn full_move_number(&self) -> usize {
         self.ply / 2 + 1
     }
@@ -1843,34 +2300,67 @@ impl Board {
                     return true;
                 }
 
+                // before or at the root, the move has to be ours to play, and the
+                // position it goes back to must already have repeated, just as
+                // has_repeated only draws pre-root repetitions on the third occurrence.
                 let mut piece = self.piece_at(mv.from());
                 if piece.is_none() {
                     piece = self.piece_at(mv.to());
                 }
-
-                return piece.unwrap().colour() == self.side;
+                if piece.unwrap().colour() != self.side {
+                    continue;
+                }
+                if (i + 2..=end).step_by(2).any(|j| old_key(j) == curr_key) {
+                    return true;
+                }
             }
         }
 
         false
     }
 
+    /// If a single reversible move links this position to the one with key `other_key`,
+    /// returns it, oriented to start from whichever square its piece now stands on.
+    /// This is the same cuckoo-table lookup that `has_game_cycle` uses, so the move
+    /// is for the side to move iff it leads from this position to the other one.
+    pub fn reversible_move_between(&self, other_key: u64) -> Option<Move> {
+        let diff = self.key ^ other_key;
+
+        let mut slot = cuckoo::h1(diff);
+        if diff != cuckoo::KEYS[slot] {
+            slot = cuckoo::h2(diff);
+        }
+        if diff != cuckoo::KEYS[slot] {
+            return None;
+        }
+
+        let mv = cuckoo::MOVES[slot]?;
+        if !(self.pieces.occupied() & RAY_BETWEEN[mv.from()][mv.to()]).is_empty() {
+            return None;
+        }
+        match (self.piece_at(mv.from()), self.piece_at(mv.to())) {
+            (Some(_), None) => Some(mv),
+            (None, Some(_)) => Some(Move::new(mv.to(), mv.from())),
+            _ => None,
+        }
+    }
+
     #[cfg(feature = "datagen")]
-    pub fn make_random_move(&mut self, rng: &mut ThreadRng, t: &mut ThreadData) -> Option<Move> {
+    pub fn make_random_move(&mut self, rng: &mut impl Rng, t: &mut ThreadData) -> Option<Move> {
         let mut ml = MoveList::new();
-        self.generate_moves(&mut ml);
-        let self::movegen::MoveListEntry { mov, .. } = ml.choose(rng)?;
+        self.generate_legal_moves(&mut ml);
+        let crate::chess::chessmove::ScoredMove { mov, .. } = ml.choose(rng)?;
         self.make_move(*mov, t);
         Some(*mov)
     }
 
-    #[cfg(any(feature = "datagen", test))]
+    /// Whether neither side has the material to mate, even with the other's help.
     pub fn is_insufficient_material(&self) -> bool {
         self.has_insufficient_material::<White>() && self.has_insufficient_material::<Black>()
     }
 
     #[cfg(any(feature = "datagen", test))]
-    pub fn outcome(&mut self) -> GameOutcome {
+    pub fn outcome(&self) -> GameOutcome {
         if self.fifty_move_counter >= 100 {
             return GameOutcome::Draw(DrawType::FiftyMoves);
         }
@@ -1891,16 +2381,8 @@ impl Board {
             return GameOutcome::Draw(DrawType::InsufficientMaterial);
         }
         let mut move_list = MoveList::new();
-        self.generate_moves(&mut move_list);
-        let mut legal_moves = false;
-        for &m in move_list.iter_moves() {
-            if self.make_move_simple(m) {
-                self.unmake_move_base();
-                legal_moves = true;
-                break;
-            }
-        }
-        if legal_moves {
+        self.generate_legal_moves(&mut move_list);
+        if !move_list.is_empty() {
             GameOutcome::Ongoing
         } else if self.in_check() {
             match self.side {
@@ -1974,83 +2456,13 @@ impl Default for Board {
 
 impl Display for Board {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        let mut counter = 0;
-        for rank in Rank::all().rev() {
-            for file in File::all() {
-                let sq = Square::from_rank_file(rank, file);
-                let piece = self.piece_at(sq);
-                if let Some(piece) = piece {
-                    if counter != 0 {
-                        write!(f, "{counter}")?;
-                    }
-                    counter = 0;
-                    write!(f, "{piece}")?;
-                } else {
-                    counter += 1;
-                }
-            }
-            if counter != 0 {
-                write!(f, "{counter}")?;
-            }
-            counter = 0;
-            if rank != Rank::One {
-                write!(f, "/")?;
-            }
-        }
-
-        if self.side == Colour::White {
-            write!(f, " w")?;
-        } else {
-            write!(f, " b")?;
-        }
-        write!(f, " ")?;
-        if self.castle_perm == CastlingRights::NONE {
-            write!(f, "-")?;
-        } else {
-            for (_, ch) in [
-                self.castle_perm.wk,
-                self.castle_perm.wq,
-                self.castle_perm.bk,
-                self.castle_perm.bq,
-            ]
-            .into_iter()
-            .zip("KQkq".chars())
-            .filter(|(m, _)| m.is_some())
-            {
-                write!(f, "{ch}")?;
-            }
-        }
-        if let Some(ep_sq) = self.ep_sq {
-            write!(f, " {ep_sq}")?;
-        } else {
-            write!(f, " -")?;
-        }
-        write!(f, " {}", self.fifty_move_counter)?;
-        write!(f, " {}", self.ply / 2 + 1)?;
-
-        Ok(())
+        self.write_fen_into(f)
     }
 }
 
 impl std::fmt::UpperHex for Board {
     fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
-        for rank in Rank::all().rev() {
-            write!(f, "{} ", rank as u8 + 1)?;
-            for file in File::all() {
-                let sq = Square::from_rank_file(rank, file);
-                if let Some(piece) = self.piece_at(sq) {
-                    write!(f, "{piece} ")?;
-                } else {
-                    write!(f, ". ")?;
-                }
-            }
-            writeln!(f)?;
-        }
-
-        writeln!(f, "  a b c d e f g h")?;
-        writeln!(f, "FEN: {self}")?;
-
-        Ok(())
+        write!(f, "{}", self.render(render::BoardStyle::PLAIN))
     }
 }
 
@@ -2077,7 +2489,7 @@ mod tests {
         use super::{DrawType, GameOutcome};
         use crate::{chess::chessmove::Move, chess::types::Square};
 
-        let mut fiftymove_draw =
+        let fiftymove_draw =
             Board::from_fen("rnbqkb1r/pppppppp/5n2/8/3N4/8/PPPPPPPP/RNBQKB1R b KQkq - 100 2")
                 .unwrap();
         assert_eq!(
@@ -2101,15 +2513,15 @@ mod tests {
             draw_repetition.outcome(),
             GameOutcome::Draw(DrawType::Repetition)
         );
-        let mut stalemate = Board::from_fen("7k/8/6Q1/8/8/8/8/K7 b - - 0 1").unwrap();
+        let stalemate = Board::from_fen("7k/8/6Q1/8/8/8/8/K7 b - - 0 1").unwrap();
         assert_eq!(stalemate.outcome(), GameOutcome::Draw(DrawType::Stalemate));
-        let mut insufficient_material_bare_kings =
+        let insufficient_material_bare_kings =
             Board::from_fen("8/8/5k2/8/8/2K5/8/8 b - - 0 1").unwrap();
         assert_eq!(
             insufficient_material_bare_kings.outcome(),
             GameOutcome::Draw(DrawType::InsufficientMaterial)
         );
-        let mut insufficient_material_knights =
+        let insufficient_material_knights =
             Board::from_fen("8/8/5k2/8/2N5/2K2N2/8/8 b - - 0 1").unwrap();
         assert_eq!(
             insufficient_material_knights.outcome(),
@@ -2131,10 +2543,16 @@ mod tests {
             .map(|l| l.unwrap().split_once(';').unwrap().0.trim().to_owned())
             .collect::<Vec<_>>();
         let mut board = Board::new();
+        let mut buffer = String::new();
         for fen in fens {
             board.set_from_fen(&fen).expect("setfen failed.");
             let fen_2 = board.to_string();
             assert_eq!(fen, fen_2);
+            buffer.clear();
+            board.write_fen_into(&mut buffer).unwrap();
+            assert_eq!(fen, buffer);
+            let compact = board.compact_fen();
+            assert_eq!(Some(compact.as_str()), fen.rsplitn(3, ' ').nth(2));
         }
     }
 
@@ -2205,6 +2623,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn incremental_threats_match_regeneration() {
+        use super::Board;
+        use crate::chess::board::movegen::MoveList;
+        use std::{
+            fs::File,
+            io::{BufRead, BufReader},
+        };
+
+        // walk the tree like perft, checking the incrementally-updated threats after every
+        // move, as the debug assertion in make_move is compiled out of release builds.
+        fn walk(board: &mut Board, depth: usize) {
+            if depth == 0 {
+                return;
+            }
+            let mut ml = MoveList::new();
+            board.generate_legal_moves(&mut ml);
+            for &m in ml.iter_moves() {
+                assert!(board.make_move_simple(m));
+                assert_eq!(
+                    board.threats,
+                    board.generate_threats(board.turn().flip()),
+                    "threats diverged after {} in {board}",
+                    m.display(false)
+                );
+                walk(board, depth - 1);
+                board.unmake_move_base();
+            }
+        }
+
+        let fens = BufReader::new(File::open("epds/perftsuite.epd").unwrap())
+            .lines()
+            .map(|l| l.unwrap().split_once(';').unwrap().0.trim().to_owned())
+            .collect::<Vec<_>>();
+        let mut board = Board::new();
+        for fen in fens {
+            board.set_from_fen(&fen).unwrap();
+            walk(&mut board, 2);
+        }
+    }
+
+    #[test]
+    fn threat_queries() {
+        use super::Board;
+        use crate::chess::{piece::Colour, squareset::SquareSet, types::Square};
+
+        // the side to move gets the same answer as the cached threats give its opponent.
+        let fen = "3k4/8/8/5N2/8/1P6/8/K1Q1RB2";
+        let to_move = Board::from_fen(&format!("{fen} w - - 0 1")).unwrap();
+        let waiting = Board::from_fen(&format!("{fen} b - - 0 1")).unwrap();
+        assert_eq!(
+            to_move.threats_by(Colour::White),
+            waiting.threats_by(Colour::White)
+        );
+
+        let board = Board::from_fen("4k3/8/8/8/1b6/8/3P4/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.checkers(), SquareSet::EMPTY);
+        assert_eq!(board.pinned(Colour::White), Square::D2.as_set());
+        assert_eq!(board.pinned(Colour::Black), SquareSet::EMPTY);
+        assert_eq!(
+            board.attackers_to(Square::D2, board.pieces.occupied()),
+            Square::B4.as_set() | Square::E1.as_set()
+        );
+    }
+
     #[test]
     fn key_after_works_for_simple_moves() {
         use super::Board;
@@ -2298,4 +2781,186 @@ mod tests {
         let board2 = Board::default();
         assert_eq!(board, board2);
     }
+
+    #[test]
+    fn parse_san() {
+        use super::Board;
+        use crate::chess::chessmove::Move;
+        use crate::chess::types::Square;
+        let mut board = Board::default();
+        for mv in board.legal_moves() {
+            let san = board.san(mv).unwrap();
+            assert_eq!(board.parse_san(&san).unwrap(), mv);
+        }
+        // the knight on c3 is pinned, so "Nge2" is over-disambiguated.
+        let mut board =
+            Board::from_fen("r1bqk1nr/pppp1ppp/2n5/4p3/1b2P3/2NP4/PPP2PPP/R1BQKBNR w KQkq - 1 4")
+                .unwrap();
+        let ne2 = Move::new(Square::G1, Square::E2);
+        assert_eq!(board.parse_san("Ne2").unwrap(), ne2);
+        assert_eq!(board.parse_san("Nge2!?").unwrap(), ne2);
+        assert!(board.parse_san("Nce2").is_err());
+        assert!(board.parse_san("Ke3").is_err());
+    }
+
+    #[test]
+    fn claimable_draws() {
+        use super::Board;
+        let mut board = Board::default();
+        for san in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"] {
+            let mv = board.parse_san(san).unwrap();
+            board.make_move_simple(mv);
+            assert!(!board.is_claimable_draw());
+        }
+        // the third occurrence of the starting position.
+        let mv = board.parse_san("Ng8").unwrap();
+        board.make_move_simple(mv);
+        assert!(board.is_claimable_draw());
+
+        let board = Board::from_fen("8/8/8/4k3/8/8/8/4K2R w K - 100 80").unwrap();
+        assert!(board.is_claimable_draw());
+        // mate on the hundredth ply is still mate.
+        let mut board = Board::from_fen("7k/6pp/8/8/8/8/8/R5K1 w - - 99 80").unwrap();
+        let mv = board.parse_san("Ra8#").unwrap();
+        board.make_move_simple(mv);
+        assert!(!board.is_claimable_draw());
+    }
+
+    #[test]
+    fn pre_root_game_cycles_need_a_repetition() {
+        use super::Board;
+        let mut board = Board::default();
+        for san in ["Nf3", "Nf6", "Ng1"] {
+            let mv = board.parse_san(san).unwrap();
+            board.make_move_simple(mv);
+        }
+        // Ng8 would only give the second occurrence of the starting position.
+        assert!(!board.has_game_cycle(0));
+        // unless the game so far is all inside the search tree.
+        assert!(board.has_game_cycle(4));
+        for san in ["Ng8", "Nf3", "Nf6", "Ng1"] {
+            let mv = board.parse_san(san).unwrap();
+            board.make_move_simple(mv);
+        }
+        // now it would be the third.
+        assert!(board.has_game_cycle(0));
+    }
+
+    #[test]
+    fn flips_and_mirrors_regenerate_state() {
+        use super::Board;
+        use crate::symmetry::flip_fen;
+
+        let fens = [
+            Board::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 7 41",
+        ];
+        // a transformed board must match the one its FEN gives, keys and threats included.
+        let check = |board: &Board| {
+            let fresh = Board::from_fen(&board.to_string()).unwrap();
+            assert!(*board == fresh, "{board} differs from its own FEN");
+        };
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+
+            let vertical = board.mirror_vertical();
+            check(&vertical);
+            assert_eq!(vertical.to_string(), flip_fen(fen).unwrap());
+            assert!(vertical.mirror_vertical() == board);
+
+            let horizontal = board.mirror_horizontal();
+            check(&horizontal);
+            assert_eq!(horizontal.castling_rights().hashkey_index(), 0);
+            if board.castling_rights().hashkey_index() == 0 {
+                assert!(horizontal.mirror_horizontal() == board);
+            }
+
+            let flipped = board.flip_side().unwrap();
+            check(&flipped);
+            assert_eq!(flipped.turn(), board.turn().flip());
+            assert_eq!(flipped.full_move_number(), board.full_move_number());
+            if board.ep_sq.is_none() {
+                assert!(flipped.flip_side().unwrap() == board);
+            }
+        }
+        let in_check = Board::from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+        assert!(in_check.flip_side().is_err());
+    }
+
+    #[test]
+    fn edits_keep_the_board_consistent() {
+        use super::Board;
+        use crate::chess::{
+            piece::{Colour, Piece},
+            types::Square,
+        };
+
+        // play 1. e4 by hand.
+        let mut board = Board::default();
+        board.remove_piece(Square::E2);
+        board.put_piece(Square::E4, Piece::WP).unwrap();
+        board.set_side_to_move(Colour::Black);
+        let e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        assert!(board == Board::from_fen(e4).unwrap());
+        board.validate().unwrap();
+
+        // replacing a rook loses its castling right.
+        board.put_piece(Square::H1, Piece::WN).unwrap();
+        assert!(board.castling_rights().wk.is_none());
+        assert!(board.put_piece(Square::E8, Piece::BP).is_err());
+        assert!(board.put_piece(Square::D4, Piece::WK).is_err());
+
+        // white's king left in check with black to move.
+        board.put_piece(Square::E2, Piece::BQ).unwrap();
+        assert!(board.validate().is_err());
+        board.remove_piece(Square::E2);
+        board.validate().unwrap();
+        board.remove_piece(Square::E1);
+        assert!(board.validate().is_err());
+    }
+
+    #[test]
+    fn malformed_fens_are_refused() {
+        use super::Board;
+
+        // each of these once panicked, or parsed into a position that broke the search.
+        for fen in [
+            "rnbqkbnr/pp1p1ppp/4p3/2p35/3/4/6P1/nPP1PP1P/RNBQKBNR w KQkq - 0 3",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR/8 w KQkq - 0 1",
+            "rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1",
+            "2Bk1r2/p4r2/4p2R/1b1pPn1p/2nPpP1p/N7/PRP5/2K2N2 w - -b 2 40",
+        ] {
+            assert!(Board::from_fen(fen).is_err(), "{fen}");
+        }
+        for fen in [
+            "rnbqkbnr/pQpp1ppp/4p3/8/Q7/N7/PPPPPPPP/R1BQKBNR w KQkq - 0 2",
+            "r1bn2nr/p2k1p1p/2p3pb/4p3/pPP1PqPP/Qp3B2/3PNP2/RNBK3R b - b3 0 19",
+            "1nbkq2r/rpppbppp/7n/p1P1p3/P7/1Q3P1P/1P1PP1P1/RNB1KBNR b KQk - 0 6",
+        ] {
+            assert!(Board::from_fen(fen).unwrap().validate().is_err(), "{fen}");
+        }
+        let zeroth_move = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 0").unwrap();
+        assert_eq!(zeroth_move.ply(), 0);
+        let overlong_clock = Board::from_fen("8/8/4k3/8/8/4K3/4P3/8 w - - 120 150").unwrap();
+        assert_eq!(overlong_clock.fifty_move_counter(), 100);
+    }
+
+    #[test]
+    fn reversible_move_between() {
+        use super::Board;
+        use crate::chess::chessmove::Move;
+        use crate::chess::types::Square;
+        let mut board = Board::default();
+        let start = board.zobrist_key();
+        assert!(board.make_move_simple(Move::new(Square::G1, Square::F3)));
+        // black to move, and it was white's knight that moved.
+        assert_eq!(
+            board.reversible_move_between(start),
+            Some(Move::new(Square::F3, Square::G1))
+        );
+        assert!(board.make_move_simple(Move::new(Square::E7, Square::E5)));
+        assert_eq!(board.reversible_move_between(start), None);
+    }
 }
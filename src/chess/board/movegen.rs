@@ -1,3 +1,7 @@
+#[cfg(feature = "movegen-audit")]
+pub mod audit;
+mod pext;
+
 use arrayvec::ArrayVec;
 
 use std::{
@@ -10,7 +14,7 @@ use crate::{
     cfor,
     chess::{
         board::Board,
-        chessmove::{Move, MoveFlags},
+        chessmove::{Move, MoveFlags, ScoredMove},
         magic::{
             BISHOP_ATTACKS, BISHOP_MAGICS, BISHOP_MASKS, BISHOP_REL_BITS, ROOK_ATTACKS,
             ROOK_MAGICS, ROOK_MASKS, ROOK_REL_BITS,
@@ -24,22 +28,16 @@ use crate::{
 
 pub const MAX_POSITION_MOVES: usize = 218;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct MoveListEntry {
-    pub mov: Move,
-    pub score: i32,
-}
-
-impl MoveListEntry {
+impl ScoredMove {
     pub const TACTICAL_SENTINEL: i32 = 0x7FFF_FFFF;
     pub const QUIET_SENTINEL: i32 = 0x7FFF_FFFE;
 }
 
 #[derive(Clone)]
 pub struct MoveList {
-    // moves: [MoveListEntry; MAX_POSITION_MOVES],
+    // moves: [ScoredMove; MAX_POSITION_MOVES],
     // count: usize,
-    inner: ArrayVec<MoveListEntry, MAX_POSITION_MOVES>,
+    inner: ArrayVec<ScoredMove, MAX_POSITION_MOVES>,
 }
 
 impl MoveList {
@@ -52,12 +50,16 @@ impl MoveList {
     fn push<const TACTICAL: bool>(&mut self, m: Move) {
         // debug_assert!(self.count < MAX_POSITION_MOVES, "overflowed {self}");
         let score = if TACTICAL {
-            MoveListEntry::TACTICAL_SENTINEL
+            ScoredMove::TACTICAL_SENTINEL
         } else {
-            MoveListEntry::QUIET_SENTINEL
+            ScoredMove::QUIET_SENTINEL
         };
 
-        self.inner.push(MoveListEntry { mov: m, score });
+        self.inner.push(ScoredMove { mov: m, score });
+    }
+
+    pub fn push_entry(&mut self, entry: ScoredMove) {
+        self.inner.push(entry);
     }
 
     pub fn iter_moves(&self) -> impl Iterator<Item = &Move> {
@@ -70,15 +72,15 @@ impl MoveList {
 }
 
 impl Deref for MoveList {
-    type Target = [MoveListEntry];
+    type Target = [ScoredMove];
 
-    fn deref(&self) -> &[MoveListEntry] {
+    fn deref(&self) -> &[ScoredMove] {
         &self.inner
     }
 }
 
 impl DerefMut for MoveList {
-    fn deref_mut(&mut self) -> &mut [MoveListEntry] {
+    fn deref_mut(&mut self) -> &mut [ScoredMove] {
         &mut self.inner
     }
 }
@@ -169,8 +171,21 @@ const fn init_jumping_attacks<const IS_KNIGHT: bool>() -> [SquareSet; 64] {
     attacks
 }
 
-#[allow(clippy::cast_possible_truncation)]
+/// Whether sliding attacks are looked up with `pext` instead of magic bitboards.
+pub fn pext_enabled() -> bool {
+    pext::enabled()
+}
+
 pub fn bishop_attacks(sq: Square, blockers: SquareSet) -> SquareSet {
+    pext::bishop_attacks(sq, blockers).unwrap_or_else(|| magic_bishop_attacks(sq, blockers))
+}
+
+pub fn rook_attacks(sq: Square, blockers: SquareSet) -> SquareSet {
+    pext::rook_attacks(sq, blockers).unwrap_or_else(|| magic_rook_attacks(sq, blockers))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn magic_bishop_attacks(sq: Square, blockers: SquareSet) -> SquareSet {
     let relevant_blockers = blockers & BISHOP_MASKS[sq];
     let data = relevant_blockers.inner().wrapping_mul(BISHOP_MAGICS[sq]);
     let idx = (data >> (64 - BISHOP_REL_BITS[sq])) as usize;
@@ -187,7 +202,7 @@ pub fn bishop_attacks(sq: Square, blockers: SquareSet) -> SquareSet {
     }
 }
 #[allow(clippy::cast_possible_truncation)]
-pub fn rook_attacks(sq: Square, blockers: SquareSet) -> SquareSet {
+pub fn magic_rook_attacks(sq: Square, blockers: SquareSet) -> SquareSet {
     let relevant_blockers = blockers & ROOK_MASKS[sq];
     let data = relevant_blockers.inner().wrapping_mul(ROOK_MAGICS[sq]);
     let idx = (data >> (64 - ROOK_REL_BITS[sq])) as usize;
@@ -203,12 +218,12 @@ pub fn rook_attacks(sq: Square, blockers: SquareSet) -> SquareSet {
         ROOK_ATTACKS[sq][idx]
     }
 }
+static KNIGHT_ATTACKS: [SquareSet; 64] = init_jumping_attacks::<true>();
+static KING_ATTACKS: [SquareSet; 64] = init_jumping_attacks::<false>();
 pub fn knight_attacks(sq: Square) -> SquareSet {
-    static KNIGHT_ATTACKS: [SquareSet; 64] = init_jumping_attacks::<true>();
     KNIGHT_ATTACKS[sq]
 }
 pub fn king_attacks(sq: Square) -> SquareSet {
-    static KING_ATTACKS: [SquareSet; 64] = init_jumping_attacks::<false>();
     KING_ATTACKS[sq]
 }
 pub fn pawn_attacks<C: Col>(bb: SquareSet) -> SquareSet {
@@ -219,6 +234,25 @@ pub fn pawn_attacks<C: Col>(bb: SquareSet) -> SquareSet {
     }
 }
 
+/// The squares on the same rank, file, or diagonal as `sq`.
+pub fn queen_lines(sq: Square) -> SquareSet {
+    bishop_attacks(sq, SquareSet::EMPTY) | rook_attacks(sq, SquareSet::EMPTY)
+}
+
+/// The squares a piece of type `pt` on `sq` attacks on an empty board, for building tables
+/// at compile time. An empty board always has magic index zero.
+pub const fn empty_board_attacks(pt: PieceType, sq: Square) -> SquareSet {
+    let sq = sq.index();
+    match pt {
+        PieceType::Bishop => BISHOP_ATTACKS[sq][0],
+        PieceType::Rook => ROOK_ATTACKS[sq][0],
+        PieceType::Queen => BISHOP_ATTACKS[sq][0].union(ROOK_ATTACKS[sq][0]),
+        PieceType::Knight => KNIGHT_ATTACKS[sq],
+        PieceType::King => KING_ATTACKS[sq],
+        PieceType::Pawn => panic!("pawns don't have a single attack set"),
+    }
+}
+
 pub fn attacks_by_type(pt: PieceType, sq: Square, blockers: SquareSet) -> SquareSet {
     match pt {
         PieceType::Bishop => bishop_attacks(sq, blockers),
@@ -499,6 +533,13 @@ impl Board {
         debug_assert!(move_list.iter_moves().all(|m| m.is_valid()));
     }
 
+    /// Generate only the legal moves, using the pin and check information
+    /// from the threat map instead of making each move.
+    pub fn generate_legal_moves(&self, move_list: &mut MoveList) {
+        self.generate_moves(move_list);
+        move_list.inner.retain(|e| self.is_legal(e.mov));
+    }
+
     fn generate_moves_for<C: Col>(&self, move_list: &mut MoveList) {
         #[cfg(debug_assertions)]
         self.check_validity().unwrap();
@@ -893,6 +934,64 @@ impl Board {
             self.generate_castling_moves_for::<C>(move_list);
         }
     }
+
+    /// Generate the quiet moves that give direct check, for use in quiescence search.
+    /// Discovered checks, king moves, castling, and promotions are not generated,
+    /// and the side to move must not be in check.
+    pub fn generate_quiet_checks(&self, move_list: &mut MoveList) {
+        // we don't need to clear the move list here because we're only adding to it.
+        debug_assert!(!self.in_check());
+        if self.side == Colour::White {
+            self.generate_quiet_checks_for::<White>(move_list);
+        } else {
+            self.generate_quiet_checks_for::<Black>(move_list);
+        }
+        debug_assert!(move_list.iter_moves().all(|m| m.is_valid()));
+    }
+
+    fn generate_quiet_checks_for<C: Col>(&self, move_list: &mut MoveList) {
+        let freespace = self.pieces.empty();
+        let blockers = self.pieces.occupied();
+        let their_king_sq = self.pieces.king::<C::Opposite>().first();
+
+        // the squares from which each piece type would attack the enemy king.
+        let pawn_checks = pawn_attacks::<C::Opposite>(self.pieces.king::<C::Opposite>());
+        let knight_checks = knight_attacks(their_king_sq) & freespace;
+        let diagonal_checks = bishop_attacks(their_king_sq, blockers) & freespace;
+        let orthogonal_checks = rook_attacks(their_king_sq, blockers) & freespace;
+
+        // pawns
+        self.generate_pawn_quiet::<C>(move_list, pawn_checks);
+
+        // knights
+        for sq in self.pieces.knights::<C>() {
+            for to in knight_attacks(sq) & knight_checks {
+                move_list.push::<false>(Move::new(sq, to));
+            }
+        }
+
+        // bishops
+        for sq in self.pieces.bishops::<C>() {
+            for to in bishop_attacks(sq, blockers) & diagonal_checks {
+                move_list.push::<false>(Move::new(sq, to));
+            }
+        }
+
+        // rooks
+        for sq in self.pieces.rooks::<C>() {
+            for to in rook_attacks(sq, blockers) & orthogonal_checks {
+                move_list.push::<false>(Move::new(sq, to));
+            }
+        }
+
+        // queens can check along either kind of line, whichever way they move.
+        for sq in self.pieces.queens::<C>() {
+            let moves = bishop_attacks(sq, blockers) | rook_attacks(sq, blockers);
+            for to in moves & (diagonal_checks | orthogonal_checks) {
+                move_list.push::<false>(Move::new(sq, to));
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -926,7 +1025,7 @@ pub fn synced_perft(pos: &mut Board, depth: usize) -> u64 {
                 mvs.push(format!(
                     "{}{}",
                     pos.san(m.mov).unwrap(),
-                    if m.score == MoveListEntry::TACTICAL_SENTINEL {
+                    if m.score == ScoredMove::TACTICAL_SENTINEL {
                         "T"
                     } else {
                         "Q"
@@ -941,7 +1040,7 @@ pub fn synced_perft(pos: &mut Board, depth: usize) -> u64 {
                 mvs.push(format!(
                     "{}{}",
                     pos.san(m.mov).unwrap(),
-                    if m.score == MoveListEntry::TACTICAL_SENTINEL {
+                    if m.score == ScoredMove::TACTICAL_SENTINEL {
                         "T"
                     } else {
                         "Q"
@@ -986,6 +1085,68 @@ mod tests {
         }
     }
 
+    #[test]
+    fn legal_matches_make_filtered() {
+        fn check(pos: &mut Board, depth: usize) {
+            let mut ml = MoveList::new();
+            pos.generate_moves(&mut ml);
+            let mut legal = MoveList::new();
+            pos.generate_legal_moves(&mut legal);
+            for m in ml.iter_moves().copied() {
+                let expected = pos.make_move_simple(m);
+                if expected && depth > 1 {
+                    check(pos, depth - 1);
+                }
+                if expected {
+                    pos.unmake_move_base();
+                }
+                assert_eq!(
+                    legal.iter_moves().any(|&l| l == m),
+                    expected,
+                    "{} in {pos}",
+                    m.display(false)
+                );
+            }
+        }
+
+        let mut pos = Board::default();
+        for fen in bench::BENCH_POSITIONS {
+            pos.set_from_fen(fen).unwrap();
+            check(&mut pos, 2);
+        }
+    }
+
+    #[test]
+    fn quiet_checks_are_quiet_checks() {
+        let mut pos = Board::default();
+        let mut checks = 0;
+        for fen in bench::BENCH_POSITIONS {
+            pos.set_from_fen(fen).unwrap();
+            if pos.in_check() {
+                continue;
+            }
+            let mut ml = MoveList::new();
+            pos.generate_quiet_checks(&mut ml);
+            for m in ml.iter_moves().copied() {
+                assert!(
+                    !pos.is_tactical(m),
+                    "{} is not quiet in {fen}",
+                    m.display(false)
+                );
+                if pos.make_move_simple(m) {
+                    assert!(
+                        pos.in_check(),
+                        "{} does not give check in {fen}",
+                        m.display(false)
+                    );
+                    checks += 1;
+                    pos.unmake_move_base();
+                }
+            }
+        }
+        assert!(checks > 0);
+    }
+
     #[test]
     fn python_chess_validation() {
         // testing that the attack squaresets match the ones in the python-chess library,
@@ -0,0 +1,68 @@
+//! Cross-checking the legal move generator against the older way of finding legal
+//! moves: generating pseudo-legal moves and keeping those that make without leaving the
+//! king in check. Built with the `movegen-audit` feature, the search checks the first
+//! [`AUDIT_NODES`] nodes that it visits, and reports the first position where the two
+//! disagree.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::chess::{board::Board, chessmove::Move};
+
+use super::MoveList;
+
+/// How many nodes are checked before the audit stops, so that a long search doesn't run
+/// at a crawl from start to finish.
+pub const AUDIT_NODES: u64 = 1 << 20;
+
+static AUDITED: AtomicU64 = AtomicU64::new(0);
+static DIVERGED: AtomicBool = AtomicBool::new(false);
+
+impl Board {
+    /// Check the legal move generator at this node, if the audit has nodes left. Legality
+    /// is checked by making and unmaking each move on this board, and the moves are kept
+    /// in move lists, so that the audit doesn't allocate in the search.
+    pub fn audit_movegen(&mut self) {
+        if AUDITED.fetch_add(1, Ordering::Relaxed) >= AUDIT_NODES
+            || DIVERGED.load(Ordering::Relaxed)
+        {
+            return;
+        }
+        let mut legal = MoveList::new();
+        self.generate_legal_moves(&mut legal);
+        let mut pseudo_legal = MoveList::new();
+        self.generate_moves(&mut pseudo_legal);
+        let mut made = MoveList::new();
+        for &m in pseudo_legal.iter_moves() {
+            if self.make_move_simple(m) {
+                self.unmake_move_base();
+                made.push::<false>(m);
+            }
+        }
+        let contains = |list: &MoveList, m: &Move| list.iter_moves().any(|n| n == m);
+        let agree = legal.len() == made.len() && legal.iter_moves().all(|m| contains(&made, m));
+        if agree || DIVERGED.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let show = |moves: &MoveList, others: &MoveList| {
+            moves
+                .iter_moves()
+                .filter(|m| !contains(others, m))
+                .map(|m| m.display(false).to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        crate::uci::output::send(format!(
+            "info string movegen audit: the legal generator diverges in {self}, missing [{}] and adding [{}]",
+            show(&made, &legal),
+            show(&legal, &made),
+        ));
+    }
+}
+
+/// The number of nodes audited so far, and whether the generators have diverged.
+pub fn summary() -> (u64, bool) {
+    (
+        AUDITED.load(Ordering::Relaxed).min(AUDIT_NODES),
+        DIVERGED.load(Ordering::Relaxed),
+    )
+}
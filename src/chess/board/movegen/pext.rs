@@ -0,0 +1,201 @@
+//! Sliding piece attacks using the BMI2 `pext` instruction, which gathers the relevant
+//! blockers directly into a dense table index. This is chosen at runtime over magic
+//! bitboards when the CPU supports BMI2, except on AMD Zen 1 and Zen 2, where `pext`
+//! is implemented in microcode and is much slower than a magic multiply.
+//!
+//! Only builds that enable BMI2 at compile time (`x86-64-v3` and up, or `native`) are
+//! eligible: otherwise `pext` sits behind a call that can't be inlined, which costs
+//! more than the magic multiply it replaces. `attackbench` compares the two.
+
+use std::sync::LazyLock;
+
+use crate::{
+    cfor,
+    chess::{
+        magic::{bishop_attacks_on_the_fly, rook_attacks_on_the_fly, BISHOP_MASKS, ROOK_MASKS},
+        squareset::SquareSet,
+        types::Square,
+    },
+};
+
+/// The total number of blocker configurations over all squares, for each slider type.
+const BISHOP_ENTRIES: usize = 5248;
+const ROOK_ENTRIES: usize = 102_400;
+
+/// Attack tables for both slider types, with each square's attacks
+/// stored contiguously and indexed by `pext(blockers, mask)`.
+struct Tables {
+    attacks: [SquareSet; BISHOP_ENTRIES + ROOK_ENTRIES],
+    bishop_offsets: [usize; 64],
+    rook_offsets: [usize; 64],
+}
+
+static TABLES: Tables = Tables::new();
+
+static PEXT_IS_FAST: LazyLock<bool> = LazyLock::new(pext_is_fast);
+
+/// Whether the `pext` attack path is in use on this machine.
+pub fn enabled() -> bool {
+    *PEXT_IS_FAST
+}
+
+#[cfg(target_arch = "x86_64")]
+fn pext_is_fast() -> bool {
+    use std::arch::x86_64::__cpuid;
+    if !cfg!(target_feature = "bmi2") || !std::arch::is_x86_feature_detected!("bmi2") {
+        return false;
+    }
+    let vendor = __cpuid(0);
+    let amd = [vendor.ebx, vendor.edx, vendor.ecx] == [0x6874_7541, 0x6974_6E65, 0x444D_4163];
+    if !amd {
+        return true;
+    }
+    let signature = __cpuid(1).eax;
+    let base_family = (signature >> 8) & 0xF;
+    let family = if base_family == 0xF {
+        base_family + ((signature >> 20) & 0xFF)
+    } else {
+        base_family
+    };
+    // Zen 1, Zen+, and Zen 2 are all family 17h.
+    family != 0x17
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+const fn pext_is_fast() -> bool {
+    false
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+#[inline]
+fn pext_bmi2(value: u64, mask: u64) -> u64 {
+    std::arch::x86_64::_pext_u64(value, mask)
+}
+
+/// A portable `pext`, for machines without BMI2.
+#[allow(dead_code)]
+const fn pext_fallback(value: u64, mut mask: u64) -> u64 {
+    let mut out = 0;
+    let mut bit = 1;
+    while mask != 0 {
+        if value & mask & mask.wrapping_neg() != 0 {
+            out |= bit;
+        }
+        mask &= mask - 1;
+        bit <<= 1;
+    }
+    out
+}
+
+impl Tables {
+    /// Fill the tables by walking every subset of each square's mask. Runs entirely at compile time.
+    #[allow(clippy::large_stack_arrays, clippy::large_stack_frames)]
+    const fn new() -> Self {
+        let mut attacks = [SquareSet::EMPTY; BISHOP_ENTRIES + ROOK_ENTRIES];
+        let mut bishop_offsets = [0; 64];
+        let mut rook_offsets = [0; 64];
+        let mut len = 0;
+        cfor!(let mut kind = 0; kind < 2; kind += 1; {
+            let bishop = kind == 0;
+            cfor!(let mut square = 0; square < 64; square += 1; {
+                let sq = Square::new_clamped(square);
+                let square = square as usize;
+                let mask = if bishop {
+                    bishop_offsets[square] = len;
+                    BISHOP_MASKS[square].inner()
+                } else {
+                    rook_offsets[square] = len;
+                    ROOK_MASKS[square].inner()
+                };
+                // walk every subset of the mask, with the carry-rippler trick.
+                // the subsets come in increasing order of `pext(blockers, mask)`,
+                // so each one simply takes the next slot in the table.
+                let mut blockers = 0u64;
+                loop {
+                    attacks[len] = if bishop {
+                        bishop_attacks_on_the_fly(sq, SquareSet::from_inner(blockers))
+                    } else {
+                        rook_attacks_on_the_fly(sq, SquareSet::from_inner(blockers))
+                    };
+                    len += 1;
+                    blockers = blockers.wrapping_sub(mask) & mask;
+                    if blockers == 0 {
+                        break;
+                    }
+                }
+            });
+        });
+        assert!(len == attacks.len());
+        Self {
+            attacks,
+            bishop_offsets,
+            rook_offsets,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn lookup(&self, offset: usize, mask: SquareSet, blockers: SquareSet) -> SquareSet {
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: the tables are only used on CPUs that support BMI2.
+        let bits = unsafe { pext_bmi2(blockers.inner(), mask.inner()) };
+        #[cfg(not(target_arch = "x86_64"))]
+        let bits = pext_fallback(blockers.inner(), mask.inner());
+        let index = offset + bits as usize;
+        // SAFETY: `pext` yields at most as many bits as are set in the mask, and
+        // each square's slice of the table is 2^(bits in mask) entries long.
+        unsafe { *self.attacks.get_unchecked(index) }
+    }
+}
+
+/// Bishop attacks, or `None` if the `pext` path is not in use.
+#[inline]
+pub fn bishop_attacks(sq: Square, blockers: SquareSet) -> Option<SquareSet> {
+    if !cfg!(target_feature = "bmi2") {
+        return None;
+    }
+    enabled().then(|| TABLES.lookup(TABLES.bishop_offsets[sq], BISHOP_MASKS[sq], blockers))
+}
+
+/// Rook attacks, or `None` if the `pext` path is not in use.
+#[inline]
+pub fn rook_attacks(sq: Square, blockers: SquareSet) -> Option<SquareSet> {
+    if !cfg!(target_feature = "bmi2") {
+        return None;
+    }
+    enabled().then(|| TABLES.lookup(TABLES.rook_offsets[sq], ROOK_MASKS[sq], blockers))
+}
+
+mod tests {
+    #[test]
+    fn pext_matches_magics() {
+        use super::{pext_fallback, TABLES};
+        use crate::chess::{
+            board::movegen::{magic_bishop_attacks, magic_rook_attacks},
+            squareset::SquareSet,
+            types::Square,
+        };
+
+        assert_eq!(pext_fallback(0b1011_0110, 0b0110_0110), 0b0111);
+        // look up with the portable pext, so that this runs on any machine.
+        let mut rng = crate::rng::XorShiftState::new();
+        for _ in 0..10_000 {
+            let blockers = SquareSet::from_inner(rng.next() & rng.next());
+            for sq in Square::all() {
+                let index = |offset: usize, mask: SquareSet| {
+                    #[allow(clippy::cast_possible_truncation)]
+                    let i = offset + pext_fallback(blockers.inner(), mask.inner()) as usize;
+                    TABLES.attacks[i]
+                };
+                assert_eq!(
+                    index(TABLES.bishop_offsets[sq], super::BISHOP_MASKS[sq]),
+                    magic_bishop_attacks(sq, blockers)
+                );
+                assert_eq!(
+                    index(TABLES.rook_offsets[sq], super::ROOK_MASKS[sq]),
+                    magic_rook_attacks(sq, blockers)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Drawing the board in a terminal, as letters or as Unicode chess glyphs, optionally
+//! with the last move and any checking pieces highlighted in colour.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+use crate::chess::{
+    board::Board,
+    squareset::SquareSet,
+    types::{File, Rank, Square},
+};
+
+/// The glyphs for each piece, in the order of `Piece`.
+const UNICODE_GLYPHS: [char; 12] = ['♙', '♘', '♗', '♖', '♕', '♔', '♟', '♞', '♝', '♜', '♛', '♚'];
+
+/// ANSI background colours for highlighted squares, and the code that ends them.
+const LAST_MOVE_BACKGROUND: &str = "\x1b[43m";
+const CHECKER_BACKGROUND: &str = "\x1b[41m";
+const RESET: &str = "\x1b[0m";
+
+/// How to draw the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoardStyle {
+    /// Draw the pieces as Unicode chess glyphs rather than letters.
+    pub unicode: bool,
+    /// Highlight the squares of the last move and any checking pieces.
+    pub colour: bool,
+    /// Follow the board with the FEN and any checking pieces.
+    pub verbose: bool,
+}
+
+impl BoardStyle {
+    /// Letters and no colour, which any terminal or log file can show.
+    pub const PLAIN: Self = Self {
+        unicode: false,
+        colour: false,
+        verbose: true,
+    };
+    /// Glyphs and colour, for a person at a terminal.
+    pub const FANCY: Self = Self {
+        unicode: true,
+        colour: true,
+        verbose: true,
+    };
+
+    /// Change the style by a list of words: `unicode` or `ascii`, `colour` or `plain`,
+    /// and `compact` or `verbose`.
+    pub fn with_words<'a>(
+        mut self,
+        words: impl IntoIterator<Item = &'a str>,
+    ) -> anyhow::Result<Self> {
+        for word in words {
+            match word {
+                "unicode" => self.unicode = true,
+                "ascii" => self.unicode = false,
+                "colour" | "color" => self.colour = true,
+                "plain" => self.colour = false,
+                "compact" => self.verbose = false,
+                "verbose" => self.verbose = true,
+                _ => bail!(
+                    "unknown board style \"{word}\", expected unicode, ascii, colour, plain, compact or verbose"
+                ),
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl FromStr for BoardStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::PLAIN.with_words(s.split_ascii_whitespace())
+    }
+}
+
+/// A board, ready to be drawn in some style.
+pub struct Rendered<'a> {
+    board: &'a Board,
+    style: BoardStyle,
+}
+
+impl Board {
+    /// Draw the board in `style`.
+    pub const fn render(&self, style: BoardStyle) -> Rendered<'_> {
+        Rendered { board: self, style }
+    }
+
+    /// The squares whose contents the last move changed: usually its two squares, but
+    /// also the rook's squares in castling and the captured pawn's square in en passant.
+    fn last_move_squares(&self) -> SquareSet {
+        let Some(undo) = self.history.last() else {
+            return SquareSet::EMPTY;
+        };
+        Square::all()
+            .filter(|&sq| undo.piece_array[sq] != self.piece_at(sq))
+            .fold(SquareSet::EMPTY, SquareSet::add_square)
+    }
+}
+
+impl Display for Rendered<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let board = self.board;
+        let (last_move, checkers) = if self.style.colour {
+            (board.last_move_squares(), board.checkers())
+        } else {
+            (SquareSet::EMPTY, SquareSet::EMPTY)
+        };
+        for rank in Rank::all().rev() {
+            write!(f, "{} ", rank as u8 + 1)?;
+            for file in File::all() {
+                let sq = Square::from_rank_file(rank, file);
+                let glyph = match board.piece_at(sq) {
+                    Some(piece) if self.style.unicode => UNICODE_GLYPHS[piece as usize],
+                    Some(piece) => piece.char(),
+                    None => '.',
+                };
+                if checkers.contains_square(sq) {
+                    write!(f, "{CHECKER_BACKGROUND}{glyph}{RESET} ")?;
+                } else if last_move.contains_square(sq) {
+                    write!(f, "{LAST_MOVE_BACKGROUND}{glyph}{RESET} ")?;
+                } else {
+                    write!(f, "{glyph} ")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "  a b c d e f g h")?;
+
+        if self.style.verbose {
+            writeln!(f, "FEN: {board}")?;
+            if board.in_check() {
+                let checkers = board
+                    .checkers()
+                    .iter()
+                    .map(|sq| sq.to_string())
+                    .collect::<Vec<_>>();
+                writeln!(f, "Checkers: {}", checkers.join(" "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+mod tests {
+    #[test]
+    fn board_styles() {
+        use super::BoardStyle;
+        use crate::chess::board::Board;
+
+        let mut board = Board::default();
+        for san in ["e4", "f5", "Qh5+"] {
+            let m = board.parse_san(san).unwrap();
+            board.make_move_simple(m);
+        }
+        assert_eq!(board.last_move_squares().iter().count(), 2);
+
+        let compact = "unicode compact".parse::<BoardStyle>().unwrap();
+        let text = board.render(compact).to_string();
+        assert_eq!(text.lines().count(), 9);
+        assert!(text.starts_with("8 ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜"));
+
+        let plain = board.render(BoardStyle::PLAIN).to_string();
+        assert_eq!(plain, format!("{board:X}"));
+        assert!(plain.ends_with("Checkers: h5\n"));
+
+        // the checking queen is marked in red, and d1, where it came from, in yellow.
+        let fancy = board.render(BoardStyle::FANCY).to_string();
+        assert!(fancy.contains("\x1b[41m♕\x1b[0m"));
+        assert!(fancy.contains("1 ♖ ♘ ♗ \x1b[43m.\x1b[0m ♔"));
+
+        assert!("unicode sparkly".parse::<BoardStyle>().is_err());
+    }
+}
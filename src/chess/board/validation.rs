@@ -1,10 +1,16 @@
 #![allow(unused_imports)]
 
+use std::sync::atomic::Ordering;
+
+use anyhow::bail;
+
 use crate::{
     chess::{
         board::Board,
-        piece::{Colour, Piece},
-        types::{Rank, Square},
+        piece::{Colour, Piece, PieceType},
+        squareset::SquareSet,
+        types::{Direction, File, Rank, Square},
+        CHESS960,
     },
     nnue::network::NNUEState,
     searchinfo::SearchInfo,
@@ -14,6 +20,88 @@ use crate::{
 use crate::errors::PositionValidityError;
 
 impl Board {
+    /// Check that the position is one that could be played from: each side has one king,
+    /// no more than sixteen pieces, and no more than eight pawns, no pawns stand on the
+    /// back ranks, the side not to move isn't in check, and every
+    /// castling right and the en passant square have the pieces they rely on. Positions
+    /// built up by hand should pass this before they're searched.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for colour in Colour::all() {
+            let kings = self
+                .pieces
+                .piece_bb(Piece::new(colour, PieceType::King))
+                .count();
+            if kings != 1 {
+                bail!("{colour:?} has {kings} kings, rather than one");
+            }
+            // the network's buffers have room for the pieces of a real game, and no more.
+            let pieces = self.pieces.occupied_co(colour).count();
+            if pieces > 16 {
+                bail!("{colour:?} has {pieces} pieces, more than sixteen");
+            }
+            let pawns = self
+                .pieces
+                .piece_bb(Piece::new(colour, PieceType::Pawn))
+                .count();
+            if pawns > 8 {
+                bail!("{colour:?} has {pawns} pawns, more than eight");
+            }
+        }
+        let back_ranks = SquareSet::RANK_1 | SquareSet::RANK_8;
+        if let Some(sq) = (self.pieces.all_pawns() & back_ranks).iter().next() {
+            bail!("there is a pawn on the back rank, at {sq}");
+        }
+        if self.sq_attacked(self.king_sq(self.side.flip()), self.side) {
+            bail!(
+                "{:?} is in check, but it's {:?} to move",
+                self.side.flip(),
+                self.side
+            );
+        }
+
+        let rights = self.castle_perm;
+        let chess960 = CHESS960.load(Ordering::SeqCst);
+        for (colour, rook, rank, kingside) in [
+            (Colour::White, rights.wk, Rank::One, true),
+            (Colour::White, rights.wq, Rank::One, false),
+            (Colour::Black, rights.bk, Rank::Eight, true),
+            (Colour::Black, rights.bq, Rank::Eight, false),
+        ] {
+            let Some(rook) = rook else {
+                continue;
+            };
+            if self.piece_at(rook) != Some(Piece::new(colour, PieceType::Rook)) {
+                bail!("{colour:?} has a castling right with no rook on {rook}");
+            }
+            let king = self.king_sq(colour);
+            if king.rank() != rank {
+                bail!("{colour:?} has a castling right with its king off the back rank");
+            }
+            if (!chess960 && king.file() != File::E) || (rook.file() > king.file()) != kingside {
+                bail!("{colour:?} has a castling right with its king on {king}, which can't castle with the rook on {rook}");
+            }
+        }
+
+        if let Some(ep) = self.ep_sq {
+            let rank = match self.side {
+                Colour::White => Rank::Six,
+                Colour::Black => Rank::Three,
+            };
+            // the pawn that just moved is one step past the square, from its own side.
+            let pawn = ep.try_offset(Direction::forward(self.side.flip()));
+            let start = ep.try_offset(Direction::forward(self.side));
+            let their_pawn = Piece::new(self.side.flip(), PieceType::Pawn);
+            if ep.rank() != rank || pawn.and_then(|sq| self.piece_at(sq)) != Some(their_pawn) {
+                bail!("the en passant square {ep} has no pawn that just moved past it");
+            }
+            if self.piece_at(ep).is_some() || start.and_then(|sq| self.piece_at(sq)).is_some() {
+                bail!("the en passant square {ep} or the square the pawn left is occupied");
+            }
+        }
+
+        Ok(())
+    }
+
     #[cfg(debug_assertions)]
     #[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
     pub fn check_validity(&self) -> Result<(), PositionValidityError> {
@@ -8,19 +8,40 @@ use crate::chess::{
     types::{File, Square},
 };
 
+/// A move, packed into sixteen bits. The encoding is fixed, because viriformat files,
+/// the transposition table, and the analysis cache all store moves by their bits:
+///
+/// - bits 0-5 hold the origin square, and bits 6-11 the destination square, counting
+///   from a1 = 0 along the ranks to h8 = 63. Castling moves go from the king to the rook
+///   that it castles with.
+/// - bits 12-13 hold the promotion piece, from knight (`00`) to queen (`11`), and are
+///   zero for any other move.
+/// - bits 14-15 flag the move as en passant (`01`), castling (`10`), or promotion
+///   (`11`), and are zero for any other move.
+///
+/// The all-zeroes pattern would be a move from a1 to a1, so it is free to stand for no
+/// move at all, which keeps `Option<Move>` to sixteen bits too.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Move {
     data: NonZeroU16,
 }
 
-const _: () = assert!(std::mem::size_of::<Move>() == std::mem::size_of::<Option<Move>>());
+const _: () = assert!(std::mem::size_of::<Move>() == 2);
+const _: () = assert!(std::mem::size_of::<Option<Move>>() == 2);
+
+/// A move with the score that move ordering gave it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScoredMove {
+    pub mov: Move,
+    pub score: i32,
+}
 
 const PROMO_FLAG_BITS: u16 = 0b1100_0000_0000_0000;
 const EP_FLAG_BITS: u16 = 0b0100_0000_0000_0000;
 const CASTLE_FLAG_BITS: u16 = 0b1000_0000_0000_0000;
 
 #[repr(u16)]
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum MoveFlags {
     Promotion = PROMO_FLAG_BITS,
     EnPassant = EP_FLAG_BITS,
@@ -71,11 +92,11 @@ impl Move {
         Self { data }
     }
 
-    pub fn new(from: Square, to: Square) -> Self {
-        debug_assert!(u16::from(from) & Self::SQ_MASK == u16::from(from));
-        debug_assert!(u16::from(to) & Self::SQ_MASK == u16::from(to));
-        debug_assert_ne!(from, to);
-        let data = u16::from(from) | (u16::from(to) << Self::TO_SHIFT);
+    pub const fn new(from: Square, to: Square) -> Self {
+        debug_assert!(from as u16 & Self::SQ_MASK == from as u16);
+        debug_assert!(to as u16 & Self::SQ_MASK == to as u16);
+        debug_assert!(from as u8 != to as u8);
+        let data = from as u16 | ((to as u16) << Self::TO_SHIFT);
         // SAFETY: this function is only called from within the movegen routines,
         // where we never create A1 -> A1 moves. This function is technically unsound
         // if called as Move::new(Square::A1, Square::A1).
@@ -122,6 +143,17 @@ impl Move {
         (self.data.get() & CASTLE_FLAG_BITS) != 0 && self.data.get() & EP_FLAG_BITS == 0
     }
 
+    /// The kind of special move that this is, if any.
+    #[allow(dead_code)]
+    pub const fn flags(self) -> Option<MoveFlags> {
+        match self.data.get() & PROMO_FLAG_BITS {
+            PROMO_FLAG_BITS => Some(MoveFlags::Promotion),
+            EP_FLAG_BITS => Some(MoveFlags::EnPassant),
+            CASTLE_FLAG_BITS => Some(MoveFlags::Castle),
+            _ => None,
+        }
+    }
+
     /// Handles castling moves, which are a bit weird.
     pub fn history_to_square(self) -> Square {
         if self.is_castle() {
@@ -138,23 +170,29 @@ impl Move {
         }
     }
 
+    /// Whether the bits are ones that the constructors could have made: the promotion
+    /// bits are clear unless the move is a promotion, and the move goes somewhere.
     pub fn is_valid(self) -> bool {
-        let promotion = self.promotion_type();
-        if promotion.is_some() && !self.is_promo() {
+        let promotion_bits = self.data.get() & (Self::PROMO_MASK << Self::PROMO_SHIFT);
+        if !self.is_promo() && promotion_bits != 0 {
             // promotion type is set but not a promotion move
             return false;
         }
-        promotion.is_none() || promotion.unwrap().legal_promo()
+        self.from() != self.to() && self.promotion_type().is_none_or(PieceType::legal_promo)
     }
 
-    #[allow(dead_code)]
+    /// The move's sixteen bits, in the encoding described on [`Move`].
     pub const fn inner(self) -> u16 {
         self.data.get()
     }
 
-    #[allow(dead_code)]
-    pub fn from_raw(data: u16) -> Option<Self> {
-        NonZeroU16::new(data).map(|nz| Self { data: nz })
+    /// The move with these bits, or `None` for zero. Bits from outside the engine should
+    /// be checked with [`Self::is_valid`].
+    pub const fn from_raw(data: u16) -> Option<Self> {
+        match NonZeroU16::new(data) {
+            Some(data) => Some(Self { data }),
+            None => None,
+        }
     }
 
     pub const fn display(self, chess960: bool) -> MoveDisplay {
@@ -265,4 +303,37 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn every_bit_pattern_round_trips() {
+        use super::*;
+        // every valid pattern is exactly the move that its accessors describe.
+        let mut valid = 0;
+        for bits in 1..=u16::MAX {
+            let m = Move::from_raw(bits).unwrap();
+            assert_eq!(m.inner(), bits);
+            if !m.is_valid() {
+                continue;
+            }
+            valid += 1;
+            let rebuilt = match (m.flags(), m.promotion_type()) {
+                (Some(MoveFlags::Promotion), Some(promo)) => {
+                    Move::new_with_promo(m.from(), m.to(), promo)
+                }
+                (Some(flags), None) => Move::new_with_flags(m.from(), m.to(), flags),
+                (None, None) => Move::new(m.from(), m.to()),
+                _ => panic!("{m:?} has a promotion piece without the flag"),
+            };
+            assert_eq!(rebuilt, m);
+        }
+        // 64 * 63 pairs of squares, each as a plain move, en passant, castling, or one of
+        // four promotions.
+        assert_eq!(valid, 64 * 63 * 7);
+        assert!(Move::from_raw(0).is_none());
+        assert!(
+            !Move::from_raw(Move::new(Square::A1, Square::B1).inner() | 1 << 12)
+                .unwrap()
+                .is_valid()
+        );
+    }
 }
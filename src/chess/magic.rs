@@ -117,7 +117,7 @@ const fn mask_rook_attacks(sq: Square) -> SquareSet {
     SquareSet::from_inner(attacks)
 }
 
-const fn bishop_attacks_on_the_fly(square: Square, block: SquareSet) -> SquareSet {
+pub const fn bishop_attacks_on_the_fly(square: Square, block: SquareSet) -> SquareSet {
     let mut attacks = 0;
 
     // so sue me
@@ -162,7 +162,7 @@ const fn bishop_attacks_on_the_fly(square: Square, block: SquareSet) -> SquareSe
     SquareSet::from_inner(attacks)
 }
 
-const fn rook_attacks_on_the_fly(square: Square, block: SquareSet) -> SquareSet {
+pub const fn rook_attacks_on_the_fly(square: Square, block: SquareSet) -> SquareSet {
     let mut attacks = 0;
 
     // so sue me
@@ -341,11 +341,41 @@ macro_rules! init_masks_with {
 pub static BISHOP_MASKS: [SquareSet; 64] = init_masks_with!(mask_bishop_attacks);
 pub static ROOK_MASKS: [SquareSet; 64] = init_masks_with!(mask_rook_attacks);
 
-pub static BISHOP_ATTACKS: [[SquareSet; 512]; 64] =
-    unsafe { std::mem::transmute(*include_bytes!("../../embeds/diagonal_attacks.bin")) };
+/// Fill in the attack table for every square, placing each blocker configuration
+/// at the index its magic number sends it to. Runs entirely at compile time.
+const fn init_attacks<const N: usize, const BISHOP: bool>() -> [[SquareSet; N]; 64] {
+    let mut attacks = [[SquareSet::EMPTY; N]; 64];
+    cfor!(let mut square = 0; square < 64; square += 1; {
+        let sq = Square::new_clamped(square);
+        let square = square as usize;
+        let (mask, magic, relevant_bits) = if BISHOP {
+            (mask_bishop_attacks(sq), BISHOP_MAGICS[square], BISHOP_REL_BITS[square])
+        } else {
+            (mask_rook_attacks(sq), ROOK_MAGICS[square], ROOK_REL_BITS[square])
+        };
+        // walk every subset of the mask, with the carry-rippler trick.
+        let mut occupancy = 0u64;
+        loop {
+            #[allow(clippy::cast_possible_truncation)]
+            let magic_index = (occupancy.wrapping_mul(magic) >> (64 - relevant_bits)) as usize;
+            let occupancy_set = SquareSet::from_inner(occupancy);
+            attacks[square][magic_index] = if BISHOP {
+                bishop_attacks_on_the_fly(sq, occupancy_set)
+            } else {
+                rook_attacks_on_the_fly(sq, occupancy_set)
+            };
+            occupancy = occupancy.wrapping_sub(mask.inner()) & mask.inner();
+            if occupancy == 0 {
+                break;
+            }
+        }
+    });
+    attacks
+}
+
+pub static BISHOP_ATTACKS: [[SquareSet; 512]; 64] = init_attacks::<512, true>();
 #[allow(clippy::large_stack_arrays)]
-pub static ROOK_ATTACKS: [[SquareSet; 4096]; 64] =
-    unsafe { std::mem::transmute(*include_bytes!("../../embeds/orthogonal_attacks.bin")) };
+pub static ROOK_ATTACKS: [[SquareSet; 4096]; 64] = init_attacks::<4096, false>();
 
 pub static BISHOP_MAGICS: [u64; 64] = [
     0x0231_100A_1344_0020,
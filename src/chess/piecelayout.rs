@@ -82,17 +82,14 @@ impl PieceLayout {
         self.all_knights() & self.our_pieces::<C>()
     }
 
-    #[cfg(any(feature = "datagen", test))]
     pub fn rooks<C: Col>(&self) -> SquareSet {
         self.all_rooks() & self.our_pieces::<C>()
     }
 
-    #[cfg(any(feature = "datagen", test))]
     pub fn bishops<C: Col>(&self) -> SquareSet {
         self.all_bishops() & self.our_pieces::<C>()
     }
 
-    #[cfg(any(feature = "datagen", test))]
     pub fn queens<C: Col>(&self) -> SquareSet {
         self.all_queens() & self.our_pieces::<C>()
     }
@@ -298,6 +295,10 @@ pub struct Threats {
     // pub minor: SquareSet,
     // pub rook: SquareSet,
     pub checkers: SquareSet,
+    /// For each colour, the pieces that are all that stands between their king and an enemy slider.
+    pub pinned: [SquareSet; 2],
+    /// For each colour, the squares attacked by its bishops, rooks, and queens.
+    pub sliders: [SquareSet; 2],
 }
 
 impl Display for PieceLayout {
@@ -41,12 +41,17 @@ impl File {
     }
 
     pub const fn add(self, diff: u8) -> Option<Self> {
-        Self::from_index(self as u8 + diff)
+        match (self as u8).checked_add(diff) {
+            Some(index) => Self::from_index(index),
+            None => None,
+        }
     }
 
     pub const fn sub(self, diff: u8) -> Option<Self> {
-        #![allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
-        Self::from_index((self as i8 - diff as i8) as u8)
+        match (self as u8).checked_sub(diff) {
+            Some(index) => Self::from_index(index),
+            None => None,
+        }
     }
 
     pub fn all() -> impl DoubleEndedIterator<Item = Self> {
@@ -101,12 +106,17 @@ impl Rank {
     }
 
     pub const fn add(self, diff: u8) -> Option<Self> {
-        Self::from_index(self as u8 + diff)
+        match (self as u8).checked_add(diff) {
+            Some(index) => Self::from_index(index),
+            None => None,
+        }
     }
 
     pub const fn sub(self, diff: u8) -> Option<Self> {
-        #![allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
-        Self::from_index((self as i8 - diff as i8) as u8)
+        match (self as u8).checked_sub(diff) {
+            Some(index) => Self::from_index(index),
+            None => None,
+        }
     }
 
     pub fn all() -> impl DoubleEndedIterator<Item = Self> {
@@ -131,6 +141,50 @@ impl<T> IndexMut<Rank> for [T; 8] {
     }
 }
 
+/// A step from a square to one of its eight neighbours, with north towards the eighth
+/// rank and east towards the h-file.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    /// The direction that `side`'s pawns move in.
+    pub const fn forward(side: Colour) -> Self {
+        match side {
+            Colour::White => Self::North,
+            Colour::Black => Self::South,
+        }
+    }
+
+    /// The changes in file and in rank of a step in this direction.
+    pub const fn deltas(self) -> (i8, i8) {
+        match self {
+            Self::North => (0, 1),
+            Self::NorthEast => (1, 1),
+            Self::East => (1, 0),
+            Self::SouthEast => (1, -1),
+            Self::South => (0, -1),
+            Self::SouthWest => (-1, -1),
+            Self::West => (-1, 0),
+            Self::NorthWest => (-1, 1),
+        }
+    }
+
+    /// The change in square index of a step in this direction.
+    pub const fn offset(self) -> i8 {
+        let (file, rank) = self.deltas();
+        rank * 8 + file
+    }
+}
+
 #[rustfmt::skip]
 #[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash, Debug, Default)]
 #[repr(u8)]
@@ -256,13 +310,10 @@ impl Square {
     }
 
     pub const fn add(self, offset: u8) -> Option<Self> {
-        #![allow(
-            clippy::cast_possible_truncation,
-            clippy::cast_possible_wrap,
-            clippy::cast_sign_loss
-        )]
-        let res = self as u8 + offset;
-        Self::new(res)
+        match (self as u8).checked_add(offset) {
+            Some(inner) => Self::new(inner),
+            None => None,
+        }
     }
 
     pub const fn saturating_add(self, offset: u8) -> Self {
@@ -293,43 +344,58 @@ impl Square {
         Self::new_unchecked(res)
     }
 
-    /// SAFETY: You may not call this function with a square and offset such that
-    /// `square as u8 - offset` is outwith `0..64`.
     pub const fn sub(self, offset: u8) -> Option<Self> {
-        #![allow(
-            clippy::cast_possible_truncation,
-            clippy::cast_possible_wrap,
-            clippy::cast_sign_loss
-        )]
-        let res = self as u8 - offset;
-        Self::new(res)
+        match (self as u8).checked_sub(offset) {
+            Some(inner) => Self::new(inner),
+            None => None,
+        }
+    }
+
+    /// The square one step in `direction` from this one, or `None` if that step leaves
+    /// the board. Unlike [`Self::add`] and [`Self::sub`], a step east or west never wraps
+    /// around onto the next rank.
+    pub const fn try_offset(self, direction: Direction) -> Option<Self> {
+        #![allow(clippy::cast_sign_loss)]
+        let (file, rank) = direction.deltas();
+        let file = self.file() as i8 + file;
+        let rank = self.rank() as i8 + rank;
+        if 0 <= file && file < 8 && 0 <= rank && rank < 8 {
+            // SAFETY: both coordinates are in 0..8, so the index is in 0..64.
+            Some(unsafe { Self::new_unchecked((rank * 8 + file) as u8) })
+        } else {
+            None
+        }
+    }
+
+    /// The square one step in `direction` from this one.
+    ///
+    /// SAFETY: You may only call this function when the step stays on the board, that
+    /// is, when [`Self::try_offset`] would return `Some`.
+    pub const unsafe fn offset_unchecked(self, direction: Direction) -> Self {
+        #![allow(clippy::cast_sign_loss)]
+        debug_assert!(self.try_offset(direction).is_some());
+        Self::new_unchecked((self as i8 + direction.offset()) as u8)
     }
 
     pub const fn as_set(self) -> SquareSet {
         SquareSet::from_inner(1 << self as u8)
     }
 
-    pub fn pawn_push(self, side: Colour) -> Option<Self> {
-        if side == Colour::White {
-            self.add(8)
-        } else {
-            self.sub(8)
-        }
+    pub const fn pawn_push(self, side: Colour) -> Option<Self> {
+        self.try_offset(Direction::forward(side))
     }
 
-    pub fn pawn_right(self, side: Colour) -> Option<Self> {
-        if side == Colour::White {
-            self.add(9)
-        } else {
-            self.sub(7)
+    pub const fn pawn_right(self, side: Colour) -> Option<Self> {
+        match side {
+            Colour::White => self.try_offset(Direction::NorthEast),
+            Colour::Black => self.try_offset(Direction::SouthEast),
         }
     }
 
-    pub fn pawn_left(self, side: Colour) -> Option<Self> {
-        if side == Colour::White {
-            self.add(7)
-        } else {
-            self.sub(9)
+    pub const fn pawn_left(self, side: Colour) -> Option<Self> {
+        match side {
+            Colour::White => self.try_offset(Direction::NorthWest),
+            Colour::Black => self.try_offset(Direction::SouthWest),
         }
     }
 
@@ -417,10 +483,7 @@ impl Default for Undo {
             castle_perm: CastlingRights::NONE,
             ep_square: None,
             fifty_move_counter: 0,
-            threats: Threats {
-                all: SquareSet::EMPTY,
-                checkers: SquareSet::EMPTY,
-            },
+            threats: Threats::default(),
             piece_layout: PieceLayout::NULL,
             piece_array: [None; 64],
             key: 0,
@@ -599,4 +662,36 @@ mod tests {
         assert_eq!(Square::A8.relative_to(Colour::White), Square::A8);
         assert_eq!(Square::A8.relative_to(Colour::Black), Square::A1);
     }
+
+    #[test]
+    fn square_offsets() {
+        use super::{Colour, Direction, File, Rank, Square};
+
+        assert_eq!(Square::E4.try_offset(Direction::North), Some(Square::E5));
+        assert_eq!(
+            Square::E4.try_offset(Direction::SouthWest),
+            Some(Square::D3)
+        );
+        // steps off the side of the board don't wrap onto the next rank.
+        assert_eq!(Square::H4.try_offset(Direction::East), None);
+        assert_eq!(Square::A4.try_offset(Direction::NorthWest), None);
+        assert_eq!(Square::H1.pawn_right(Colour::White), None);
+        assert_eq!(Square::A8.pawn_left(Colour::Black), None);
+        assert_eq!(Square::E8.pawn_push(Colour::White), None);
+        assert_eq!(Square::E2.pawn_push(Colour::White), Some(Square::E3));
+        assert_eq!(Square::E7.pawn_push(Colour::Black), Some(Square::E6));
+        for sq in Square::all() {
+            for direction in [Direction::North, Direction::SouthEast, Direction::West] {
+                if let Some(to) = sq.try_offset(direction) {
+                    // SAFETY: the step stays on the board.
+                    assert_eq!(unsafe { sq.offset_unchecked(direction) }, to);
+                }
+            }
+        }
+
+        assert_eq!(Square::A1.sub(8), None);
+        assert_eq!(Square::H8.add(1), None);
+        assert_eq!(File::A.sub(1), None);
+        assert_eq!(Rank::Eight.add(u8::MAX), None);
+    }
 }
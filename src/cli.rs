@@ -7,6 +7,13 @@ pub struct Cli {
     /// All sub-commands that viri supports.
     #[clap(subcommand)]
     pub subcommand: Option<Subcommands>,
+    /// Load search parameters from a JSON or TOML file, as emitted by `spsa --json` or an
+    /// SPSA tuner. In UCI mode, the file is loaded again whenever it changes.
+    #[clap(long, value_name = "PATH")]
+    pub params: Option<std::path::PathBuf>,
+    /// Resume the analysis session saved in a file, and keep saving the session there.
+    #[clap(long, value_name = "PATH")]
+    pub resume: Option<std::path::PathBuf>,
 }
 
 #[derive(Parser)]
@@ -15,6 +22,15 @@ pub enum Subcommands {
     Bench {
         /// Optionally specify the depth at which to run the benchmark.
         depth: Option<usize>,
+        /// Size of the hash table in megabytes, in place of the default of 16.
+        #[clap(long, value_name = "MB")]
+        hash: Option<usize>,
+        /// Number of search threads, in place of the default of 1.
+        #[clap(long, value_name = "N")]
+        threads: Option<usize>,
+        /// Write the node count to this file, for release scripts to embed.
+        #[clap(long, value_name = "PATH")]
+        signature: Option<std::path::PathBuf>,
     },
     /// Run the perft suite.
     Perft,
@@ -24,9 +40,185 @@ pub enum Subcommands {
         input: std::path::PathBuf,
         /// Path to output network parameter file.
         output: std::path::PathBuf,
+        /// Read the input as a directory of .npy arrays, as written by `export --npy`.
+        #[clap(long)]
+        npy: bool,
+    },
+    /// Average floating-point networks in bullet's layout, for later quantisation.
+    Merge {
+        /// Paths to input network parameter files.
+        #[clap(required_unless_present = "swa", conflicts_with = "swa")]
+        inputs: Vec<std::path::PathBuf>,
+        /// Comma-separated weight for each input network. Defaults to a uniform average.
+        #[clap(long, value_delimiter = ',', value_name = "W1,W2,...")]
+        weights: Option<Vec<f32>>,
+        /// Instead of merging inputs, exponentially average all checkpoints in a directory.
+        #[clap(long, value_name = "DIR")]
+        swa: Option<std::path::PathBuf>,
+        /// Decay factor for the exponential average of checkpoints.
+        #[clap(long, default_value_t = 0.9, requires = "swa")]
+        decay: f32,
+        /// Path to output network parameter file.
+        #[clap(long, value_name = "PATH")]
+        output: std::path::PathBuf,
+    },
+    /// Dequantise a network into bullet's checkpoint layout, for round-tripping with training code.
+    Export {
+        /// Path to output network parameter file (or directory, with --npy).
+        output: std::path::PathBuf,
+        /// Path to input quantised network file. Defaults to the embedded network.
+        #[clap(long, value_name = "PATH")]
+        input: Option<std::path::PathBuf>,
+        /// Write a directory of named .npy arrays instead of a raw parameter file.
+        #[clap(long)]
+        npy: bool,
+    },
+    /// Generate graphical visualisations of the NNUE weights, and an HTML report.
+    VisNNUE {
+        /// File of FENs, one per line, to gather activation statistics over (defaults to the bench positions).
+        #[clap(long, value_name = "PATH")]
+        fens: Option<std::path::PathBuf>,
+    },
+    /// Evaluate every position in an EPD or FEN file, writing the scores as CSV.
+    #[clap(name = "evalfile")]
+    EvalFile {
+        /// Path to input file, one position per line.
+        input: std::path::PathBuf,
+        /// Number of threads to evaluate with.
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        threads: usize,
+        /// Path to output CSV file. Defaults to stdout.
+        #[clap(long, value_name = "PATH")]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Check that every position in an EPD or FEN file evaluates the same as its colour-flipped mirror.
+    Symmetry {
+        /// Path to input file, one position per line.
+        #[clap(long, value_name = "PATH")]
+        positions: std::path::PathBuf,
+        /// Number of threads to evaluate with.
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        threads: usize,
+        /// The largest difference between a position and its mirror that isn't reported.
+        #[clap(long, value_name = "CP", default_value_t = 0)]
+        tolerance: i32,
+    },
+    /// Play random games and parse mangled FENs, checking movegen, make/unmake, evaluation,
+    /// and notation round trips against each other.
+    Fuzz {
+        /// How long to fuzz for.
+        #[clap(long, value_name = "N", default_value_t = 60)]
+        seconds: u64,
+        /// Seed for the random games, to reproduce a run. Defaults to a fresh seed.
+        #[clap(long, value_name = "N")]
+        seed: Option<u64>,
+    },
+    /// Analyse every position in an EPD or FEN file, writing results as JSON lines, CSV, or EPD.
+    #[clap(name = "analysefile")]
+    AnalyseFile {
+        /// Path to input file, one position per line.
+        input: std::path::PathBuf,
+        /// Search each position for this many milliseconds.
+        #[clap(long, value_name = "MS", required_unless_present_any = ["depth", "nodes"], conflicts_with_all = ["depth", "nodes"])]
+        movetime: Option<u64>,
+        /// Search each position to this depth.
+        #[clap(long, value_name = "N", conflicts_with = "nodes")]
+        depth: Option<i32>,
+        /// Search each position for this many nodes.
+        #[clap(long, value_name = "N")]
+        nodes: Option<u64>,
+        /// Number of positions to analyse concurrently. Defaults to all available threads.
+        #[clap(long, value_name = "N")]
+        threads: Option<usize>,
+        /// Hash table size for each concurrent search, in megabytes.
+        #[clap(long, value_name = "MB", default_value_t = 16)]
+        hash: usize,
+        /// Path to output file, as CSV if it ends in .csv, EPD with analysis operations if it ends in .epd, and JSON lines otherwise. Defaults to stdout.
+        #[clap(long, value_name = "PATH")]
+        output: Option<std::path::PathBuf>,
+        /// Path to a persistent analysis cache, used to skip positions searched in earlier runs.
+        #[clap(long, value_name = "PATH")]
+        cache: Option<std::path::PathBuf>,
+        /// Size in megabytes to create the analysis cache with, if it does not exist.
+        #[clap(long, value_name = "MB", default_value_t = 64, requires = "cache")]
+        cache_size: usize,
+    },
+    /// Report the moves in a PGN file that lose more than a threshold against the best move.
+    #[clap(name = "blundercheck")]
+    BlunderCheck {
+        /// Path to the PGN file of games to check.
+        input: std::path::PathBuf,
+        /// Smallest loss, in centipawns, for a move to be reported.
+        #[clap(long, value_name = "CP", default_value_t = 150)]
+        threshold: i32,
+        /// Search each position for this many milliseconds.
+        #[clap(long, value_name = "MS", required_unless_present_any = ["depth", "nodes"], conflicts_with_all = ["depth", "nodes"])]
+        movetime: Option<u64>,
+        /// Search each position to this depth.
+        #[clap(long, value_name = "N", conflicts_with = "nodes")]
+        depth: Option<i32>,
+        /// Search each position for this many nodes.
+        #[clap(long, value_name = "N")]
+        nodes: Option<u64>,
+        /// Number of positions to analyse concurrently. Defaults to all available threads.
+        #[clap(long, value_name = "N")]
+        threads: Option<usize>,
+        /// Hash table size for each concurrent search, in megabytes.
+        #[clap(long, value_name = "MB", default_value_t = 16)]
+        hash: usize,
+    },
+    /// Report transpositions between the positions of a PGN or EPD file.
+    #[clap(name = "dedupcheck")]
+    DedupCheck {
+        /// Path to input file. Files ending in .pgn are read as games, anything else as one position per line.
+        input: std::path::PathBuf,
+        /// Also search each new position to this depth, sharing one hash table across the batch.
+        #[clap(long, value_name = "N")]
+        depth: Option<usize>,
+        /// Hash table size in megabytes, for use with --depth.
+        #[clap(long, value_name = "MB", default_value_t = 64, requires = "depth")]
+        hash: usize,
+    },
+    /// Build an opening repertoire from a position, written as PGN with evaluations.
+    #[clap(name = "repertoire")]
+    Repertoire {
+        /// Position to build the repertoire from. Defaults to the starting position.
+        #[clap(long, value_name = "FEN")]
+        fen: Option<String>,
+        /// Depth to search each candidate move to.
+        #[clap(long, value_name = "N", default_value_t = 12)]
+        depth: i32,
+        /// Expand every move scoring within this many centipawns of the best move.
+        #[clap(long, value_name = "CP", default_value_t = 30)]
+        margin: i32,
+        /// Most moves to expand from any one position.
+        #[clap(long, value_name = "N", default_value_t = 3)]
+        width: usize,
+        /// Depth of the repertoire tree, in plies.
+        #[clap(long, value_name = "N", default_value_t = 6)]
+        plies: usize,
+        /// Hash table size in megabytes.
+        #[clap(long, value_name = "MB", default_value_t = 64)]
+        hash: usize,
+        /// Path to output PGN file. Defaults to stdout.
+        #[clap(long, value_name = "PATH")]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Draw a position as an image, optionally with a heatmap laid over the board.
+    Render {
+        /// Position to draw. Defaults to the starting position.
+        #[clap(long, value_name = "FEN")]
+        fen: Option<String>,
+        /// Path to output image, as TGA, BMP, or PPM by its extension.
+        #[clap(long, value_name = "PATH")]
+        out: std::path::PathBuf,
+        /// Heatmap to lay over the board.
+        #[clap(long, value_enum)]
+        heatmap: Option<crate::image::diagram::Heatmap>,
+        /// Depth to search each position to for the eval heatmap, or 0 for the static evaluation.
+        #[clap(long, value_name = "N", default_value_t = 0)]
+        depth: i32,
     },
-    /// Generate graphical visualisations of the NNUE weights.
-    VisNNUE,
     /// Count the number of positions contained within one or more packed game records.
     #[cfg(feature = "datagen")]
     CountPositions {
@@ -38,6 +230,9 @@ pub enum Subcommands {
     Analyse {
         /// Path to input packed game record.
         input: std::path::PathBuf,
+        /// Also plot histograms of the distributions, as TGA and BMP images.
+        #[clap(long)]
+        plots: bool,
     },
     /// Emit configuration for SPSA
     Spsa {
@@ -63,6 +258,172 @@ pub enum Subcommands {
         /// Override the filter settings.
         #[clap(long)]
         cfg_path: Option<std::path::PathBuf>,
+        /// Deal games out in turn to this many output files, written in parallel, each named for the output path with its index appended.
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        shards: usize,
+        /// Compress the output with zstd (requires the zstd feature).
+        #[clap(long)]
+        zstd: bool,
+    },
+    /// Rewrite a packed game record with its evaluations rescaled, reporting calibration against the game results before and after.
+    #[cfg(feature = "datagen")]
+    Rescale {
+        /// Path to input packed game record.
+        input: std::path::PathBuf,
+        /// Output path.
+        output: std::path::PathBuf,
+        /// Multiply every evaluation by this factor.
+        #[clap(long, value_name = "F", default_value_t = 1.0)]
+        scale: f64,
+        /// Fit the sigmoid scale of the input's evaluations against its results, and rescale so that the fit becomes K.
+        #[clap(long, value_name = "K")]
+        fit_k: Option<f64>,
+        /// Further factors by game phase, from the endgame to the opening, interpolated between.
+        #[clap(long, value_delimiter = ',', value_name = "F1,F2,...")]
+        phase_scales: Vec<f64>,
+        /// Bring rescaled evaluations within this bound, which mate and tablebase scores are also set to.
+        #[clap(long, value_name = "CP")]
+        clip: Option<i32>,
+        /// How evaluations beyond the bound are brought within it.
+        #[clap(long, value_enum, default_value_t = crate::datagen::rescale::ClipPolicy::Saturate)]
+        clip_policy: crate::datagen::rescale::ClipPolicy,
+    },
+    /// Replay every game in a packed game record, reporting any that are corrupt
+    #[cfg(feature = "datagen")]
+    Verify {
+        /// Path to input packed game record.
+        input: std::path::PathBuf,
+        /// Write the games that pass to this path.
+        #[clap(long, value_name = "PATH")]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Tune search parameters locally with SPSA over self-play games
+    #[cfg(feature = "datagen")]
+    Tune {
+        /// Path to a JSON or TOML file of parameters to tune, as emitted by `spsa --json`.
+        #[clap(long, value_name = "PATH")]
+        params: std::path::PathBuf,
+        /// Number of games to play
+        #[clap(long, value_name = "N")]
+        games: usize,
+        /// Time control, as base+increment in seconds
+        #[clap(long, value_name = "TC", default_value = "5+0.05")]
+        tc: crate::tune::TimeControl,
+        /// Number of game pairs to play concurrently
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        threads: usize,
+        /// When a player resigns, as "movecount=N score=S" in the style of cutechess, or "off"
+        #[clap(long, value_name = "RULE", default_value = "movecount=2 score=2500")]
+        resign: crate::adjudication::ResignRule,
+        /// When the players agree to a draw, as "movenumber=N movecount=N score=S", or "off"
+        #[clap(
+            long,
+            value_name = "RULE",
+            default_value = "movenumber=0 movecount=6 score=4"
+        )]
+        draw: crate::adjudication::DrawRule,
+        /// Seed for the perturbations and openings; a fresh one is chosen if this isn't given
+        #[clap(long, value_name = "N")]
+        seed: Option<u64>,
+        /// Where to write the tuned parameters
+        #[clap(long, value_name = "PATH", default_value = "tuned.json")]
+        output: std::path::PathBuf,
+    },
+    /// Play self-play games between two configurations until an SPRT concludes
+    #[cfg(feature = "datagen")]
+    Sprt {
+        /// Path to a JSON parameter file for the configuration under test
+        #[clap(long, value_name = "PATH")]
+        dev: Option<std::path::PathBuf>,
+        /// Path to a JSON parameter file for the configuration to test against
+        #[clap(long, value_name = "PATH")]
+        base: Option<std::path::PathBuf>,
+        /// Lower Elo bound (null hypothesis)
+        #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        elo0: f64,
+        /// Upper Elo bound (alternative hypothesis)
+        #[clap(long, default_value_t = 5.0, allow_negative_numbers = true)]
+        elo1: f64,
+        /// False positive rate
+        #[clap(long, default_value_t = 0.05)]
+        alpha: f64,
+        /// False negative rate
+        #[clap(long, default_value_t = 0.05)]
+        beta: f64,
+        /// Maximum number of games to play
+        #[clap(long, value_name = "N", default_value_t = 20_000)]
+        max_games: usize,
+        /// Time control, as base+increment in seconds
+        #[clap(long, value_name = "TC", default_value = "5+0.05")]
+        tc: crate::tune::TimeControl,
+        /// Number of game pairs to play concurrently
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        threads: usize,
+        /// When a player resigns, as "movecount=N score=S" in the style of cutechess, or "off"
+        #[clap(long, value_name = "RULE", default_value = "movecount=2 score=2500")]
+        resign: crate::adjudication::ResignRule,
+        /// When the players agree to a draw, as "movenumber=N movecount=N score=S", or "off"
+        #[clap(
+            long,
+            value_name = "RULE",
+            default_value = "movenumber=0 movecount=6 score=4"
+        )]
+        draw: crate::adjudication::DrawRule,
+        /// Seed for the openings; a fresh one is chosen if this isn't given
+        #[clap(long, value_name = "N")]
+        seed: Option<u64>,
+    },
+    /// Play a round-robin or gauntlet tournament between configurations and external engines
+    #[cfg(feature = "datagen")]
+    Tournament {
+        /// A player, as comma-separated KEY=VALUE pairs: an optional name, then params and net
+        /// files for a configuration of this engine, or the cmd of an external engine and its
+        /// UCI options as option.NAME (can be repeated)
+        #[clap(long = "player", value_name = "SPEC", required = true)]
+        players: Vec<crate::tune::tournament::PlayerSpec>,
+        /// Play only the first player against each of the others
+        #[clap(long)]
+        gauntlet: bool,
+        /// Number of game pairs to play in each pairing
+        #[clap(long, value_name = "N", default_value_t = 100)]
+        rounds: usize,
+        /// Time control, as base+increment in seconds
+        #[clap(long, value_name = "TC", default_value = "5+0.05")]
+        tc: crate::tune::TimeControl,
+        /// Number of game pairs to play concurrently
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        threads: usize,
+        /// When a player resigns, as "movecount=N score=S" in the style of cutechess, or "off"
+        #[clap(long, value_name = "RULE", default_value = "movecount=2 score=2500")]
+        resign: crate::adjudication::ResignRule,
+        /// When the players agree to a draw, as "movenumber=N movecount=N score=S", or "off"
+        #[clap(
+            long,
+            value_name = "RULE",
+            default_value = "movenumber=0 movecount=6 score=4"
+        )]
+        draw: crate::adjudication::DrawRule,
+        /// Stop playing each pairing once an SPRT between its players concludes
+        #[clap(long)]
+        sprt: bool,
+        /// Lower Elo bound (null hypothesis) for --sprt
+        #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        elo0: f64,
+        /// Upper Elo bound (alternative hypothesis) for --sprt
+        #[clap(long, default_value_t = 5.0, allow_negative_numbers = true)]
+        elo1: f64,
+        /// False positive rate for --sprt
+        #[clap(long, default_value_t = 0.05)]
+        alpha: f64,
+        /// False negative rate for --sprt
+        #[clap(long, default_value_t = 0.05)]
+        beta: f64,
+        /// Seed for the openings; a fresh one is chosen if this isn't given
+        #[clap(long, value_name = "N")]
+        seed: Option<u64>,
+        /// Path to write the games to, as PGN
+        #[clap(long, value_name = "PATH")]
+        pgn: Option<std::path::PathBuf>,
     },
     /// Generate self-play data
     #[cfg(feature = "datagen")]
@@ -82,8 +443,59 @@ pub enum Subcommands {
         /// Limit by depth instead of nodes
         #[clap(long)]
         depth_limit: bool,
+        /// Node budget at the start of each game, as a percentage of the node limit
+        #[clap(long, value_name = "PERCENT", default_value_t = 100)]
+        opening_nodes: u64,
+        /// Node budget once the game is `--ramp-plies` long, as a percentage of the node limit
+        #[clap(long, value_name = "PERCENT", default_value_t = 100)]
+        endgame_nodes: u64,
+        /// Number of plies over which the node budget moves from the opening to the endgame budget
+        #[clap(long, value_name = "N", default_value_t = 0)]
+        ramp_plies: u64,
+        /// Largest random change to the node budget of each move, as a percentage
+        #[clap(long, value_name = "PERCENT", default_value_t = 0, value_parser = clap::value_parser!(u64).range(0..=100))]
+        node_jitter: u64,
+        /// Largest random change to the depth of each move, with --depth-limit
+        #[clap(long, value_name = "PLIES", default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..))]
+        depth_jitter: i32,
+        /// Play on from tablebase positions, labelling the game with the tablebase result
+        #[clap(long, requires = "tbs")]
+        tb_relabel: bool,
+        /// Path to an external UCI engine to choose and label moves with, in place of our own search
+        #[clap(long, value_name = "PATH")]
+        engine: Option<std::path::PathBuf>,
+        /// A UCI option to give the external engine, as NAME=VALUE (can be repeated)
+        #[clap(long, value_name = "NAME=VALUE", requires = "engine", value_parser = parse_engine_option)]
+        engine_option: Vec<(String, String)>,
         // Whether to generate DFRC data.
         #[clap(long)]
         dfrc: bool,
+        /// Seed for the openings and search limits of every game, so that a run can be
+        /// reproduced. A fresh seed is chosen and printed if this isn't given.
+        #[clap(long, value_name = "N")]
+        seed: Option<u64>,
+        /// When a game is lost, as "movecount=N score=S [twosided=true]" in the style of cutechess, or "off"
+        #[clap(
+            long,
+            value_name = "RULE",
+            default_value = "movecount=2 score=2500 twosided=true"
+        )]
+        resign: crate::adjudication::ResignRule,
+        /// When a game is drawn, as "movenumber=N movecount=N score=S" in the style of cutechess, or "off"
+        #[clap(
+            long,
+            value_name = "RULE",
+            default_value = "movenumber=0 movecount=6 score=4"
+        )]
+        draw: crate::adjudication::DrawRule,
     },
 }
+
+/// Parse a `NAME=VALUE` pair for an external engine's UCI option.
+#[cfg(feature = "datagen")]
+fn parse_engine_option(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got \"{s}\""))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
@@ -1,18 +1,18 @@
 use crate::{
+    cfor,
     chess::{
-        board::movegen::attacks_by_type,
+        board::movegen::empty_board_attacks,
         chessmove::Move,
         piece::{Piece, PieceType},
-        squareset::SquareSet,
         types::Square,
     },
     lookups::{PIECE_KEYS, SIDE_KEY},
 };
 
-pub static KEYS: [u64; 8192] =
-    unsafe { std::mem::transmute(*include_bytes!("../embeds/cuckoo_keys.bin")) };
-pub static MOVES: [Option<Move>; 8192] =
-    unsafe { std::mem::transmute(*include_bytes!("../embeds/cuckoo_moves.bin")) };
+const TABLES: ([u64; 8192], [Option<Move>; 8192]) = init_tables();
+
+pub static KEYS: [u64; 8192] = TABLES.0;
+pub static MOVES: [Option<Move>; 8192] = TABLES.1;
 
 pub const fn h1(key: u64) -> usize {
     (key & 0x1FFF) as usize
@@ -22,51 +22,50 @@ pub const fn h2(key: u64) -> usize {
     ((key >> 16) & 0x1FFF) as usize
 }
 
-pub fn init() -> anyhow::Result<()> {
-    println!("Initialising cuckoo-hash tables.");
+/// Insert every reversible non-pawn move into the cuckoo-hash tables,
+/// keyed by the change it makes to the zobrist hash.
+#[allow(clippy::large_stack_arrays, clippy::large_stack_frames)]
+const fn init_tables() -> ([u64; 8192], [Option<Move>; 8192]) {
     // keep a tally of the table entries to sanity-check the initialisation process.
     let mut count = 0;
 
-    let mut keys = vec![0; 8192];
-    let mut cuckoo_moves = vec![None; 8192];
+    let mut keys = [0; 8192];
+    let mut cuckoo_moves = [None; 8192];
 
-    for piece in Piece::all() {
-        if piece.piece_type() == PieceType::Pawn {
-            continue;
-        }
-        for square0 in Square::all() {
-            for square1 in Square::all().filter(|&s1| s1 > square0) {
-                // check if a piece of this type standing on square0 could attack square1
-                let attack_overlap = attacks_by_type(piece.piece_type(), square0, SquareSet::EMPTY)
-                    & square1.as_set();
-                if attack_overlap == SquareSet::EMPTY {
-                    continue;
-                }
+    cfor!(let mut index = 0; index < 12; index += 1; {
+        let Some(piece) = Piece::from_index(index) else {
+            panic!("there are twelve pieces");
+        };
+        // pawn moves are never reversible.
+        if !matches!(piece.piece_type(), PieceType::Pawn) {
+            cfor!(let mut square0 = 0; square0 < 64; square0 += 1; {
+                let attacks = empty_board_attacks(piece.piece_type(), Square::new_clamped(square0));
+                cfor!(let mut square1 = square0 + 1; square1 < 64; square1 += 1; {
+                    // check if a piece of this type standing on square0 could attack square1
+                    if attacks.contains_square(Square::new_clamped(square1)) {
+                        let mut mv = Some(Move::new(
+                            Square::new_clamped(square0),
+                            Square::new_clamped(square1),
+                        ));
+                        let keys_for_piece = PIECE_KEYS[index as usize];
+                        let mut key = keys_for_piece[square0 as usize] ^ keys_for_piece[square1 as usize] ^ SIDE_KEY;
+                        let mut slot = h1(key);
+                        loop {
+                            std::mem::swap(&mut keys[slot], &mut key);
+                            std::mem::swap(&mut cuckoo_moves[slot], &mut mv);
 
-                let mut mv = Some(Move::new(square0, square1));
-                let mut key = PIECE_KEYS[piece][square0] ^ PIECE_KEYS[piece][square1] ^ SIDE_KEY;
-                let mut slot = h1(key);
-                loop {
-                    std::mem::swap(&mut keys[slot], &mut key);
-                    std::mem::swap(&mut cuckoo_moves[slot], &mut mv);
+                            if mv.is_none() {
+                                break;
+                            }
 
-                    if mv.is_none() {
-                        break;
+                            slot = if slot == h1(key) { h2(key) } else { h1(key) };
+                        }
+                        count += 1;
                     }
-
-                    slot = if slot == h1(key) { h2(key) } else { h1(key) };
-                }
-                count += 1;
-            }
+                });
+            });
         }
-    }
-    assert_eq!(count, 3668);
-    // SAFETY: u64 is fine to view as bytes.
-    let key_bytes = unsafe { keys.align_to::<u8>().1 };
-    // SAFETY: Option<Move> is repr-equivalent to u16.
-    let move_bytes = unsafe { cuckoo_moves.align_to::<u8>().1 };
-    std::fs::write("embeds/cuckoo_keys.bin", key_bytes)?;
-    std::fs::write("embeds/cuckoo_moves.bin", move_bytes)?;
-    println!("Wrote cuckoo-hash tables.");
-    Ok(())
+    });
+    assert!(count == 3668);
+    (keys, cuckoo_moves)
 }
@@ -1,20 +1,23 @@
 #![allow(dead_code)]
 
 mod dataformat;
+pub mod rescale;
+pub mod uciengine;
+pub mod verify;
 
 use std::{
     borrow::Cow,
     cmp::Reverse,
     collections::HashMap,
-    fmt::{Display, Formatter},
+    fmt::{Display, Formatter, Write as _},
     fs::{self, File},
     hash::Hash,
     io::{BufReader, BufWriter, Seek, Write},
-    ops::ControlFlow,
+    ops::{ControlFlow, RangeInclusive},
     path::{Path, PathBuf},
     str::FromStr,
     sync::{
-        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Mutex,
     },
     time::Instant,
@@ -23,18 +26,27 @@ use std::{
 use anyhow::{anyhow, bail, Context};
 use bulletformat::ChessBoard;
 use dataformat::Filter;
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
+    adjudication::{Adjudicator, DrawRule, ResignRule},
     chess::{
-        board::{Board, DrawType, GameOutcome, WinType},
+        board::{eco::Opening, Board, DrawType, GameOutcome, WinType},
         piece::{Colour, PieceType},
         types::Square,
         CHESS960,
     },
-    datagen::dataformat::Game,
+    datagen::{
+        dataformat::{
+            viriformat::{self, GameReader},
+            Game,
+        },
+        uciengine::UciEngine,
+    },
     evaluation::{is_game_theoretic_score, is_mate_score},
-    nnue::network::NNUEParams,
+    image::{self, Image},
+    nnue::network::{output_bucket, NNUEParams, OUTPUT_BUCKETS},
+    rng,
     searchinfo::SearchInfo,
     tablebases::{self, probe::WDL},
     threadlocal::ThreadData,
@@ -46,6 +58,20 @@ use crate::{
 
 const MIN_SAVE_PLY: usize = 16;
 const MAX_RNG_PLY: usize = 24;
+/// Openings that the classical evaluation already scores beyond this are discarded unsearched.
+const CLASSICAL_PREFILTER_MARGIN: i32 = 1500;
+/// Games are lost once four consecutive plies are scored at 2500 or more in one side's
+/// favour, and drawn once twelve consecutive plies are scored within 4 of zero.
+const DEFAULT_RESIGN: ResignRule = ResignRule {
+    moves: 2,
+    score: 2500,
+    two_sided: true,
+};
+const DEFAULT_DRAW: DrawRule = DrawRule {
+    move_number: 0,
+    moves: 6,
+    score: 4,
+};
 
 static FENS_GENERATED: AtomicU64 = AtomicU64::new(0);
 static STOP_GENERATION: AtomicBool = AtomicBool::new(false);
@@ -57,6 +83,71 @@ enum DataGenLimit {
     Nodes(u64),
 }
 
+/// How the search limit varies from move to move over the course of a game.
+#[derive(Clone, Debug, Hash)]
+pub struct LimitSchedule {
+    /// The node budget at the start of the game, as a percentage of the node limit.
+    pub opening_percent: u64,
+    /// The node budget from `ramp_plies` onwards, as a percentage of the node limit.
+    pub endgame_percent: u64,
+    /// The number of plies over which the budget moves from the opening to the endgame percentage.
+    pub ramp_plies: u64,
+    /// The most that the node budget of each move may be randomly moved, as a percentage.
+    pub node_jitter_percent: u64,
+    /// The most that the depth of each move may be randomly moved, in plies.
+    pub depth_jitter: i32,
+}
+
+impl LimitSchedule {
+    /// A schedule that searches every move with the same limit.
+    pub const FIXED: Self = Self {
+        opening_percent: 100,
+        endgame_percent: 100,
+        ramp_plies: 0,
+        node_jitter_percent: 0,
+        depth_jitter: 0,
+    };
+
+    const fn is_fixed(&self) -> bool {
+        self.opening_percent == 100
+            && self.endgame_percent == 100
+            && self.node_jitter_percent == 0
+            && self.depth_jitter == 0
+    }
+
+    /// The node budget for a move made `ply` plies into the game, before jitter.
+    const fn scheduled_nodes(&self, nodes: u64, ply: u64) -> u64 {
+        let percent = if ply >= self.ramp_plies {
+            self.endgame_percent
+        } else {
+            // interpolate linearly between the opening and endgame percentages.
+            (self.opening_percent * (self.ramp_plies - ply) + self.endgame_percent * ply)
+                / self.ramp_plies
+        };
+        nodes * percent / 100
+    }
+
+    /// The search limit for a move made `ply` plies into the game.
+    fn limit_for(&self, limit: &DataGenLimit, ply: u64, rng: &mut impl Rng) -> SearchLimit {
+        match *limit {
+            DataGenLimit::Depth(depth) => {
+                let jitter = rng.gen_range(-self.depth_jitter..=self.depth_jitter);
+                SearchLimit::Depth((depth + jitter).max(1))
+            }
+            DataGenLimit::Nodes(nodes) => {
+                let nodes = self.scheduled_nodes(nodes, ply);
+                let jitter = self.node_jitter_percent.min(100);
+                let percent = rng.gen_range(100 - jitter..=100 + jitter);
+                let nodes = (nodes * percent / 100).max(1);
+                SearchLimit::SoftNodes {
+                    soft_limit: nodes,
+                    hard_limit: nodes * 8,
+                }
+            }
+        }
+    }
+}
+
 /// Configuration options for Viri's self-play data generation.
 #[derive(Clone, Debug, Hash)]
 struct DataGenOptions {
@@ -70,8 +161,22 @@ struct DataGenOptions {
     book: Option<PathBuf>,
     // The depth or node limit for searches.
     limit: DataGenLimit,
+    // How the limit varies over the course of each game.
+    schedule: LimitSchedule,
+    // Whether to play on from tablebase positions, labelling the game with the tablebase result.
+    tb_relabel: bool,
+    // The (optional) path to an external UCI engine to choose and label the moves of each game.
+    engine: Option<PathBuf>,
+    // UCI options to give the external engine, as (name, value) pairs.
+    engine_options: Vec<(String, String)>,
     // Whether to generate DFRC data.
     generate_dfrc: bool,
+    // The (optional) seed from which every game's randomness is derived.
+    seed: Option<u64>,
+    // When a game is adjudicated as lost, in the style of cutechess's `-resign`.
+    resign: ResignRule,
+    // When a game is adjudicated as drawn, in the style of cutechess's `-draw`.
+    draw: DrawRule,
     // log level
     log_level: u8,
 }
@@ -88,8 +193,22 @@ pub struct DataGenOptionsBuilder {
     pub book: Option<PathBuf>,
     // The depth or node limit for searches.
     pub depth_limit: bool,
+    // How the limit varies over the course of each game.
+    pub schedule: LimitSchedule,
+    // Whether to play on from tablebase positions, labelling the game with the tablebase result.
+    pub tb_relabel: bool,
+    // The (optional) path to an external UCI engine to choose and label the moves of each game.
+    pub engine: Option<PathBuf>,
+    // UCI options to give the external engine, as (name, value) pairs.
+    pub engine_options: Vec<(String, String)>,
     // Whether to generate DFRC data.
     pub dfrc: bool,
+    // The (optional) seed from which every game's randomness is derived.
+    pub seed: Option<u64>,
+    // When a game is adjudicated as lost, in the style of cutechess's `-resign`.
+    pub resign: ResignRule,
+    // When a game is adjudicated as drawn, in the style of cutechess's `-draw`.
+    pub draw: DrawRule,
 }
 
 impl DataGenOptionsBuilder {
@@ -104,7 +223,14 @@ impl DataGenOptionsBuilder {
             } else {
                 DataGenLimit::Nodes(25000)
             },
+            schedule: self.schedule,
+            tb_relabel: self.tb_relabel,
+            engine: self.engine,
+            engine_options: self.engine_options,
             generate_dfrc: self.dfrc,
+            seed: self.seed,
+            resign: self.resign,
+            draw: self.draw,
             log_level: 1,
         }
     }
@@ -119,7 +245,14 @@ impl DataGenOptions {
             tablebases_path: None,
             book: None,
             limit: DataGenLimit::Depth(8),
+            schedule: LimitSchedule::FIXED,
+            tb_relabel: false,
+            engine: None,
+            engine_options: Vec::new(),
             generate_dfrc: true,
+            seed: None,
+            resign: DEFAULT_RESIGN,
+            draw: DEFAULT_DRAW,
             log_level: 1,
         }
     }
@@ -127,11 +260,12 @@ impl DataGenOptions {
     /// Gives a summarised string representation of the options.
     fn summary(&self) -> String {
         format!(
-            "{}g-{}t-{}-{}-{}{}",
+            "{}g-{}t-{}-{}-{}{}{}{}{}",
             self.num_games,
             self.num_threads,
             if self.tablebases_path.is_some() {
-                format!("tb{}", tablebases::probe::get_max_pieces_count())
+                let relabel = if self.tb_relabel { "rl" } else { "" };
+                format!("tb{}{relabel}", tablebases::probe::get_max_pieces_count())
             } else {
                 "no_tb".into()
             },
@@ -144,38 +278,71 @@ impl DataGenOptions {
                 DataGenLimit::Depth(depth) => format!("d{depth}"),
                 DataGenLimit::Nodes(nodes) => format!("n{nodes}"),
             },
+            if self.schedule.is_fixed() {
+                String::new()
+            } else {
+                let s = &self.schedule;
+                match self.limit {
+                    DataGenLimit::Depth(_) => format!("-dj{}", s.depth_jitter),
+                    DataGenLimit::Nodes(_) => format!(
+                        "-s{}to{}over{}-j{}",
+                        s.opening_percent, s.endgame_percent, s.ramp_plies, s.node_jitter_percent
+                    ),
+                }
+            },
+            self.engine
+                .as_ref()
+                .map_or_else(String::new, |engine| format!(
+                    "-{}",
+                    engine.file_stem().unwrap_or_default().to_string_lossy()
+                )),
             self.book.as_ref().map_or_else(String::new, |book| format!(
                 "-{}",
-                book.file_name().unwrap().to_string_lossy().trim_end_matches(".epd")
-            ))
+                book.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .trim_end_matches(".epd")
+            )),
+            self.seed
+                .map_or_else(String::new, |seed| format!("-seed{seed}"))
         )
     }
 }
 
+/// A source of starting positions. Each position is chosen by the game's index and its
+/// random number generator alone, so that a seeded run picks the same openings however
+/// its games are shared out between threads.
 trait StartposGenerator {
-    fn generate(&mut self, board: &mut Board, thread_data: &mut ThreadData) -> ControlFlow<(), ()>;
+    fn generate(
+        &mut self,
+        board: &mut Board,
+        thread_data: &mut ThreadData,
+        index: usize,
+        rng: &mut StdRng,
+    ) -> ControlFlow<(), ()>;
 }
 
-struct ClassicalStartposGenerator {
-    rng: ThreadRng,
-}
+struct ClassicalStartposGenerator;
 
-struct DFRCStartposGenerator {
-    rng: ThreadRng,
-}
+struct DFRCStartposGenerator;
 
 struct BookStartposGenerator<'a> {
     source: &'a [&'a str],
-    cursor: &'a AtomicUsize,
 }
 
 impl StartposGenerator for ClassicalStartposGenerator {
-    fn generate(&mut self, board: &mut Board, thread_data: &mut ThreadData) -> ControlFlow<(), ()> {
+    fn generate(
+        &mut self,
+        board: &mut Board,
+        thread_data: &mut ThreadData,
+        _index: usize,
+        rng: &mut StdRng,
+    ) -> ControlFlow<(), ()> {
         board.set_startpos();
         thread_data.nnue.reinit_from(board, thread_data.nnue_params);
-        let max = if self.rng.gen_bool(0.5) { 8 } else { 9 };
+        let max = if rng.gen_bool(0.5) { 8 } else { 9 };
         for _ in 0..max {
-            let res = board.make_random_move(&mut self.rng, thread_data);
+            let res = board.make_random_move(rng, thread_data);
             if res.is_none() {
                 return ControlFlow::Break(());
             }
@@ -189,12 +356,18 @@ impl StartposGenerator for ClassicalStartposGenerator {
 }
 
 impl StartposGenerator for DFRCStartposGenerator {
-    fn generate(&mut self, board: &mut Board, thread_data: &mut ThreadData) -> ControlFlow<(), ()> {
-        board.set_dfrc_idx(rand::Rng::gen_range(&mut self.rng, 0..960 * 960));
+    fn generate(
+        &mut self,
+        board: &mut Board,
+        thread_data: &mut ThreadData,
+        _index: usize,
+        rng: &mut StdRng,
+    ) -> ControlFlow<(), ()> {
+        board.set_dfrc_idx(rng.gen_range(0..960 * 960));
         thread_data.nnue.reinit_from(board, thread_data.nnue_params);
-        let max = if self.rng.gen_bool(0.5) { 8 } else { 9 };
+        let max = if rng.gen_bool(0.5) { 8 } else { 9 };
         for _ in 0..max {
-            let res = board.make_random_move(&mut self.rng, thread_data);
+            let res = board.make_random_move(rng, thread_data);
             if res.is_none() {
                 return ControlFlow::Break(());
             }
@@ -208,13 +381,15 @@ impl StartposGenerator for DFRCStartposGenerator {
 }
 
 impl StartposGenerator for BookStartposGenerator<'_> {
-    fn generate(&mut self, board: &mut Board, thread_data: &mut ThreadData) -> ControlFlow<(), ()> {
-        let idx = self.cursor.fetch_add(1, Ordering::Relaxed);
-        if idx >= self.source.len() {
-            println!("Book exhausted!");
-            self.cursor.store(0, Ordering::Relaxed);
-            return ControlFlow::Break(());
-        }
+    fn generate(
+        &mut self,
+        board: &mut Board,
+        thread_data: &mut ThreadData,
+        index: usize,
+        _rng: &mut StdRng,
+    ) -> ControlFlow<(), ()> {
+        // games past the end of the book start again from its beginning.
+        let idx = index % self.source.len();
         let fen = self.source[idx];
         board
             .set_from_fen(fen)
@@ -241,8 +416,13 @@ pub fn gen_data_main(cli_config: DataGenOptionsBuilder) -> anyhow::Result<()> {
 
     let options: DataGenOptions = cli_config.build();
 
+    if options.tb_relabel && options.tablebases_path.is_none() {
+        bail!("relabelling with tablebases needs a tablebases path (pass --tbs)");
+    }
+
     CHESS960.store(options.generate_dfrc, Ordering::SeqCst);
     FENS_GENERATED.store(0, Ordering::SeqCst);
+    let seed = options.seed.unwrap_or_else(rng::fresh_seed);
 
     if options.log_level > 0 {
         println!("Starting data generation with the following configuration:");
@@ -257,6 +437,9 @@ pub fn gen_data_main(cli_config: DataGenOptionsBuilder) -> anyhow::Result<()> {
             );
         }
     }
+    if options.log_level > 0 {
+        println!("Seeded with {seed}: pass --seed {seed} to generate the same games again.");
+    }
     if let Some(tb_path) = &options.tablebases_path {
         let tb_path = tb_path.to_string_lossy();
         tablebases::probe::init(&tb_path);
@@ -299,9 +482,10 @@ pub fn gen_data_main(cli_config: DataGenOptionsBuilder) -> anyhow::Result<()> {
     let book_positions = book_positions
         .as_deref()
         .map(|book| book.lines().collect::<Vec<_>>());
-    let cursor = AtomicUsize::new(0);
+    if book_positions.as_ref().is_some_and(Vec::is_empty) {
+        bail!("Book file has no positions.");
+    }
     let book_positions = book_positions.as_deref();
-    let cursor = &cursor;
     std::thread::scope(|s| {
         let thread_handles = (0..options.num_threads)
             .map(|id| {
@@ -309,19 +493,15 @@ pub fn gen_data_main(cli_config: DataGenOptionsBuilder) -> anyhow::Result<()> {
                 let path_ref = &data_dir;
                 let nnue_params_ref = &nnue_params;
                 s.spawn(move || {
-                    // this rng is different between each thread
-                    // (https://rust-random.github.io/book/guide-parallel.html)
-                    // so no worries :3
-                    let rng = rand::thread_rng();
                     #[allow(clippy::option_if_let_else)]
                     let startpos_src = if let Some(source) = book_positions {
-                        Box::new(BookStartposGenerator { source, cursor }) as Box<_>
+                        Box::new(BookStartposGenerator { source }) as Box<_>
                     } else if opt_ref.generate_dfrc {
-                        Box::new(DFRCStartposGenerator { rng }) as Box<_>
+                        Box::new(DFRCStartposGenerator) as Box<_>
                     } else {
-                        Box::new(ClassicalStartposGenerator { rng }) as Box<_>
+                        Box::new(ClassicalStartposGenerator) as Box<_>
                     };
-                    generate_on_thread(id, opt_ref, path_ref, nnue_params_ref, startpos_src)
+                    generate_on_thread(id, opt_ref, seed, path_ref, nnue_params_ref, startpos_src)
                 })
             })
             .collect::<Vec<_>>();
@@ -376,6 +556,7 @@ fn print_game_stats(counters: &HashMap<GameOutcome, u64>) {
 fn generate_on_thread<'a>(
     id: usize,
     options: &DataGenOptions,
+    seed: u64,
     data_dir: &Path,
     nnue_params: &NNUEParams,
     mut startpos_src: Box<dyn StartposGenerator + 'a>,
@@ -393,11 +574,22 @@ fn generate_on_thread<'a>(
         },
     });
     let nodes = AtomicU64::new(0);
+    let mut engine = options
+        .engine
+        .as_deref()
+        .map(|path| UciEngine::spawn(path, &options.engine_options))
+        .transpose()?;
+    if let Some(engine) = &engine {
+        if id == 0 && options.log_level > 0 {
+            println!("Choosing and labelling moves with {}", engine.name());
+        }
+    }
     let mut info = SearchInfo {
         time_manager,
         print_to_stdout: false,
         ..SearchInfo::new(&stopped, &nodes)
     };
+    let mut adjudicator = Adjudicator::new(options.resign, options.draw);
 
     let n_games_to_run = std::cmp::max(options.num_games / options.num_threads, 1);
 
@@ -456,47 +648,75 @@ fn generate_on_thread<'a>(
         }
         // reset everything: board, thread data, tt, search info
         tt.clear(1);
+        thread_data.clear_tables();
         info.set_up_for_search();
+        // every game draws its randomness from its own generator, seeded by its index,
+        // so that it can be reproduced without replaying the games before it.
+        let index = id * n_games_to_run + game;
+        let mut rng = StdRng::seed_from_u64(rng::seed_for(seed, index as u64));
         // generate game
         // STEP 1: get the next starting position from the callback
-        match startpos_src.generate(&mut board, &mut thread_data) {
+        match startpos_src.generate(&mut board, &mut thread_data, index, &mut rng) {
             ControlFlow::Break(()) => continue 'generation_main_loop,
             ControlFlow::Continue(()) => {}
         }
 
         // STEP 2: evaluate the exit position with reasonable depth
-        // to make sure that it isn't silly.
+        // to make sure that it isn't silly, throwing out hopelessly
+        // lopsided positions cheaply before spending a search on them.
+        if board.evaluate_classical().abs() > CLASSICAL_PREFILTER_MARGIN {
+            continue 'generation_main_loop;
+        }
         let temp_limit = info.time_manager.limit().clone();
         info.time_manager.set_limit(SearchLimit::Depth(10));
         let (eval, _) =
             board.search_position(&mut info, std::array::from_mut(&mut thread_data), tt.view());
         info.time_manager.set_limit(temp_limit);
-        if eval.abs() > 1000 {
+        if eval.get().abs() > 1000 {
             // if the position is too good or too bad, we don't want it
             continue 'generation_main_loop;
         }
         let mut game = Game::new(&board);
+        if let Some(engine) = &mut engine {
+            engine.new_game()?;
+        }
         // STEP 3: play out to the end of the game
-        let mut win_adj_counter = 0;
-        let mut draw_adj_counter = 0;
+        adjudicator.new_game();
+        // the tablebase result of the first tablebase position reached, when relabelling.
+        let mut tb_outcome = None;
         let outcome = loop {
             let outcome = board.outcome();
             if outcome != GameOutcome::Ongoing {
                 break outcome;
             }
-            if options.tablebases_path.is_some() {
+            if options.tablebases_path.is_some() && tb_outcome.is_none() {
                 if let Some(wdl) = tablebases::probe::get_wdl_white(&board) {
-                    break match wdl {
+                    let outcome = match wdl {
                         WDL::Win => GameOutcome::WhiteWin(WinType::TB),
                         WDL::Loss => GameOutcome::BlackWin(WinType::TB),
                         WDL::Draw => GameOutcome::Draw(DrawType::TB),
                     };
+                    if !options.tb_relabel {
+                        break outcome;
+                    }
+                    tb_outcome = Some(outcome);
                 }
             }
             tt.increase_age();
 
-            let (score, best_move) =
-                board.search_position(&mut info, std::array::from_mut(&mut thread_data), tt.view());
+            let ply = game.len() as u64;
+            let limit = options.schedule.limit_for(&options.limit, ply, &mut rng);
+            let (score, best_move) = if let Some(engine) = &mut engine {
+                engine.search(&game, &board, &limit)?
+            } else {
+                info.time_manager.set_limit(limit);
+                let (score, best_move) = board.search_position(
+                    &mut info,
+                    std::array::from_mut(&mut thread_data),
+                    tt.view(),
+                );
+                (score.get(), best_move)
+            };
 
             let Some(best_move) = best_move else {
                 println!("[WARNING!] search returned a null move as the best move!");
@@ -511,29 +731,14 @@ fn generate_on_thread<'a>(
                     .with_context(|| "Failed to convert score into eval.")?,
             );
 
-            let abs_score = score.abs();
-            if abs_score >= 2500 {
-                win_adj_counter += 1;
-                draw_adj_counter = 0;
-            } else if abs_score <= 4 {
-                draw_adj_counter += 1;
-                win_adj_counter = 0;
+            let our_score = if board.turn() == Colour::White {
+                score
             } else {
-                win_adj_counter = 0;
-                draw_adj_counter = 0;
-            }
-
-            if win_adj_counter >= 4 {
-                let outcome = if score > 0 {
-                    GameOutcome::WhiteWin(WinType::Adjudication)
-                } else {
-                    GameOutcome::BlackWin(WinType::Adjudication)
-                };
+                -score
+            };
+            if let Some(outcome) = adjudicator.adjudicate(&board, our_score) {
                 break outcome;
             }
-            if draw_adj_counter >= 12 {
-                break GameOutcome::Draw(DrawType::Adjudication);
-            }
             if is_game_theoretic_score(score) {
                 // if the score is game theoretic, we don't want to play out the rest of the game
                 let is_mate = is_mate_score(score);
@@ -549,13 +754,15 @@ fn generate_on_thread<'a>(
             board.make_move(best_move, &mut thread_data);
         };
         assert_ne!(outcome, GameOutcome::Ongoing, "Game should be over by now.");
+        // the played-out endgame is labelled with the exact result from the tablebases.
+        let outcome = tb_outcome.unwrap_or(outcome);
         // STEP 4: write the game to the output file
         let count = game.len();
         // update with outcome
         game.set_outcome(outcome);
 
         // write to file
-        game.serialise_into(&mut output_buffer)
+        viriformat::write_game(&mut output_buffer, &game)
             .with_context(|| "Failed to serialise game into output buffer.")?;
 
         // increment the counter
@@ -682,6 +889,13 @@ fn config_loop(mut options: DataGenOptions) -> anyhow::Result<DataGenOptions> {
                     eprintln!("Invalid value for dfrc, must be a boolean");
                 }
             }
+            "seed" => {
+                if let Ok(seed) = value.parse::<u64>() {
+                    options.seed = Some(seed);
+                } else {
+                    eprintln!("Invalid value for seed, must be a non-negative integer");
+                }
+            }
             "log_level" => {
                 let log_level = match value.parse::<u8>() {
                     Ok(log_level) => log_level,
@@ -721,7 +935,30 @@ impl Display for DataGenOptions {
                 DataGenLimit::Nodes(nodes) => format!("nodes {nodes}"),
             }
         )?;
+        match self.limit {
+            DataGenLimit::Depth(_) => {
+                writeln!(f, " |> depth jitter: ±{}", self.schedule.depth_jitter)?;
+            }
+            DataGenLimit::Nodes(_) => {
+                let s = &self.schedule;
+                writeln!(
+                    f,
+                    " |> node schedule: {}% to {}% over {} plies, jitter ±{}%",
+                    s.opening_percent, s.endgame_percent, s.ramp_plies, s.node_jitter_percent
+                )?;
+            }
+        }
+        writeln!(f, " |> tb_relabel: {}", self.tb_relabel)?;
+        if let Some(engine) = &self.engine {
+            writeln!(f, " |> engine: {}", engine.display())?;
+            for (name, value) in &self.engine_options {
+                writeln!(f, "    - {name} = {value}")?;
+            }
+        }
         writeln!(f, " |> dfrc: {}", self.generate_dfrc)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, " |> seed: {seed}")?;
+        }
         writeln!(f, " |> log_level: {}", self.log_level)?;
         if self.tablebases_path.is_none() {
             writeln!(
@@ -757,50 +994,91 @@ impl FromStr for DataGenLimit {
     }
 }
 
-/// Unpacks the variable-length game format into either bulletformat or marlinformat records,
-/// filtering as it goes.
-pub fn run_splat(
-    input: &Path,
-    output: &Path,
-    cfg_path: Option<&Path>,
-    marlinformat: bool,
-    limit: Option<usize>,
-) -> anyhow::Result<()> {
-    // check that the input file exists
-    if !input.try_exists()? {
-        bail!("Input file does not exist.");
+/// The file that shard `index` of a splat is written to: the output path itself when
+/// there is only one shard, and the output path with the shard index appended otherwise.
+fn shard_path(output: &Path, index: usize, shards: usize) -> PathBuf {
+    if shards == 1 {
+        return output.to_path_buf();
     }
-    // check that the output does not exist
-    if output.try_exists()? {
-        bail!("Output file already exists.");
+    let mut name = output.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+/// A splat output shard, optionally zstd-compressed.
+enum ShardWriter {
+    Plain(BufWriter<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+}
+
+impl ShardWriter {
+    fn create(path: &Path, compress: bool) -> anyhow::Result<Self> {
+        #[cfg(not(feature = "zstd"))]
+        if compress {
+            bail!("zstd output requires viridithas to be built with the zstd feature.");
+        }
+        let file = BufWriter::new(
+            File::create(path)
+                .with_context(|| format!("Failed to create output file {}", path.display()))?,
+        );
+        #[cfg(feature = "zstd")]
+        if compress {
+            return Ok(Self::Zstd(
+                zstd::stream::write::Encoder::new(file, 0)
+                    .with_context(|| "Failed to create zstd encoder.")?,
+            ));
+        }
+        Ok(Self::Plain(file))
     }
 
-    let filter = cfg_path.map_or_else(|| Ok(Filter::default()), Filter::from_path)?;
-    let mut rng = rand::thread_rng();
+    /// Flush everything to disk, ending the zstd frame if there is one.
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(mut writer) => writer.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
 
-    // open the input file
-    let input_file = File::open(input).with_context(|| "Failed to create input file")?;
-    let mut input_buffer = BufReader::new(input_file);
+impl Write for ShardWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
 
-    // open the output file
-    let output_file = File::create(output).with_context(|| "Failed to create output file")?;
-    let mut output_buffer = BufWriter::new(output_file);
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
 
-    println!("Splatting...");
-    print!("0 games splatted");
-    let mut game_count = 0;
-    let mut move_buffer = Vec::new();
-    while let Ok(game) =
-        dataformat::Game::deserialise_from(&mut input_buffer, std::mem::take(&mut move_buffer))
-    {
+/// Splat each game received into `output`, sampling positions with a generator seeded by
+/// the shard index, so that the same input always splats to the same shards.
+fn splat_shard(
+    games: &std::sync::mpsc::Receiver<Game>,
+    mut output: ShardWriter,
+    index: usize,
+    filter: &Filter,
+    marlinformat: bool,
+) -> anyhow::Result<()> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(index as u64);
+    for game in games {
         if marlinformat {
             game.splat_to_marlinformat(
                 |packed_board| {
-                    output_buffer
+                    output
                         .write_all(&packed_board.as_bytes())
                         .with_context(|| "Failed to write PackedBoard into buffered writer.")
                 },
-                &filter,
+                filter,
                 &mut rng,
             )?;
         } else {
@@ -808,34 +1086,99 @@ pub fn run_splat(
                 |chess_board| {
                     // SAFETY: ChessBoard is composed entirely of integer types, which are safe to transmute into bytes.
                     let bytes = unsafe { std::mem::transmute::<ChessBoard, [u8; 32]>(chess_board) };
-                    output_buffer.write_all(&bytes).with_context(|| {
+                    output.write_all(&bytes).with_context(|| {
                         "Failed to write bulletformat::ChessBoard into buffered writer."
                     })
                 },
-                &filter,
+                filter,
                 &mut rng,
             )?;
         }
-        move_buffer = game.into_move_buffer();
-        game_count += 1;
-        if game_count % 2048 == 0 {
-            print!("\r{game_count} games splatted");
-            std::io::stdout()
-                .flush()
-                .with_context(|| "Failed to flush stdout.")?;
+    }
+    output
+        .finish()
+        .with_context(|| "Failed to flush output buffer to file.")
+}
+
+/// Unpacks the variable-length game format into either bulletformat or marlinformat records,
+/// filtering as it goes. Games are dealt out in turn to `shards` output files, which are
+/// written in parallel and optionally zstd-compressed.
+pub fn run_splat(
+    input: &Path,
+    output: &Path,
+    cfg_path: Option<&Path>,
+    marlinformat: bool,
+    limit: Option<usize>,
+    shards: usize,
+    compress: bool,
+) -> anyhow::Result<()> {
+    // check that the input file exists
+    if !input.try_exists()? {
+        bail!("Input file does not exist.");
+    }
+    if shards == 0 {
+        bail!("Cannot splat into zero shards.");
+    }
+    // check that the outputs do not exist
+    for index in 0..shards {
+        let path = shard_path(output, index, shards);
+        if path.try_exists()? {
+            bail!("Output file {} already exists.", path.display());
         }
-        if let Some(limit) = limit {
-            if game_count >= limit {
+    }
+
+    let filter = cfg_path.map_or_else(|| Ok(Filter::default()), Filter::from_path)?;
+
+    // open the input file
+    let input_file = File::open(input).with_context(|| "Failed to create input file")?;
+    let input_buffer = BufReader::new(input_file);
+
+    // open the output files
+    let writers = (0..shards)
+        .map(|index| ShardWriter::create(&shard_path(output, index, shards), compress))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    println!("Splatting...");
+    print!("0 games splatted");
+    let filter = &filter;
+    let game_count = std::thread::scope(|s| -> anyhow::Result<usize> {
+        let mut senders = Vec::new();
+        let mut handles = Vec::new();
+        for (index, writer) in writers.into_iter().enumerate() {
+            let (sender, receiver) = std::sync::mpsc::sync_channel(256);
+            senders.push(sender);
+            handles
+                .push(s.spawn(move || splat_shard(&receiver, writer, index, filter, marlinformat)));
+        }
+
+        let mut game_count = 0;
+        for game in GameReader::new(input_buffer).map_while(Result::ok) {
+            // games are dealt out in turn, so each shard gets the same games on every run.
+            if senders[game_count % shards].send(game).is_err() {
+                // the shard has stopped, and will report why when it's joined.
+                break;
+            }
+            game_count += 1;
+            if game_count % 2048 == 0 {
+                print!("\r{game_count} games splatted");
+                std::io::stdout()
+                    .flush()
+                    .with_context(|| "Failed to flush stdout.")?;
+            }
+            if limit.is_some_and(|limit| game_count >= limit) {
                 break;
             }
         }
-    }
+        drop(senders);
+        for handle in handles {
+            handle
+                .join()
+                .map_err(|_| anyhow!("Splatting thread panicked."))??;
+        }
+        Ok(game_count)
+    })?;
     println!("\r{game_count} games splatted.");
 
-    output_buffer
-        .flush()
-        .with_context(|| "Failed to flush output buffer to file.")?;
-
     Ok(())
 }
 
@@ -852,7 +1195,7 @@ pub fn run_topgn(input: &Path, output: &Path, limit: Option<usize>) -> anyhow::R
 
     // open the input file
     let input_file = File::open(input).with_context(|| "Failed to create input file")?;
-    let mut input_buffer = BufReader::new(input_file);
+    let input_buffer = BufReader::new(input_file);
 
     // open the output file
     let output_file = File::create(output).with_context(|| "Failed to create output file")?;
@@ -862,8 +1205,8 @@ pub fn run_topgn(input: &Path, output: &Path, limit: Option<usize>) -> anyhow::R
         .file_name()
         .with_context(|| "Failed to get filename.")?
         .to_string_lossy();
-    let make_header = |outcome: WDL, fen: String| {
-        format!(
+    let make_header = |outcome: WDL, fen: String, opening: Option<Opening>| {
+        let mut header = format!(
             r#"[Event "datagen id {}"]
 [Site "NA"]
 [Date "NA"]
@@ -878,23 +1221,26 @@ pub fn run_topgn(input: &Path, output: &Path, limit: Option<usize>) -> anyhow::R
                 WDL::Draw => "1/2-1/2",
             },
             fen
-        )
+        );
+        if let Some(Opening { code, name }) = opening {
+            write!(header, "\n[ECO \"{code}\"]\n[Opening \"{name}\"]").unwrap();
+        }
+        header
     };
 
     println!("Converting to PGN...");
-    let mut move_buffer = Vec::new();
     let mut game_count = 0;
-    while let Ok(game) =
-        dataformat::Game::deserialise_from(&mut input_buffer, std::mem::take(&mut move_buffer))
-    {
+    let mut games = GameReader::new(input_buffer);
+    while let Some(Ok(game)) = games.next() {
         let outcome = game.outcome();
         let mut board = game.initial_position();
-        let header = make_header(outcome, board.to_string());
-        writeln!(output_buffer, "{header}\n").unwrap();
+        let fen = board.to_string();
+        // the moves are written out first, as the opening can only be classified once they're played.
+        let mut movetext = String::new();
         let mut fullmoves = 0;
         for mv in game.moves() {
             if fullmoves % 12 == 0 && board.turn() == Colour::White {
-                writeln!(output_buffer).unwrap();
+                movetext.push('\n');
             }
             let san = board.san(mv).with_context(|| {
                 format!(
@@ -903,13 +1249,15 @@ pub fn run_topgn(input: &Path, output: &Path, limit: Option<usize>) -> anyhow::R
                 )
             })?;
             if board.turn() == Colour::White {
-                write!(output_buffer, "{}. ", board.ply() / 2 + 1).unwrap();
+                write!(movetext, "{}. ", board.ply() / 2 + 1).unwrap();
             } else {
                 fullmoves += 1;
             }
-            write!(output_buffer, "{san} ").unwrap();
+            write!(movetext, "{san} ").unwrap();
             board.make_move_simple(mv);
         }
+        let header = make_header(outcome, fen, board.eco());
+        write!(output_buffer, "{header}\n\n{movetext}").unwrap();
 
         write!(
             output_buffer,
@@ -922,7 +1270,7 @@ pub fn run_topgn(input: &Path, output: &Path, limit: Option<usize>) -> anyhow::R
         )
         .unwrap();
 
-        move_buffer = game.into_move_buffer();
+        games.recycle(game);
         game_count += 1;
         if let Some(limit) = limit {
             if game_count >= limit {
@@ -1005,7 +1353,12 @@ impl From<&Board> for MaterialConfiguration {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// The width of the evaluation buckets in `score_vs_result.csv`, in centipawns.
+const SCORE_BUCKET_WIDTH: i32 = 50;
+/// Evaluations are clamped to this magnitude when compared with game results.
+const SCORE_CLAMP: i32 = 2000;
+
+#[derive(Debug, Clone, PartialEq, Default)]
 struct DataSetStats {
     games: usize,
     length_counts: HashMap<usize, usize>,
@@ -1013,12 +1366,102 @@ struct DataSetStats {
     piece_counts: HashMap<u8, usize>,
     material_counts: HashMap<MaterialConfiguration, usize>,
     pov_king_positions: HashMap<Square, usize>,
+    phase_counts: HashMap<i32, usize>,
+    bucket_counts: [usize; OUTPUT_BUCKETS],
+    /// Counts of white losses, draws, and wins, for each bucket of white-relative evaluation.
+    score_results: HashMap<i32, [usize; 3]>,
+    /// The game length in plies before the first capture, or `None` for games without one.
+    first_capture_counts: HashMap<Option<usize>, usize>,
+    /// Running sums for the correlation between clamped evaluation and game result.
+    correlation: Correlation,
+}
+
+/// Accumulates the sums needed for Pearson's correlation coefficient between two variables.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Correlation {
+    n: f64,
+    x: f64,
+    y: f64,
+    xx: f64,
+    yy: f64,
+    xy: f64,
+}
+
+impl Correlation {
+    fn push(&mut self, x: f64, y: f64) {
+        self.n += 1.0;
+        self.x += x;
+        self.y += y;
+        self.xx += x * x;
+        self.yy += y * y;
+        self.xy += x * y;
+    }
+
+    /// The correlation coefficient, or `None` if either variable never varied.
+    fn coefficient(&self) -> Option<f64> {
+        let covariance = self.n.mul_add(self.xy, -self.x * self.y);
+        let x_spread = self.n.mul_add(self.xx, -self.x * self.x).sqrt();
+        let y_spread = self.n.mul_add(self.yy, -self.y * self.y).sqrt();
+        let spread = x_spread * y_spread;
+        (spread > 0.0).then(|| covariance / spread)
+    }
+}
+
+/// Write `(key, count)` pairs as a two-column CSV file with the given header.
+fn write_counts<K: Display>(
+    path: &str,
+    header: &str,
+    counts: impl IntoIterator<Item = (K, usize)>,
+) -> anyhow::Result<()> {
+    let mut file =
+        BufWriter::new(File::create(path).with_context(|| format!("Failed to create {path}"))?);
+    writeln!(file, "{header}")?;
+    for (key, count) in counts {
+        writeln!(file, "{key},{count}")?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Save a bar chart of `counts` as TGA and BMP images named `name`, with each bar
+/// coloured by its height.
+fn save_histogram(name: &str, counts: &[usize]) {
+    const BAR_WIDTH: usize = 4;
+    const HEIGHT: usize = 256;
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let mut image = Image::zeroed((counts.len() * BAR_WIDTH).max(1), HEIGHT);
+    for (i, &count) in counts.iter().enumerate() {
+        let bar_height = count * HEIGHT / max;
+        #[allow(clippy::cast_possible_truncation)]
+        let colour = image::inferno_colour_map((count * 255 / max) as u8);
+        for x in i * BAR_WIDTH..(i + 1) * BAR_WIDTH - 1 {
+            // rows are stored bottom-up, so bars rise from the first row.
+            for y in 0..bar_height {
+                image.set(x, y, colour);
+            }
+        }
+    }
+    image.save_as_tga(format!("{name}.tga"));
+    image.save_as_bmp(format!("{name}.bmp"));
+}
+
+/// Spread a sparse map of counts over every key between its least and greatest, for plotting.
+fn dense_counts<K: Copy + Ord + Hash>(counts: &HashMap<K, usize>) -> Vec<usize>
+where
+    RangeInclusive<K>: Iterator<Item = K>,
+{
+    let (Some(&lo), Some(&hi)) = (counts.keys().min(), counts.keys().max()) else {
+        return Vec::new();
+    };
+    (lo..=hi)
+        .map(|k| counts.get(&k).copied().unwrap_or(0))
+        .collect()
 }
 
-/// Scans a variable-length game format file and prints statistics about it.
+/// Scans a variable-length game format file and prints statistics about it,
+/// optionally plotting histograms of the distributions it writes out.
 #[allow(clippy::too_many_lines)]
-pub fn dataset_stats(dataset_path: &Path) -> anyhow::Result<()> {
-    let mut move_buffer = Vec::new();
+pub fn dataset_stats(dataset_path: &Path, plots: bool) -> anyhow::Result<()> {
     let mut stats = DataSetStats::default();
 
     println!("Scanning dataset at {}", dataset_path.display());
@@ -1040,14 +1483,22 @@ pub fn dataset_stats(dataset_path: &Path) -> anyhow::Result<()> {
         })?
         .len();
 
-    let mut reader =
-        BufReader::new(File::open(dataset_path).with_context(|| "Failed to open dataset.")?);
+    let mut games = GameReader::new(BufReader::new(
+        File::open(dataset_path).with_context(|| "Failed to open dataset.")?,
+    ));
 
-    while let Ok(game) =
-        dataformat::Game::deserialise_from(&mut reader, std::mem::take(&mut move_buffer))
-    {
+    while let Some(Ok(game)) = games.next() {
         stats.games += 1;
         *stats.length_counts.entry(game.len()).or_default() += 1;
+        let (result, result_index) = match game.outcome() {
+            WDL::Loss => (0.0, 0),
+            WDL::Draw => (0.5, 1),
+            WDL::Win => (1.0, 2),
+        };
+        let mut first_capture = None;
+        let mut ply = 0;
+        // positions are visited in the same order as the moves made from them.
+        let mut moves = game.moves();
         game.visit_positions(|position, evaluation| {
             *stats.eval_counts.entry(evaluation).or_default() += 1;
             *stats
@@ -1066,12 +1517,27 @@ pub fn dataset_stats(dataset_path: &Path) -> anyhow::Result<()> {
                 .pov_king_positions
                 .entry(position.king_sq(Colour::Black).flip_rank())
                 .or_default() += 1;
+            *stats.phase_counts.entry(position.phase()).or_default() += 1;
+            stats.bucket_counts[output_bucket(position)] += 1;
+            let clamped = evaluation.clamp(-SCORE_CLAMP, SCORE_CLAMP);
+            stats
+                .score_results
+                .entry(clamped.div_euclid(SCORE_BUCKET_WIDTH) * SCORE_BUCKET_WIDTH)
+                .or_default()[result_index] += 1;
+            stats.correlation.push(f64::from(clamped), result);
+            if first_capture.is_none() && moves.next().is_some_and(|mv| position.is_capture(mv)) {
+                first_capture = Some(ply);
+            }
+            ply += 1;
         });
-        move_buffer = game.into_move_buffer();
+        drop(moves);
+        *stats.first_capture_counts.entry(first_capture).or_default() += 1;
+        games.recycle(game);
 
         // print progress
         if stats.games % 1024 == 0 {
-            let progress = reader
+            let progress = games
+                .get_mut()
                 .stream_position()
                 .with_context(|| "Failed to get stream position.")?;
             let percentage = progress * 100 / file_size;
@@ -1086,48 +1552,65 @@ pub fn dataset_stats(dataset_path: &Path) -> anyhow::Result<()> {
     println!("Statistics for dataset at {}", dataset_path.display());
     println!("Number of games: {}", stats.games);
     println!("Writing length counts to length_counts.csv");
-    let mut length_counts = stats.length_counts.iter().collect::<Vec<_>>();
+    let mut length_counts = stats.length_counts.clone().into_iter().collect::<Vec<_>>();
     length_counts.sort_unstable_by_key(|(length, _)| *length);
-    let mut length_counts_file = BufWriter::new(File::create("length_counts.csv")?);
-    writeln!(length_counts_file, "length,count")?;
-    for (length, count) in length_counts {
-        writeln!(length_counts_file, "{length},{count}")?;
-    }
-    length_counts_file.flush()?;
+    write_counts("length_counts.csv", "length,count", length_counts)?;
     println!("Writing eval counts to eval_counts.csv");
-    let mut eval_counts = stats.eval_counts.into_iter().collect::<Vec<_>>();
+    let mut eval_counts = stats.eval_counts.clone().into_iter().collect::<Vec<_>>();
     eval_counts.sort_unstable_by_key(|(eval, _)| *eval);
-    let mut eval_counts_file = BufWriter::new(File::create("eval_counts.csv")?);
-    writeln!(eval_counts_file, "eval,count")?;
-    for (eval, count) in eval_counts {
-        writeln!(eval_counts_file, "{eval},{count}")?;
-    }
-    eval_counts_file.flush()?;
+    write_counts("eval_counts.csv", "eval,count", eval_counts)?;
     println!("Writing piece counts to piece_counts.csv");
-    let mut piece_counts = stats.piece_counts.into_iter().collect::<Vec<_>>();
+    let mut piece_counts = stats.piece_counts.clone().into_iter().collect::<Vec<_>>();
     piece_counts.sort_unstable_by_key(|(count, _)| *count);
-    let mut piece_counts_file = BufWriter::new(File::create("piece_counts.csv")?);
-    writeln!(piece_counts_file, "men,count")?;
-    for (men, count) in piece_counts {
-        writeln!(piece_counts_file, "{men},{count}")?;
-    }
-    piece_counts_file.flush()?;
+    write_counts("piece_counts.csv", "men,count", piece_counts)?;
     println!("Writing material counts to material_counts.csv");
-    let material_counts = stats.material_counts.into_iter().collect::<Vec<_>>();
-    let mut material_counts_file = BufWriter::new(File::create("material_counts.csv")?);
-    writeln!(material_counts_file, "material,count")?;
-    for (mc, count) in material_counts {
-        writeln!(material_counts_file, "{mc},{count}")?;
-    }
-    material_counts_file.flush()?;
+    write_counts(
+        "material_counts.csv",
+        "material,count",
+        stats.material_counts,
+    )?;
     println!("Writing PoV king positions to pov_king_positions.csv");
-    let pov_king_positions = stats.pov_king_positions.into_iter().collect::<Vec<_>>();
-    let mut pov_king_positions_file = BufWriter::new(File::create("pov_king_positions.csv")?);
-    writeln!(pov_king_positions_file, "square,count")?;
-    for (sq, count) in pov_king_positions {
-        writeln!(pov_king_positions_file, "{sq},{count}", sq = sq.index())?;
+    write_counts(
+        "pov_king_positions.csv",
+        "square,count",
+        stats
+            .pov_king_positions
+            .into_iter()
+            .map(|(sq, count)| (sq.index(), count)),
+    )?;
+    println!("Writing phase counts to phase_counts.csv");
+    let mut phase_counts = stats.phase_counts.clone().into_iter().collect::<Vec<_>>();
+    phase_counts.sort_unstable_by_key(|(phase, _)| *phase);
+    write_counts("phase_counts.csv", "phase,count", phase_counts)?;
+    println!("Writing output bucket counts to bucket_counts.csv");
+    write_counts(
+        "bucket_counts.csv",
+        "bucket,count",
+        stats.bucket_counts.into_iter().enumerate(),
+    )?;
+    println!("Writing first capture plies to first_capture_counts.csv");
+    let mut first_capture_counts = stats
+        .first_capture_counts
+        .iter()
+        .map(|(&ply, &count)| (ply, count))
+        .collect::<Vec<_>>();
+    first_capture_counts.sort_unstable_by_key(|(ply, _)| ply.unwrap_or(usize::MAX));
+    write_counts(
+        "first_capture_counts.csv",
+        "ply,count",
+        first_capture_counts
+            .into_iter()
+            .map(|(ply, count)| (ply.map_or_else(|| "none".into(), |p| p.to_string()), count)),
+    )?;
+    println!("Writing results by evaluation to score_vs_result.csv");
+    let mut score_results = stats.score_results.into_iter().collect::<Vec<_>>();
+    score_results.sort_unstable_by_key(|(eval, _)| *eval);
+    let mut score_results_file = BufWriter::new(File::create("score_vs_result.csv")?);
+    writeln!(score_results_file, "eval,losses,draws,wins")?;
+    for (eval, [losses, draws, wins]) in score_results {
+        writeln!(score_results_file, "{eval},{losses},{draws},{wins}")?;
     }
-    pov_king_positions_file.flush()?;
+    score_results_file.flush()?;
 
     #[allow(clippy::cast_precision_loss)]
     let mean_game_len = ((stats
@@ -1139,6 +1622,37 @@ pub fn dataset_stats(dataset_path: &Path) -> anyhow::Result<()> {
         / stats.games as u128) as f64
         / 1000.0;
     println!("Mean game length: {mean_game_len}");
+    match stats.correlation.coefficient() {
+        Some(r) => println!("Correlation between evaluation and result: {r:.3}"),
+        None => {
+            println!("Correlation between evaluation and result: undefined, as one never varies");
+        }
+    }
+
+    if plots {
+        save_histogram("length_counts", &dense_counts(&stats.length_counts));
+        let eval_buckets =
+            stats
+                .eval_counts
+                .iter()
+                .fold(HashMap::new(), |mut buckets, (&eval, &count)| {
+                    let clamped = eval.clamp(-SCORE_CLAMP, SCORE_CLAMP);
+                    *buckets
+                        .entry(clamped.div_euclid(SCORE_BUCKET_WIDTH))
+                        .or_default() += count;
+                    buckets
+                });
+        save_histogram("eval_counts", &dense_counts(&eval_buckets));
+        save_histogram("piece_counts", &dense_counts(&stats.piece_counts));
+        save_histogram("phase_counts", &dense_counts(&stats.phase_counts));
+        save_histogram("bucket_counts", &stats.bucket_counts);
+        let first_captures = stats
+            .first_capture_counts
+            .iter()
+            .filter_map(|(&ply, &count)| Some((ply?, count)))
+            .collect::<HashMap<_, _>>();
+        save_histogram("first_capture_counts", &dense_counts(&first_captures));
+    }
 
     Ok(())
 }
@@ -1186,24 +1700,24 @@ pub fn dataset_count(path: &Path) -> anyhow::Result<()> {
                 thread_handles.push(s.spawn(move || -> anyhow::Result<(u64, u64, Vec<u64>)> {
                 let file = File::open(&path)?;
                 let len = file.metadata().with_context(|| "Failed to get file metadata!")?.len();
-                let mut reader = BufReader::new(file);
+                let mut games = GameReader::new(BufReader::new(file));
                 let mut count = 0u64;
                 let mut filtered = 0u64;
                 let mut pass_count_buckets = vec![0u64; Game::MAX_SPLATTABLE_GAME_SIZE];
-                let mut move_buffer = Vec::new();
                 loop {
-                    match dataformat::Game::deserialise_from(&mut reader, std::mem::take(&mut move_buffer)) {
-                        Ok(game) => {
+                    match games.next() {
+                        None => break,
+                        Some(Ok(game)) => {
                             count += game.len() as u64;
                             let pass_count = game.filter_pass_count(filter);
                             filtered += pass_count;
                             pass_count_buckets[usize::try_from(pass_count).unwrap().min(Game::MAX_SPLATTABLE_GAME_SIZE - 1)] += 1;
-                            move_buffer = game.into_move_buffer();
+                            games.recycle(game);
                         }
-                        Err(error) => {
+                        Some(Err(error)) => {
                             match error.kind() {
                                 std::io::ErrorKind::UnexpectedEof => {}
-                                _ => eprintln!("[WARN] dataset_count encountered an unexpected error wile reading {file}: {error}\n[WARN] this occured at an offset of {:?} into the file (but probably earlier than this, as we use buffered IO)\n[WARN] for reference, {file} is {} bytes long.", reader.into_inner().stream_position(), len, file = path.file_name().map_or(Cow::Borrowed("<???>"), |oss| oss.to_string_lossy()))
+                                _ => eprintln!("[WARN] dataset_count encountered an unexpected error wile reading {file}: {error}\n[WARN] this occured at an offset of {:?} into the file (but probably earlier than this, as we use buffered IO)\n[WARN] for reference, {file} is {} bytes long.", games.get_mut().get_mut().stream_position(), len, file = path.file_name().map_or(Cow::Borrowed("<???>"), |oss| oss.to_string_lossy()))
                             }
                             break;
                         }
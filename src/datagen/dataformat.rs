@@ -10,13 +10,13 @@ use crate::{
     tablebases::probe::WDL,
 };
 
-use self::marlinformat::{util::I16Le, PackedBoard};
 use anyhow::{anyhow, Context};
 use arrayvec::ArrayVec;
 use rand::prelude::SliceRandom;
 use serde::{Deserialize, Serialize};
 
 mod marlinformat;
+pub mod viriformat;
 
 /// The configuration for a filter that can be applied to a game during unpacking.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -127,10 +127,6 @@ pub struct Game {
     moves: Vec<(Move, marlinformat::util::I16Le)>,
 }
 
-const SEQUENCE_ELEM_SIZE: usize =
-    std::mem::size_of::<Move>() + std::mem::size_of::<marlinformat::util::I16Le>();
-const NULL_TERMINATOR: [u8; SEQUENCE_ELEM_SIZE] = [0; SEQUENCE_ELEM_SIZE];
-
 impl WDL {
     pub fn from_packed(packed: u8) -> Self {
         match packed {
@@ -177,74 +173,6 @@ impl Game {
         self.moves.len()
     }
 
-    /// Serialises the game into a byte stream.
-    pub fn serialise_into(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
-        writer.write_all(&self.initial_position.as_bytes())?;
-        for (mv, eval) in &self.moves {
-            writer.write_all(&mv.inner().to_le_bytes())?;
-            writer.write_all(&eval.get().to_le_bytes())?;
-        }
-        writer.write_all(&NULL_TERMINATOR)?;
-        Ok(())
-    }
-
-    /// Deserialises a game from a byte stream.
-    pub fn deserialise_from(
-        reader: &mut impl std::io::BufRead,
-        buffer: Vec<(Move, marlinformat::util::I16Le)>,
-    ) -> std::io::Result<Self> {
-        let mut initial_position = [0; std::mem::size_of::<marlinformat::PackedBoard>()];
-        reader.read_exact(&mut initial_position)?;
-        let initial_position = PackedBoard::from_bytes(initial_position);
-        #[cfg(debug_assertions)]
-        let (mut real_board, _, _, _) = initial_position.unpack();
-        #[cfg(debug_assertions)]
-        if let Err(problem) = real_board.check_validity() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("marlinformat header malformed: {problem}"),
-            ));
-        }
-        // we allow the caller to give us a pre-allocated buffer as an optimisation
-        let mut moves = buffer;
-        moves.clear();
-        loop {
-            let mut buf = [0; SEQUENCE_ELEM_SIZE];
-            reader.read_exact(&mut buf)?;
-            if buf == NULL_TERMINATOR {
-                break;
-            }
-            let mv = Move::from_raw(u16::from_le_bytes([buf[0], buf[1]]));
-            let Some(mv) = mv else {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "parsed invalid move - move was null (the all-zeroes bitpattern)".to_string(),
-                ));
-            };
-            if !mv.is_valid() || mv.from() == mv.to() {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("parsed invalid move: {mv:?}"),
-                ));
-            }
-            #[cfg(debug_assertions)]
-            if !real_board.legal_moves().contains(&mv) {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("parsed illegal move: {mv:?}"),
-                ));
-            }
-            let eval = I16Le::new(i16::from_le_bytes([buf[2], buf[3]]));
-            moves.push((mv, eval));
-            #[cfg(debug_assertions)]
-            real_board.make_move_simple(mv);
-        }
-        Ok(Self {
-            initial_position,
-            moves,
-        })
-    }
-
     /// Exposes a reference to each position and associated evaluation in the game sequentially, via a callback.
     pub fn visit_positions(&self, mut callback: impl FnMut(&Board, i32)) {
         let (mut board, _, _, _) = self.initial_position.unpack();
@@ -255,6 +183,16 @@ impl Game {
         }
     }
 
+    /// Replaces the evaluation of each position in the game with the one that `f` gives
+    /// for the position and its current evaluation.
+    pub fn map_evals(&mut self, mut f: impl FnMut(&Board, i16) -> i16) {
+        let (mut board, _, _, _) = self.initial_position.unpack();
+        for (mv, eval) in &mut self.moves {
+            *eval = marlinformat::util::I16Le::new(f(&board, eval.get()));
+            board.make_move_simple(*mv);
+        }
+    }
+
     /// Internally counts how many positions would pass the filter in this game.
     pub fn filter_pass_count(&self, filter: &Filter) -> u64 {
         let mut cnt = 0;
@@ -461,8 +399,8 @@ mod tests {
         game.add_move(Move::new(Square::G1, Square::F3), 200);
 
         let mut buf = Vec::new();
-        game.serialise_into(&mut buf).unwrap();
-        let game2 = Game::deserialise_from(&mut buf.as_slice(), Vec::new()).unwrap();
+        viriformat::write_game(&mut buf, &game).unwrap();
+        let game2 = viriformat::read_game(&mut buf.as_slice(), Vec::new()).unwrap();
         assert_eq!(game.initial_position, game2.initial_position);
         assert_eq!(game.moves, game2.moves);
     }
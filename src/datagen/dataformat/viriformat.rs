@@ -0,0 +1,333 @@
+//! Reading and writing games in viriformat, the format that datagen records games in.
+//!
+//! A viriformat file is a sequence of games with no file header. Each game is:
+//!
+//! - a 32-byte marlinformat [`PackedBoard`] holding the position the game began from,
+//!   with the game's result in its `wdl` byte: 2 for a white win, 1 for a draw, and 0
+//!   for a black win. The evaluation and extra bytes of this header are unused.
+//! - one 4-byte record for each move of the game: the move as a little-endian `u16`,
+//!   then the evaluation of the position the move was played in, as a little-endian
+//!   `i16` in centipawns from white's point of view.
+//! - four zero bytes, which end the game.
+//!
+//! Moves are encoded with the origin square in bits 0-5 and the destination square in
+//! bits 6-11, where squares count from a1 = 0 along the ranks to h8 = 63. Bits 14-15
+//! flag the move as en passant (`01`), castling (`10`), or promotion (`11`), and bits
+//! 12-13 give the promotion piece, from knight (`00`) to queen (`11`). Castling moves
+//! go from the king to the rook that it castles with.
+//!
+//! [`GameReader`] streams games out of any [`BufRead`], and [`GameWriter`] appends them
+//! to any [`Write`].
+
+use std::io::{self, BufRead, Write};
+
+use crate::chess::{
+    chessmove::Move,
+    piece::{Colour, PieceType},
+    squareset::SquareSet,
+    types::Rank,
+};
+
+use super::{
+    marlinformat::{util::I16Le, PackedBoard},
+    Game,
+};
+
+/// The size of each move record, and of the terminator that ends a game.
+const RECORD_SIZE: usize = std::mem::size_of::<Move>() + std::mem::size_of::<I16Le>();
+const NULL_TERMINATOR: [u8; RECORD_SIZE] = [0; RECORD_SIZE];
+/// The piece code that marlinformat uses for rooks with castling rights.
+const UNMOVED_ROOK: u8 = 6;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Check that a game header can be unpacked without breaking the board's invariants:
+/// one king per side, castling rooks on their own back rank, and an en passant square,
+/// move counters, and result that are in range. Whether the position is playable is
+/// left to [`Board::validate`](crate::chess::board::Board::validate) once it has been unpacked.
+fn check_header(header: &PackedBoard) -> io::Result<()> {
+    let occupied = SquareSet::from_inner(header.occupancy.get());
+    if occupied.count() > 32 {
+        return Err(invalid_data(format!(
+            "header has {} pieces, more than the 32 that fit",
+            occupied.count()
+        )));
+    }
+    let mut kings = [0; 2];
+    for (i, sq) in occupied.iter().enumerate() {
+        let code = header.pieces.get(i);
+        let colour = Colour::new(code >> 3 != 0);
+        match PieceType::new(code & 0b0111) {
+            Some(PieceType::King) => kings[colour] += 1,
+            Some(_) => {}
+            None if code & 0b0111 == UNMOVED_ROOK && sq.relative_to(colour).rank() == Rank::One => {
+            }
+            None => {
+                return Err(invalid_data(format!(
+                    "header has the invalid piece code {code} on {sq}"
+                )));
+            }
+        }
+    }
+    if kings != [1, 1] {
+        return Err(invalid_data(format!(
+            "header has {} white and {} black kings",
+            kings[0], kings[1]
+        )));
+    }
+    if header.stm_ep_square & 0b0111_1111 > 64 {
+        return Err(invalid_data(
+            "header has an en passant square off the board",
+        ));
+    }
+    if header.halfmove_clock > 100 {
+        return Err(invalid_data(format!(
+            "header has the halfmove clock {}, past the fifty-move limit",
+            header.halfmove_clock
+        )));
+    }
+    if header.fullmove_number.get() == 0 {
+        return Err(invalid_data("header has a move number of zero"));
+    }
+    if header.wdl > PackedBoard::WDL_WIN {
+        return Err(invalid_data(format!(
+            "header has the invalid result {}",
+            header.wdl
+        )));
+    }
+    Ok(())
+}
+
+/// Write `game` in viriformat.
+pub fn write_game(writer: &mut impl Write, game: &Game) -> io::Result<()> {
+    writer.write_all(&game.initial_position.as_bytes())?;
+    for (mv, eval) in &game.moves {
+        writer.write_all(&mv.inner().to_le_bytes())?;
+        writer.write_all(&eval.get().to_le_bytes())?;
+    }
+    writer.write_all(&NULL_TERMINATOR)
+}
+
+/// Read one game in viriformat, storing its moves in `buffer`, which is cleared first.
+/// Reading from the end of the input gives an error of kind [`io::ErrorKind::UnexpectedEof`],
+/// as does a game that the input ends partway through.
+pub fn read_game(reader: &mut impl BufRead, buffer: Vec<(Move, I16Le)>) -> io::Result<Game> {
+    let mut initial_position = [0; std::mem::size_of::<PackedBoard>()];
+    reader.read_exact(&mut initial_position)?;
+    let initial_position = PackedBoard::from_bytes(initial_position);
+    check_header(&initial_position)?;
+    let (real_board, _, _, _) = initial_position.unpack();
+    real_board
+        .validate()
+        .map_err(|e| invalid_data(format!("header is not a playable position: {e}")))?;
+    #[cfg(debug_assertions)]
+    let mut real_board = real_board;
+    #[cfg(debug_assertions)]
+    if let Err(problem) = real_board.check_validity() {
+        return Err(invalid_data(format!(
+            "marlinformat header malformed: {problem}"
+        )));
+    }
+    // we allow the caller to give us a pre-allocated buffer as an optimisation
+    let mut moves = buffer;
+    moves.clear();
+    loop {
+        let mut buf = [0; RECORD_SIZE];
+        reader.read_exact(&mut buf)?;
+        if buf == NULL_TERMINATOR {
+            break;
+        }
+        let mv = Move::from_raw(u16::from_le_bytes([buf[0], buf[1]]));
+        let Some(mv) = mv else {
+            return Err(invalid_data(
+                "parsed invalid move - move was null (the all-zeroes bitpattern)",
+            ));
+        };
+        if !mv.is_valid() {
+            return Err(invalid_data(format!("parsed invalid move: {mv:?}")));
+        }
+        #[cfg(debug_assertions)]
+        if !real_board.legal_moves().contains(&mv) {
+            return Err(invalid_data(format!("parsed illegal move: {mv:?}")));
+        }
+        let eval = I16Le::new(i16::from_le_bytes([buf[2], buf[3]]));
+        moves.push((mv, eval));
+        #[cfg(debug_assertions)]
+        real_board.make_move_simple(mv);
+    }
+    Ok(Game {
+        initial_position,
+        moves,
+    })
+}
+
+/// Streams the games out of a viriformat file, stopping cleanly at the end of the input.
+/// A game that the input ends partway through is reported as an error, after which the
+/// reader yields nothing more.
+pub struct GameReader<R> {
+    reader: R,
+    buffer: Vec<(Move, I16Le)>,
+    done: bool,
+}
+
+impl<R: BufRead> GameReader<R> {
+    pub const fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Hand back a game that is finished with, so that the next one read can reuse its
+    /// allocation.
+    pub fn recycle(&mut self, game: Game) {
+        self.buffer = game.into_move_buffer();
+    }
+
+    /// The underlying reader, for checking progress through the input.
+    pub const fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+}
+
+impl<R: BufRead> Iterator for GameReader<R> {
+    type Item = io::Result<Game>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        // only an input that ends exactly between two games is a clean end.
+        match self.reader.fill_buf() {
+            Ok([]) => {
+                self.done = true;
+                return None;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        let game = read_game(&mut self.reader, std::mem::take(&mut self.buffer));
+        self.done = game.is_err();
+        Some(game)
+    }
+}
+
+/// Writes games to a viriformat file, one after another.
+pub struct GameWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> GameWriter<W> {
+    pub const fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write(&mut self, game: &Game) -> io::Result<()> {
+        write_game(&mut self.writer, game)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    use crate::chess::{board::Board, types::Square};
+
+    fn sample_game() -> Game {
+        let mut game = Game::new(&Board::default());
+        game.add_move(Move::new(Square::E2, Square::E4), 31);
+        game.add_move(Move::new(Square::E7, Square::E5), -314);
+        game.add_move(Move::new(Square::G1, Square::F3), 200);
+        game.set_outcome(crate::chess::board::GameOutcome::Draw(
+            crate::chess::board::DrawType::Repetition,
+        ));
+        game
+    }
+
+    #[test]
+    fn format_spec() {
+        let mut bytes = Vec::new();
+        write_game(&mut bytes, &sample_game()).unwrap();
+
+        assert_eq!(bytes.len(), 32 + 3 * 4 + 4);
+        assert_eq!(&bytes[..32], &Board::default().pack(0, 1, 0).as_bytes());
+        // e2e4 is 12 + 28 * 64, with the evaluation after it.
+        assert_eq!(&bytes[32..36], &[0x0C, 0x07, 31, 0]);
+        assert_eq!(&bytes[36..38], &(52u16 + 36 * 64).to_le_bytes());
+        assert_eq!(&bytes[38..40], &(-314i16).to_le_bytes());
+        assert_eq!(&bytes[44..], &[0; 4]);
+
+        // the header alone unpacks to the starting position.
+        let header = PackedBoard::from_bytes(bytes[..32].try_into().unwrap());
+        check_header(&header).unwrap();
+        assert_eq!(header.unpack().0.to_string(), Board::STARTING_FEN);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut bytes = Vec::new();
+        let mut writer = GameWriter::new(&mut bytes);
+        for _ in 0..3 {
+            writer.write(&sample_game()).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let mut reader = GameReader::new(bytes.as_slice());
+        let mut games = 0;
+        while let Some(game) = reader.next() {
+            let game = game.unwrap();
+            assert_eq!(game.moves, sample_game().moves);
+            reader.recycle(game);
+            games += 1;
+        }
+        assert_eq!(games, 3);
+
+        // a truncated game is an error, not the end of the input.
+        let truncated = &bytes[..bytes.len() - 1];
+        let results = GameReader::new(truncated).collect::<Vec<_>>();
+        assert_eq!(results.len(), 3);
+        assert!(results[..2].iter().all(Result::is_ok));
+        assert!(matches!(&results[2], Err(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn decoder_fuzz() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0x5EED);
+        let mut valid = Vec::new();
+        write_game(&mut valid, &sample_game()).unwrap();
+
+        // random bytes, and a valid game with some bytes corrupted or cut off, must be
+        // either read or rejected, never panic.
+        for _ in 0..20_000 {
+            let mut bytes = if rng.gen_bool(0.5) {
+                (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect()
+            } else {
+                valid.clone()
+            };
+            for _ in 0..rng.gen_range(0..4) {
+                if !bytes.is_empty() {
+                    let i = rng.gen_range(0..bytes.len());
+                    bytes[i] = rng.gen();
+                }
+            }
+            bytes.truncate(rng.gen_range(0..=bytes.len()));
+            for game in GameReader::new(bytes.as_slice()).flatten() {
+                game.visit_positions(|_, _| {});
+                let _ = game.outcome();
+            }
+        }
+    }
+}
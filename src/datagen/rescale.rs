@@ -0,0 +1,286 @@
+//! Rescaling the evaluations in a viriformat dataset, for moving data between networks
+//! whose evaluations are on different scales without generating it again.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    chess::board::Board,
+    datagen::dataformat::viriformat::{GameReader, GameWriter},
+    evaluation::is_game_theoretic_score,
+    tablebases::probe::WDL,
+};
+
+/// The game phase of the starting position, where the last entry of a phase scale vector applies.
+const MAX_PHASE: f64 = 24.0;
+
+/// The range of sigmoid scales that fitting searches.
+const K_SEARCH_RANGE: (f64, f64) = (10.0, 10_000.0);
+
+/// How evaluations beyond the clipping bound are brought within it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClipPolicy {
+    /// Clamp evaluations to the bound.
+    Saturate,
+    /// Squash evaluations smoothly towards the bound, with `bound * tanh(eval / bound)`.
+    Soft,
+}
+
+pub struct RescaleOptions {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// A factor to multiply every evaluation by.
+    pub scale: f64,
+    /// Fit the sigmoid scale of the input's evaluations against its results, and rescale
+    /// them so that this becomes the fitted scale.
+    pub fit_k: Option<f64>,
+    /// Further factors by game phase, spread evenly from the endgame to the opening and
+    /// interpolated between.
+    pub phase_scales: Vec<f64>,
+    /// The largest magnitude an evaluation may have after rescaling.
+    pub clip: Option<i32>,
+    pub clip_policy: ClipPolicy,
+}
+
+/// Counts of white losses, draws, and wins at each white-relative evaluation, leaving out
+/// game-theoretic scores, from which the calibration of a dataset can be measured.
+#[derive(Default)]
+struct Calibration {
+    counts: HashMap<i16, [u64; 3]>,
+}
+
+impl Calibration {
+    fn push(&mut self, eval: i16, result: usize) {
+        if !is_game_theoretic_score(i32::from(eval)) {
+            self.counts.entry(eval).or_default()[result] += 1;
+        }
+    }
+
+    fn positions(&self) -> u64 {
+        self.counts.values().flatten().sum()
+    }
+
+    /// The mean cross-entropy between the results and the win probability `sigmoid(eval / k)`.
+    fn loss(&self, k: f64) -> f64 {
+        #![allow(clippy::cast_precision_loss)]
+        let mut total = 0.0;
+        for (&eval, counts) in &self.counts {
+            let p = (1.0 / (1.0 + (-f64::from(eval) / k).exp())).clamp(1e-9, 1.0 - 1e-9);
+            for (result, &count) in [0.0f64, 0.5, 1.0].into_iter().zip(counts) {
+                total -= count as f64 * result.mul_add(p.ln(), (1.0 - result) * (1.0 - p).ln());
+            }
+        }
+        total / self.positions().max(1) as f64
+    }
+
+    /// The sigmoid scale that best predicts the results, found by golden-section search
+    /// over its logarithm.
+    fn fit_k(&self) -> f64 {
+        let phi = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut lo, mut hi) = (K_SEARCH_RANGE.0.ln(), K_SEARCH_RANGE.1.ln());
+        for _ in 0..64 {
+            let a = hi - phi * (hi - lo);
+            let b = lo + phi * (hi - lo);
+            if self.loss(a.exp()) < self.loss(b.exp()) {
+                hi = b;
+            } else {
+                lo = a;
+            }
+        }
+        f64::midpoint(lo, hi).exp()
+    }
+
+    fn mean_abs_eval(&self) -> f64 {
+        #![allow(clippy::cast_precision_loss)]
+        let total = self
+            .counts
+            .iter()
+            .map(|(&eval, counts)| {
+                f64::from(eval.unsigned_abs()) * counts.iter().sum::<u64>() as f64
+            })
+            .sum::<f64>();
+        total / self.positions().max(1) as f64
+    }
+
+    fn report(&self, label: &str) {
+        let k = self.fit_k();
+        println!(
+            "{label}: {} positions, mean |eval| {:.1}, fitted k {k:.1}, loss at fitted k {:.5}",
+            self.positions(),
+            self.mean_abs_eval(),
+            self.loss(k),
+        );
+    }
+}
+
+const fn result_index(outcome: WDL) -> usize {
+    match outcome {
+        WDL::Loss => 0,
+        WDL::Draw => 1,
+        WDL::Win => 2,
+    }
+}
+
+fn open_games(path: &Path) -> anyhow::Result<GameReader<BufReader<File>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(GameReader::new(BufReader::new(file)))
+}
+
+impl RescaleOptions {
+    /// The factor for a position's phase, interpolated between the nearest two entries.
+    fn phase_scale(&self, board: &Board) -> f64 {
+        #![allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        match self.phase_scales.as_slice() {
+            [] => 1.0,
+            [scale] => *scale,
+            scales => {
+                let position = f64::from(board.phase()) / MAX_PHASE * (scales.len() - 1) as f64;
+                let i = (position.floor() as usize).min(scales.len() - 2);
+                let t = position - i as f64;
+                (scales[i + 1] - scales[i]).mul_add(t, scales[i])
+            }
+        }
+    }
+
+    fn clip(&self, eval: f64) -> f64 {
+        let Some(bound) = self.clip.map(f64::from) else {
+            return eval;
+        };
+        match self.clip_policy {
+            ClipPolicy::Saturate => eval.clamp(-bound, bound),
+            ClipPolicy::Soft => bound * (eval / bound).tanh(),
+        }
+    }
+
+    /// Rescale one evaluation. Game-theoretic scores are left alone unless there is a
+    /// clipping bound, in which case they go to it.
+    fn rescale(&self, board: &Board, eval: i16, factor: f64) -> i16 {
+        #![allow(clippy::cast_possible_truncation)]
+        if is_game_theoretic_score(i32::from(eval)) {
+            return self.clip.map_or(eval, |bound| {
+                let bound = i16::try_from(bound).unwrap_or(i16::MAX);
+                if eval > 0 {
+                    bound
+                } else {
+                    -bound
+                }
+            });
+        }
+        let scaled = f64::from(eval) * factor * self.phase_scale(board);
+        // keep rescaled evaluations clear of the game-theoretic range.
+        let limit = f64::from(crate::evaluation::MINIMUM_TB_WIN_SCORE - 1);
+        self.clip(scaled).round().clamp(-limit, limit) as i16
+    }
+}
+
+/// Rewrite a viriformat dataset with its evaluations rescaled, reporting how well the
+/// evaluations predict the game results before and after.
+pub fn run_rescale(options: &RescaleOptions) -> anyhow::Result<()> {
+    if !options.input.try_exists()? {
+        bail!("Input file does not exist.");
+    }
+    if options.output.try_exists()? {
+        bail!("Output file already exists.");
+    }
+    if options.clip.is_some_and(|bound| bound <= 0) {
+        bail!("The clipping bound must be positive.");
+    }
+
+    let mut factor = options.scale;
+    if let Some(target) = options.fit_k {
+        let mut before = Calibration::default();
+        for game in open_games(&options.input)? {
+            let game = game.with_context(|| "Failed to read a game from the input.")?;
+            let result = result_index(game.outcome());
+            game.visit_positions(|_, eval| before.push(eval.try_into().unwrap_or(0), result));
+        }
+        let k = before.fit_k();
+        if !(K_SEARCH_RANGE.0 * 1.01..K_SEARCH_RANGE.1 / 1.01).contains(&k) {
+            bail!("The input's evaluations don't predict its results well enough to fit k (the fit ran to {k:.1}).");
+        }
+        println!(
+            "Input fits k = {k:.1}, so evaluations are scaled by {:.4} to fit k = {target}",
+            target / k
+        );
+        factor *= target / k;
+    }
+
+    let output = File::create(&options.output)
+        .with_context(|| format!("Failed to create {}", options.output.display()))?;
+    let mut writer = GameWriter::new(BufWriter::new(output));
+    let mut before = Calibration::default();
+    let mut after = Calibration::default();
+    let mut games = open_games(&options.input)?;
+    let mut game_count = 0;
+    while let Some(game) = games.next() {
+        let mut game = game.with_context(|| "Failed to read a game from the input.")?;
+        let result = result_index(game.outcome());
+        game.map_evals(|board, eval| {
+            let rescaled = options.rescale(board, eval, factor);
+            before.push(eval, result);
+            after.push(rescaled, result);
+            rescaled
+        });
+        writer
+            .write(&game)
+            .with_context(|| "Failed to write a game to the output.")?;
+        games.recycle(game);
+        game_count += 1;
+    }
+    writer
+        .flush()
+        .with_context(|| "Failed to flush the output.")?;
+
+    println!("Rescaled {game_count} games.");
+    before.report("Before");
+    after.report("After");
+
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn rescaling() {
+        use super::{Calibration, ClipPolicy, RescaleOptions};
+        use crate::chess::board::Board;
+
+        let mut options = RescaleOptions {
+            input: "".into(),
+            output: "".into(),
+            scale: 1.0,
+            fit_k: None,
+            phase_scales: vec![0.5, 1.0],
+            clip: Some(1000),
+            clip_policy: ClipPolicy::Saturate,
+        };
+        let start = Board::default();
+        let kings = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        // the opening takes the last phase scale, and a bare-kings ending the first.
+        assert_eq!(options.rescale(&start, 100, 2.0), 200);
+        assert_eq!(options.rescale(&kings, 100, 2.0), 100);
+        assert_eq!(options.rescale(&start, 700, 2.0), 1000);
+        assert_eq!(options.rescale(&start, 31_000, 2.0), 1000);
+        options.clip_policy = ClipPolicy::Soft;
+        assert!((900..1000).contains(&options.rescale(&start, 800, 2.0)));
+
+        // results drawn exactly from a sigmoid with k = 300 fit back to it.
+        let mut calibration = Calibration::default();
+        for eval in (-1000..=1000).step_by(10) {
+            let p = 1.0 / (1.0 + (-f64::from(eval) / 300.0).exp());
+            let wins = (p * 1000.0).round() as u64;
+            calibration.counts.insert(eval, [1000 - wins, 0, wins]);
+        }
+        assert!((calibration.fit_k() - 300.0).abs() < 1.0);
+    }
+}
@@ -0,0 +1,251 @@
+//! Driving an external UCI engine, so that datagen can label its games with the
+//! searches of another engine.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::atomic::Ordering,
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::{
+    chess::{board::Board, chessmove::Move, piece::Colour, CHESS960},
+    datagen::dataformat::Game,
+    evaluation::{mate_in, mated_in, MINIMUM_TB_WIN_SCORE},
+    timemgmt::SearchLimit,
+    util::MAX_PLY,
+};
+
+/// An external engine, running as a child process that we talk to over UCI.
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    name: String,
+}
+
+impl UciEngine {
+    /// Start the engine at `path`, and give it `options` as `(name, value)` pairs.
+    pub fn spawn(path: &Path, options: &[(String, String)]) -> anyhow::Result<Self> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to start the engine at {}", path.display()))?;
+        let stdin = child.stdin.take().context("engine has no stdin")?;
+        let stdout = BufReader::new(child.stdout.take().context("engine has no stdout")?);
+        let mut engine = Self {
+            child,
+            stdin,
+            stdout,
+            name: path.display().to_string(),
+        };
+
+        engine.send("uci")?;
+        loop {
+            let line = engine.read_line()?;
+            if line == "uciok" {
+                break;
+            }
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+        }
+        if CHESS960.load(Ordering::Relaxed) {
+            engine.send("setoption name UCI_Chess960 value true")?;
+        }
+        for (name, value) in options {
+            engine.send(&format!("setoption name {name} value {value}"))?;
+        }
+        engine.send("isready")?;
+        engine.wait_for("readyok")?;
+        Ok(engine)
+    }
+
+    /// The name that the engine gave for itself.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Tell the engine that the next search is from a new game.
+    pub fn new_game(&mut self) -> anyhow::Result<()> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok")
+    }
+
+    /// Search the position at the end of `game`, which is `board`, and give back the white-relative
+    /// score and the best move, if the engine found one.
+    pub fn search(
+        &mut self,
+        game: &Game,
+        board: &Board,
+        limit: &SearchLimit,
+    ) -> anyhow::Result<(i32, Option<Move>)> {
+        let moves = game.moves().collect::<Vec<_>>();
+        self.search_line(&game.initial_position(), &moves, board, limit)
+    }
+
+    /// Search `board`, reached by playing `moves` from `start`, and give back the white-relative
+    /// score and the best move, if the engine found one. Only depth, node, and clock limits
+    /// are supported.
+    pub fn search_line(
+        &mut self,
+        start: &Board,
+        moves: &[Move],
+        board: &Board,
+        limit: &SearchLimit,
+    ) -> anyhow::Result<(i32, Option<Move>)> {
+        let chess960 = CHESS960.load(Ordering::Relaxed);
+        let moves = moves
+            .iter()
+            .map(|mv| mv.display(chess960).to_string())
+            .collect::<Vec<_>>();
+        if moves.is_empty() {
+            self.send(&format!("position fen {start}"))?;
+        } else {
+            self.send(&format!("position fen {start} moves {}", moves.join(" ")))?;
+        }
+        match limit {
+            SearchLimit::Depth(depth) => self.send(&format!("go depth {depth}"))?,
+            SearchLimit::SoftNodes { soft_limit, .. } => {
+                self.send(&format!("go nodes {soft_limit}"))?;
+            }
+            &SearchLimit::Dynamic {
+                our_clock,
+                their_clock,
+                our_inc,
+                their_inc,
+                moves_to_go,
+            } => {
+                let ((wtime, winc), (btime, binc)) = if board.turn() == Colour::White {
+                    ((our_clock, our_inc), (their_clock, their_inc))
+                } else {
+                    ((their_clock, their_inc), (our_clock, our_inc))
+                };
+                let movestogo = moves_to_go
+                    .map(|n| format!(" movestogo {n}"))
+                    .unwrap_or_default();
+                self.send(&format!(
+                    "go wtime {wtime} btime {btime} winc {winc} binc {binc}{movestogo}"
+                ))?;
+            }
+            other => bail!("external engines can't be given the search limit {other:?}"),
+        }
+
+        let mut score = 0;
+        loop {
+            let line = self.read_line()?;
+            let mut words = line.split_ascii_whitespace();
+            match words.next() {
+                Some("info") => {
+                    if let Some(s) = parse_score(&line) {
+                        score = s;
+                    }
+                }
+                Some("bestmove") => {
+                    let best_move = match words.next() {
+                        None | Some("(none)" | "0000") => None,
+                        Some(mv) => Some(board.parse_uci(mv).with_context(|| {
+                            format!("{} gave the illegal move {mv} in {board}", self.name)
+                        })?),
+                    };
+                    let score = if board.turn() == Colour::White {
+                        score
+                    } else {
+                        -score
+                    };
+                    return Ok((score, best_move));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn send(&mut self, command: &str) -> anyhow::Result<()> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|()| self.stdin.flush())
+            .with_context(|| format!("Failed to send \"{command}\" to {}", self.name))
+    }
+
+    fn read_line(&mut self) -> anyhow::Result<String> {
+        let mut line = String::new();
+        let read = self
+            .stdout
+            .read_line(&mut line)
+            .with_context(|| format!("Failed to read from {}", self.name))?;
+        if read == 0 {
+            return Err(anyhow!("{} exited unexpectedly", self.name));
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    fn wait_for(&mut self, reply: &str) -> anyhow::Result<()> {
+        while self.read_line()? != reply {}
+        Ok(())
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        if self.send("quit").is_err() || self.child.wait().is_err() {
+            let _ = self.child.kill();
+        }
+    }
+}
+
+/// Read the side-to-move relative score out of an `info` line, bringing centipawn scores
+/// within the range that our own search gives for scores that aren't game-theoretic.
+fn parse_score(line: &str) -> Option<i32> {
+    let mut words = line
+        .split_ascii_whitespace()
+        .skip_while(|&w| w != "score")
+        .skip(1);
+    let kind = words.next()?;
+    let value = words.next()?.parse::<i32>().ok()?;
+    if words
+        .next()
+        .is_some_and(|w| w == "lowerbound" || w == "upperbound")
+    {
+        return None;
+    }
+    match kind {
+        "cp" => Some(value.clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1)),
+        "mate" => {
+            let ply = (value.unsigned_abs() as usize * 2).min(MAX_PLY);
+            Some(if value > 0 {
+                mate_in(ply - 1)
+            } else {
+                mated_in(ply)
+            })
+        }
+        _ => None,
+    }
+}
+
+mod tests {
+    #[test]
+    fn score_parsing() {
+        use super::parse_score;
+        use crate::evaluation::{mate_in, mated_in, MINIMUM_TB_WIN_SCORE};
+
+        assert_eq!(
+            parse_score("info depth 10 seldepth 14 score cp 35 nodes 1000 pv e2e4"),
+            Some(35)
+        );
+        assert_eq!(
+            parse_score("info depth 10 score cp -35 lowerbound nodes 1000"),
+            None
+        );
+        assert_eq!(
+            parse_score("info depth 30 score cp 40000"),
+            Some(MINIMUM_TB_WIN_SCORE - 1)
+        );
+        assert_eq!(parse_score("info depth 5 score mate 2"), Some(mate_in(3)));
+        assert_eq!(parse_score("info depth 5 score mate -1"), Some(mated_in(2)));
+        assert_eq!(parse_score("info string hello"), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! Verifying a viriformat dataset by replaying its games, so that corrupt datagen output
+//! is found before a network is trained on it.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Seek},
+    path::Path,
+    sync::atomic::Ordering,
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    chess::{board::GameOutcome, CHESS960},
+    datagen::dataformat::{
+        viriformat::{GameReader, GameWriter},
+        Game,
+    },
+    tablebases::probe::WDL,
+};
+
+/// Replay `game` move by move, checking that every move is legal, that the incrementally
+/// updated key agrees with the key generated from scratch, that the game doesn't carry on
+/// past its end, and that a game which ends in mate or a draw by rule has that result.
+fn check_game(game: &Game) -> anyhow::Result<()> {
+    let chess960 = CHESS960.load(Ordering::Relaxed);
+    let mut board = game.initial_position();
+    for (ply, mv) in game.moves().enumerate() {
+        let outcome = board.outcome();
+        if outcome != GameOutcome::Ongoing {
+            bail!("carries on at ply {ply} after the game ended in {outcome:?}");
+        }
+        if !board.legal_moves().contains(&mv) {
+            bail!(
+                "has the illegal move {} at ply {ply}, in {board}",
+                mv.display(chess960)
+            );
+        }
+        board.make_move_simple(mv);
+        if board.zobrist_key() != board.generate_pos_keys().0 {
+            bail!(
+                "has a mismatched key after the move {} at ply {ply}",
+                mv.display(chess960)
+            );
+        }
+    }
+    let expected = match board.outcome() {
+        GameOutcome::WhiteWin(_) => WDL::Win,
+        GameOutcome::BlackWin(_) => WDL::Loss,
+        GameOutcome::Draw(_) => WDL::Draw,
+        GameOutcome::Ongoing => return Ok(()),
+    };
+    if game.outcome() != expected {
+        bail!(
+            "is recorded as {:?}, but ends in {:?}",
+            game.outcome(),
+            board.outcome()
+        );
+    }
+    Ok(())
+}
+
+/// Check every game in `input`, reporting each corrupt one with its byte offset, and
+/// optionally write the games that pass to `output`.
+pub fn run_verify(input: &Path, output: Option<&Path>) -> anyhow::Result<()> {
+    let file = File::open(input).with_context(|| format!("Failed to open {}", input.display()))?;
+    let file_len = file.metadata()?.len();
+    let mut reader = GameReader::new(BufReader::new(file));
+    let mut writer = match output {
+        Some(path) => {
+            if path.try_exists()? {
+                bail!("Output file already exists.");
+            }
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            Some(GameWriter::new(BufWriter::new(file)))
+        }
+        None => None,
+    };
+
+    let mut games = 0;
+    let mut corrupt = 0;
+    let mut offset = 0;
+    while let Some(game) = reader.next() {
+        let game = match game {
+            Ok(game) => game,
+            Err(e) => {
+                // without a valid game to measure, there's no telling where the next one begins.
+                println!(
+                    "game {games} at byte {offset}: unreadable ({e}), so the last {} bytes are unchecked",
+                    file_len - offset
+                );
+                corrupt += 1;
+                break;
+            }
+        };
+        if let Err(problem) = check_game(&game) {
+            println!("game {games} at byte {offset}: {problem}");
+            corrupt += 1;
+        } else if let Some(writer) = &mut writer {
+            writer.write(&game)?;
+        }
+        games += 1;
+        offset = reader.get_mut().stream_position()?;
+        reader.recycle(game);
+    }
+    if let Some(writer) = &mut writer {
+        writer.flush()?;
+    }
+
+    println!("Checked {games} games, {corrupt} of which are corrupt.");
+    if corrupt > 0 {
+        if let Some(path) = output {
+            println!(
+                "The {} games that passed were written to {}",
+                games - corrupt,
+                path.display()
+            );
+        }
+        bail!("{} has {corrupt} corrupt games.", input.display());
+    }
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn replay_checks() {
+        use super::check_game;
+        use crate::{
+            chess::{
+                board::{Board, GameOutcome, WinType},
+                chessmove::Move,
+                types::Square,
+            },
+            datagen::dataformat::Game,
+        };
+
+        // fool's mate, recorded as a black win.
+        let fools_mate = |outcome| {
+            let mut board = Board::default();
+            let mut game = Game::new(&board);
+            for san in ["f3", "e5", "g4", "Qh4#"] {
+                let mv = board.parse_san(san).unwrap();
+                game.add_move(mv, 0);
+                board.make_move_simple(mv);
+            }
+            game.set_outcome(outcome);
+            game
+        };
+        assert!(check_game(&fools_mate(GameOutcome::BlackWin(WinType::Mate))).is_ok());
+        assert!(check_game(&fools_mate(GameOutcome::WhiteWin(WinType::Adjudication))).is_err());
+
+        let mut past_the_end = fools_mate(GameOutcome::BlackWin(WinType::Mate));
+        past_the_end.add_move(Move::new(Square::E1, Square::F2), 0);
+        assert!(check_game(&past_the_end).is_err());
+
+        let mut illegal = Game::new(&Board::default());
+        illegal.add_move(Move::new(Square::E2, Square::E5), 0);
+        assert!(check_game(&illegal).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Detection of transpositions across a set of games or positions, for opening preparation
+//! and for checking datasets for duplicated positions.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use anyhow::Context;
+
+use crate::{
+    chess::{board::Board, chessmove::Move, piece::Colour, CHESS960},
+    evalfile::fen_from_epd,
+    evaluation::Value,
+    nnue::network::NNUEParams,
+    notation::read_pgn,
+    searchinfo::SearchInfo,
+    threadlocal::ThreadData,
+    timemgmt::SearchLimit,
+    transpositiontable::{Bound, TTView, TT},
+    util::MEGABYTE,
+};
+
+/// Where a position came from in the input.
+#[derive(Clone, Copy, Debug)]
+enum Origin {
+    Game { game: usize, ply: usize },
+    Line(usize),
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Game { game, ply } => write!(f, "game {game} ply {ply}"),
+            Self::Line(line) => write!(f, "line {line}"),
+        }
+    }
+}
+
+/// A position from the input, along with the key of the position that preceded it in its game.
+struct Position {
+    origin: Origin,
+    board: Board,
+    parent: Option<u64>,
+}
+
+/// What we know about a position the first time we see it.
+struct Seen {
+    origin: Origin,
+    parent: Option<u64>,
+    analysis: Option<(Value, Option<Move>)>,
+}
+
+fn read_positions(input: &Path) -> anyhow::Result<Vec<Position>> {
+    let text = std::fs::read_to_string(input)
+        .with_context(|| format!("Failed to read {}", input.display()))?;
+    let mut positions = Vec::new();
+    if input.extension().is_some_and(|ext| ext == "pgn") {
+        for (game, pgn) in read_pgn(&text).into_iter().enumerate() {
+            let game = game + 1;
+            let mut board = Board::default();
+            if let Some(fen) = &pgn.fen {
+                board
+                    .set_from_fen(fen)
+                    .with_context(|| format!("Invalid FEN tag in game {game}: {fen}"))?;
+            }
+            positions.push(Position {
+                origin: Origin::Game { game, ply: 0 },
+                board: board.clone(),
+                parent: None,
+            });
+            for (ply, san) in pgn.moves.iter().enumerate() {
+                let parent = board.zobrist_key();
+                let mv = board
+                    .parse_san(san)
+                    .with_context(|| format!("In game {game} at ply {}", ply + 1))?;
+                board.make_move_simple(mv);
+                positions.push(Position {
+                    origin: Origin::Game { game, ply: ply + 1 },
+                    board: board.clone(),
+                    parent: Some(parent),
+                });
+            }
+        }
+    } else {
+        for (line, text) in text.lines().enumerate() {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let mut board = Board::default();
+            let fen = fen_from_epd(text);
+            board
+                .set_from_fen(&fen)
+                .with_context(|| format!("Invalid FEN on line {}: {fen}", line + 1))?;
+            positions.push(Position {
+                origin: Origin::Line(line + 1),
+                board,
+                parent: None,
+            });
+        }
+    }
+    Ok(positions)
+}
+
+/// Searches each new position to a fixed depth, with one transposition table kept
+/// across the whole batch so that earlier analysis is reused where positions meet.
+struct Analyser<'a> {
+    depth: i32,
+    tt: TTView<'a>,
+    info: SearchInfo<'a>,
+    thread_data: Vec<ThreadData<'a>>,
+    reused: usize,
+}
+
+impl Analyser<'_> {
+    /// The score of `board` from white's perspective, as the search gives it, and the
+    /// best move.
+    fn analyse(&mut self, board: &mut Board) -> (Value, Option<Move>) {
+        if let Some(hit) = self.tt.probe(board.zobrist_key(), 0) {
+            // only an exact score stands in for a search, and the move guards
+            // against a collision in the table.
+            let reusable = hit.depth >= self.depth
+                && hit.bound == Bound::Exact
+                && hit
+                    .mov
+                    .is_some_and(|m| board.is_pseudo_legal(m) && board.is_legal(m));
+            if reusable {
+                self.reused += 1;
+                // the table holds scores for the side to move.
+                let score = hit.value;
+                let score = if board.turn() == Colour::White {
+                    score
+                } else {
+                    -score
+                };
+                return (score, hit.mov);
+            }
+        }
+        self.info
+            .time_manager
+            .set_limit(SearchLimit::Depth(self.depth));
+        self.info.time_manager.start();
+        board.search_position(&mut self.info, &mut self.thread_data, self.tt)
+    }
+}
+
+/// Report every position in a PGN or EPD file that transposes into, or is a single
+/// reversible move away from, a position seen earlier in the file. With `depth`, each
+/// new position is also searched, and transpositions report the earlier analysis.
+#[allow(clippy::too_many_lines)]
+pub fn run_dedupcheck(input: &Path, depth: Option<usize>, hash_mb: usize) -> anyhow::Result<()> {
+    let positions = read_positions(input)?;
+    let stopped = AtomicBool::new(false);
+    let nodes = AtomicU64::new(0);
+    let mut tt = TT::new();
+    let mut analyser = match depth {
+        Some(depth) => {
+            tt.resize(hash_mb * MEGABYTE);
+            let nnue_params = NNUEParams::decompress_and_alloc()?;
+            Some(Analyser {
+                depth: i32::try_from(depth).with_context(|| "depth is far too large")?,
+                tt: tt.view(),
+                info: SearchInfo {
+                    print_to_stdout: false,
+                    ..SearchInfo::new(&stopped, &nodes)
+                },
+                thread_data: vec![ThreadData::new(
+                    0,
+                    &Board::default(),
+                    tt.view(),
+                    nnue_params,
+                )],
+                reused: 0,
+            })
+        }
+        None => None,
+    };
+    let chess960 = CHESS960.load(Ordering::Relaxed);
+
+    let mut seen = HashMap::<u64, Seen>::new();
+    // positions one reversible move apart share a pawn structure, so this
+    // narrows down the candidates for the cuckoo-table check.
+    let mut by_pawn_key = HashMap::<u64, Vec<u64>>::new();
+    let (mut shared, mut transpositions, mut near) = (0, 0, 0);
+    let total = positions.len();
+    for Position {
+        origin,
+        mut board,
+        parent,
+    } in positions
+    {
+        let key = board.zobrist_key();
+        if let Some(first) = seen.get(&key) {
+            let relation = match origin {
+                Origin::Game { .. } if parent == first.parent => {
+                    // the same line as an earlier game, not a transposition.
+                    shared += 1;
+                    continue;
+                }
+                Origin::Game { .. } => "transposes into",
+                Origin::Line(_) => "duplicates",
+            };
+            transpositions += 1;
+            print!("{origin}: {relation} {} ({board})", first.origin);
+            if let Some((score, Some(mv))) = first.analysis {
+                print!(" [score {score}, best move {}]", mv.display(chess960));
+            }
+            println!();
+            continue;
+        }
+
+        let candidates = by_pawn_key.entry(board.pawn_key()).or_default();
+        let link = candidates
+            .iter()
+            .filter(|&&other| Some(other) != parent)
+            .find_map(|&other| Some((other, board.reversible_move_between(other)?)));
+        if let Some((other, mv)) = link {
+            near += 1;
+            let other = seen[&other].origin;
+            let ours = board
+                .piece_at(mv.from())
+                .is_some_and(|piece| piece.colour() == board.turn());
+            if ours {
+                println!(
+                    "{origin}: {} leads to {other} ({board})",
+                    mv.display(chess960)
+                );
+            } else {
+                println!(
+                    "{origin}: reached by {} from {other} ({board})",
+                    mv.display(chess960)
+                );
+            }
+        }
+        candidates.push(key);
+
+        let analysis = analyser.as_mut().map(|a| a.analyse(&mut board));
+        seen.insert(
+            key,
+            Seen {
+                origin,
+                parent,
+                analysis,
+            },
+        );
+    }
+
+    println!(
+        "{} positions: {} unique, {shared} on shared lines, {transpositions} transpositions, {near} one reversible move from an earlier position",
+        total,
+        seen.len()
+    );
+    if let Some(analyser) = analyser {
+        println!(
+            "analysed {} positions, reusing {} results from the transposition table",
+            seen.len(),
+            analyser.reused
+        );
+    }
+
+    Ok(())
+}
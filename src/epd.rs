@@ -0,0 +1,200 @@
+//! Extended Position Description records, as used by test suites: a position followed by
+//! operations such as `id`, `bm` (best move), `am` (avoid move), `ce` (centipawn
+//! evaluation) and `dm` (direct mate), each written as an opcode, its operands, and a
+//! semicolon.
+
+use std::fmt::{self, Display};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    chess::{board::Board, chessmove::Move},
+    evalfile::fen_from_epd,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Epd {
+    /// The position, as a FEN with move counters filled in if the record had none.
+    pub fen: String,
+    /// The operations, in the order they were given.
+    ops: Vec<(String, Vec<String>)>,
+}
+
+/// Skip over the first whitespace-separated field of `text`, returning it and the rest.
+fn split_field(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+impl Epd {
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        let fen = fen_from_epd(line);
+        let mut rest = line;
+        for _ in 0..4 {
+            rest = split_field(rest).1;
+        }
+        // some records carry move counters, as in a FEN.
+        for _ in 0..2 {
+            let (field, after) = split_field(rest);
+            if field.is_empty() || !field.bytes().all(|b| b.is_ascii_digit()) {
+                break;
+            }
+            rest = after;
+        }
+        // datasets append results after a bar, rather than as operations.
+        if rest.trim_start().starts_with('|') {
+            rest = "";
+        }
+
+        let mut ops = Vec::new();
+        let mut tokens = Vec::new();
+        let mut token = String::new();
+        let mut quoted = false;
+        let mut chars = rest.chars();
+        loop {
+            let c = chars.next();
+            match c {
+                Some('"') => {
+                    quoted = !quoted;
+                    if !quoted {
+                        // an empty string is still an operand.
+                        tokens.push(std::mem::take(&mut token));
+                    }
+                }
+                Some(c) if quoted => token.push(c),
+                Some(';') | None => {
+                    if !token.is_empty() {
+                        tokens.push(std::mem::take(&mut token));
+                    }
+                    let mut tokens = std::mem::take(&mut tokens).into_iter();
+                    if let Some(opcode) = tokens.next() {
+                        ops.push((opcode, tokens.collect()));
+                    }
+                    if c.is_none() {
+                        break;
+                    }
+                }
+                Some(c) if c.is_whitespace() => {
+                    if !token.is_empty() {
+                        tokens.push(std::mem::take(&mut token));
+                    }
+                }
+                Some(c) => token.push(c),
+            }
+        }
+        if quoted {
+            bail!("unterminated string in EPD record {line}");
+        }
+
+        Ok(Self { fen, ops })
+    }
+
+    /// The operands of the first operation with this opcode.
+    pub fn get(&self, opcode: &str) -> Option<&[String]> {
+        self.ops
+            .iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.get("id")?.first().map(String::as_str)
+    }
+
+    /// Replace the operands of an operation, adding it at the end if it isn't present.
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        if let Some((_, old)) = self.ops.iter_mut().find(|(op, _)| op == opcode) {
+            *old = operands;
+        } else {
+            self.ops.push((opcode.to_string(), operands));
+        }
+    }
+
+    /// The moves given as operands to `opcode`, in SAN or UCI notation.
+    pub fn moves(&self, board: &Board, opcode: &str) -> anyhow::Result<Vec<Move>> {
+        let mut board = board.clone();
+        self.get(opcode)
+            .unwrap_or_default()
+            .iter()
+            .map(|text| {
+                board
+                    .parse_san(text)
+                    .or_else(|_| board.parse_uci(text))
+                    .with_context(|| format!("Invalid move {text} in \"{opcode}\" operation"))
+            })
+            .collect()
+    }
+
+    /// Whether `m` is one of the best moves and none of the moves to avoid, or `None`
+    /// if the record has neither `bm` nor `am` operations.
+    pub fn judge(&self, board: &Board, m: Move) -> anyhow::Result<Option<bool>> {
+        if self.get("bm").is_none() && self.get("am").is_none() {
+            return Ok(None);
+        }
+        let best = self.moves(board, "bm")?;
+        let avoid = self.moves(board, "am")?;
+        Ok(Some(
+            (best.is_empty() || best.contains(&m)) && !avoid.contains(&m),
+        ))
+    }
+}
+
+impl Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the move counters aren't part of an EPD record.
+        let fields = self.fen.split_whitespace().take(4).collect::<Vec<_>>();
+        write!(f, "{}", fields.join(" "))?;
+        for (opcode, operands) in &self.ops {
+            // identifiers and comments are always strings.
+            let string_valued = opcode == "id"
+                || (opcode.len() == 2
+                    && opcode.starts_with('c')
+                    && opcode.ends_with(|c: char| c.is_ascii_digit()));
+            write!(f, " {opcode}")?;
+            for operand in operands {
+                if string_valued
+                    || operand.is_empty()
+                    || operand.contains(|c: char| c.is_whitespace() || c == ';')
+                {
+                    write!(f, " \"{operand}\"")?;
+                } else {
+                    write!(f, " {operand}")?;
+                }
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+mod tests {
+    #[test]
+    fn round_trip_and_judge() {
+        use super::Epd;
+        use crate::chess::{board::Board, chessmove::Move, types::Square};
+
+        let line =
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";";
+        let mut epd = Epd::parse(line).unwrap();
+        assert_eq!(
+            epd.fen,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.to_string(), line);
+
+        let board = Board::from_fen(&epd.fen).unwrap();
+        let qg6 = Move::new(Square::G3, Square::G6);
+        let qh4 = Move::new(Square::G3, Square::H4);
+        assert_eq!(epd.judge(&board, qg6).unwrap(), Some(true));
+        assert_eq!(epd.judge(&board, qh4).unwrap(), Some(false));
+
+        epd.set("acd", vec!["12".into()]);
+        assert!(epd.to_string().ends_with("bm Qg6; id \"WAC.001\"; acd 12;"));
+
+        let plain = Epd::parse("8/8/8/4k3/8/8/8/4K2R w K - 3 40").unwrap();
+        assert_eq!(plain.fen, "8/8/8/4k3/8/8/8/4K2R w K - 3 40");
+        assert_eq!(plain.judge(&board, qg6).unwrap(), None);
+    }
+}
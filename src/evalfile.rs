@@ -0,0 +1,161 @@
+//! Batch evaluation of positions with the network, for building datasets and eval-diff studies
+//! without driving the engine over UCI one position at a time.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::Context;
+
+use crate::{
+    chess::board::Board,
+    evaluation::Value,
+    nnue::network::{self, NNUEParams},
+    search::parameters::Config,
+    threadlocal::ThreadData,
+    transpositiontable::TT,
+    util::MEGABYTE,
+};
+
+/// The evaluations of a single position, both from the side to move's perspective.
+#[derive(Clone, Copy, Debug)]
+pub struct Evaluation {
+    /// The raw output of the network, before any scaling.
+    pub raw: i32,
+    /// The static evaluation as used in search.
+    pub eval: Value,
+}
+
+/// Extract a FEN from a line of an EPD file, dropping any trailing operations
+/// and filling in the move counters if they are missing.
+pub fn fen_from_epd(line: &str) -> String {
+    let mut fields = line.split_whitespace();
+    let mut fen = fields.by_ref().take(4).collect::<Vec<_>>();
+    fen.extend(
+        fields
+            .take(2)
+            .take_while(|f| f.bytes().all(|b| b.is_ascii_digit())),
+    );
+    fen.extend(["0", "1"].into_iter().skip(fen.len().saturating_sub(4)));
+    fen.join(" ")
+}
+
+/// Evaluate `fens` across `threads` threads, returning the evaluations in input order.
+pub fn evaluate_fens(
+    fens: &[String],
+    threads: usize,
+    conf: &Config,
+    nnue_params: &NNUEParams,
+) -> anyhow::Result<Vec<Evaluation>> {
+    let mut tt = TT::new();
+    tt.resize(MEGABYTE);
+    let chunk_size = fens.len().div_ceil(threads.max(1)).max(1);
+    std::thread::scope(|s| {
+        let handles = fens
+            .chunks(chunk_size)
+            .enumerate()
+            .map(|(thread_id, chunk)| {
+                let tt = tt.view();
+                s.spawn(move || -> anyhow::Result<Vec<Evaluation>> {
+                    let mut board = Board::default();
+                    // the thread data holds the accumulators and their bucket
+                    // cache, which are reused from one position to the next.
+                    let mut t = ThreadData::new(thread_id, &board, tt, nnue_params);
+                    chunk
+                        .iter()
+                        .map(|fen| {
+                            board
+                                .set_from_fen(fen)
+                                .with_context(|| format!("Failed to parse FEN {fen}"))?;
+                            t.nnue.refresh_from(&board, nnue_params);
+                            let raw = t.nnue.evaluate(
+                                nnue_params,
+                                board.turn(),
+                                network::output_bucket(&board),
+                            );
+                            let eval = board.evaluate(&mut t, conf, 0);
+                            Ok(Evaluation { raw, eval })
+                        })
+                        .collect()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut evaluations = Vec::with_capacity(fens.len());
+        for handle in handles {
+            evaluations.extend(
+                handle
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("evaluation thread panicked"))??,
+            );
+        }
+        Ok(evaluations)
+    })
+}
+
+/// Evaluate every position in an EPD or FEN file, writing CSV to `output` (or stdout).
+pub fn run_evalfile(
+    input: &Path,
+    threads: usize,
+    output: Option<&Path>,
+    conf: &Config,
+) -> anyhow::Result<()> {
+    #![allow(clippy::cast_precision_loss)]
+    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let text = std::fs::read_to_string(input)
+        .with_context(|| format!("Failed to read {}", input.display()))?;
+    let fens = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(fen_from_epd)
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let evaluations = evaluate_fens(&fens, threads, conf, nnue_params)?;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).with_context(|| {
+                format!("Failed to create {}", path.display())
+            })?))
+        }
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    writeln!(writer, "fen,raw,eval")?;
+    for (fen, Evaluation { raw, eval }) in fens.iter().zip(&evaluations) {
+        writeln!(writer, "{fen},{raw},{eval}")?;
+    }
+    writer.flush()?;
+
+    eprintln!(
+        "Evaluated {} positions in {elapsed:.2}s ({:.0} positions/s)",
+        fens.len(),
+        fens.len() as f64 / elapsed.max(f64::EPSILON)
+    );
+
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn epd_lines_become_fens() {
+        use super::fen_from_epd;
+        assert_eq!(
+            fen_from_epd(
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 bm e5; id \"x\";"
+            ),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(
+            fen_from_epd("8/8/8/4k3/8/8/8/4K2R w K - 3 40"),
+            "8/8/8/4k3/8/8/8/4K2R w K - 3 40"
+        );
+        assert_eq!(
+            fen_from_epd("8/8/8/4k3/8/8/8/4K2R w K - 0 1 | 120 | 1.0"),
+            "8/8/8/4k3/8/8/8/4K2R w K - 0 1"
+        );
+    }
+}
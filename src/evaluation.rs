@@ -1,15 +1,75 @@
 // The granularity of evaluation in this engine is in centipawns.
 
+mod classical;
+
+use std::{
+    fmt::{self, Display},
+    ops::{Add, Neg, Sub},
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+};
+
 use crate::{
-    chess::board::Board,
+    chess::board::{movegen, Board},
     chess::chessmove::Move,
     chess::piece::{Colour, Piece, PieceType},
-    nnue::network,
-    search::draw_score,
+    chess::squareset::SquareSet,
+    lookups::PIECE_KEYS,
+    nnue::network::{self, small::SmallNetwork},
+    search::{draw_score, parameters::Config},
     threadlocal::ThreadData,
-    util::{MAX_DEPTH, MAX_PLY},
+    util::{INFINITY, MAX_DEPTH, MAX_PLY, VALUE_NONE},
 };
 
+/// Whether to blend the classical evaluation into the network's at extreme material imbalances.
+pub static EVAL_BLEND: AtomicBool = AtomicBool::new(false);
+/// Whether to evaluate with the classical evaluation alone, as when no network could be loaded.
+pub static CLASSICAL_FALLBACK: AtomicBool = AtomicBool::new(false);
+/// The engine's playing style, from [`-STYLE_MAX`] for solid to [`STYLE_MAX`] for attacking.
+pub static STYLE: AtomicI32 = AtomicI32::new(0);
+
+// Per-output-bucket scale factors for the network's output, out of 1024.
+// Output buckets are indexed by the number of pieces on the board, so these
+// let the scaling be tuned separately for each phase of the game.
+pub const EVAL_SCALE_BUCKET_0: i32 = 1024;
+pub const EVAL_SCALE_BUCKET_1: i32 = 1024;
+pub const EVAL_SCALE_BUCKET_2: i32 = 1024;
+pub const EVAL_SCALE_BUCKET_3: i32 = 1024;
+pub const EVAL_SCALE_BUCKET_4: i32 = 1024;
+pub const EVAL_SCALE_BUCKET_5: i32 = 1024;
+pub const EVAL_SCALE_BUCKET_6: i32 = 1024;
+pub const EVAL_SCALE_BUCKET_7: i32 = 1024;
+
+// Scale factors for endings that are more drawish than their material suggests, out of 1024.
+// Opposite-coloured bishops with nothing but pawns besides:
+pub const OCB_SCALE: i32 = 640;
+// A rook each with nothing but pawns besides:
+pub const ROOK_ENDING_SCALE: i32 = 896;
+// The stronger side having few pawns to win with, where the scale is
+// the base plus so much per pawn, up to 1024:
+pub const PAWN_SCALE_BASE: i32 = 768;
+pub const PAWN_SCALE_PER_PAWN: i32 = 64;
+
+/// The material imbalance (in SEE units) at which the small network takes over from the
+/// full one, when a small network is loaded. This is about a minor piece and two pawns.
+pub const SMALL_NET_THRESHOLD: i32 = 800;
+
+/// The material imbalance (in SEE units) at which blending begins.
+const BLEND_IMBALANCE_START: i32 = 1500;
+/// The further imbalance over which the classical weight ramps up to its maximum.
+const BLEND_IMBALANCE_RANGE: i32 = 1500;
+/// The maximum weight, out of 1024, given to the classical evaluation.
+const BLEND_MAX_WEIGHT: i32 = 512;
+/// The classical weight, out of 1024, added for each piece beyond a side's starting set.
+const BLEND_WEIGHT_PER_EXTRA_PIECE: i32 = 128;
+
+/// The largest style in either direction.
+pub const STYLE_MAX: i32 = 50;
+/// The weight, in centipawns at the largest style, of each attacked square around a king.
+const STYLE_ZONE_WEIGHT: i32 = 6;
+/// The most that the style can move an evaluation: every square around a king attacked,
+/// and the king's own square too.
+const STYLE_MAX_ADJUSTMENT: i32 = 9 * STYLE_ZONE_WEIGHT;
+
 /// The value of checkmate.
 /// To recover depth-to-mate, we subtract depth (ply) from this value.
 /// e.g. if white has a mate in two ply, the output from a depth-5 search will be
@@ -51,6 +111,145 @@ pub const fn is_game_theoretic_score(score: i32) -> bool {
     score.abs() >= MINIMUM_TB_WIN_SCORE
 }
 
+/// A score as the search sees it: from the side to move's point of view, with mate and
+/// tablebase scores counted from the root. Scores stored in the transposition table are
+/// instead counted from the node they were found at, and may only be turned back into a
+/// `Value` through [`Value::from_tt`], so the two can't be mixed up.
+///
+/// The static evaluation, the scores in the transposition table, the score of a principal
+/// variation and the result of a search are all values. The alpha-beta arithmetic within a
+/// node is done on plain `i32`s, which are wrapped again when they leave it.
+///
+/// Arithmetic on values is checked in debug builds, so a score that drifts out of the
+/// range of real scores is caught where it happens rather than where it's stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Value(i32);
+
+impl Value {
+    pub const DRAW: Self = Self(0);
+    /// The absence of a value, as for the static evaluation of a position in check.
+    pub const NONE: Self = Self(VALUE_NONE);
+
+    /// Wrap a score computed by the search, which may be any real score or `VALUE_NONE`.
+    pub const fn new(v: i32) -> Self {
+        debug_assert!(
+            v.abs() <= INFINITY || v == VALUE_NONE,
+            "score is out of range"
+        );
+        Self(v)
+    }
+
+    /// A heuristic score in centipawns, which must not reach into the mate or TB range.
+    pub const fn cp(v: i32) -> Self {
+        debug_assert!(
+            v.abs() < MINIMUM_TB_WIN_SCORE,
+            "centipawn score reaches into the TB range"
+        );
+        Self(v)
+    }
+
+    pub const fn mate_in(ply: usize) -> Self {
+        Self(mate_in(ply))
+    }
+
+    pub const fn mated_in(ply: usize) -> Self {
+        Self(mated_in(ply))
+    }
+
+    pub const fn tb_win_in(ply: usize) -> Self {
+        Self(tb_win_in(ply))
+    }
+
+    pub const fn tb_loss_in(ply: usize) -> Self {
+        Self(tb_loss_in(ply))
+    }
+
+    pub const fn get(self) -> i32 {
+        self.0
+    }
+
+    pub const fn is_none(self) -> bool {
+        self.0 == VALUE_NONE
+    }
+
+    pub const fn is_mate(self) -> bool {
+        is_mate_score(self.0)
+    }
+
+    pub const fn is_game_theoretic(self) -> bool {
+        is_game_theoretic_score(self.0)
+    }
+
+    /// The form in which this value is stored in the transposition table for a node at
+    /// `ply`, with mate and TB scores counted from that node rather than from the root.
+    pub fn to_tt(self, ply: usize) -> i16 {
+        #![allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+        let v = if self.is_none() {
+            self.0
+        } else if self.0 >= MINIMUM_TB_WIN_SCORE {
+            self.0 + ply as i32
+        } else if self.0 <= -MINIMUM_TB_WIN_SCORE {
+            self.0 - ply as i32
+        } else {
+            self.0
+        };
+        v.try_into()
+            .expect("attempted to store a score with value outwith [i16::MIN, i16::MAX] in the transposition table")
+    }
+
+    /// Recover a value stored in the transposition table by a node at `ply`.
+    pub fn from_tt(stored: i16, ply: usize) -> Self {
+        #![allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+        let v = i32::from(stored);
+        Self::new(if v == VALUE_NONE {
+            v
+        } else if v >= MINIMUM_TB_WIN_SCORE {
+            v - ply as i32
+        } else if v <= -MINIMUM_TB_WIN_SCORE {
+            v + ply as i32
+        } else {
+            v
+        })
+    }
+}
+
+impl From<Value> for i32 {
+    fn from(v: Value) -> Self {
+        v.0
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Neg for Value {
+    type Output = Self;
+    fn neg(self) -> Self {
+        debug_assert!(!self.is_none(), "negated VALUE_NONE");
+        Self(-self.0)
+    }
+}
+
+impl Add<i32> for Value {
+    type Output = Self;
+    fn add(self, rhs: i32) -> Self {
+        debug_assert!(!self.is_none(), "added to VALUE_NONE");
+        Self::new(self.0 + rhs)
+    }
+}
+
+impl Sub<i32> for Value {
+    type Output = Self;
+    fn sub(self, rhs: i32) -> Self {
+        debug_assert!(!self.is_none(), "subtracted from VALUE_NONE");
+        Self::new(self.0 - rhs)
+    }
+}
+
 impl Board {
     fn material_scale(&self) -> i32 {
         #![allow(clippy::cast_possible_wrap)]
@@ -61,17 +260,92 @@ impl Board {
             / 32
     }
 
-    pub fn evaluate_nnue(&self, t: &ThreadData) -> i32 {
+    /// The factor, out of 1024, by which to scale down an evaluation of `v`, from the side to
+    /// move's perspective, in endings that are hard to win: opposite-coloured bishops, rook
+    /// endings, and a stronger side with few pawns left and no extra piece to win with.
+    pub fn endgame_scale(&self, v: i32, conf: &Config) -> i32 {
+        #![allow(clippy::cast_possible_wrap)]
+        let pieces = &self.pieces;
+        let white = pieces.occupied_co(Colour::White);
+        let black = pieces.occupied_co(Colour::Black);
+        let knights = pieces.all_knights();
+        let bishops = pieces.all_bishops();
+        let rooks = pieces.all_rooks();
+        let queens = pieces.all_queens();
+
+        let (strong, weak) = if (v >= 0) == (self.turn() == Colour::White) {
+            (white, black)
+        } else {
+            (black, white)
+        };
+        let piece_material = |side: SquareSet| {
+            [
+                (knights, PieceType::Knight),
+                (bishops, PieceType::Bishop),
+                (rooks, PieceType::Rook),
+            ]
+            .into_iter()
+            .map(|(set, piece_type)| piece_type.see_value() * (set & side).count() as i32)
+            .sum::<i32>()
+        };
+        // a side that's a piece up wins without its pawns, and so does a queen.
+        let pawn_scale = if queens.is_empty()
+            && piece_material(strong) - piece_material(weak) < PieceType::Knight.see_value()
+        {
+            let strong_pawns = (pieces.all_pawns() & strong).count() as i32;
+            (conf.pawn_scale_base + conf.pawn_scale_per_pawn * strong_pawns).min(1024)
+        } else {
+            1024
+        };
+
+        let one_each = |set: SquareSet| (set & white).count() == 1 && (set & black).count() == 1;
+        let shape_scale = if knights.is_empty()
+            && rooks.is_empty()
+            && queens.is_empty()
+            && one_each(bishops)
+            && (bishops & SquareSet::LIGHT_SQUARES).count() == 1
+        {
+            conf.ocb_scale
+        } else if knights.is_empty() && bishops.is_empty() && queens.is_empty() && one_each(rooks) {
+            conf.rook_ending_scale
+        } else {
+            1024
+        };
+
+        pawn_scale.min(shape_scale)
+    }
+
+    pub fn evaluate_nnue(&self, t: &ThreadData, conf: &Config) -> Value {
         // get the raw network output
         let output_bucket = network::output_bucket(self);
         let v = t.nnue.evaluate(t.nnue_params, self.turn(), output_bucket);
 
+        // apply the scale factor for this output bucket.
+        let v = v * conf.eval_scale(output_bucket) / 1024;
+
+        self.scale_network_output(v, conf)
+    }
+
+    /// Evaluate the position with the small network, which is only used
+    /// for positions that are clearly decided.
+    pub fn evaluate_small(&self, t: &mut ThreadData, net: &SmallNetwork, conf: &Config) -> Value {
+        t.small_nnue.refresh(net, self);
+        let v = t.small_nnue.evaluate(net, self.turn());
+        self.scale_network_output(v, conf)
+    }
+
+    /// Adjust a network's output for the features of the position that it doesn't judge well.
+    fn scale_network_output(&self, v: i32, conf: &Config) -> Value {
         // scale down the value estimate when there's not much
         // material left - this will incentivize keeping material
         // on the board if we have winning chances, and trading
         // material off if the position is worse for us.
         let v = v * self.material_scale() / 1024;
 
+        // scale down the value in endings that are known to be
+        // hard to win, whatever the material says.
+        let v = v * self.endgame_scale(v, conf) / 1024;
+
         // scale down the value when the fifty-move counter is high.
         // this goes some way toward making viri realise when he's not
         // making progress in a position.
@@ -81,31 +355,152 @@ impl Board {
         // this basically never comes up, but the network will
         // occasionally output OOB values in crazy positions with
         // massive material imbalances.
-        v.clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1)
+        Value::cp(v.clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1))
     }
 
-    pub fn evaluate(&self, t: &mut ThreadData, nodes: u64) -> i32 {
+    /// The absolute difference in material between the two sides, in SEE units.
+    fn material_imbalance(&self) -> i32 {
+        #![allow(clippy::cast_possible_wrap)]
+        let white = self.pieces.occupied_co(Colour::White);
+        let black = self.pieces.occupied_co(Colour::Black);
+        [
+            (self.pieces.all_pawns(), PieceType::Pawn),
+            (self.pieces.all_knights(), PieceType::Knight),
+            (self.pieces.all_bishops(), PieceType::Bishop),
+            (self.pieces.all_rooks(), PieceType::Rook),
+            (self.pieces.all_queens(), PieceType::Queen),
+        ]
+        .into_iter()
+        .map(|(pieces, piece_type)| {
+            piece_type.see_value()
+                * ((pieces & white).count() as i32 - (pieces & black).count() as i32)
+        })
+        .sum::<i32>()
+        .abs()
+    }
+
+    /// The number of pieces beyond those each side starts with: queens past the first,
+    /// and knights, bishops, or rooks past the second. Balanced material can still be far
+    /// from anything the network was trained on, as with four queens each.
+    fn extra_pieces(&self) -> i32 {
+        #![allow(clippy::cast_possible_wrap)]
+        let pieces = &self.pieces;
+        [Colour::White, Colour::Black]
+            .into_iter()
+            .map(|colour| {
+                let ours = pieces.occupied_co(colour);
+                let beyond =
+                    |set: SquareSet, start: u32| (set & ours).count().saturating_sub(start);
+                (beyond(pieces.all_queens(), 1)
+                    + beyond(pieces.all_rooks(), 2)
+                    + beyond(pieces.all_bishops(), 2)
+                    + beyond(pieces.all_knights(), 2)) as i32
+            })
+            .sum()
+    }
+
+    /// The weight, out of 1024, to give the classical evaluation: none for ordinary
+    /// positions, rising with the material imbalance and with the number of extra pieces.
+    fn classical_blend_weight(&self) -> i32 {
+        let excess =
+            (self.material_imbalance() - BLEND_IMBALANCE_START).clamp(0, BLEND_IMBALANCE_RANGE);
+        let imbalance_weight = BLEND_MAX_WEIGHT * excess / BLEND_IMBALANCE_RANGE;
+        let extra_weight = BLEND_WEIGHT_PER_EXTRA_PIECE * self.extra_pieces();
+        imbalance_weight.max(extra_weight).min(BLEND_MAX_WEIGHT)
+    }
+
+    /// Mix in the classical evaluation when the material is so lopsided, or so unusual,
+    /// that the network is unlikely to have seen anything like it in training.
+    fn blend_with_classical(&self, nnue: Value) -> Value {
+        let weight = self.classical_blend_weight();
+        if weight == 0 {
+            return nnue;
+        }
+        let classical = self.evaluate_classical();
+        let v = (nnue.get() * (1024 - weight) + classical * weight) / 1024;
+        Value::cp(v.clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1))
+    }
+
+    pub fn evaluate(&self, t: &mut ThreadData, conf: &Config, nodes: u64) -> Value {
         // detect draw by insufficient material
         if !self.pieces.any_pawns() && self.pieces.is_material_draw() {
-            return if self.turn() == Colour::White {
-                draw_score(t, nodes, self.turn())
-            } else {
-                -draw_score(t, nodes, self.turn())
+            let v = draw_score(t, nodes, self.turn());
+            return Value::cp(if self.turn() == Colour::White { v } else { -v });
+        }
+        let v = if CLASSICAL_FALLBACK.load(Ordering::Relaxed) {
+            let v = self.evaluate_classical();
+            let v = v * (200 - i32::from(self.fifty_move_counter())) / 200;
+            Value::cp(v.clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1))
+        } else {
+            let v = match t.small_net {
+                // the small network is accurate enough for positions this lopsided.
+                Some(net) if self.material_imbalance() >= conf.small_net_threshold => {
+                    self.evaluate_small(t, net, conf)
+                }
+                _ => {
+                    // apply all in-waiting updates to generate a valid
+                    // neural network accumulator state.
+                    t.nnue.force(self, t.nnue_params);
+                    // run the neural network evaluation
+                    self.evaluate_nnue(t, conf)
+                }
             };
+            if EVAL_BLEND.load(Ordering::Relaxed) {
+                self.blend_with_classical(v)
+            } else {
+                v
+            }
+        };
+
+        let style = STYLE.load(Ordering::Relaxed);
+        if style == 0 || t.analyse_mode {
+            return v;
         }
-        // apply all in-waiting updates to generate a valid
-        // neural network accumulator state.
-        t.nnue.force(self, t.nnue_params);
-        // run the neural network evaluation
-        self.evaluate_nnue(t)
+        // the style belongs to the engine, so it's applied for the side it plays.
+        let adjustment = self.style_adjustment(t.stm_at_root, style);
+        let v = if self.turn() == t.stm_at_root {
+            v.get() + adjustment
+        } else {
+            v.get() - adjustment
+        };
+        Value::cp(v.clamp(-MINIMUM_TB_WIN_SCORE + 1, MINIMUM_TB_WIN_SCORE - 1))
+    }
+
+    /// The adjustment, in centipawns for `us`, made by a playing style of `style`.
+    /// An attacking style counts each square around the enemy king that we attack in
+    /// our favour, and a solid style counts each square around our king that the enemy
+    /// attacks against us, so neither style minds the other half of king safety any
+    /// more than the evaluation already does. The adjustment is never more than
+    /// [`STYLE_MAX_ADJUSTMENT`] either way.
+    fn style_adjustment(&self, us: Colour, style: i32) -> i32 {
+        #![allow(clippy::cast_possible_wrap)]
+        let style = style.clamp(-STYLE_MAX, STYLE_MAX);
+        let (attacker, defender) = if style > 0 {
+            (us, us.flip())
+        } else {
+            (us.flip(), us)
+        };
+        let king = self.king_sq(defender);
+        let zone = movegen::king_attacks(king) | king.as_set();
+        let threatened = (zone & self.threats_by(attacker)).count() as i32;
+        let adjustment = style * STYLE_ZONE_WEIGHT * threatened / STYLE_MAX;
+        debug_assert!(adjustment.abs() <= STYLE_MAX_ADJUSTMENT);
+        adjustment
     }
 
     pub fn zugzwang_unlikely(&self) -> bool {
+        // the non-pawn key hashes the king along with the other pieces,
+        // so it holds only the king's key when we have nothing but pawns.
         let stm = self.turn();
-        let us = self.pieces.occupied_co(stm);
-        let kings = self.pieces.all_kings();
-        let pawns = self.pieces.all_pawns();
-        (us & (kings | pawns)) != us
+        let king = Piece::new(stm, PieceType::King);
+        self.non_pawn_key(stm) != PIECE_KEYS[king][self.king_sq(stm)]
+    }
+
+    /// The number of squares around the side to move's king, and the king's own square,
+    /// that the opponent attacks.
+    pub fn king_danger(&self) -> u32 {
+        let king = self.king_sq(self.turn());
+        ((movegen::king_attacks(king) | king.as_set()) & self.threats().all).count()
     }
 
     pub fn estimated_see(&self, m: Move) -> i32 {
@@ -125,3 +520,166 @@ impl Board {
         value
     }
 }
+
+mod tests {
+    #[test]
+    fn drawish_endings_are_scaled_down() {
+        use crate::{chess::board::Board, search::parameters::Config};
+
+        let conf = Config::default();
+        let scale = |fen: &str, v: i32| {
+            let mut board = Board::default();
+            board.set_from_fen(fen).unwrap();
+            board.endgame_scale(v, &conf)
+        };
+
+        // opposite-coloured bishops, with white a pawn up.
+        assert_eq!(
+            scale("4k3/5p2/4b3/8/3P4/4B3/5P2/4K3 w - - 0 1", 100),
+            conf.ocb_scale
+        );
+        // same-coloured bishops aren't scaled for their colour, only for the pawns.
+        assert_eq!(scale("4k3/5p2/3b4/8/3P4/4B3/2P2P2/4K3 w - - 0 1", 100), 960);
+        assert_eq!(
+            scale("4k3/5p2/4r3/8/3P4/4R3/5P2/4K3 w - - 0 1", 100),
+            conf.rook_ending_scale
+        );
+        // black is the stronger side here, with a single pawn.
+        assert_eq!(scale("4k3/5p2/4r3/8/3P4/4R3/P4P2/4K3 w - - 0 1", -100), 832);
+        // the same position mirrored, with black to move: white is stronger.
+        assert_eq!(scale("4k3/p4p2/4r3/3p4/8/4R3/5P2/4K3 b - - 0 1", -100), 832);
+        // a rook up, a single pawn is no obstacle.
+        assert_eq!(scale("4k3/8/8/8/8/8/4P3/R3K3 w - - 0 1", 500), 1024);
+        assert_eq!(
+            scale("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w - - 0 1", 100),
+            1024
+        );
+    }
+
+    #[test]
+    fn small_net_takes_over_in_decided_positions() {
+        use crate::{
+            chess::board::Board,
+            evaluation::Value,
+            nnue::network::{
+                small::{SmallNetwork, PARAMETER_COUNT},
+                NNUEParams,
+            },
+            search::parameters::Config,
+            threadlocal::ThreadData,
+            transpositiontable::TT,
+            util::MEGABYTE,
+        };
+
+        // a small network that scores every position as 400 for the side to move.
+        let mut params = vec![0i16; PARAMETER_COUNT];
+        params[PARAMETER_COUNT - 1] = 255 * 64;
+        let bytes = params.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+        let net = SmallNetwork::from_bytes(&bytes).unwrap();
+
+        let mut conf = Config::default();
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+        let eval = |fen: &str, small_net: Option<&'static SmallNetwork>, conf: &Config| {
+            let board = Board::from_fen(fen).unwrap();
+            let mut t = ThreadData::new(0, &board, tt.view(), nnue_params);
+            t.small_net = small_net;
+            board.evaluate(&mut t, conf, 0)
+        };
+        let net: &'static SmallNetwork = Box::leak(Box::new(net));
+
+        // white is a queen up, well past the threshold.
+        let decided = "3k4/8/8/8/8/8/4P3/3QK3 w - - 0 1";
+        let full = eval(decided, None, &conf);
+        let small = eval(decided, Some(net), &conf);
+        assert_ne!(small, full);
+        assert!(small > Value::DRAW);
+        // below the threshold, the full network is used as before.
+        let balanced = "3qk3/8/8/8/8/8/4P3/3QK3 w - - 0 1";
+        assert_eq!(eval(balanced, Some(net), &conf), eval(balanced, None, &conf));
+        // and raising the threshold above the imbalance hands the decided position back.
+        conf.small_net_threshold = 2000;
+        assert_eq!(eval(decided, Some(net), &conf), full);
+    }
+
+    #[test]
+    fn unusual_material_is_blended() {
+        use crate::chess::board::Board;
+
+        let weight = |fen: &str| {
+            let mut board = Board::default();
+            board.set_from_fen(fen).unwrap();
+            board.classical_blend_weight()
+        };
+
+        assert_eq!(weight(Board::STARTING_FEN), 0);
+        // a queen for a rook isn't unusual enough to distrust the network.
+        assert_eq!(weight("r3k3/pppppppp/8/8/8/8/PPPPPPPP/3QK3 w - - 0 1"), 0);
+        // two queens each, with level material.
+        assert_eq!(
+            weight("qq2k3/8/8/8/8/8/8/QQ2K3 w - - 0 1"),
+            2 * super::BLEND_WEIGHT_PER_EXTRA_PIECE
+        );
+        // three extra queens against a bare king is as far out as it gets.
+        assert_eq!(
+            weight("4k3/8/8/8/8/8/8/QQQQK3 w - - 0 1"),
+            super::BLEND_MAX_WEIGHT
+        );
+    }
+
+    #[test]
+    fn style_weighs_one_side_of_king_safety() {
+        use super::{STYLE_MAX, STYLE_ZONE_WEIGHT};
+        use crate::chess::{board::Board, piece::Colour};
+
+        // the queen attacks f7, h7, and h8 around the black king.
+        let board = Board::from_fen("6k1/8/8/7Q/8/8/8/6K1 w - - 0 1").unwrap();
+        let adjustment = |us, style| board.style_adjustment(us, style);
+
+        assert_eq!(adjustment(Colour::White, 0), 0);
+        assert_eq!(adjustment(Colour::White, STYLE_MAX), 3 * STYLE_ZONE_WEIGHT);
+        assert_eq!(adjustment(Colour::White, -STYLE_MAX), 0);
+        assert_eq!(adjustment(Colour::Black, STYLE_MAX), 0);
+        assert_eq!(
+            adjustment(Colour::Black, -STYLE_MAX),
+            -3 * STYLE_ZONE_WEIGHT
+        );
+        // out-of-range styles are held to the largest.
+        assert_eq!(adjustment(Colour::White, 1000), 3 * STYLE_ZONE_WEIGHT);
+
+        // a king on the edge, with all six squares of its zone attacked.
+        let board = Board::from_fen("4k3/8/8/8/8/8/2q1q3/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            board.style_adjustment(Colour::White, -STYLE_MAX),
+            -6 * STYLE_ZONE_WEIGHT
+        );
+    }
+
+    #[test]
+    fn style_impact_is_bounded() {
+        use super::{STYLE_MAX, STYLE_MAX_ADJUSTMENT};
+        use crate::{
+            chess::{board::Board, piece::Colour},
+            evalfile::fen_from_epd,
+        };
+
+        // across the tactical positions of WAC, where kings are often under fire, no
+        // style moves the evaluation past its bound or against its direction, and a
+        // stronger style never moves it less than a weaker one.
+        let epds = std::fs::read_to_string("epds/wac.epd").unwrap();
+        for epd in epds.lines() {
+            let board = Board::from_fen(&fen_from_epd(epd)).unwrap();
+            for us in [Colour::White, Colour::Black] {
+                let (mut attacking, mut solid) = (0, 0);
+                for style in 1..=STYLE_MAX {
+                    let more_attacking = board.style_adjustment(us, style);
+                    let more_solid = board.style_adjustment(us, -style);
+                    assert!((attacking..=STYLE_MAX_ADJUSTMENT).contains(&more_attacking));
+                    assert!((-STYLE_MAX_ADJUSTMENT..=solid).contains(&more_solid));
+                    (attacking, solid) = (more_attacking, more_solid);
+                }
+            }
+        }
+    }
+}
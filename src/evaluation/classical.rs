@@ -1,9 +1,35 @@
-use crate::{board::evaluation::score::S, cfor, piece::Colour, util::Square};
+//! A small material and piece-square table evaluator, for use when no network is
+//! available, and to steady the network in positions far outside its training data.
 
-use super::PieceSquareTable;
+use crate::chess::{
+    board::Board,
+    piece::{Colour, PieceType},
+    types::Square,
+};
+
+/// A (midgame, endgame) score pair.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct S(i32, i32);
+
+impl S {
+    const NULL: Self = Self(0, 0);
+}
+
+const PIECE_VALUES: [S; 6] = [
+    S(90, 130),
+    S(330, 320),
+    S(350, 340),
+    S(460, 620),
+    S(1000, 1150),
+    S::NULL,
+];
+
+/// The contribution of each piece type to the game phase, which is 24 in the starting position.
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
 
 // Scores are explicit for files A to D, implicitly mirrored for E to H.
-const BONUS: [[[S; 4]; 8]; 7] = [
+const BONUS: [[[S; 4]; 8]; 6] = [
     [[S::NULL; 4]; 8],
     [
         // Knight
@@ -60,7 +86,6 @@ const BONUS: [[[S; 4]; 8]; 7] = [
         [S(241, 99), S(200, 202), S(231, 181), S(200, 160)],
         [S(243, -126), S(340, 121), S(268, 158), S(180, 125)],
     ],
-    [[S::NULL; 4]; 8],
 ];
 
 #[rustfmt::skip]
@@ -76,27 +101,74 @@ const P_BONUS: [[S; 8]; 8] = [
     [ S::NULL; 8 ],
 ];
 
-pub const fn construct_piece_square_table() -> PieceSquareTable {
-    let mut pst = [[S::NULL; 64]; 13];
-    cfor!(let mut colour = 0; colour < 2; colour += 1; {
-        let offset = if colour == Colour::BLACK.inner() { 6 } else { 0 };
-        let multiplier = if colour == Colour::BLACK.inner() { -1 } else { 1 };
-        cfor!(let mut pieces_idx = 0; pieces_idx < 6; pieces_idx += 1; {
-            cfor!(let mut pst_idx = 0; pst_idx < 64; pst_idx += 1; {
-                let pst_idx = Square::new(pst_idx);
-                let sq = if colour == Colour::WHITE.inner() { pst_idx } else { pst_idx.flip_rank() };
-                let r = pst_idx.rank() as usize;
-                let f = pst_idx.file() as usize;
-                let value = if pieces_idx == 0 {
-                    P_BONUS[r][f]
-                } else {
-                    let f = if f >= 4 { 7 - f } else { f };
-                    BONUS[pieces_idx][r][f]
-                };
-                let S(mg, eg) = value;
-                pst[pieces_idx + offset][sq.index()] = S(mg * multiplier, eg * multiplier);
-            });
-        });
-    });
-    pst
+/// The piece-square bonus for a piece on `sq`, relative to the piece's own side.
+fn piece_square_bonus(piece_type: PieceType, sq: Square) -> S {
+    let rank = sq.index() / 8;
+    let file = sq.index() % 8;
+    if piece_type == PieceType::Pawn {
+        P_BONUS[rank][file]
+    } else {
+        BONUS[piece_type.index()][rank][file.min(7 - file)]
+    }
+}
+
+impl Board {
+    /// The game phase, from 24 in the starting position down to 0 when only kings and pawns remain.
+    pub fn phase(&self) -> i32 {
+        #![allow(clippy::cast_possible_wrap)]
+        let phase = PieceType::all()
+            .map(|piece_type| {
+                PHASE_WEIGHTS[piece_type.index()] * self.pieces.of_type(piece_type).count() as i32
+            })
+            .sum::<i32>();
+        // early promotions can take the phase above its starting value.
+        phase.min(MAX_PHASE)
+    }
+
+    /// Evaluate the position with material and piece-square tables, from the side to move's perspective.
+    pub fn evaluate_classical(&self) -> i32 {
+        let mut mg = 0;
+        let mut eg = 0;
+        for sq in Square::all() {
+            let Some(piece) = self.piece_at(sq) else {
+                continue;
+            };
+            let piece_type = piece.piece_type();
+            let value = PIECE_VALUES[piece_type.index()];
+            let bonus = piece_square_bonus(piece_type, sq.relative_to(piece.colour()));
+            let sign = if piece.colour() == Colour::White {
+                1
+            } else {
+                -1
+            };
+            mg += sign * (value.0 + bonus.0);
+            eg += sign * (value.1 + bonus.1);
+        }
+        let phase = self.phase();
+        let v = (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE;
+        if self.turn() == Colour::White {
+            v
+        } else {
+            -v
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn classical_eval_is_symmetric() {
+        use crate::chess::board::Board;
+
+        let mut board = Board::default();
+        assert_eq!(board.evaluate_classical(), 0);
+        board
+            .set_from_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+            .unwrap();
+        let white_up_a_queen = board.evaluate_classical();
+        assert!(white_up_a_queen > 800);
+        board
+            .set_from_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1")
+            .unwrap();
+        assert_eq!(board.evaluate_classical(), -white_up_a_queen);
+    }
 }
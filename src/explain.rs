@@ -0,0 +1,204 @@
+//! Evaluation explanation by ablation: each piece except the kings is taken off the board
+//! in turn and the position evaluated again, so that the change in the evaluation
+//! approximates what the network thinks that piece is worth where it stands.
+
+use std::{
+    fmt::Write as _,
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicU64},
+};
+
+use crate::{
+    chess::{
+        board::Board,
+        piece::{Colour, PieceType},
+        types::{File, Rank, Square},
+    },
+    image::{self, Image},
+    search::{parameters::Config, LMTable},
+    searchinfo::SearchInfo,
+    threadlocal::ThreadData,
+    timemgmt::SearchLimit,
+};
+
+/// The width and height of each square in the TGA heatmap, in pixels.
+const SQUARE_PIXELS: usize = 16;
+
+pub struct Explanation {
+    /// The evaluation of the position, from white's perspective.
+    pub eval: i32,
+    /// How much the evaluation drops when the piece on each square is removed, from
+    /// white's perspective. `None` for empty squares, kings, and pieces that can't be
+    /// removed without leaving the side not to move in check.
+    pub contributions: [Option<i32>; 64],
+}
+
+/// Score a position from white's perspective, with the static evaluation if `depth` is
+/// zero and a search to `depth` otherwise.
+fn score(board: &Board, info: &mut SearchInfo, t: &mut ThreadData, depth: i32) -> i32 {
+    if depth == 0 {
+        t.nnue.reinit_from(board, t.nnue_params);
+        let v = board.evaluate_nnue(t, &info.conf).get();
+        return if board.turn() == Colour::White { v } else { -v };
+    }
+    info.time_manager.set_limit(SearchLimit::Depth(depth));
+    info.time_manager.start();
+    let tt = t.tt;
+    // search a copy, as searching resets the board's height.
+    board
+        .clone()
+        .search_position(info, std::array::from_mut(t), tt)
+        .0
+        .get()
+}
+
+/// Ablate every piece on `board` other than the kings.
+pub fn explain(board: &Board, t: &mut ThreadData, conf: &Config, depth: i32) -> Explanation {
+    let stopped = AtomicBool::new(false);
+    let nodes = AtomicU64::new(0);
+    let mut info = SearchInfo {
+        print_to_stdout: false,
+        conf: conf.clone(),
+        lm_table: LMTable::new(conf),
+        ..SearchInfo::new(&stopped, &nodes)
+    };
+
+    let eval = score(board, &mut info, t, depth);
+    let mut contributions = [None; 64];
+    for sq in Square::all() {
+        let Some(piece) = board.piece_at(sq) else {
+            continue;
+        };
+        if piece.piece_type() == PieceType::King {
+            continue;
+        }
+        let mut ablated = board.clone();
+        ablated.remove_piece(sq);
+        // removing a blocker can expose the king of the side that just moved.
+        let exposed = ablated.sq_attacked(ablated.king_sq(ablated.turn().flip()), ablated.turn());
+        // positions with no moves can't be searched.
+        if exposed || (depth > 0 && ablated.legal_moves().is_empty()) {
+            continue;
+        }
+        contributions[sq] = Some(eval - score(&ablated, &mut info, t, depth));
+    }
+
+    // leave the network state as we found it.
+    t.nnue.reinit_from(board, t.nnue_params);
+
+    Explanation {
+        eval,
+        contributions,
+    }
+}
+
+impl Explanation {
+    /// The largest contribution of any piece, in either direction.
+    fn scale(&self) -> i32 {
+        self.contributions
+            .iter()
+            .flatten()
+            .map(|c| c.abs())
+            .max()
+            .unwrap_or(0)
+            .max(1)
+    }
+
+    /// Map a contribution onto the colour map, with the most negative at the dark end.
+    fn heat(&self, contribution: i32) -> u8 {
+        #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let scale = self.scale();
+        ((contribution + scale) * 255 / (2 * scale)) as u8
+    }
+
+    /// The contributions of every piece, mapped onto the colour map.
+    pub fn heats(&self) -> [Option<u8>; 64] {
+        self.contributions.map(|c| c.map(|c| self.heat(c)))
+    }
+
+    /// Lay out the contributions as a board, coloured with ANSI escape codes.
+    pub fn heatmap(&self, board: &Board) -> String {
+        let mut out = String::new();
+        for rank in Rank::all().rev() {
+            write!(out, " {} ", rank as u8 + 1).unwrap();
+            for file in File::all() {
+                let sq = Square::from_rank_file(rank, file);
+                let Some(piece) = board.piece_at(sq) else {
+                    out.push_str("   .   ");
+                    continue;
+                };
+                let Some(contribution) = self.contributions[sq] else {
+                    write!(out, "   {}   ", piece.char()).unwrap();
+                    continue;
+                };
+                let heat = self.heat(contribution);
+                let bg = image::inferno_colour_map(heat);
+                // dark text on the bright end of the map.
+                let fg = if heat >= 128 { 30 } else { 97 };
+                write!(
+                    out,
+                    "\u{001b}[{fg};48;2;{};{};{}m {}{contribution:+5}\u{001b}[0m",
+                    bg >> 16,
+                    bg >> 8 & 0xFF,
+                    bg & 0xFF,
+                    piece.char()
+                )
+                .unwrap();
+            }
+            out.push('\n');
+        }
+        out.push_str("      a      b      c      d      e      f      g      h\n");
+        out
+    }
+
+    /// Write the contributions as a heatmap image, with empty squares left black.
+    pub fn save_as_tga(&self, path: &Path) {
+        let mut image = Image::zeroed(8 * SQUARE_PIXELS, 8 * SQUARE_PIXELS);
+        for sq in Square::all() {
+            let Some(contribution) = self.contributions[sq] else {
+                continue;
+            };
+            let colour = image::inferno_colour_map(self.heat(contribution));
+            // TGA rows run bottom-up, so the first rank is at the bottom.
+            for y in 0..SQUARE_PIXELS {
+                for x in 0..SQUARE_PIXELS {
+                    image.set(
+                        sq.file() as usize * SQUARE_PIXELS + x,
+                        sq.rank() as usize * SQUARE_PIXELS + y,
+                        colour,
+                    );
+                }
+            }
+        }
+        image.save_as_tga(path);
+    }
+}
+
+mod tests {
+    #[test]
+    fn ablates_every_piece_but_the_kings() {
+        use super::explain;
+        use crate::{
+            chess::{board::Board, types::Square},
+            nnue::network::NNUEParams,
+            search::parameters::Config,
+            threadlocal::ThreadData,
+            transpositiontable::TT,
+            util::MEGABYTE,
+        };
+
+        // without the rook on e2, black could take white's king.
+        let board = Board::from_fen("4r1k1/5ppp/8/8/8/8/4R3/4K3 b - - 0 1").unwrap();
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+        let mut t = ThreadData::new(0, &board, tt.view(), nnue_params);
+        let explanation = explain(&board, &mut t, &Config::default(), 0);
+
+        let ablated = explanation.contributions.iter().flatten().count();
+        assert_eq!(ablated, 4);
+        assert!(explanation.contributions[Square::E2].is_none());
+        assert!(explanation.contributions[Square::G8].is_none());
+        assert!(explanation.contributions[Square::E8].is_some());
+    }
+}
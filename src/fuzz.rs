@@ -0,0 +1,259 @@
+//! A fuzzing harness for the board. Random games and mangled FENs are put through move
+//! generation, making and unmaking moves, evaluation, and FEN, SAN, and UCI round trips,
+//! and every check compares the board with another way of getting the same answer. It
+//! needs no test data and no toolchain, so a build on an unusual platform can be checked
+//! on the machine that will run it.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{ensure, Context};
+
+use crate::{
+    chess::board::{movegen::MoveList, Board},
+    nnue::network::NNUEParams,
+    rng::{self, XorShiftState},
+    search::parameters::Config,
+    threadlocal::ThreadData,
+    transpositiontable::TT,
+    util::MEGABYTE,
+};
+
+/// The longest random game played from the starting position.
+const MAX_GAME_PLIES: u64 = 200;
+/// How many mangled FENs are tried from each position of a game.
+const MANGLES_PER_POSITION: usize = 2;
+/// The characters that a mangled FEN is made from.
+const FEN_ALPHABET: &[u8] = b"pnbrqkPNBRQK12345678/ -wbKQkqacdefgh09";
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The position being checked, for the panic hook to report.
+static CURRENT_FEN: Mutex<String> = Mutex::new(String::new());
+
+#[derive(Default)]
+struct Counts {
+    games: u64,
+    positions: u64,
+    moves: u64,
+    mangled: u64,
+    accepted: u64,
+}
+
+struct Fuzzer<'a> {
+    rng: XorShiftState,
+    conf: Config,
+    /// Updates its network incrementally through each move from a position.
+    t: ThreadData<'a>,
+    /// Evaluates each position from scratch, to compare against `t`.
+    fresh: ThreadData<'a>,
+    counts: Counts,
+}
+
+impl Fuzzer<'_> {
+    fn random_below(&mut self, n: u64) -> u64 {
+        self.rng.next() % n
+    }
+
+    /// Check every invariant of `board`, and of each move from it.
+    fn check_position(&mut self, board: &mut Board) -> anyhow::Result<()> {
+        let fen = board.to_string();
+        if let Ok(mut current) = CURRENT_FEN.lock() {
+            current.clone_from(&fen);
+        }
+        self.counts.positions += 1;
+
+        ensure!(
+            board.all_keys() == board.generate_pos_keys(),
+            "the incrementally updated keys differ from the regenerated ones"
+        );
+        let reparsed = Board::from_fen(&fen).with_context(|| "the board's own FEN is refused")?;
+        ensure!(
+            reparsed.to_string() == fen && reparsed.all_keys() == board.all_keys(),
+            "the FEN doesn't round-trip, giving {reparsed}"
+        );
+
+        let params = self.t.nnue_params;
+        self.t.nnue.reinit_from(board, params);
+
+        let mut legal = board.legal_moves();
+        let mut pseudo_legal = MoveList::new();
+        board.generate_moves(&mut pseudo_legal);
+        let mut filtered = pseudo_legal
+            .iter_moves()
+            .copied()
+            .filter(|&m| board.is_legal(m))
+            .collect::<Vec<_>>();
+        legal.sort_unstable();
+        filtered.sort_unstable();
+        ensure!(
+            legal == filtered,
+            "the legal moves differ from the legal pseudo-legal moves"
+        );
+
+        let keys = board.all_keys();
+        for m in legal {
+            self.counts.moves += 1;
+            let uci = m.display(false).to_string();
+            ensure!(
+                board.is_pseudo_legal(m),
+                "{uci} is generated but not pseudo-legal"
+            );
+            ensure!(
+                board.parse_uci(&uci)? == m,
+                "{uci} doesn't round-trip as UCI"
+            );
+            let san = board.san(m).with_context(|| format!("{uci} has no SAN"))?;
+            ensure!(
+                board.parse_san(&san)? == m,
+                "{san} ({uci}) doesn't round-trip as SAN"
+            );
+
+            ensure!(
+                board.make_move(m, &mut self.t),
+                "{uci} is generated but refused"
+            );
+            ensure!(
+                board.all_keys() == board.generate_pos_keys(),
+                "the keys are wrong after {uci}"
+            );
+            self.t.nnue.force(board, params);
+            self.fresh.nnue.reinit_from(board, params);
+            let (incremental, fresh) = (
+                board.evaluate_nnue(&self.t, &self.conf),
+                board.evaluate_nnue(&self.fresh, &self.conf),
+            );
+            ensure!(
+                incremental == fresh,
+                "after {uci}, the incremental evaluation is {incremental}, but from scratch it is {fresh}"
+            );
+            board.unmake_move(&mut self.t);
+            ensure!(
+                board.to_string() == fen && board.all_keys() == keys,
+                "unmaking {uci} doesn't restore the position"
+            );
+        }
+        Ok(())
+    }
+
+    /// Change one to three characters of `fen`, by replacing, inserting, or deleting.
+    fn mangle(&mut self, fen: &str) -> String {
+        let mut bytes = fen.as_bytes().to_vec();
+        for _ in 0..=self.random_below(3) {
+            #[allow(clippy::cast_possible_truncation)]
+            let i = self.random_below(bytes.len() as u64 + 1) as usize;
+            #[allow(clippy::cast_possible_truncation)]
+            let c = FEN_ALPHABET[self.random_below(FEN_ALPHABET.len() as u64) as usize];
+            match self.random_below(3) {
+                0 if i < bytes.len() => bytes[i] = c,
+                1 => bytes.insert(i, c),
+                _ if i < bytes.len() => {
+                    bytes.remove(i);
+                }
+                _ => bytes.push(c),
+            }
+        }
+        // the alphabet is ASCII, so this never replaces anything.
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// Parse mangled versions of `fen`, checking any that give a playable position.
+    fn check_mangled(&mut self, fen: &str) -> anyhow::Result<()> {
+        for _ in 0..MANGLES_PER_POSITION {
+            let mangled = self.mangle(fen);
+            if let Ok(mut current) = CURRENT_FEN.lock() {
+                current.clone_from(&mangled);
+            }
+            self.counts.mangled += 1;
+            let Ok(mut board) = Board::from_fen(&mangled) else {
+                continue;
+            };
+            if board.validate().is_err() {
+                continue;
+            }
+            self.counts.accepted += 1;
+            self.check_position(&mut board)
+                .with_context(|| format!("in {board}, parsed from the mangled FEN {mangled}"))?;
+        }
+        Ok(())
+    }
+
+    /// Play a random game from the starting position, checking each position on the way.
+    fn play_game(&mut self) -> anyhow::Result<()> {
+        let mut board = Board::default();
+        self.counts.games += 1;
+        for _ in 0..self.random_below(MAX_GAME_PLIES) {
+            self.check_position(&mut board)
+                .with_context(|| format!("in {board}"))?;
+            let fen = board.to_string();
+            self.check_mangled(&fen)?;
+            let moves = board.legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            let m = moves[self.random_below(moves.len() as u64) as usize];
+            board.make_move_simple(m);
+        }
+        Ok(())
+    }
+}
+
+/// Fuzz the board for `seconds`, from `seed` or a fresh seed, stopping at the first
+/// broken invariant.
+pub fn run_fuzz(seconds: u64, seed: Option<u64>) -> anyhow::Result<()> {
+    let seed = seed.unwrap_or_else(rng::fresh_seed);
+    println!("fuzzing for {seconds}s with seed {seed}");
+
+    // name the position that caused a panic, even if the panic aborts the process.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Ok(fen) = CURRENT_FEN.lock() {
+            eprintln!("panicked while fuzzing {fen} (seed {seed})");
+        }
+        default_hook(info);
+    }));
+
+    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let mut tt = TT::new();
+    tt.resize(MEGABYTE);
+    let board = Board::default();
+    let mut fuzzer = Fuzzer {
+        rng: XorShiftState {
+            state: u128::from(rng::mix(seed)) << 64 | 1,
+        },
+        conf: Config::default(),
+        t: ThreadData::new(0, &board, tt.view(), nnue_params),
+        fresh: ThreadData::new(1, &board, tt.view(), nnue_params),
+        counts: Counts::default(),
+    };
+
+    let start = Instant::now();
+    let mut last_report = start;
+    let duration = Duration::from_secs(seconds);
+    while start.elapsed() < duration {
+        fuzzer
+            .play_game()
+            .with_context(|| format!("fuzzing failed (seed {seed})"))?;
+        if last_report.elapsed() >= REPORT_INTERVAL && start.elapsed() < duration {
+            last_report = Instant::now();
+            report(&fuzzer.counts, start.elapsed());
+        }
+    }
+    report(&fuzzer.counts, start.elapsed());
+    println!("no invariants were broken");
+    Ok(())
+}
+
+fn report(counts: &Counts, elapsed: Duration) {
+    println!(
+        "{:>5.0}s: {} games, {} positions, {} moves, {} mangled FENs ({} playable)",
+        elapsed.as_secs_f64(),
+        counts.games,
+        counts.positions,
+        counts.moves,
+        counts.mangled,
+        counts.accepted
+    );
+}
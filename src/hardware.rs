@@ -0,0 +1,136 @@
+//! Detection of the machine we're running on, so that we can suggest `Threads` and `Hash`
+//! values better suited to it than the defaults of one thread and 16 MB.
+
+use std::fmt::{self, Display};
+
+use crate::util::MEGABYTE;
+
+/// Hash we'd like to have per thread, in megabytes.
+const HASH_PER_THREAD_MB: usize = 64;
+/// We never suggest taking more than this fraction of the free memory for hash.
+const MEMORY_FRACTION: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hardware {
+    pub physical_cores: usize,
+    pub logical_cores: usize,
+    /// The size of the largest CPU cache, in bytes, where it can be found.
+    pub cache: Option<usize>,
+    /// The memory available to new processes, in bytes, where it can be found.
+    pub available_memory: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Recommendation {
+    pub threads: usize,
+    pub hash_mb: usize,
+}
+
+/// Parse a cache size as written in sysfs, like `32768K`.
+fn parse_size(text: &str) -> Option<usize> {
+    let text = text.trim();
+    let (digits, unit) = text
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((text, ""), |i| text.split_at(i));
+    let scale = match unit {
+        "" => 1,
+        "K" => 1024,
+        "M" => MEGABYTE,
+        _ => return None,
+    };
+    digits.parse::<usize>().ok().map(|n| n * scale)
+}
+
+/// The largest cache that the first CPU can see.
+fn detect_cache() -> Option<usize> {
+    let entries = std::fs::read_dir("/sys/devices/system/cpu/cpu0/cache").ok()?;
+    entries
+        .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path().join("size")).ok())
+        .filter_map(|size| parse_size(&size))
+        .max()
+}
+
+/// The memory that can be allocated without swapping, from `/proc/meminfo`.
+fn detect_available_memory() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+impl Hardware {
+    pub fn detect() -> Self {
+        Self {
+            physical_cores: num_cpus::get_physical(),
+            logical_cores: num_cpus::get(),
+            cache: detect_cache(),
+            available_memory: detect_available_memory(),
+        }
+    }
+
+    /// One thread per physical core, as hyperthreads share a core's caches and gain us
+    /// little, and enough hash for those threads that the memory can spare.
+    pub fn recommend(&self, max_threads: usize, max_hash_mb: usize) -> Recommendation {
+        let threads = self.physical_cores.clamp(1, max_threads);
+        let mut hash_mb = threads * HASH_PER_THREAD_MB;
+        if let Some(memory) = self.available_memory {
+            hash_mb = hash_mb.min(memory / MEMORY_FRACTION / MEGABYTE);
+        }
+        // the table is fastest to index when its size is a power of two.
+        let hash_mb = match hash_mb.clamp(1, max_hash_mb) {
+            mb if mb.is_power_of_two() => mb,
+            mb => mb.next_power_of_two() / 2,
+        };
+        Recommendation { threads, hash_mb }
+    }
+}
+
+impl Display for Hardware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} physical cores, {} logical cores",
+            self.physical_cores, self.logical_cores
+        )?;
+        if let Some(cache) = self.cache {
+            write!(f, ", {} KB cache", cache / 1024)?;
+        }
+        if let Some(memory) = self.available_memory {
+            write!(f, ", {} MB available", memory / MEGABYTE)?;
+        }
+        Ok(())
+    }
+}
+
+mod tests {
+    #[test]
+    fn recommendations() {
+        use super::{parse_size, Hardware, Recommendation};
+        use crate::util::MEGABYTE;
+
+        assert_eq!(parse_size("32768K\n"), Some(32 * MEGABYTE));
+        assert_eq!(parse_size("48K"), Some(48 * 1024));
+        assert_eq!(parse_size("huge"), None);
+
+        let workstation = Hardware {
+            physical_cores: 8,
+            logical_cores: 16,
+            cache: Some(32 * MEGABYTE),
+            available_memory: Some(32 * 1024 * MEGABYTE),
+        };
+        assert_eq!(
+            workstation.recommend(512, 1 << 20),
+            Recommendation {
+                threads: 8,
+                hash_mb: 512
+            }
+        );
+        // a quarter of 1.5 GB is 384 MB, which rounds down to 256 MB.
+        let laptop = Hardware {
+            available_memory: Some(1536 * MEGABYTE),
+            ..workstation
+        };
+        assert_eq!(laptop.recommend(512, 1 << 20).hash_mb, 256);
+        assert_eq!(laptop.recommend(4, 1 << 20).threads, 4);
+    }
+}
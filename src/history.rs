@@ -17,7 +17,7 @@ use crate::{
     util::MAX_PLY,
 };
 
-use crate::chess::board::{movegen::MoveListEntry, Board};
+use crate::chess::{board::Board, chessmove::ScoredMove};
 
 impl ThreadData<'_> {
     /// Update the history counters of a batch of moves.
@@ -49,13 +49,14 @@ impl ThreadData<'_> {
             } else {
                 -history_malus(conf, depth)
             };
-            update_history(val, delta);
+            update_history(conf, val, delta);
         }
     }
 
     /// Update the history counters for a single move.
     pub fn update_history_single(
         &mut self,
+        conf: &Config,
         from: Square,
         to: Square,
         moved: Piece,
@@ -68,11 +69,11 @@ impl ThreadData<'_> {
             threats.contains_square(from),
             threats.contains_square(to),
         );
-        update_history(val, delta);
+        update_history(conf, val, delta);
     }
 
     /// Get the history scores for a batch of moves.
-    pub(super) fn get_history_scores(&self, pos: &Board, ms: &mut [MoveListEntry]) {
+    pub(super) fn get_history_scores(&self, pos: &Board, ms: &mut [ScoredMove]) {
         for m in ms {
             let piece_moved = pos.moved_piece(m.mov);
             let from = m.mov.from();
@@ -125,12 +126,12 @@ impl ThreadData<'_> {
             } else {
                 -history_malus(conf, depth)
             };
-            update_history(val, delta);
+            update_history(conf, val, delta);
         }
     }
 
     /// Get the tactical history scores for a batch of moves.
-    pub(super) fn get_tactical_history_scores(&self, pos: &Board, ms: &mut [MoveListEntry]) {
+    pub(super) fn get_tactical_history_scores(&self, pos: &Board, ms: &mut [ScoredMove]) {
         for m in ms {
             let piece_moved = pos.moved_piece(m.mov);
             let capture = caphist_piece_type(pos, m.mov);
@@ -174,13 +175,14 @@ impl ThreadData<'_> {
             } else {
                 -history_malus(conf, depth)
             };
-            update_history(cmh_block.get_mut(piece, to), delta);
+            update_history(conf, cmh_block.get_mut(piece, to), delta);
         }
     }
 
     /// Update the continuation history counter for a single move.
     pub fn update_continuation_history_single(
         &mut self,
+        conf: &Config,
         pos: &Board,
         to: Square,
         moved: Piece,
@@ -195,14 +197,14 @@ impl ThreadData<'_> {
             return;
         };
         let cmh_block = self.continuation_history.get_index_mut(ss.conthist_index);
-        update_history(cmh_block.get_mut(moved, to), delta);
+        update_history(conf, cmh_block.get_mut(moved, to), delta);
     }
 
     /// Get the continuation history scores for a batch of moves.
     pub(super) fn get_continuation_history_scores(
         &self,
         pos: &Board,
-        ms: &mut [MoveListEntry],
+        ms: &mut [ScoredMove],
         index: usize,
     ) {
         let height = pos.height();
@@ -238,12 +240,12 @@ impl ThreadData<'_> {
     /// Add a killer move.
     pub fn insert_killer(&mut self, pos: &Board, m: Move) {
         debug_assert!(pos.height() < MAX_PLY);
-        let idx = pos.height();
-        if self.killer_move_table[idx][0] == Some(m) {
+        let killers = &mut self.ss[pos.height()].killers;
+        if killers[0] == Some(m) {
             return;
         }
-        self.killer_move_table[idx][1] = self.killer_move_table[idx][0];
-        self.killer_move_table[idx][0] = Some(m);
+        killers[1] = killers[0];
+        killers[0] = Some(m);
     }
 
     /// Add a move to the countermove table.
@@ -10,30 +10,32 @@ use crate::{
 
 const AGEING_DIVISOR: i16 = 2;
 
+/// The history bonus for a move that caused a cutoff at `depth`.
+/// Never negative, so that a badly-tuned offset can't turn a bonus into a malus.
 pub fn history_bonus(conf: &Config, depth: i32) -> i32 {
-    i32::min(
-        conf.history_bonus_mul * depth + conf.history_bonus_offset,
-        conf.history_bonus_max,
-    )
+    (conf.history_bonus_mul * depth + conf.history_bonus_offset).clamp(0, conf.history_bonus_max)
 }
+/// The history malus for a move that was searched before the move that caused a cutoff at `depth`.
 pub fn history_malus(conf: &Config, depth: i32) -> i32 {
-    i32::min(
-        conf.history_malus_mul * depth + conf.history_malus_offset,
-        conf.history_malus_max,
-    )
+    (conf.history_malus_mul * depth + conf.history_malus_offset).clamp(0, conf.history_malus_max)
 }
 
+/// The largest magnitude any history entry can have, and the upper bound on `HISTORY_MAX`.
 pub const MAX_HISTORY: i16 = i16::MAX / 2;
 pub const CORRECTION_HISTORY_SIZE: usize = 16_384;
 pub const CORRECTION_HISTORY_GRAIN: i32 = 256;
 pub const CORRECTION_HISTORY_WEIGHT_SCALE: i32 = 256;
 pub const CORRECTION_HISTORY_MAX: i32 = CORRECTION_HISTORY_GRAIN * 32;
 
-pub fn update_history(val: &mut i16, delta: i32) {
+/// Apply `delta` to a history entry with the gravity formula, which shrinks updates
+/// that push an entry further towards `conf.history_max`. As long as the entry starts
+/// inside `[-history_max, history_max]`, it stays there.
+pub fn update_history(conf: &Config, val: &mut i16, delta: i32) {
     #![allow(clippy::cast_possible_truncation)]
-    const MAX_HISTORY: i32 = crate::historytable::MAX_HISTORY as i32;
+    let max = conf.history_max;
+    let delta = delta.clamp(-max, max);
     let curr = i32::from(*val);
-    *val += delta as i16 - (curr * delta.abs() / MAX_HISTORY) as i16;
+    *val = (curr + delta - curr * delta.abs() / max) as i16;
 }
 
 #[repr(transparent)]
@@ -259,3 +261,28 @@ impl CorrectionHistoryTable {
         &mut self.table[(key % CORRECTION_HISTORY_SIZE as u64) as usize][side]
     }
 }
+
+mod tests {
+    #[test]
+    fn history_never_saturates() {
+        use super::{history_bonus, history_malus, update_history, MAX_HISTORY};
+        use crate::search::parameters::Config;
+
+        let mut conf = Config::default();
+        for max in [2048, i32::from(MAX_HISTORY)] {
+            conf.history_max = max;
+            let mut val = 0;
+            for depth in 1..200 {
+                update_history(&conf, &mut val, history_bonus(&conf, depth));
+                assert!(i32::from(val) <= max);
+            }
+            for depth in 1..200 {
+                update_history(&conf, &mut val, -history_malus(&conf, depth));
+                assert!(i32::from(val) >= -max);
+            }
+            // even absurd deltas can't push an entry out of range.
+            update_history(&conf, &mut val, i32::MAX / 2);
+            assert!((-max..=max).contains(&i32::from(val)));
+        }
+    }
+}
@@ -1,9 +1,13 @@
+pub mod diagram;
+
 use std::{
     fs::File,
     io::{BufWriter, Write},
     path::Path,
 };
 
+use anyhow::bail;
+
 pub struct Image {
     data: Vec<u32>,
     height: usize,
@@ -84,6 +88,17 @@ pub fn inferno_colour_map(value: u8) -> u32 {
     u32::from(v[2]) | (u32::from(v[1]) << 8) | (u32::from(v[0]) << 16)
 }
 
+/// Blend two colours, taking `weight / 255` of `over` and the rest of `under`.
+pub fn mix(under: u32, over: u32, weight: u8) -> u32 {
+    let weight = u32::from(weight);
+    (0..3).fold(0, |acc, channel| {
+        let shift = channel * 8;
+        let a = under >> shift & 0xFF;
+        let b = over >> shift & 0xFF;
+        acc | ((a * (255 - weight) + b * weight) / 255) << shift
+    })
+}
+
 impl Image {
     pub fn zeroed(width: usize, height: usize) -> Self {
         Self {
@@ -93,7 +108,7 @@ impl Image {
         }
     }
 
-    pub fn rows(&self) -> impl Iterator<Item = &[u32]> {
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[u32]> {
         self.data.chunks(self.width)
     }
 
@@ -101,6 +116,13 @@ impl Image {
         self.data[y * self.width + x] = value;
     }
 
+    /// Colour the `width` by `height` rectangle whose bottom-left corner is at `x`, `y`.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, value: u32) {
+        for row in y..y + height {
+            self.data[row * self.width + x..row * self.width + x + width].fill(value);
+        }
+    }
+
     pub const fn width(&self) -> usize {
         self.width
     }
@@ -109,6 +131,21 @@ impl Image {
         self.height
     }
 
+    /// Write the image in the format named by the extension of `filename`: TGA, BMP, or PPM.
+    pub fn save(&self, filename: impl AsRef<Path>) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        match filename.extension().and_then(|ext| ext.to_str()) {
+            Some("tga") => self.save_as_tga(filename),
+            Some("bmp") => self.save_as_bmp(filename),
+            Some("ppm") => self.save_as_ppm(filename),
+            _ => bail!(
+                "can't tell the image format of {}, expected a .tga, .bmp, or .ppm extension",
+                filename.display()
+            ),
+        }
+        Ok(())
+    }
+
     // Write the image to a TGA file with the given name.
     // Format specification: http://www.gamers.org/dEngine/quake3/TGA.txt
     pub fn save_as_tga(&self, filename: impl AsRef<Path>) {
@@ -149,4 +186,82 @@ impl Image {
 
         println!("Wrote {}", filename.as_ref().display());
     }
+
+    // Write the image to an uncompressed 24-bit BMP file with the given name,
+    // for viewing in browsers, which don't support TGA.
+    pub fn save_as_bmp(&self, filename: impl AsRef<Path>) {
+        #![allow(clippy::cast_possible_truncation)]
+        let file = File::create(&filename).unwrap();
+        let mut writer = BufWriter::new(file);
+
+        // rows are padded to a multiple of four bytes.
+        let row_size = (self.width() * 3).next_multiple_of(4);
+        let image_size = (row_size * self.height()) as u32;
+        let file_size = 54 + image_size;
+
+        let mut header = Vec::with_capacity(54);
+        // file header
+        header.extend_from_slice(b"BM");
+        header.extend_from_slice(&file_size.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes()); // reserved
+        header.extend_from_slice(&54u32.to_le_bytes()); // offset of pixel data
+                                                        // info header
+        header.extend_from_slice(&40u32.to_le_bytes()); // info header size
+        header.extend_from_slice(&(self.width() as u32).to_le_bytes());
+        header.extend_from_slice(&(self.height() as u32).to_le_bytes()); // positive: bottom-up, as in TGA
+        header.extend_from_slice(&1u16.to_le_bytes()); // colour planes
+        header.extend_from_slice(&24u16.to_le_bytes()); // bits per pixel
+        header.extend_from_slice(&0u32.to_le_bytes()); // no compression
+        header.extend_from_slice(&image_size.to_le_bytes());
+        header.extend_from_slice(&2835u32.to_le_bytes()); // 72 DPI, horizontal
+        header.extend_from_slice(&2835u32.to_le_bytes()); // 72 DPI, vertical
+        header.extend_from_slice(&0u32.to_le_bytes()); // no palette
+        header.extend_from_slice(&0u32.to_le_bytes()); // all colours important
+
+        writer.write_all(&header).unwrap();
+
+        let padding = [0u8; 3];
+        for row in self.rows() {
+            for &loc in row {
+                let pixel: [u8; 3] = [
+                    (loc & 0xFF) as u8,
+                    (loc >> 8 & 0xFF) as u8,
+                    (loc >> 16 & 0xFF) as u8,
+                ];
+                writer.write_all(&pixel).unwrap();
+            }
+            writer
+                .write_all(&padding[..row_size - self.width() * 3])
+                .unwrap();
+        }
+
+        writer.flush().unwrap();
+
+        println!("Wrote {}", filename.as_ref().display());
+    }
+
+    // Write the image to a binary PPM file with the given name, which nearly every
+    // image tool can read. PPM rows run top-down, unlike TGA and BMP.
+    pub fn save_as_ppm(&self, filename: impl AsRef<Path>) {
+        #![allow(clippy::cast_possible_truncation)]
+        let file = File::create(&filename).unwrap();
+        let mut writer = BufWriter::new(file);
+
+        write!(writer, "P6\n{} {}\n255\n", self.width(), self.height()).unwrap();
+
+        for row in self.rows().rev() {
+            for &loc in row {
+                let pixel: [u8; 3] = [
+                    (loc >> 16 & 0xFF) as u8,
+                    (loc >> 8 & 0xFF) as u8,
+                    (loc & 0xFF) as u8,
+                ];
+                writer.write_all(&pixel).unwrap();
+            }
+        }
+
+        writer.flush().unwrap();
+
+        println!("Wrote {}", filename.as_ref().display());
+    }
 }
@@ -0,0 +1,191 @@
+//! Position diagrams as images, with the pieces drawn as letters on coloured squares and
+//! an optional heatmap laid over the board.
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use crate::{
+    chess::{board::Board, piece::Colour, types::Square},
+    explain,
+    image::{self, Image},
+    nnue::network::NNUEParams,
+    search::parameters::Config,
+    threadlocal::ThreadData,
+    transpositiontable::TT,
+    util::MEGABYTE,
+};
+
+/// The width and height of each square, in pixels.
+const SQUARE_PIXELS: usize = 32;
+/// How many pixels wide and high each cell of a glyph is drawn.
+const GLYPH_SCALE: usize = 3;
+
+const LIGHT_SQUARE: u32 = 0xF0_D9_B5;
+const DARK_SQUARE: u32 = 0xB5_88_63;
+const WHITE_PIECE: u32 = 0xFF_FF_FF;
+const BLACK_PIECE: u32 = 0x00_00_00;
+/// How strongly a heatmap shows over the squares, out of 255.
+const HEAT_WEIGHT: u8 = 176;
+
+/// Five-by-seven letters for each piece type, in the order of `PieceType`, with the top
+/// row first and the leftmost column in the highest bit.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 7]; 6] = [
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // P
+    [0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001, 0b10001], // N
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // B
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001], // R
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // Q
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001], // K
+];
+
+/// What to lay over the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Heatmap {
+    /// The balance of white and black attackers of each square, brightest where white
+    /// has the most control.
+    Threats,
+    /// How much each piece adds to the evaluation for white, by ablation.
+    Eval,
+}
+
+pub struct RenderOptions {
+    /// The position to draw, or the starting position if `None`.
+    pub fen: Option<String>,
+    pub out: PathBuf,
+    pub heatmap: Option<Heatmap>,
+    /// The depth of the searches behind the eval heatmap, or zero for the static evaluation.
+    pub depth: i32,
+}
+
+/// Draw `board`, colouring each square with a value in `heat` on the inferno map.
+pub fn draw(board: &Board, heat: Option<&[Option<u8>; 64]>) -> Image {
+    let mut image = Image::zeroed(8 * SQUARE_PIXELS, 8 * SQUARE_PIXELS);
+    for sq in Square::all() {
+        // rows run bottom-up, so the first rank is at the bottom.
+        let x = sq.file() as usize * SQUARE_PIXELS;
+        let y = sq.rank() as usize * SQUARE_PIXELS;
+        let mut background = if (sq.file() as usize + sq.rank() as usize) % 2 == 1 {
+            LIGHT_SQUARE
+        } else {
+            DARK_SQUARE
+        };
+        if let Some(h) = heat.and_then(|heat| heat[sq]) {
+            background = image::mix(background, image::inferno_colour_map(h), HEAT_WEIGHT);
+        }
+        image.fill_rect(x, y, SQUARE_PIXELS, SQUARE_PIXELS, background);
+
+        if let Some(piece) = board.piece_at(sq) {
+            let (fill, outline) = match piece.colour() {
+                Colour::White => (WHITE_PIECE, BLACK_PIECE),
+                Colour::Black => (BLACK_PIECE, WHITE_PIECE),
+            };
+            let glyph = GLYPHS[piece.piece_type() as usize];
+            // outline the letter first, so it shows on any background.
+            draw_glyph(&mut image, glyph, x, y, 1, outline);
+            draw_glyph(&mut image, glyph, x, y, 0, fill);
+        }
+    }
+    image
+}
+
+/// Draw `glyph` centred in the square at `x`, `y`, with each cell grown by `spread` pixels.
+fn draw_glyph(image: &mut Image, glyph: [u8; 7], x: usize, y: usize, spread: usize, colour: u32) {
+    let left = x + (SQUARE_PIXELS - 5 * GLYPH_SCALE) / 2;
+    let bottom = y + (SQUARE_PIXELS - 7 * GLYPH_SCALE) / 2;
+    for (row, bits) in glyph.iter().enumerate() {
+        for column in (0..5).filter(|column| bits >> (4 - column) & 1 != 0) {
+            image.fill_rect(
+                left + column * GLYPH_SCALE - spread,
+                bottom + (6 - row) * GLYPH_SCALE - spread,
+                GLYPH_SCALE + 2 * spread,
+                GLYPH_SCALE + 2 * spread,
+                colour,
+            );
+        }
+    }
+}
+
+/// The balance of attackers on each square attacked by either side, mapped onto the
+/// colour map with the squares black controls most at the dark end.
+pub fn threat_heat(board: &Board) -> [Option<u8>; 64] {
+    #![allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_possible_wrap
+    )]
+    let occupied = board.pieces.occupied();
+    let white = board.pieces.occupied_co(Colour::White);
+    let mut balance = [None; 64];
+    for sq in Square::all() {
+        let attackers = board.attackers_to(sq, occupied);
+        if attackers.non_empty() {
+            let white_attackers = (attackers & white).count() as i32;
+            balance[sq] = Some(2 * white_attackers - attackers.count() as i32);
+        }
+    }
+    let scale = balance
+        .iter()
+        .flatten()
+        .map(|b: &i32| b.abs())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    balance.map(|b| b.map(|b| ((b + scale) * 255 / (2 * scale)) as u8))
+}
+
+pub fn run_render(options: &RenderOptions, conf: &Config) -> anyhow::Result<()> {
+    let mut board = Board::default();
+    match &options.fen {
+        Some(fen) => board
+            .set_from_fen(fen)
+            .with_context(|| format!("Failed to parse FEN {fen}"))?,
+        None => board.set_startpos(),
+    }
+
+    let heat = match options.heatmap {
+        None => None,
+        Some(Heatmap::Threats) => Some(threat_heat(&board)),
+        Some(Heatmap::Eval) => {
+            let nnue_params = NNUEParams::decompress_and_alloc()?;
+            let mut tt = TT::new();
+            tt.resize(16 * MEGABYTE);
+            let mut t = ThreadData::new(0, &board, tt.view(), nnue_params);
+            let explanation = explain::explain(&board, &mut t, conf, options.depth);
+            println!("eval {:+} (white's perspective)", explanation.eval);
+            Some(explanation.heats())
+        }
+    };
+
+    draw(&board, heat.as_ref()).save(&options.out)
+}
+
+mod tests {
+    #[test]
+    fn diagrams() {
+        use super::{draw, threat_heat, DARK_SQUARE, LIGHT_SQUARE, SQUARE_PIXELS};
+        use crate::chess::{board::Board, types::Square};
+
+        let board = Board::from_fen("4k3/8/8/8/R7/8/8/6K1 w - - 0 1").unwrap();
+        let image = draw(&board, None);
+        assert_eq!(image.width(), 8 * SQUARE_PIXELS);
+        // a1 is dark and h1 light.
+        let bottom = image.rows().next().unwrap();
+        assert_eq!(bottom[0], DARK_SQUARE);
+        assert_eq!(bottom[8 * SQUARE_PIXELS - 1], LIGHT_SQUARE);
+        // the rook on a4 is drawn across the middle of its square.
+        let middle = image
+            .rows()
+            .nth(3 * SQUARE_PIXELS + SQUARE_PIXELS / 2)
+            .unwrap();
+        assert!(middle[..SQUARE_PIXELS].iter().any(|&p| p != LIGHT_SQUARE));
+
+        let heat = threat_heat(&board);
+        // only the rook attacks a8, and only the black king attacks e7.
+        assert_eq!(heat[Square::A8], Some(255));
+        assert_eq!(heat[Square::E7], Some(0));
+        assert_eq!(heat[Square::H5], None);
+        assert!(draw(&board, Some(&heat)).save("diagram.png").is_err());
+    }
+}
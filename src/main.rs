@@ -10,12 +10,22 @@ mod macros;
 #[cfg(feature = "datagen")]
 mod datagen;
 
+mod adjudication;
+mod analysefile;
 mod bench;
+mod blundercheck;
+mod cache;
 mod chess;
 mod cli;
 mod cuckoo;
+mod dedupcheck;
+mod epd;
 mod errors;
+mod evalfile;
 mod evaluation;
+mod explain;
+mod fuzz;
+mod hardware;
 mod history;
 mod historytable;
 mod image;
@@ -23,45 +33,211 @@ mod lookups;
 mod makemove;
 mod movepicker;
 mod nnue;
+mod notation;
 mod perft;
+mod repertoire;
 mod rng;
 mod search;
 mod searchinfo;
 mod stack;
+#[cfg(feature = "datagen")]
+mod stats;
+mod symmetry;
 mod tablebases;
 mod term;
 mod threadlocal;
+mod threadpool;
 mod timemgmt;
 mod transpositiontable;
+#[cfg(feature = "datagen")]
+mod tune;
 mod uci;
 mod util;
 
 #[cfg(feature = "datagen")]
-use cli::Subcommands::{Analyse, CountPositions, Datagen, Splat};
-use cli::Subcommands::{Bench, Perft, Quantise, Spsa, VisNNUE};
+use cli::Subcommands::{
+    Analyse, CountPositions, Datagen, Rescale, Splat, Sprt, Tournament, Tune, Verify,
+};
+use cli::Subcommands::{
+    AnalyseFile, Bench, BlunderCheck, DedupCheck, EvalFile, Export, Fuzz, Merge, Perft, Quantise,
+    Render, Repertoire, Spsa, Symmetry, VisNNUE,
+};
 
 /// The name of the engine.
 pub static NAME: &str = "Viridithas";
 /// The version of the engine.
 pub static VERSION: &str = env!("CARGO_PKG_VERSION");
 
+#[allow(clippy::too_many_lines)]
 fn main() -> anyhow::Result<()> {
     #[cfg(debug_assertions)]
     std::env::set_var("RUST_BACKTRACE", "1");
 
     if std::env::args_os().len() == 1 {
         // fast path to UCI:
-        return uci::main_loop();
+        return uci::main_loop(None, None);
     }
 
     let cli = <cli::Cli as clap::Parser>::parse();
 
     match cli.subcommand {
         Some(Perft) => perft::gamut(),
-        Some(VisNNUE) => nnue::network::visualise_nnue(),
-        Some(Quantise { input, output }) => nnue::network::quantise(&input, &output),
+        Some(VisNNUE { fens }) => nnue::network::visualise_nnue(fens.as_deref()),
+        Some(Quantise { input, output, npy }) => nnue::network::quantise(&input, &output, npy),
+        Some(Merge {
+            inputs,
+            weights,
+            swa,
+            decay,
+            output,
+        }) => swa.map_or_else(
+            || nnue::network::merge(&inputs, weights.as_deref(), &output),
+            |dir| nnue::network::swa(&dir, decay, &output),
+        ),
+        Some(Export { output, input, npy }) => {
+            nnue::network::export(input.as_deref(), &output, npy)
+        }
+        Some(EvalFile {
+            input,
+            threads,
+            output,
+        }) => {
+            let conf = match &cli.params {
+                Some(path) => search::parameters::Config::from_file(path)?,
+                None => search::parameters::Config::default(),
+            };
+            evalfile::run_evalfile(&input, threads, output.as_deref(), &conf)
+        }
+        Some(Symmetry {
+            positions,
+            threads,
+            tolerance,
+        }) => {
+            let conf = match &cli.params {
+                Some(path) => search::parameters::Config::from_file(path)?,
+                None => search::parameters::Config::default(),
+            };
+            symmetry::run_symmetry(&positions, threads, tolerance, &conf)
+        }
+        Some(Fuzz { seconds, seed }) => fuzz::run_fuzz(seconds, seed),
+        Some(AnalyseFile {
+            input,
+            movetime,
+            depth,
+            nodes,
+            threads,
+            hash,
+            output,
+            cache,
+            cache_size,
+        }) => {
+            let limit = match (movetime, depth, nodes) {
+                (Some(ms), ..) => timemgmt::SearchLimit::Time(ms),
+                (_, Some(depth), _) => timemgmt::SearchLimit::Depth(depth),
+                (.., Some(nodes)) => timemgmt::SearchLimit::Nodes(nodes),
+                _ => unreachable!("clap requires one of the limits"),
+            };
+            let threads = threads.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+            });
+            let conf = match &cli.params {
+                Some(path) => search::parameters::Config::from_file(path)?,
+                None => search::parameters::Config::default(),
+            };
+            analysefile::run_analysefile(
+                &analysefile::AnalyseOptions {
+                    input,
+                    output,
+                    limit,
+                    threads,
+                    hash_mb: hash,
+                    cache: cache.map(|path| (path, cache_size)),
+                },
+                &conf,
+            )
+        }
+        Some(BlunderCheck {
+            input,
+            threshold,
+            movetime,
+            depth,
+            nodes,
+            threads,
+            hash,
+        }) => {
+            let limit = match (movetime, depth, nodes) {
+                (Some(ms), ..) => timemgmt::SearchLimit::Time(ms),
+                (_, Some(depth), _) => timemgmt::SearchLimit::Depth(depth),
+                (.., Some(nodes)) => timemgmt::SearchLimit::Nodes(nodes),
+                _ => unreachable!("clap requires one of the limits"),
+            };
+            let threads = threads.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+            });
+            let conf = match &cli.params {
+                Some(path) => search::parameters::Config::from_file(path)?,
+                None => search::parameters::Config::default(),
+            };
+            blundercheck::run_blundercheck(
+                &blundercheck::BlunderCheckOptions {
+                    input,
+                    threshold,
+                    limit,
+                    threads,
+                    hash_mb: hash,
+                },
+                &conf,
+            )
+        }
+        Some(DedupCheck { input, depth, hash }) => dedupcheck::run_dedupcheck(&input, depth, hash),
+        Some(Repertoire {
+            fen,
+            depth,
+            margin,
+            width,
+            plies,
+            hash,
+            output,
+        }) => {
+            let conf = match &cli.params {
+                Some(path) => search::parameters::Config::from_file(path)?,
+                None => search::parameters::Config::default(),
+            };
+            repertoire::run_repertoire(
+                &repertoire::RepertoireOptions {
+                    fen,
+                    depth,
+                    margin,
+                    width,
+                    plies,
+                    hash_mb: hash,
+                    output,
+                },
+                &conf,
+            )
+        }
+        Some(Render {
+            fen,
+            out,
+            heatmap,
+            depth,
+        }) => {
+            let conf = match &cli.params {
+                Some(path) => search::parameters::Config::from_file(path)?,
+                None => search::parameters::Config::default(),
+            };
+            image::diagram::run_render(
+                &image::diagram::RenderOptions {
+                    fen,
+                    out,
+                    heatmap,
+                    depth,
+                },
+                &conf,
+            )
+        }
         #[cfg(feature = "datagen")]
-        Some(Analyse { input }) => datagen::dataset_stats(&input),
+        Some(Analyse { input, plots }) => datagen::dataset_stats(&input, plots),
         #[cfg(feature = "datagen")]
         Some(CountPositions { input }) => datagen::dataset_count(&input),
         Some(Spsa { json }) => {
@@ -86,37 +262,189 @@ fn main() -> anyhow::Result<()> {
             output,
             limit,
             cfg_path,
+            shards,
+            zstd,
         }) => {
             if pgn {
                 datagen::run_topgn(&input, &output, limit)
             } else {
-                datagen::run_splat(&input, &output, cfg_path.as_deref(), marlinformat, limit)
+                datagen::run_splat(
+                    &input,
+                    &output,
+                    cfg_path.as_deref(),
+                    marlinformat,
+                    limit,
+                    shards,
+                    zstd,
+                )
             }
         }
         #[cfg(feature = "datagen")]
+        Some(Rescale {
+            input,
+            output,
+            scale,
+            fit_k,
+            phase_scales,
+            clip,
+            clip_policy,
+        }) => datagen::rescale::run_rescale(&datagen::rescale::RescaleOptions {
+            input,
+            output,
+            scale,
+            fit_k,
+            phase_scales,
+            clip,
+            clip_policy,
+        }),
+        #[cfg(feature = "datagen")]
+        Some(Verify { input, output }) => datagen::verify::run_verify(&input, output.as_deref()),
+        #[cfg(feature = "datagen")]
         Some(Datagen {
             games,
             threads,
             tbs,
             book,
             depth_limit,
+            opening_nodes,
+            endgame_nodes,
+            ramp_plies,
+            node_jitter,
+            depth_jitter,
+            tb_relabel,
+            engine,
+            engine_option,
             dfrc,
+            seed,
+            resign,
+            draw,
         }) => datagen::gen_data_main(datagen::DataGenOptionsBuilder {
             games,
             threads,
             tbs,
             book,
             depth_limit,
+            schedule: datagen::LimitSchedule {
+                opening_percent: opening_nodes,
+                endgame_percent: endgame_nodes,
+                ramp_plies,
+                node_jitter_percent: node_jitter,
+                depth_jitter,
+            },
+            tb_relabel,
+            engine,
+            engine_options: engine_option,
             dfrc,
+            seed,
+            resign,
+            draw,
+        }),
+        #[cfg(feature = "datagen")]
+        Some(Tune {
+            params,
+            games,
+            tc,
+            threads,
+            resign,
+            draw,
+            seed,
+            output,
+        }) => tune::run_tune(&tune::TuneOptions {
+            params,
+            games,
+            tc,
+            threads,
+            resign,
+            draw,
+            seed,
+            output,
+        }),
+        #[cfg(feature = "datagen")]
+        Some(Sprt {
+            dev,
+            base,
+            elo0,
+            elo1,
+            alpha,
+            beta,
+            max_games,
+            tc,
+            threads,
+            resign,
+            draw,
+            seed,
+        }) => tune::run_sprt(&tune::SprtOptions {
+            dev,
+            base,
+            elo0,
+            elo1,
+            alpha,
+            beta,
+            max_games,
+            tc,
+            threads,
+            resign,
+            draw,
+            seed,
+        }),
+        #[cfg(feature = "datagen")]
+        Some(Tournament {
+            players,
+            gauntlet,
+            rounds,
+            tc,
+            threads,
+            resign,
+            draw,
+            sprt,
+            elo0,
+            elo1,
+            alpha,
+            beta,
+            seed,
+            pgn,
+        }) => tune::tournament::run_tournament(&tune::tournament::TournamentOptions {
+            players,
+            gauntlet,
+            rounds,
+            tc,
+            threads,
+            resign,
+            draw,
+            sprt: sprt
+                .then(|| stats::Sprt::new(elo0, elo1, alpha, beta))
+                .transpose()?,
+            seed,
+            pgn,
         }),
-        Some(Bench { depth }) => {
-            let nnue_params = nnue::network::NNUEParams::decompress_and_alloc()?;
+        Some(Bench {
+            depth,
+            hash,
+            threads,
+            signature,
+        }) => {
+            let nnue_params = nnue::network::NNUEParams::decompress_or_fallback();
             let stopped = std::sync::atomic::AtomicBool::new(false);
             let nodes = std::sync::atomic::AtomicU64::new(0);
-            let info = searchinfo::SearchInfo::new(&stopped, &nodes);
-            uci::bench("openbench", &info.conf, nnue_params, depth)?;
+            let mut info = searchinfo::SearchInfo::new(&stopped, &nodes);
+            if let Some(path) = &cli.params {
+                info.conf = search::parameters::Config::from_file(path)?;
+            }
+            let options = uci::BenchOptions {
+                depth,
+                hash_mb: hash,
+                threads,
+            };
+            if signature.is_some() && !options.gives_signature() {
+                anyhow::bail!("the bench signature needs the default hash size and one thread");
+            }
+            let nodes = uci::bench("openbench", &info.conf, nnue_params, &options)?;
+            if let Some(path) = signature {
+                std::fs::write(&path, format!("{nodes}\n"))
+                    .map_err(|e| anyhow::anyhow!("Failed to write {}: {e}", path.display()))?;
+            }
             Ok(())
         }
-        None => uci::main_loop(),
+        None => uci::main_loop(cli.params.as_deref(), cli.resume.as_deref()),
     }
 }
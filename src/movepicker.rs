@@ -1,9 +1,9 @@
 use crate::{
-    chess::board::Board, chess::chessmove::Move, history, historytable::MAX_HISTORY,
-    threadlocal::ThreadData,
+    chess::board::Board, chess::chessmove::Move, chess::chessmove::ScoredMove, history,
+    historytable::MAX_HISTORY, search::rootmoves::RootMoves, threadlocal::ThreadData,
 };
 
-use crate::chess::board::movegen::{AllMoves, MoveList, MoveListEntry, SkipQuiets};
+use crate::chess::board::movegen::{AllMoves, MoveList, SkipQuiets};
 
 pub const TT_MOVE_SCORE: i32 = 20_000_000;
 pub const FIRST_KILLER_SCORE: i32 = 9_000_000;
@@ -16,11 +16,14 @@ pub enum Stage {
     TTMove,
     GenerateCaptures,
     YieldGoodCaptures,
+    GenerateQuietChecks,
+    YieldQuietChecks,
     YieldKiller1,
     YieldKiller2,
     YieldCounterMove,
     GenerateQuiets,
     YieldRemaining,
+    YieldRootMoves,
     Done,
 }
 
@@ -32,6 +35,8 @@ pub struct MovePicker {
     killers: [Option<Move>; 2],
     counter_move: Option<Move>,
     pub skip_quiets: bool,
+    /// Whether to follow the good captures with quiet checks when skipping quiets.
+    pub with_quiet_checks: bool,
     see_threshold: i32,
 }
 
@@ -55,10 +60,24 @@ impl MovePicker {
             killers,
             counter_move,
             skip_quiets: false,
+            with_quiet_checks: false,
             see_threshold,
         }
     }
 
+    /// A move picker for the root, which yields the root moves in their current order.
+    pub fn root(root_moves: &RootMoves) -> Self {
+        let mut picker = Self::new(None, [None; 2], None, 0);
+        for rm in root_moves.iter() {
+            picker.movelist.push_entry(ScoredMove {
+                mov: rm.mov,
+                score: 0,
+            });
+        }
+        picker.stage = Stage::YieldRootMoves;
+        picker
+    }
+
     /// Returns true if a move was already yielded by the movepicker.
     pub fn was_tried_lazily(&self, m: Move) -> bool {
         let m = Some(m);
@@ -66,20 +85,33 @@ impl MovePicker {
     }
 
     /// Select the next move to try. Returns None if there are no more moves to try.
-    #[allow(clippy::cognitive_complexity)]
-    pub fn next(&mut self, position: &Board, t: &ThreadData) -> Option<MoveListEntry> {
+    #[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
+    ///
+    /// Moves from the hash and refutation tables were found in other positions, so each is
+    /// checked for pseudo-legality here before it's yielded, and counted if it's rejected.
+    /// Legality is then checked by `make_move`, as for every other move.
+    pub fn next(&mut self, position: &Board, t: &mut ThreadData) -> Option<ScoredMove> {
         if self.stage == Stage::Done {
             return None;
         }
+        if self.stage == Stage::YieldRootMoves {
+            let m = self.movelist.get(self.index).copied();
+            self.index += 1;
+            if m.is_none() {
+                self.stage = Stage::Done;
+            }
+            return m;
+        }
         if self.stage == Stage::TTMove {
             self.stage = Stage::GenerateCaptures;
             if let Some(tt_move) = self.tt_move {
                 if position.is_pseudo_legal(tt_move) {
-                    return Some(MoveListEntry {
+                    return Some(ScoredMove {
                         mov: tt_move,
                         score: TT_MOVE_SCORE,
                     });
                 }
+                t.stats.rejected_tt_moves += 1;
             }
         }
         if self.stage == Stage::GenerateCaptures {
@@ -107,22 +139,44 @@ impl MovePicker {
                 // the index so we can try this move again.
                 self.index -= 1;
             }
-            self.stage = if self.skip_quiets {
-                Stage::Done
-            } else {
+            self.stage = if !self.skip_quiets {
                 Stage::YieldKiller1
+            } else if self.with_quiet_checks {
+                Stage::GenerateQuietChecks
+            } else {
+                Stage::Done
             };
         }
+        if self.stage == Stage::GenerateQuietChecks {
+            self.stage = Stage::YieldQuietChecks;
+            // the remaining captures are all losing, so make way for the checks.
+            self.movelist.clear();
+            self.index = 0;
+            position.generate_quiet_checks(&mut self.movelist);
+            Self::score_quiets(t, position, &mut self.movelist);
+            self.movelist.sort_unstable_by_key(|m| -m.score);
+        }
+        if self.stage == Stage::YieldQuietChecks {
+            while let Some(&m) = self.movelist.get(self.index) {
+                self.index += 1;
+                // checks that hang the checking piece are rarely worth a look.
+                if Some(m.mov) != self.tt_move && position.static_exchange_eval(m.mov, 0) {
+                    return Some(m);
+                }
+            }
+            self.stage = Stage::Done;
+        }
         if self.stage == Stage::YieldKiller1 {
             self.stage = Stage::YieldKiller2;
             if !self.skip_quiets && self.killers[0] != self.tt_move {
                 if let Some(killer) = self.killers[0] {
                     if position.is_pseudo_legal(killer) {
-                        return Some(MoveListEntry {
+                        return Some(ScoredMove {
                             mov: killer,
                             score: FIRST_KILLER_SCORE,
                         });
                     }
+                    t.stats.rejected_killers += 1;
                 }
             }
         }
@@ -131,11 +185,12 @@ impl MovePicker {
             if !self.skip_quiets && self.killers[1] != self.tt_move {
                 if let Some(killer) = self.killers[1] {
                     if position.is_pseudo_legal(killer) {
-                        return Some(MoveListEntry {
+                        return Some(ScoredMove {
                             mov: killer,
                             score: SECOND_KILLER_SCORE,
                         });
                     }
+                    t.stats.rejected_killers += 1;
                 }
             }
         }
@@ -148,11 +203,12 @@ impl MovePicker {
             {
                 if let Some(counter) = self.counter_move {
                     if position.is_pseudo_legal(counter) {
-                        return Some(MoveListEntry {
+                        return Some(ScoredMove {
                             mov: counter,
                             score: COUNTER_MOVE_SCORE,
                         });
                     }
+                    t.stats.rejected_counter_moves += 1;
                 }
             }
         }
@@ -181,7 +237,7 @@ impl MovePicker {
     /// Usually only one iteration is performed, but in the case where
     /// the best move has already been tried or doesn't meet SEE requirements,
     /// we will continue to iterate until we find a move that is valid.
-    fn yield_once(&mut self, pos: &Board) -> Option<MoveListEntry> {
+    fn yield_once(&mut self, pos: &Board) -> Option<ScoredMove> {
         loop {
             // If we have already tried all moves, return None.
             if self.index == self.movelist.len() {
@@ -234,7 +290,7 @@ impl MovePicker {
         }
     }
 
-    pub fn score_quiets(t: &ThreadData, pos: &Board, ms: &mut [MoveListEntry]) {
+    pub fn score_quiets(t: &ThreadData, pos: &Board, ms: &mut [ScoredMove]) {
         // zero-out the ordering scores
         for m in &mut *ms {
             m.score = 0;
@@ -246,7 +302,7 @@ impl MovePicker {
         // t.get_continuation_history_scores(pos, ms, 3);
     }
 
-    pub fn score_captures(t: &ThreadData, pos: &Board, moves: &mut [MoveListEntry]) {
+    pub fn score_captures(t: &ThreadData, pos: &Board, moves: &mut [ScoredMove]) {
         const MVV_SCORE: [i32; 6] = [0, 2400, 2400, 4800, 9600, 0];
 
         // provisionally set the WINNING_CAPTURE offset, for lazily SEE-guarding stuff later.
@@ -255,8 +311,47 @@ impl MovePicker {
         }
 
         t.get_tactical_history_scores(pos, moves);
-        for MoveListEntry { mov, score } in moves {
+        for ScoredMove { mov, score } in moves {
             *score += MVV_SCORE[history::caphist_piece_type(pos, *mov)];
         }
     }
 }
+
+mod tests {
+    #[test]
+    fn table_moves_are_checked() {
+        use super::MovePicker;
+        use crate::{
+            chess::{board::Board, chessmove::Move, types::Square},
+            nnue::network::NNUEParams,
+            threadlocal::ThreadData,
+            transpositiontable::TT,
+            util::MEGABYTE,
+        };
+
+        let mut board = Board::default();
+        board.set_startpos();
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+        let mut t = ThreadData::new(0, &board, tt.view(), nnue_params);
+
+        // none of these can be played from the starting position.
+        let tt_move = Move::new(Square::E2, Square::E5);
+        let killers = [Some(Move::new(Square::D1, Square::H5)), None];
+        let counter_move = Move::new(Square::A1, Square::A3);
+        let mut picker = MovePicker::new(Some(tt_move), killers, Some(counter_move), 0);
+        let mut yielded = Vec::new();
+        while let Some(entry) = picker.next(&board, &mut t) {
+            yielded.push(entry.mov);
+        }
+
+        let mut legal = board.legal_moves();
+        legal.sort_by_key(|m| (m.from(), m.to()));
+        yielded.sort_by_key(|m| (m.from(), m.to()));
+        assert_eq!(yielded, legal);
+        assert_eq!(t.stats.rejected_tt_moves, 1);
+        assert_eq!(t.stats.rejected_killers, 1);
+        assert_eq!(t.stats.rejected_counter_moves, 1);
+    }
+}
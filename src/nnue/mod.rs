@@ -1,4 +1,5 @@
 mod accumulator;
 pub mod network;
+pub mod report;
 #[macro_use]
 mod simd;
@@ -4,7 +4,7 @@ use std::{
     hash::Hasher,
     io::BufReader,
     ops::{Deref, DerefMut},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{Mutex, OnceLock},
     time::Duration,
 };
@@ -30,6 +30,8 @@ use super::accumulator::{self, Accumulator};
 
 pub mod feature;
 pub mod layers;
+mod npy;
+pub mod small;
 
 /// The size of the input layer of the network.
 pub const INPUT: usize = 11 * 64;
@@ -350,9 +352,162 @@ impl UnquantisedNetwork {
             Ok(net)
         }
     }
+
+    fn write(&self, writer: &mut impl std::io::Write) -> anyhow::Result<()> {
+        let ptr = util::from_ref::<Self>(self).cast::<u8>();
+        let len = std::mem::size_of::<Self>();
+        // SAFETY: We're writing a slice of bytes, and we know that the slice is valid.
+        writer.write_all(unsafe { std::slice::from_raw_parts(ptr, len) })?;
+        Ok(())
+    }
+
+    /// The parameter arrays, with names and shapes, in the order bullet lays them out.
+    fn named_arrays(&mut self) -> [(&'static str, Vec<usize>, &mut [f32]); 8] {
+        [
+            (
+                "ft_weights",
+                vec![BUCKETS + 1, 12 * 64, L1_SIZE],
+                &mut self.ft_weights,
+            ),
+            ("ft_biases", vec![L1_SIZE], &mut self.ft_biases),
+            (
+                "l1_weights",
+                vec![L1_SIZE, OUTPUT_BUCKETS, L2_SIZE],
+                self.l1_weights.as_flattened_mut().as_flattened_mut(),
+            ),
+            (
+                "l1_biases",
+                vec![OUTPUT_BUCKETS, L2_SIZE],
+                self.l1_biases.as_flattened_mut(),
+            ),
+            (
+                "l2_weights",
+                vec![L2_SIZE, OUTPUT_BUCKETS, L3_SIZE],
+                self.l2_weights.as_flattened_mut().as_flattened_mut(),
+            ),
+            (
+                "l2_biases",
+                vec![OUTPUT_BUCKETS, L3_SIZE],
+                self.l2_biases.as_flattened_mut(),
+            ),
+            (
+                "l3_weights",
+                vec![L3_SIZE, OUTPUT_BUCKETS],
+                self.l3_weights.as_flattened_mut(),
+            ),
+            ("l3_biases", vec![OUTPUT_BUCKETS], &mut self.l3_biases),
+        ]
+    }
+
+    /// Set `self` to `self_weight * self + other_weight * other`, parameter-wise.
+    fn blend(&mut self, self_weight: f32, other: &mut Self, other_weight: f32) {
+        for ((_, _, ours), (_, _, theirs)) in
+            self.named_arrays().into_iter().zip(other.named_arrays())
+        {
+            for (x, y) in ours.iter_mut().zip(theirs.iter()) {
+                *x = self_weight.mul_add(*x, other_weight * *y);
+            }
+        }
+    }
+
+    fn read_file(path: &Path) -> anyhow::Result<Box<Self>> {
+        let mut reader = BufReader::new(
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+        );
+        Self::read(&mut reader)
+            .with_context(|| format!("Failed to read network from {}", path.display()))
+    }
+
+    /// Write the network as a directory of `.npy` files, one per parameter array.
+    fn write_npy(&mut self, dir: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        for (name, shape, data) in self.named_arrays() {
+            npy::write(&dir.join(format!("{name}.npy")), &shape, data)?;
+        }
+        Ok(())
+    }
+
+    /// Read a network from a directory of `.npy` files, as written by `write_npy`.
+    fn read_npy(dir: &Path) -> anyhow::Result<Box<Self>> {
+        let mut net = Self::zeroed();
+        for (name, _, data) in net.named_arrays() {
+            npy::read(&dir.join(format!("{name}.npy")), data)?;
+        }
+        Ok(net)
+    }
 }
 
 impl QuantisedNetwork {
+    /// Convert a quantised network back into bullet's floating-point layout.
+    /// The feature factoriser was merged into the king buckets by quantisation, so it is left at zero.
+    /// Values are clamped to the quantisation bounds so that re-quantising reproduces this network.
+    fn dequantise(&self) -> Box<UnquantisedNetwork> {
+        const BOUND: f32 = 1.98;
+
+        let mut net = UnquantisedNetwork::zeroed();
+        let mut buckets = net.ft_weights.chunks_exact_mut(12 * 64 * L1_SIZE);
+        let _factoriser = buckets.next().unwrap();
+        for (bucket_idx, (tgt_bucket, src_bucket)) in buckets
+            .zip(self.ft_weights.chunks_exact(INPUT * L1_SIZE))
+            .enumerate()
+        {
+            for piece in Piece::all() {
+                for sq in Square::all() {
+                    // mirror the king-slot sharing in UnquantisedNetwork::quantise.
+                    let in_bucket = BUCKET_MAP[sq] == bucket_idx;
+                    if (in_bucket && piece == Piece::BK) || (!in_bucket && piece == Piece::WK) {
+                        continue;
+                    }
+                    let i =
+                        feature::index_full(Colour::White, Square::A1, FeatureUpdate { sq, piece });
+                    let j = feature::index(Colour::White, Square::A1, FeatureUpdate { sq, piece })
+                        .index();
+                    let src = &src_bucket[j * L1_SIZE..j * L1_SIZE + L1_SIZE];
+                    let tgt = &mut tgt_bucket[i * L1_SIZE..i * L1_SIZE + L1_SIZE];
+                    for (src, tgt) in src.iter().zip(tgt) {
+                        *tgt = (f32::from(*src) / f32::from(QA)).clamp(-BOUND, BOUND);
+                    }
+                }
+            }
+        }
+
+        for (src, tgt) in self.ft_biases.iter().zip(net.ft_biases.iter_mut()) {
+            *tgt = (f32::from(*src) / f32::from(QA)).clamp(-BOUND, BOUND);
+        }
+
+        for (src, tgt) in self
+            .l1_weights
+            .as_flattened()
+            .as_flattened()
+            .iter()
+            .zip(net.l1_weights.as_flattened_mut().as_flattened_mut())
+        {
+            *tgt = (f32::from(*src) / f32::from(QB)).clamp(-BOUND, BOUND);
+        }
+
+        net.l1_biases = self.l1_biases;
+        net.l2_weights = self.l2_weights;
+        net.l2_biases = self.l2_biases;
+        net.l3_weights = self.l3_weights;
+        net.l3_biases = self.l3_biases;
+
+        net
+    }
+
+    fn read(reader: &mut impl std::io::Read) -> anyhow::Result<Box<Self>> {
+        // SAFETY: QuantisedNetwork can be zeroed, and any bit pattern is valid for it.
+        unsafe {
+            let mut net = Self::zeroed();
+            let mem = std::slice::from_raw_parts_mut(
+                util::from_mut(net.as_mut()).cast::<u8>(),
+                std::mem::size_of::<Self>(),
+            );
+            reader.read_exact(mem)?;
+            Ok(net)
+        }
+    }
+
     /// Convert the network parameters into a format optimal for inference.
     #[allow(
         clippy::cognitive_complexity,
@@ -561,6 +716,32 @@ fn repermute_ft_bucket(tgt_bucket: &mut [i16], unsorted: &[i16]) {
 }
 
 impl NNUEParams {
+    /// Load the embedded network, or if that fails, switch over to the classical
+    /// evaluation and hand back an all-zero network, so that the engine stays usable.
+    pub fn decompress_or_fallback() -> &'static Self {
+        match Self::decompress_and_alloc() {
+            Ok(params) => params,
+            Err(e) => {
+                crate::uci::output::send(format!("info string failed to load NNUE weights ({e:#}), falling back to classical evaluation"));
+                crate::evaluation::CLASSICAL_FALLBACK
+                    .store(true, std::sync::atomic::Ordering::SeqCst);
+                Box::leak(Self::zeroed())
+            }
+        }
+    }
+
+    /// Load a quantised network file in place of the embedded network, so that networks
+    /// can be played against each other. The network is kept for the rest of the program.
+    #[cfg(feature = "datagen")]
+    pub fn load_quantised(path: &Path) -> anyhow::Result<&'static Self> {
+        let mut reader = BufReader::new(
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+        );
+        let net = QuantisedNetwork::read(&mut reader)
+            .with_context(|| format!("Failed to read network from {}", path.display()))?;
+        Ok(Box::leak(net.permute(cfg!(target_feature = "ssse3"))))
+    }
+
     #[allow(clippy::too_many_lines)]
     pub fn decompress_and_alloc() -> anyhow::Result<&'static Self> {
         #[cfg(not(feature = "zstd"))]
@@ -707,7 +888,10 @@ impl NNUEParams {
         #[cfg(debug_assertions)]
         {
             // log that we've created the file freshly
-            println!("Created NNUE weights file at {weights_path:#?} from decompressed data");
+            eprintln!(
+                "Created NNUE weights file at {} from decompressed data",
+                weights_path.display()
+            );
         }
 
         // file created, return the mapped weights
@@ -775,9 +959,10 @@ impl NNUEParams {
         #[cfg(debug_assertions)]
         {
             // log the address of the mmap with pointer formatting
-            println!(
-                "Loaded NNUE weights from mmap at {:p} from file {weights_path:#?}",
-                mmap.as_ptr()
+            eprintln!(
+                "Loaded NNUE weights from mmap at {:p} from file {}",
+                mmap.as_ptr(),
+                weights_path.display()
             );
         }
 
@@ -817,15 +1002,120 @@ impl NNUEParams {
     }
 }
 
-pub fn quantise(input: &std::path::Path, output: &std::path::Path) -> anyhow::Result<()> {
-    let mut reader = BufReader::new(File::open(input)?);
+pub fn quantise(
+    input: &std::path::Path,
+    output: &std::path::Path,
+    npy: bool,
+) -> anyhow::Result<()> {
+    let unquantised_net = if npy {
+        UnquantisedNetwork::read_npy(input)?
+    } else {
+        let mut reader = BufReader::new(File::open(input)?);
+        UnquantisedNetwork::read(&mut reader)?
+    };
     let mut writer = File::create(output)?;
-    let unquantised_net = UnquantisedNetwork::read(&mut reader)?;
     let net = unquantised_net.quantise();
     net.write(&mut writer)?;
     Ok(())
 }
 
+/// Average several floating-point networks, optionally with per-network weights.
+pub fn merge(inputs: &[PathBuf], weights: Option<&[f32]>, output: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(!inputs.is_empty(), "No networks given to merge.");
+    #[allow(clippy::cast_precision_loss)]
+    let uniform = vec![1.0 / inputs.len() as f32; inputs.len()];
+    let weights = weights.unwrap_or(&uniform);
+    anyhow::ensure!(
+        weights.len() == inputs.len(),
+        "Got {} weights for {} networks.",
+        weights.len(),
+        inputs.len()
+    );
+    let total = weights.iter().sum::<f32>();
+    anyhow::ensure!(
+        weights.iter().all(|w| w.is_finite() && *w >= 0.0) && total > 0.0,
+        "Merge weights must be non-negative and not all zero."
+    );
+
+    let mut merged = UnquantisedNetwork::zeroed();
+    for (path, weight) in inputs.iter().zip(weights) {
+        let mut net = UnquantisedNetwork::read_file(path)?;
+        merged.blend(1.0, &mut net, weight / total);
+    }
+
+    let mut writer = std::io::BufWriter::new(File::create(output)?);
+    merged.write(&mut writer)?;
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+/// Exponentially average the floating-point checkpoints in `dir`, ordered by the
+/// number in their names (so `net-20` comes before `net-100`), such that the latest
+/// checkpoint has weight `1 - decay`.
+pub fn swa(dir: &Path, decay: f32, output: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!((0.0..1.0).contains(&decay), "SWA decay must be in [0, 1).");
+    let mut checkpoints = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read checkpoint directory {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    checkpoints.retain(|path| path.is_file());
+    checkpoints.sort_by_key(|path| {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let number = name
+            .split(|c: char| !c.is_ascii_digit())
+            .find(|run| !run.is_empty())
+            .and_then(|run| run.parse::<u64>().ok());
+        (number, name)
+    });
+    let (first, rest) = checkpoints
+        .split_first()
+        .with_context(|| format!("No checkpoints in {}", dir.display()))?;
+
+    let mut averaged = UnquantisedNetwork::read_file(first)?;
+    for path in rest {
+        let mut net = UnquantisedNetwork::read_file(path)?;
+        averaged.blend(decay, &mut net, 1.0 - decay);
+    }
+    println!(
+        "Averaged {} checkpoints from {}",
+        checkpoints.len(),
+        dir.display()
+    );
+
+    let mut writer = std::io::BufWriter::new(File::create(output)?);
+    averaged.write(&mut writer)?;
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+/// Export a quantised network (by default, the embedded one) in a format usable by training code:
+/// either bullet's raw floating-point checkpoint layout, or a directory of `.npy` arrays.
+pub fn export(input: Option<&Path>, output: &Path, npy: bool) -> anyhow::Result<()> {
+    let net = if let Some(input) = input {
+        let mut reader = BufReader::new(
+            File::open(input).with_context(|| format!("Failed to open {}", input.display()))?,
+        );
+        QuantisedNetwork::read(&mut reader)?
+    } else {
+        let mut decoder = ruzstd::StreamingDecoder::new(COMPRESSED_NNUE)
+            .with_context(|| "Failed to construct zstd decoder for NNUE weights.")?;
+        QuantisedNetwork::read(&mut decoder)?
+    };
+    let mut unquantised_net = net.dequantise();
+    if npy {
+        unquantised_net.write_npy(output)?;
+    } else {
+        let mut writer = std::io::BufWriter::new(File::create(output)?);
+        unquantised_net.write(&mut writer)?;
+    }
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
 /// The size of the stack used to store the activations of the hidden layer.
 const ACC_STACK_SIZE: usize = MAX_PLY + 1;
 
@@ -996,6 +1286,14 @@ impl NNUEState {
             *board_state = PieceLayout::NULL;
         }
 
+        self.refresh_from(board, nnue_params);
+    }
+
+    /// Point the state at an unrelated board, keeping the bucket cache, so that
+    /// only the difference from the last position seen in each king bucket is applied.
+    pub fn refresh_from(&mut self, board: &Board, nnue_params: &NNUEParams) {
+        self.current_acc = 0;
+
         // refresh the first accumulator
         for colour in Colour::all() {
             self.bucket_cache.load_accumulator_for_position(
@@ -1275,7 +1573,30 @@ pub fn inference_benchmark(state: &NNUEState, nnue_params: &NNUEParams) {
     println!("{ns_per_eval} ns per evaluation");
 }
 
-pub fn visualise_nnue() -> anyhow::Result<()> {
+/// Activation statistics for the feature transformer over a set of positions.
+pub struct ActivationStats {
+    /// The number of positions evaluated.
+    pub positions: u64,
+    /// For each neuron, the number of accumulators (two per position) in which it was active.
+    pub active_counts: Vec<u64>,
+    /// For each neuron, the sum of its clipped activations.
+    pub activation_sums: Vec<u64>,
+    /// The number of positions that used each output bucket.
+    pub bucket_counts: [u64; OUTPUT_BUCKETS],
+}
+
+impl ActivationStats {
+    /// Neurons that were never active in any position.
+    pub fn dead_neurons(&self) -> impl Iterator<Item = usize> + '_ {
+        self.active_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count == 0)
+            .map(|(neuron, _)| neuron)
+    }
+}
+
+pub fn visualise_nnue(fens: Option<&Path>) -> anyhow::Result<()> {
     let nnue_params = NNUEParams::decompress_and_alloc()?;
     // create folder for the images
     let path = std::path::PathBuf::from("nnue-visualisations");
@@ -1286,6 +1607,35 @@ pub fn visualise_nnue() -> anyhow::Result<()> {
     }
     let (min, max) = nnue_params.min_max_feature_weight();
     println!("Min / Max FT values: {min} / {max}");
+
+    let fen_file;
+    let fens = if let Some(fens) = fens {
+        fen_file = std::fs::read_to_string(fens)
+            .with_context(|| format!("Failed to read FEN file {}", fens.display()))?;
+        fen_file.lines().filter(|l| !l.trim().is_empty()).collect()
+    } else {
+        crate::bench::BENCH_POSITIONS.to_vec()
+    };
+    let positions = fens
+        .into_iter()
+        .map(|fen| {
+            let mut board = Board::default();
+            board
+                .set_from_fen(fen)
+                .with_context(|| format!("Failed to parse FEN {fen}"))?;
+            Ok(board)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let stats = nnue_params.activation_statistics(&positions);
+    println!(
+        "Dead neurons over {} positions: {}",
+        stats.positions,
+        stats.dead_neurons().count()
+    );
+    let report_path = path.join("index.html");
+    nnue::report::write_html_report(&report_path, &stats, (min, max))?;
+    println!("Wrote {}", report_path.display());
+
     Ok(())
 }
 
@@ -1351,8 +1701,35 @@ impl NNUEParams {
             }
         }
 
-        let path = path.join(format!("neuron_{neuron}.tga"));
-        image.save_as_tga(path);
+        image.save_as_tga(path.join(format!("neuron_{neuron}.tga")));
+        image.save_as_bmp(path.join(format!("neuron_{neuron}.bmp")));
+    }
+
+    /// Stream positions through the feature transformer, recording how often each neuron fires.
+    pub fn activation_statistics(&self, positions: &[Board]) -> ActivationStats {
+        let mut stats = ActivationStats {
+            positions: 0,
+            active_counts: vec![0; L1_SIZE],
+            activation_sums: vec![0; L1_SIZE],
+            bucket_counts: [0; OUTPUT_BUCKETS],
+        };
+        let mut nnue_state = NNUEState::new(&Board::default(), self);
+        for board in positions {
+            nnue_state.reinit_from(board, self);
+            let acc = &nnue_state.accumulators[nnue_state.current_acc];
+            for perspective in [&acc.white, &acc.black] {
+                for (neuron, &value) in perspective.iter().enumerate() {
+                    let activation = value.clamp(0, QA);
+                    if activation > 0 {
+                        stats.active_counts[neuron] += 1;
+                        stats.activation_sums[neuron] += u64::from(activation.unsigned_abs());
+                    }
+                }
+            }
+            stats.bucket_counts[output_bucket(board)] += 1;
+            stats.positions += 1;
+        }
+        stats
     }
 
     pub fn min_max_feature_weight(&self) -> (i16, i16) {
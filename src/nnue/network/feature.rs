@@ -64,3 +64,42 @@ pub fn index_full(colour: Colour, king: Square, f: FeatureUpdate) -> usize {
     assert!(idx < 12 * 64);
     idx
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn king_buckets_are_horizontally_mirrored() {
+        use super::index;
+        use crate::{
+            chess::{
+                piece::{Colour, Piece},
+                types::{File, Square},
+            },
+            nnue::network::{FeatureUpdate, BUCKETS, BUCKET_MAP},
+        };
+
+        for king in Square::all() {
+            let mirror = king.flip_file();
+            // both halves of the board share one set of weights, and are told apart
+            // only so that the accumulator cache keeps them separate.
+            assert_eq!(BUCKET_MAP[king] % BUCKETS, BUCKET_MAP[mirror] % BUCKETS);
+            assert_eq!(BUCKET_MAP[king] >= BUCKETS, king.file() >= File::E);
+            for colour in Colour::all() {
+                for piece in Piece::all() {
+                    for sq in Square::all() {
+                        let ours = index(colour, king, FeatureUpdate { sq, piece });
+                        let theirs = index(
+                            colour,
+                            mirror,
+                            FeatureUpdate {
+                                sq: sq.flip_file(),
+                                piece,
+                            },
+                        );
+                        assert_eq!(ours.index(), theirs.index());
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Minimal reading and writing of little-endian `f32` arrays in numpy's `.npy` format.
+//! Format specification: <https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html>
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Write `data` as an array of the given `shape` to `path`.
+pub fn write(path: &Path, shape: &[usize], data: &[f32]) -> anyhow::Result<()> {
+    assert_eq!(shape.iter().product::<usize>(), data.len());
+    let dims = shape.iter().fold(String::new(), |mut dims, d| {
+        write!(dims, "{d}, ").unwrap();
+        dims
+    });
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({dims}), }}");
+    // the header is padded with spaces so that the data starts on a 64-byte boundary,
+    // counting the magic string, the version, the header length, and a terminating newline.
+    let unpadded = MAGIC.len() + 2 + 2 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+    let header_len = u16::try_from(header.len()).with_context(|| "npy header too long")?;
+
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&header_len.to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for &x in data {
+        writer.write_all(&x.to_le_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read an array from `path` into `data`, checking that it holds exactly `data.len()` `f32`s.
+pub fn read(path: &Path, data: &mut [f32]) -> anyhow::Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut preamble = [0; 10];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC || preamble[6] != 1 {
+        bail!("{} is not a version 1 npy file", path.display());
+    }
+    let header_len = u16::from_le_bytes([preamble[8], preamble[9]]);
+    let mut header = vec![0; usize::from(header_len)];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8(header)?;
+    if !header.contains("'descr': '<f4'") || !header.contains("'fortran_order': False") {
+        bail!(
+            "{} must contain little-endian f32 data in C order, but has header {}",
+            path.display(),
+            header.trim()
+        );
+    }
+    let shape = header
+        .split_once("'shape': (")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .with_context(|| format!("No shape in header of {}", path.display()))?
+        .0;
+    let len = shape
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(str::parse::<usize>)
+        .product::<Result<usize, _>>()
+        .with_context(|| format!("Invalid shape ({shape}) in {}", path.display()))?;
+    if len != data.len() {
+        bail!(
+            "{} has {len} elements, expected {}",
+            path.display(),
+            data.len()
+        );
+    }
+    let mut buf = [0; 4];
+    for x in data {
+        reader.read_exact(&mut buf)?;
+        *x = f32::from_le_bytes(buf);
+    }
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("viri-npy-test-{}.npy", std::process::id()));
+        let data = [1.0, -2.5, 3.25, 0.0, 1e-3, 7.0];
+        super::write(&path, &[2, 3], &data).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!((bytes.len() - data.len() * 4) % 64, 0);
+        let mut out = [0.0; 6];
+        super::read(&path, &mut out).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(data
+            .iter()
+            .zip(&out)
+            .all(|(a, b)| a.to_bits() == b.to_bits()));
+        let mut wrong_size = [0.0; 5];
+        assert!(super::write(&path, &[5], &wrong_size).is_ok());
+        assert!(super::read(&path, &mut wrong_size[..4]).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! A small network, for positions that are so clearly decided that the full network's
+//! extra accuracy can't change the outcome. It has no king buckets and a narrow hidden
+//! layer, so refreshing its accumulator from scratch costs less than evaluating the full
+//! network does, and no incremental updates are kept for it.
+
+use std::{path::Path, sync::Mutex};
+
+use anyhow::{bail, Context};
+
+use crate::chess::{board::Board, piece::Colour, types::Square};
+
+/// The number of input features: every piece type of each colour, on every square.
+const INPUT: usize = 12 * 64;
+/// The size of one-half of the hidden layer of the network.
+pub const L1_SIZE: usize = 128;
+/// The amount to scale the output of the network by.
+const SCALE: i32 = 400;
+const QA: i16 = 255;
+const QB: i16 = 64;
+
+/// The number of `i16` values in a network file, not counting any padding at the end.
+pub const PARAMETER_COUNT: usize = INPUT * L1_SIZE + L1_SIZE + 2 * L1_SIZE + 1;
+
+/// The network loaded with the `SmallNetFile` option, if any.
+static LOADED: Mutex<Option<&'static SmallNetwork>> = Mutex::new(None);
+
+/// A quantised small network, in the layout that bullet writes for a simple
+/// `(768 -> N)x2 -> 1` network with a `SCReLU` activation.
+pub struct SmallNetwork {
+    /// One row of weights for each input feature.
+    ft_weights: Vec<[i16; L1_SIZE]>,
+    ft_biases: [i16; L1_SIZE],
+    /// The output weights for the side to move's half of the hidden layer, then the other's.
+    out_weights: [[i16; L1_SIZE]; 2],
+    out_bias: i16,
+}
+
+impl SmallNetwork {
+    /// Read a network from the little-endian bytes of a network file.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        // bullet pads its output to a multiple of 64 bytes.
+        let size = PARAMETER_COUNT * 2;
+        if bytes.len() != size && bytes.len() != size.next_multiple_of(64) {
+            bail!(
+                "small network file is {} bytes, but a network with {L1_SIZE} hidden neurons is {size} bytes",
+                bytes.len()
+            );
+        }
+        let mut values = bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]));
+        let mut row = || std::array::from_fn(|_| values.next().unwrap_or_default());
+        let ft_weights = (0..INPUT).map(|_| row()).collect();
+        let ft_biases = row();
+        let out_weights = [row(), row()];
+        let out_bias = values.next().unwrap_or_default();
+        Ok(Self {
+            ft_weights,
+            ft_biases,
+            out_weights,
+            out_bias,
+        })
+    }
+
+    /// The row of the feature transformer for `piece` on `sq`, from the point of view of `colour`.
+    fn feature(colour: Colour, board: &Board, sq: Square) -> usize {
+        const COLOUR_STRIDE: usize = 64 * 6;
+        const PIECE_STRIDE: usize = 64;
+
+        let piece = board.piece_at(sq).expect("feature for an empty square");
+        let theirs = usize::from(piece.colour() != colour);
+        theirs * COLOUR_STRIDE
+            + piece.piece_type().index() * PIECE_STRIDE
+            + sq.relative_to(colour).index()
+    }
+}
+
+/// Load the small network from a file, for all searches started from now on.
+pub fn load(path: &Path) -> anyhow::Result<()> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("failed to read small network file {}", path.display()))?;
+    let net = SmallNetwork::from_bytes(&bytes)?;
+    // searches keep a reference to the network, so it lives for the rest of the program.
+    set_loaded(Some(Box::leak(Box::new(net))));
+    Ok(())
+}
+
+/// Set the small network used by searches started from now on, or stop using one.
+pub fn set_loaded(net: Option<&'static SmallNetwork>) {
+    *LOADED.lock().unwrap() = net;
+}
+
+/// The small network to use, if one has been loaded.
+pub fn loaded() -> Option<&'static SmallNetwork> {
+    *LOADED.lock().unwrap()
+}
+
+/// The hidden layer of the small network, from each side's point of view.
+pub struct SmallAccumulator {
+    values: [[i16; L1_SIZE]; 2],
+}
+
+impl SmallAccumulator {
+    pub const fn new() -> Self {
+        Self {
+            values: [[0; L1_SIZE]; 2],
+        }
+    }
+
+    /// Compute the hidden layer for `board` from scratch.
+    pub fn refresh(&mut self, net: &SmallNetwork, board: &Board) {
+        for colour in Colour::all() {
+            let values = &mut self.values[colour];
+            *values = net.ft_biases;
+            for sq in board.pieces.occupied() {
+                let weights = &net.ft_weights[SmallNetwork::feature(colour, board, sq)];
+                for (v, &w) in values.iter_mut().zip(weights) {
+                    *v = v.wrapping_add(w);
+                }
+            }
+        }
+    }
+
+    /// The network's evaluation of the position, from the point of view of `stm`.
+    pub fn evaluate(&self, net: &SmallNetwork, stm: Colour) -> i32 {
+        #![allow(clippy::cast_possible_truncation)]
+        let halves = [&self.values[stm], &self.values[stm.flip()]];
+        let mut sum = 0i64;
+        for (values, weights) in halves.into_iter().zip(&net.out_weights) {
+            for (&v, &w) in values.iter().zip(weights) {
+                let v = i64::from(v.clamp(0, QA));
+                sum += v * v * i64::from(w);
+            }
+        }
+        // the squared activations carry an extra factor of QA, which we divide out first.
+        let output = sum / i64::from(QA) + i64::from(net.out_bias);
+        (output * i64::from(SCALE) / (i64::from(QA) * i64::from(QB))) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn files_of_the_wrong_size_are_refused() {
+        use super::{SmallNetwork, PARAMETER_COUNT};
+
+        let size = PARAMETER_COUNT * 2;
+        assert!(SmallNetwork::from_bytes(&vec![0; size]).is_ok());
+        assert!(SmallNetwork::from_bytes(&vec![0; size.next_multiple_of(64)]).is_ok());
+        assert!(SmallNetwork::from_bytes(&vec![0; size - 2]).is_err());
+        assert!(SmallNetwork::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn evaluation_is_from_the_side_to_move() {
+        use super::{SmallAccumulator, SmallNetwork, INPUT, L1_SIZE, PARAMETER_COUNT, QB};
+        use crate::chess::{board::Board, piece::Colour};
+
+        // a network with one neuron, which counts the queens of the side it's looking from,
+        // and counts them in favour of the side to move.
+        let mut params = vec![0i16; PARAMETER_COUNT];
+        for row in 4 * 64..5 * 64 {
+            params[row * L1_SIZE] = 64;
+        }
+        let out_weights = INPUT * L1_SIZE + L1_SIZE;
+        params[out_weights] = QB;
+        params[out_weights + L1_SIZE] = -QB;
+        let bytes = params
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        let net = SmallNetwork::from_bytes(&bytes).unwrap();
+
+        let eval = |fen: &str, stm: Colour| {
+            let board = Board::from_fen(fen).unwrap();
+            let mut acc = SmallAccumulator::new();
+            acc.refresh(&net, &board);
+            acc.evaluate(&net, stm)
+        };
+        // white has two queens to black's one.
+        let fen = "3qk3/8/8/8/8/8/8/2QQK3 w - - 0 1";
+        assert!(eval(fen, Colour::White) > 0);
+        assert_eq!(eval(fen, Colour::Black), -eval(fen, Colour::White));
+        assert_eq!(eval("3qk3/8/8/8/8/8/8/3QK3 w - - 0 1", Colour::White), 0);
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use super::network::{nnue_checksum, ActivationStats, L1_SIZE};
+
+/// The number of bins in the histogram of neuron activation frequencies.
+const FREQUENCY_BINS: usize = 10;
+
+/// Write an HTML page gathering the neuron heatmaps produced by `visualise_neuron`
+/// (which must live in the same directory) alongside activation statistics.
+pub fn write_html_report(
+    path: &Path,
+    stats: &ActivationStats,
+    (min_weight, max_weight): (i16, i16),
+) -> anyhow::Result<()> {
+    #![allow(clippy::cast_precision_loss)]
+    // two accumulators per position.
+    let samples = (stats.positions * 2).max(1) as f64;
+    let dead = stats.dead_neurons().collect::<Vec<_>>();
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Viridithas NNUE report</title>\n<style>\n");
+    html.push_str("body { font-family: sans-serif; background: #111; color: #ddd; }\n");
+    html.push_str(
+        "table { border-collapse: collapse; } td, th { padding: 2px 8px; text-align: right; }\n",
+    );
+    html.push_str(".bar { background: #e8641c; height: 12px; }\n");
+    html.push_str(".gallery { display: flex; flex-wrap: wrap; gap: 6px; }\n");
+    html.push_str("figure { margin: 0; font-size: 11px; }\n");
+    html.push_str("figure img { width: 159px; image-rendering: pixelated; display: block; }\n");
+    html.push_str("figure.dead { outline: 2px solid #c00; }\n");
+    html.push_str("</style>\n</head>\n<body>\n");
+
+    writeln!(
+        html,
+        "<h1>NNUE report for net {:016X}</h1>",
+        nnue_checksum()
+    )?;
+    writeln!(
+        html,
+        "<p>{} positions. Feature weights range from {min_weight} to {max_weight}.</p>",
+        stats.positions
+    )?;
+
+    html.push_str("<h2>Output bucket usage</h2>\n<table>\n<tr><th>bucket</th><th>positions</th><th></th></tr>\n");
+    let max_bucket = stats
+        .bucket_counts
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    for (bucket, &count) in stats.bucket_counts.iter().enumerate() {
+        writeln!(
+            html,
+            "<tr><td>{bucket}</td><td>{count}</td><td><div class=\"bar\" style=\"width: {}px\"></div></td></tr>",
+            count * 300 / max_bucket
+        )?;
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>L1 activation frequency</h2>\n<table>\n<tr><th>active in</th><th>neurons</th><th></th></tr>\n");
+    let mut bins = [0u64; FREQUENCY_BINS];
+    for &count in &stats.active_counts {
+        let frequency = count as f64 / samples;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let bin = ((frequency * FREQUENCY_BINS as f64) as usize).min(FREQUENCY_BINS - 1);
+        bins[bin] += 1;
+    }
+    let max_bin = bins.iter().copied().max().unwrap_or(0).max(1);
+    for (bin, &count) in bins.iter().enumerate() {
+        writeln!(
+            html,
+            "<tr><td>{}-{}%</td><td>{count}</td><td><div class=\"bar\" style=\"width: {}px\"></div></td></tr>",
+            bin * 100 / FREQUENCY_BINS,
+            (bin + 1) * 100 / FREQUENCY_BINS,
+            count * 300 / max_bin
+        )?;
+    }
+    html.push_str("</table>\n");
+
+    writeln!(html, "<h2>Dead neurons ({} of {L1_SIZE})</h2>", dead.len())?;
+    if dead.is_empty() {
+        html.push_str("<p>None.</p>\n");
+    } else {
+        let list = dead.iter().map(ToString::to_string).collect::<Vec<_>>();
+        writeln!(html, "<p>{}</p>", list.join(", "))?;
+    }
+
+    html.push_str("<h2>Neurons</h2>\n<div class=\"gallery\">\n");
+    for neuron in 0..L1_SIZE {
+        let count = stats.active_counts[neuron];
+        let class = if count == 0 { " class=\"dead\"" } else { "" };
+        let mean = if count == 0 {
+            0.0
+        } else {
+            stats.activation_sums[neuron] as f64 / count as f64
+        };
+        writeln!(
+            html,
+            "<figure{class}><img src=\"neuron_{neuron}.bmp\" alt=\"neuron {neuron}\"><figcaption>#{neuron}: active {:.1}%, mean {mean:.0}</figcaption></figure>",
+            count as f64 / samples * 100.0
+        )?;
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(html.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    writer.flush()?;
+    Ok(())
+}
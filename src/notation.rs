@@ -0,0 +1,250 @@
+//! Writing games and analysis as PGN: tag pairs, move numbers, score comments, and
+//! movetext wrapped to the usual width.
+
+use std::fmt::Write as _;
+
+use anyhow::anyhow;
+
+use crate::{
+    chess::{
+        board::{eco::Opening, Board},
+        piece::Colour,
+    },
+    evaluation::{is_mate_score, MATE_SCORE},
+    search::pv::PVariation,
+};
+
+/// The longest a line of movetext can be.
+const LINE_WIDTH: usize = 80;
+
+/// The number of moves to mate for a mate score, negative if we're the ones getting mated.
+const fn mate_moves(score: i32) -> i32 {
+    let moves = (MATE_SCORE - score.abs() + 1) / 2;
+    if score > 0 {
+        moves
+    } else {
+        -moves
+    }
+}
+
+/// Format a white-relative score in the usual PGN comment style.
+pub fn pgn_score(score: i32) -> String {
+    if is_mate_score(score) {
+        format!("#{}", mate_moves(score))
+    } else {
+        format!("{:+.2}", f64::from(score) / 100.0)
+    }
+}
+
+/// Format a white-relative score and the depth it was searched to as an `[%eval]` command,
+/// which most GUIs and analysis tools will read out of a comment.
+pub fn eval_command(score: i32, depth: usize) -> String {
+    if is_mate_score(score) {
+        format!("[%eval #{},{depth}]", mate_moves(score))
+    } else {
+        format!("[%eval {:.2},{depth}]", f64::from(score) / 100.0)
+    }
+}
+
+/// The move number for a move made at `ply`, with the ellipsis that marks black's moves.
+pub fn move_number(ply: usize) -> String {
+    let dots = if ply.is_multiple_of(2) { "." } else { "..." };
+    format!("{}{dots}", ply / 2 + 1)
+}
+
+/// Write the tag pairs for a game with no result yet, and a blank line to end them.
+/// Games that don't begin from the starting position need their `fen`.
+pub fn write_headers(pgn: &mut String, event: &str, fen: Option<&str>, opening: Option<Opening>) {
+    writeln!(pgn, "[Event \"{event}\"]").unwrap();
+    writeln!(pgn, "[White \"?\"]").unwrap();
+    writeln!(pgn, "[Black \"?\"]").unwrap();
+    writeln!(pgn, "[Result \"*\"]").unwrap();
+    writeln!(pgn, "[Annotator \"{} {}\"]", crate::NAME, crate::VERSION).unwrap();
+    if let Some(Opening { code, name }) = opening {
+        writeln!(pgn, "[ECO \"{code}\"]").unwrap();
+        writeln!(pgn, "[Opening \"{name}\"]").unwrap();
+    }
+    if let Some(fen) = fen {
+        writeln!(pgn, "[SetUp \"1\"]").unwrap();
+        writeln!(pgn, "[FEN \"{fen}\"]").unwrap();
+    }
+    pgn.push('\n');
+}
+
+/// Join `words` with spaces into lines no longer than [`LINE_WIDTH`], unless a word
+/// is longer than that by itself.
+pub fn wrap_movetext(pgn: &mut String, words: &[String]) {
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + word.len() + 1 > LINE_WIDTH {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        } else if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+}
+
+/// Write `pv`, searched to `depth` from `board`, as a PGN whose first move carries the
+/// evaluation, ready to be pasted into another tool.
+pub fn pv_to_pgn(board: &mut Board, pv: &PVariation, depth: usize) -> anyhow::Result<String> {
+    let sans = board
+        .pv_san(pv)
+        .map_err(|_| anyhow!("Failed to write the PV in SAN"))?;
+    let score = if board.turn() == Colour::White {
+        pv.score().get()
+    } else {
+        -pv.score().get()
+    };
+
+    let mut pgn = String::new();
+    let fen = board.to_string();
+    write_headers(
+        &mut pgn,
+        "Analysis",
+        (fen != Board::STARTING_FEN).then_some(fen.as_str()),
+        board.eco(),
+    );
+
+    let mut words = Vec::new();
+    for (i, san) in sans.split_ascii_whitespace().enumerate() {
+        let ply = board.ply() + i;
+        // black's move needs its number if it begins the line or follows the comment.
+        if ply.is_multiple_of(2) || i < 2 {
+            words.push(format!("{} {san}", move_number(ply)));
+        } else {
+            words.push(san.to_string());
+        }
+        if i == 0 {
+            words.push(format!("{{{}}}", eval_command(score, depth)));
+        }
+    }
+    words.push("*".into());
+    wrap_movetext(&mut pgn, &words);
+    Ok(pgn)
+}
+
+/// A single PGN game, as its starting position and the moves played from it.
+pub struct PgnGame {
+    /// The `FEN` tag, for games that don't begin from the starting position.
+    pub fen: Option<String>,
+    /// The moves of the game's main line, in SAN.
+    pub moves: Vec<String>,
+}
+
+/// Read every game in `text`, keeping only the main line of each.
+pub fn read_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut fen = None;
+    let mut movetext = String::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            if !movetext.trim().is_empty() {
+                games.push(PgnGame {
+                    fen: fen.take(),
+                    moves: san_tokens(&movetext),
+                });
+                movetext.clear();
+            }
+            if let Some(value) = line
+                .strip_prefix("[FEN \"")
+                .and_then(|rest| rest.strip_suffix("\"]"))
+            {
+                fen = Some(value.to_string());
+            }
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    if !movetext.trim().is_empty() {
+        games.push(PgnGame {
+            fen,
+            moves: san_tokens(&movetext),
+        });
+    }
+    games
+}
+
+/// Split PGN movetext into its moves, skipping comments, variations, NAGs, move numbers and results.
+fn san_tokens(movetext: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut comment = false;
+    let mut line_comment = false;
+    let mut variation_depth = 0usize;
+    for c in movetext.chars() {
+        match c {
+            _ if line_comment => line_comment = c != '\n',
+            '}' if comment => comment = false,
+            _ if comment => {}
+            '{' => comment = true,
+            ';' => line_comment = true,
+            '(' => variation_depth += 1,
+            ')' => variation_depth = variation_depth.saturating_sub(1),
+            _ if variation_depth > 0 => {}
+            c if c.is_whitespace() => tokens.push(std::mem::take(&mut token)),
+            c => token.push(c),
+        }
+    }
+    tokens.push(token);
+    tokens
+        .iter()
+        .filter(|t| !matches!(t.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*"))
+        .map(|t| t.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.'))
+        .filter(|t| !t.is_empty() && !t.starts_with('$'))
+        .map(str::to_string)
+        .collect()
+}
+
+mod tests {
+    #[test]
+    fn pv_export() {
+        use super::pv_to_pgn;
+        use crate::{chess::board::Board, evaluation::Value, search::pv::PVariation};
+
+        let mut board = Board::default();
+        board.set_startpos();
+        let mut pv = PVariation::default();
+        for san in ["e4", "e5", "Nf3"] {
+            let m = board.parse_san(san).unwrap();
+            pv.moves.push(m);
+            board.make_move_simple(m);
+        }
+        for _ in 0..3 {
+            board.unmake_move_base();
+        }
+        pv.score = Value::cp(30);
+        assert!(pv_to_pgn(&mut board, &pv, 12)
+            .unwrap()
+            .ends_with("]\n\n1. e4 {[%eval 0.30,12]} 1... e5 2. Nf3 *\n"));
+
+        // black to move, and mating.
+        let fen = "r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut pv = PVariation::default();
+        pv.moves.push(board.parse_san("Ra1#").unwrap());
+        pv.score = Value::mate_in(1);
+        let pgn = pv_to_pgn(&mut board, &pv, 5).unwrap();
+        assert!(pgn.contains(&format!("[FEN \"{fen}\"]")));
+        assert!(pgn.ends_with("\n\n1... Ra1# {[%eval #-1,5]} *\n"));
+    }
+
+    #[test]
+    fn pgn_movetext() {
+        let pgn = "[Event \"?\"]\n[FEN \"8/8/8/4k3/8/8/8/4K2R w K - 0 1\"]\n\n1. Rh5+ {check} Kd4 (1... Ke4 2. Rh4+) 2. Kd2 $1 1-0\n\n[Event \"?\"]\n\n1.e4 e5 2.Nf3 1/2-1/2\n";
+        let games = super::read_pgn(pgn);
+        assert_eq!(games.len(), 2);
+        assert_eq!(
+            games[0].fen.as_deref(),
+            Some("8/8/8/4k3/8/8/8/4K2R w K - 0 1")
+        );
+        assert_eq!(games[0].moves, ["Rh5+", "Kd4", "Kd2"]);
+        assert_eq!(games[1].fen, None);
+        assert_eq!(games[1].moves, ["e4", "e5", "Nf3"]);
+    }
+}
@@ -24,13 +24,16 @@ pub fn perft(pos: &mut Board, depth: usize) -> u64 {
     }
 
     let mut ml = MoveList::new();
-    pos.generate_moves(&mut ml);
+    pos.generate_legal_moves(&mut ml);
+
+    if depth == 1 {
+        return ml.len() as u64;
+    }
 
     let mut count = 0;
     for &m in ml.iter_moves() {
-        if !pos.make_move_simple(m) {
-            continue;
-        }
+        let legal = pos.make_move_simple(m);
+        debug_assert!(legal, "generated an illegal move in {pos}");
         count += perft(pos, depth - 1);
         pos.unmake_move_base();
     }
@@ -0,0 +1,261 @@
+//! Opening repertoire construction: starting from a position, every move that scores within
+//! a margin of the best is expanded into a tree, which is written out as an annotated PGN.
+
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, AtomicU64},
+};
+
+use anyhow::Context;
+
+use crate::{
+    chess::{board::Board, chessmove::Move, piece::Colour},
+    evaluation::MATE_SCORE,
+    nnue::network::NNUEParams,
+    notation::{move_number, pgn_score, wrap_movetext, write_headers},
+    search::{parameters::Config, LMTable},
+    searchinfo::SearchInfo,
+    threadlocal::ThreadData,
+    timemgmt::SearchLimit,
+    transpositiontable::TT,
+    util::MEGABYTE,
+};
+
+pub struct RepertoireOptions {
+    /// The position to build the repertoire from. Defaults to the starting position.
+    pub fen: Option<String>,
+    /// The depth to search each candidate move to.
+    pub depth: i32,
+    /// Moves scoring within this many centipawns of the best move are expanded.
+    pub margin: i32,
+    /// The most moves to expand from any one position.
+    pub width: usize,
+    /// How many plies deep to build the tree.
+    pub plies: usize,
+    /// Hash table size, in megabytes.
+    pub hash_mb: usize,
+    /// Where to write the PGN. Defaults to stdout.
+    pub output: Option<PathBuf>,
+}
+
+/// A move in the repertoire, along with the repertoire that follows it.
+struct Line {
+    san: String,
+    /// The score after the move, from white's perspective.
+    score: i32,
+    depth: usize,
+    /// Whether the position after the move has already been expanded elsewhere in the tree.
+    transposition: bool,
+    children: Vec<Self>,
+}
+
+struct Builder<'a> {
+    options: &'a RepertoireOptions,
+    info: SearchInfo<'a>,
+    tt: &'a TT,
+    thread_data: Vec<ThreadData<'a>>,
+    expanded: HashSet<u64>,
+}
+
+impl Builder<'_> {
+    /// Score every legal move by searching the position it leads to, returning the moves
+    /// that fall within the margin of the best, best first.
+    fn candidates(&mut self, board: &mut Board) -> Vec<(Move, i32, usize)> {
+        let white = board.turn() == Colour::White;
+        let mut scored = Vec::new();
+        for m in board.legal_moves() {
+            board.make_move_simple(m);
+            let scored_move = if board.legal_moves().is_empty() {
+                // checkmate is always bad for the side to move.
+                let mated = if board.turn() == Colour::White {
+                    -MATE_SCORE + 1
+                } else {
+                    MATE_SCORE - 1
+                };
+                (m, if board.in_check() { mated } else { 0 }, 0)
+            } else if board.is_draw() {
+                (m, 0, 0)
+            } else {
+                self.info
+                    .time_manager
+                    .set_limit(SearchLimit::Depth((self.options.depth - 1).max(1)));
+                self.info.time_manager.start();
+                // search a copy, as searching resets the board's height.
+                let (score, _) = board.clone().search_position(
+                    &mut self.info,
+                    &mut self.thread_data,
+                    self.tt.view(),
+                );
+                (m, score.get(), self.thread_data[0].completed + 1)
+            };
+            board.unmake_move_base();
+            scored.push(scored_move);
+        }
+        // sort best-first for the side to move.
+        if white {
+            scored.sort_by_key(|&(_, score, _)| -score);
+        } else {
+            scored.sort_by_key(|&(_, score, _)| score);
+        }
+        let Some(&(_, best, _)) = scored.first() else {
+            return scored;
+        };
+        scored.retain(|&(_, score, _)| (score - best).abs() <= self.options.margin);
+        scored.truncate(self.options.width.max(1));
+        scored
+    }
+
+    fn expand(&mut self, board: &mut Board, plies: usize) -> Vec<Line> {
+        if plies == 0 {
+            return Vec::new();
+        }
+        eprintln!(
+            "expanding {board} ({} positions so far)",
+            self.expanded.len()
+        );
+        let candidates = self.candidates(board);
+        let mut lines = Vec::with_capacity(candidates.len());
+        for (m, score, depth) in candidates {
+            let san = board.san(m).expect("candidate moves are legal");
+            board.make_move_simple(m);
+            let transposition = !self.expanded.insert(board.zobrist_key());
+            let children = if transposition {
+                Vec::new()
+            } else {
+                self.expand(board, plies - 1)
+            };
+            board.unmake_move_base();
+            lines.push(Line {
+                san,
+                score,
+                depth,
+                transposition,
+                children,
+            });
+        }
+        lines
+    }
+}
+
+fn push_move(tokens: &mut Vec<String>, line: &Line, ply: usize) {
+    // every move carries a comment, so black's moves always need their number.
+    tokens.push(format!("{} {}", move_number(ply), line.san));
+    let mut comment = format!("{{{}/{}", pgn_score(line.score), line.depth);
+    if line.transposition {
+        comment.push_str(" transposes");
+    }
+    comment.push('}');
+    tokens.push(comment);
+}
+
+/// Lay out `lines` as PGN movetext, with the first line as the main line and the rest as variations.
+fn push_lines(tokens: &mut Vec<String>, lines: &[Line], ply: usize) {
+    let Some((main, alternatives)) = lines.split_first() else {
+        return;
+    };
+    push_move(tokens, main, ply);
+    for alternative in alternatives {
+        tokens.push("(".into());
+        push_move(tokens, alternative, ply);
+        push_lines(tokens, &alternative.children, ply + 1);
+        tokens.push(")".into());
+    }
+    push_lines(tokens, &main.children, ply + 1);
+}
+
+fn write_pgn(board: &Board, lines: &[Line], fen: Option<&str>) -> String {
+    let mut pgn = String::new();
+    write_headers(&mut pgn, "Repertoire", fen, board.eco());
+
+    let mut tokens = Vec::new();
+    push_lines(&mut tokens, lines, board.ply());
+    tokens.push("*".into());
+    // parentheses stick to the moves they enclose, so lines only break between moves and comments.
+    let mut words = Vec::<String>::new();
+    let mut open = false;
+    for token in tokens {
+        match token.as_str() {
+            "(" => open = true,
+            ")" => words
+                .last_mut()
+                .expect("variations are never empty")
+                .push(')'),
+            _ if open => {
+                words.push(format!("({token}"));
+                open = false;
+            }
+            _ => words.push(token),
+        }
+    }
+    wrap_movetext(&mut pgn, &words);
+    pgn
+}
+
+pub fn run_repertoire(options: &RepertoireOptions, conf: &Config) -> anyhow::Result<()> {
+    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let mut board = Board::default();
+    match &options.fen {
+        Some(fen) => board
+            .set_from_fen(fen)
+            .with_context(|| format!("Failed to parse FEN {fen}"))?,
+        None => board.set_startpos(),
+    }
+
+    let stopped = AtomicBool::new(false);
+    let nodes = AtomicU64::new(0);
+    let mut tt = TT::new();
+    tt.resize(options.hash_mb * MEGABYTE);
+    let mut builder = Builder {
+        options,
+        info: SearchInfo {
+            print_to_stdout: false,
+            conf: conf.clone(),
+            lm_table: LMTable::new(conf),
+            ..SearchInfo::new(&stopped, &nodes)
+        },
+        tt: &tt,
+        thread_data: vec![ThreadData::new(0, &board, tt.view(), nnue_params)],
+        expanded: HashSet::from([board.zobrist_key()]),
+    };
+    let lines = builder.expand(&mut board, options.plies);
+    eprintln!("expanded {} positions", builder.expanded.len());
+
+    let pgn = write_pgn(&board, &lines, options.fen.as_deref());
+    match &options.output {
+        Some(path) => std::fs::write(path, pgn)
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+        None => print!("{pgn}"),
+    }
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn pgn_layout() {
+        use super::{write_pgn, Line};
+        use crate::chess::board::Board;
+
+        let line = |san: &str, score, children| Line {
+            san: san.into(),
+            score,
+            depth: 10,
+            transposition: false,
+            children,
+        };
+        let lines = vec![
+            line(
+                "e4",
+                30,
+                vec![line("e5", 25, vec![]), line("c5", 35, vec![])],
+            ),
+            line("d4", 20, vec![line("d5", 20, vec![])]),
+        ];
+        let mut board = Board::default();
+        board.set_startpos();
+        let pgn = write_pgn(&board, &lines, None);
+        assert!(pgn.ends_with(
+            "\n\n1. e4 {+0.30/10} (1. d4 {+0.20/10} 1... d5 {+0.20/10}) 1... e5 {+0.25/10}\n(1... c5 {+0.35/10}) *\n"
+        ));
+    }
+}
@@ -1,4 +1,7 @@
+use std::hash::{BuildHasher, Hasher, RandomState};
+
 const SEED: u128 = 0x246C_CB2D_3B40_2853_9918_0A6D_BC3A_F444;
+#[derive(Clone, Debug)]
 pub struct XorShiftState {
     pub state: u128,
 }
@@ -44,3 +47,22 @@ impl XorShiftState {
         first & second & third
     }
 }
+
+/// The `SplitMix64` finaliser, which spreads every bit of the input across the output.
+pub const fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// A seed that differs from run to run, for when none was given.
+pub fn fresh_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// The seed for item `index` of a run seeded with `seed`. Each item's seed depends only
+/// on the run's seed and its own index, so items can be reproduced in any order, or alone.
+#[cfg_attr(not(feature = "datagen"), allow(dead_code))]
+pub const fn seed_for(seed: u64, index: u64) -> u64 {
+    mix(seed ^ mix(index.wrapping_add(0x9E37_79B9_7F4A_7C15)))
+}
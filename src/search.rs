@@ -2,31 +2,36 @@
 
 pub mod parameters;
 pub mod pv;
+pub mod rootmoves;
+pub mod sparring;
+pub mod teaching;
+pub mod variety;
 
 use std::{
     ops::ControlFlow,
-    sync::atomic::{AtomicU64, Ordering},
-    thread,
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
 };
 
 use arrayvec::ArrayVec;
 
 use crate::{
+    adjudication::Signal,
     cfor,
     chess::{
         board::{
-            movegen::{self, MoveListEntry, MAX_POSITION_MOVES},
+            movegen::{self, MAX_POSITION_MOVES},
             Board,
         },
-        chessmove::Move,
+        chessmove::{Move, ScoredMove},
         piece::{Colour, Piece, PieceType},
         squareset::SquareSet,
         types::{ContHistIndex, Square},
         CHESS960,
     },
     evaluation::{
-        is_game_theoretic_score, mate_in, mated_in, tb_loss_in, tb_win_in, MATE_SCORE,
-        MINIMUM_TB_WIN_SCORE,
+        is_game_theoretic_score, mate_in, mated_in, Value, MATE_SCORE, MINIMUM_TB_WIN_SCORE,
     },
     historytable::history_bonus,
     movepicker::{MovePicker, Stage, WINNING_CAPTURE_SCORE},
@@ -34,9 +39,13 @@ use crate::{
     searchinfo::SearchInfo,
     tablebases::{self, probe::WDL},
     threadlocal::ThreadData,
+    threadpool::{self, ScopedJob},
     transpositiontable::{Bound, TTHit, TTView},
     uci,
-    util::{INFINITY, MAX_DEPTH, MAX_PLY, VALUE_NONE},
+    util::{
+        depth::{Depth, UNITS_PER_PLY},
+        INFINITY, MAX_DEPTH, MAX_PLY, VALUE_NONE,
+    },
 };
 
 use self::parameters::Config;
@@ -57,14 +66,32 @@ const RFP_MARGIN: i32 = 64;
 const RFP_IMPROVING_MARGIN: i32 = 50;
 const NMP_IMPROVING_MARGIN: i32 = 72;
 const NMP_REDUCTION_EVAL_DIVISOR: i32 = 192;
+const NMP_MIN_DEPTH: i32 = 3;
+const NMP_BASE_REDUCTION: i32 = 4;
+const NMP_REDUCTION_DEPTH_DIVISOR: i32 = 3;
+const NMP_REDUCTION_EVAL_MAX: i32 = 4;
+const NMP_VERIFICATION_DEPTH: i32 = 12;
+const NMP_WORSENING_REDUCTION: i32 = 1;
+// a castled king has six squares around it, so this is most of them.
+const KING_DANGER_THRESHOLD: u32 = 5;
 const SEE_QUIET_MARGIN: i32 = -78;
 const SEE_TACTICAL_MARGIN: i32 = -22;
 const FUTILITY_COEFF_0: i32 = 82;
 const FUTILITY_COEFF_1: i32 = 101;
+const FUTILITY_WORSENING_MARGIN: i32 = 32;
+const LMP_WORSENING_MOVES: i32 = 2;
 const RAZORING_COEFF_0: i32 = 427;
 const RAZORING_COEFF_1: i32 = 167;
 const PROBCUT_MARGIN: i32 = 227;
 const PROBCUT_IMPROVING_MARGIN: i32 = 58;
+// multi-cut is off until it passes a match: no search gets this deep.
+const MULTI_CUT_DEPTH: i32 = 8;
+const MULTI_CUT_REDUCTION: i32 = 4;
+const MULTI_CUT_MOVES: i32 = 6;
+const MULTI_CUT_REQUIRED: i32 = 3;
+const TT_FAST_PATH_DEPTH_MARGIN: i32 = 3;
+const TT_FAST_PATH_WINDOW_MARGIN: i32 = 30;
+const TT_FAST_PATH_FIFTY_LIMIT: i32 = 96;
 const DOUBLE_EXTENSION_MARGIN: i32 = 12;
 const LMR_BASE: f64 = 85.0;
 const LMR_DIVISION: f64 = 206.0;
@@ -83,6 +110,9 @@ const LMR_TTPV_MUL: i32 = 1202;
 const LMR_CUT_NODE_MUL: i32 = 1284;
 const LMR_NON_IMPROVING_MUL: i32 = 689;
 const LMR_TT_CAPTURE_MUL: i32 = 1141;
+// late move reductions are made in multiples of this many depth units.
+// a whole ply by default, until fractional reductions pass a match.
+const LMR_GRANULARITY: i32 = UNITS_PER_PLY;
 
 const HISTORY_BONUS_MUL: i32 = 251;
 const HISTORY_BONUS_OFFSET: i32 = 172;
@@ -92,6 +122,8 @@ const HISTORY_MALUS_MUL: i32 = 225;
 const HISTORY_MALUS_OFFSET: i32 = 278;
 const HISTORY_MALUS_MAX: i32 = 1244;
 
+const HISTORY_MAX: i32 = crate::historytable::MAX_HISTORY as i32;
+
 const PAWN_CORRHIST_WEIGHT: i32 = 1191;
 const MAJOR_CORRHIST_WEIGHT: i32 = 1289;
 const MINOR_CORRHIST_WEIGHT: i32 = 1290;
@@ -99,8 +131,6 @@ const NONPAWN_CORRHIST_WEIGHT: i32 = 1319;
 
 const TIME_MANAGER_UPDATE_MIN_DEPTH: i32 = 4;
 
-static TB_HITS: AtomicU64 = AtomicU64::new(0);
-
 pub trait NodeType {
     /// Whether this node is on the principal variation.
     const PV: bool;
@@ -152,6 +182,24 @@ impl SmpThreadType for HelperThread {
     const MAIN_THREAD: bool = false;
 }
 
+/// Which side would be able to claim a draw after a move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DrawClaim {
+    /// The move itself repeats the position for the third time or completes fifty moves.
+    Ours,
+    /// The opponent has a reply that does.
+    Theirs,
+}
+
+/// How much worse, in centipawns, a move may score than the best move
+/// and still be preferred to it in order to avoid a draw claim.
+const DRAW_AVOIDANCE_MARGIN: i32 = 10;
+
+/// How long to search before telling the GUI which root move we're on.
+const CURRMOVE_REPORT_DELAY: Duration = Duration::from_secs(3);
+/// Quiet checks are searched in this many plies at the start of quiescence search.
+const QS_CHECK_PLIES: usize = 1;
+
 impl Board {
     /// Performs the root search. Returns the score of the position, from white's perspective, and the best move.
     #[allow(clippy::too_many_lines)]
@@ -160,41 +208,63 @@ impl Board {
         info: &mut SearchInfo,
         thread_headers: &mut [ThreadData],
         tt: TTView,
-    ) -> (i32, Option<Move>) {
+    ) -> (Value, Option<Move>) {
         self.zero_height();
         info.set_up_for_search();
-        TB_HITS.store(0, Ordering::Relaxed);
 
         let legal_moves = self.legal_moves();
         if legal_moves.is_empty() {
             eprintln!("info string warning search called on a position with no legal moves");
             if self.in_check() {
-                println!("info depth 0 score mate 0");
+                uci::output::send("info depth 0 score mate 0".into());
             } else {
-                println!("info depth 0 score cp 0");
+                uci::output::send("info depth 0 score cp 0".into());
             }
-            println!("bestmove (none)");
-            return (0, None);
+            info.wait_for_stop();
+            uci::output::send("bestmove (none)".into());
+            return (Value::DRAW, None);
         }
-        if legal_moves.len() == 1 {
-            info.time_manager.notify_one_legal_move();
+        // with searchmoves, only those moves are candidates to be played.
+        let mut restricted = legal_moves.iter().any(|m| info.searchmoves.contains(m));
+        let candidates = if restricted {
+            legal_moves
+                .iter()
+                .filter(|m| info.searchmoves.contains(m))
+                .count()
+        } else {
+            legal_moves.len()
+        };
+        // an analyst wants the line after a forced move as much as any other.
+        if candidates == 1 && !info.analyse_mode {
+            info.time_manager.notify_instant_move();
+        }
+        if info.print_to_stdout && self.is_insufficient_material() {
+            uci::output::send("info string theoretical draw".into());
         }
 
         // Probe the tablebases if we're in a TB position and in a game.
-        if info.time_manager.is_dynamic() {
+        // The tablebase move would hide the alternatives, so this is never done in analysis.
+        let mut searchmoves = info.searchmoves.clone();
+        if info.time_manager.is_dynamic() && !restricted && !info.analyse_mode {
             if let Some((best_move, score)) = tablebases::probe::get_tablebase_move(self) {
-                let mut pv = PVariation::default();
-                pv.load_from(best_move, &PVariation::default());
-                pv.score = score;
-                TB_HITS.store(1, Ordering::SeqCst);
-                readout_info(self, Bound::Exact, &pv, 0, info, tt, 1, true);
-                if info.print_to_stdout {
-                    println!(
-                        "bestmove {}",
-                        best_move.display(CHESS960.load(Ordering::Relaxed))
-                    );
+                info.tbhits.increment();
+                if info.time_manager.instant_move_budget() == 0 {
+                    let mut pv = PVariation::default();
+                    pv.load_from(best_move, &PVariation::default());
+                    pv.score = score;
+                    readout_info(self, Bound::Exact, &pv, 0, info, tt, 1, true);
+                    if info.print_to_stdout {
+                        uci::output::send(format!(
+                            "bestmove {}",
+                            best_move.display(CHESS960.load(Ordering::Relaxed))
+                        ));
+                    }
+                    return (score, Some(best_move));
                 }
-                return (score, Some(best_move));
+                // spend the budget searching only the tablebase move, for a line to ponder on.
+                searchmoves = vec![best_move];
+                restricted = true;
+                info.time_manager.notify_instant_move();
             }
         }
 
@@ -208,35 +278,131 @@ impl Board {
         let (t1, rest) = thread_headers.split_first_mut().unwrap();
         let bcopy = self.clone();
         let icopy = info.clone();
-        thread::scope(|s| {
-            s.spawn(|| {
+        let pool = info.pool;
+        let mut exhausted = false;
+        let panicked = (0..=rest.len())
+            .map(|_| AtomicBool::new(false))
+            .collect::<Vec<_>>();
+        let mut jobs = Vec::<ScopedJob>::with_capacity(1 + rest.len());
+        let main_panicked = &panicked[0];
+        jobs.push(Box::new(|| {
+            let panic = sandboxed(0, global_stopped, || {
                 // copy data into thread
-                t1.set_up_for_search(self);
+                t1.set_up_for_search(self, &searchmoves, info.analyse_mode);
                 self.iterative_deepening::<MainThread>(info, t1);
-                global_stopped.store(true, Ordering::SeqCst);
+                info.flush_counters();
+                // if nothing stopped the search, it ran out of depth to search.
+                exhausted = !global_stopped.swap(true, Ordering::SeqCst);
             });
-            for t in rest.iter_mut() {
-                s.spawn(|| {
+            main_panicked.store(panic, Ordering::SeqCst);
+        }));
+        for (i, t) in rest.iter_mut().enumerate() {
+            let (bcopy, icopy, searchmoves) = (&bcopy, &icopy, &searchmoves);
+            let thread_panicked = &panicked[i + 1];
+            jobs.push(Box::new(move || {
+                let panic = sandboxed(i + 1, global_stopped, || {
                     // copy data into thread
                     let mut board = bcopy.clone();
                     let mut info = icopy.clone();
-                    t.set_up_for_search(&board);
+                    // only the main thread listens to the GUI, so that it sees every command.
+                    info.stdin_rx = None;
+                    t.set_up_for_search(&board, searchmoves, info.analyse_mode);
                     board.iterative_deepening::<HelperThread>(&mut info, t);
+                    info.flush_counters();
                 });
+                thread_panicked.store(panic, Ordering::SeqCst);
+            }));
+        }
+        threadpool::run(pool, jobs);
+        // a thread that panicked may have left anything in its lines, so only the
+        // threads that finished cleanly have a say in the move.
+        for (t, panicked) in thread_headers.iter_mut().zip(&panicked) {
+            if panicked.load(Ordering::SeqCst) {
+                t.forget_best_line();
             }
-        });
+        }
+
+        info.time_manager
+            .report_search_nodes(info.nodes.get_global());
 
-        let best_thread = select_best(self, thread_headers, info, tt, info.nodes.get_global());
+        let best_thread = select_best(thread_headers);
         let depth_achieved = best_thread.completed;
         let pv = best_thread.pv().clone();
-        let best_move = pv
+        // the teaching explanation needs the other candidates that the best thread considered.
+        let root_moves = (info.print_to_stdout && uci::EXPLAIN_MOVES.load(Ordering::Relaxed))
+            .then(|| best_thread.root_moves.clone());
+        // the best line is always that of a completed iteration, or of a fail-high that
+        // proved its move better than the last completed iteration's, so it's sound to play
+        // even when the search was stopped. A thread stopped before completing an iteration
+        // has no line at all, and then we fall back to a default move.
+        let searched = pv
             .moves()
             .first()
             .copied()
-            .unwrap_or_else(|| self.default_move(&thread_headers[0]));
-        let ponder_move = pv.moves().get(1);
+            .filter(|&m| thread_headers[0].root_moves.contains(m));
+        let mut best_move = searched.unwrap_or_else(|| self.default_move(&mut thread_headers[0]));
+        let mut ponder_move = searched.and_then(|_| pv.moves().get(1));
+
+        let sparring_margin = uci::SPARRING_MARGIN.load(Ordering::Relaxed);
+        if sparring_margin > 0 && !restricted && !info.analyse_mode && !pv.score.is_game_theoretic()
+        {
+            let sharper = sparring::sharper_move(
+                self,
+                best_move,
+                pv.score.get(),
+                depth_achieved,
+                sparring_margin,
+                &info.conf,
+                &mut thread_headers[0],
+            );
+            if let Some((sharper, sharpness)) = sharper {
+                if info.print_to_stdout {
+                    uci::output::send(format!(
+                        "info string sparring, playing {} (sharpness {sharpness}) instead of {}",
+                        sharper.display(CHESS960.load(Ordering::Relaxed)),
+                        best_move.display(CHESS960.load(Ordering::Relaxed))
+                    ));
+                }
+                best_move = sharper;
+                ponder_move = None;
+            }
+        }
+
+        if let Some(claim) = self.draw_claim_after(best_move) {
+            let alternative = if restricted || info.analyse_mode {
+                None
+            } else {
+                self.draw_avoiding_move(best_move, &pv, depth_achieved, tt)
+            };
+            if let Some(alternative) = alternative {
+                if info.print_to_stdout {
+                    uci::output::send(format!(
+                        "info string avoiding draw claim, playing {} instead of {}",
+                        alternative.display(CHESS960.load(Ordering::Relaxed)),
+                        best_move.display(CHESS960.load(Ordering::Relaxed))
+                    ));
+                }
+                best_move = alternative;
+                ponder_move = None;
+            } else if info.print_to_stdout {
+                let claimant = match claim {
+                    DrawClaim::Ours => "",
+                    DrawClaim::Theirs => " to opponent",
+                };
+                uci::output::send(format!(
+                    "info string draw claim available{claimant} after {}",
+                    best_move.display(CHESS960.load(Ordering::Relaxed))
+                ));
+            }
+        }
 
         if info.print_to_stdout {
+            if exhausted {
+                info.wait_for_stop();
+            }
+            if info.time_manager.reply_after().is_some() {
+                info.wait_for_reply_delay();
+            }
             // always give a final info log before ending search
             let nodes = info.nodes.get_global();
             readout_info(
@@ -251,24 +417,40 @@ impl Board {
             );
         }
 
+        if let Some(root_moves) = root_moves {
+            let t = &mut thread_headers[0];
+            t.nnue.reinit_from(self, t.nnue_params);
+            let static_eval = self.evaluate_nnue(t, &info.conf);
+            for line in teaching::explain(self, best_move, pv.score(), static_eval, &root_moves) {
+                uci::output::send(format!("info string {line}"));
+            }
+        }
+
         if info.print_to_stdout {
+            match info.adjudicator.record(self, pv.score.get()) {
+                Some(Signal::Resign) => uci::output::send("info string resign".into()),
+                Some(Signal::OfferDraw) => uci::output::send("info string offer draw".into()),
+                None => false,
+            };
             let maybe_ponder = ponder_move.map_or_else(String::new, |ponder_move| {
                 format!(
                     " ponder {}",
                     ponder_move.display(CHESS960.load(Ordering::Relaxed))
                 )
             });
-            println!(
+            // the statistics describe this search, so they go before the move that ends it.
+            if uci::SEARCH_STATS.load(Ordering::Relaxed) {
+                info.report_search_stats(thread_headers);
+            }
+            // the time spent getting the move out to the GUI is time that the
+            // clock is running but we can't account for.
+            let sending = Instant::now();
+            if uci::output::send(format!(
                 "bestmove {}{maybe_ponder}",
                 best_move.display(CHESS960.load(Ordering::Relaxed))
-            );
-            #[cfg(feature = "stats")]
-            info.print_stats();
-            #[cfg(feature = "stats")]
-            println!(
-                "branching factor: {}",
-                (info.nodes.get_global() as f64).powf(1.0 / thread_headers[0].completed as f64)
-            );
+            )) {
+                info.time_manager.record_latency(sending.elapsed());
+            }
         }
 
         assert!(
@@ -298,6 +480,7 @@ impl Board {
             !ThTy::MAIN_THREAD || t.thread_id == 0,
             "main thread must have thread_id 0"
         );
+        self.reserve_search_history();
         let mut aw = AspirationWindow::infinite();
         let mut pv = PVariation::default();
         let max_depth = info
@@ -312,9 +495,13 @@ impl Board {
         'deepening: for d in starting_depth..=max_depth {
             t.depth = d;
             if ThTy::MAIN_THREAD {
-                // consider stopping early if we've neatly completed a depth:
+                // consider stopping early if we've neatly completed a depth,
+                // unless we're analysing, where the GUI decides when we're done:
                 if (info.time_manager.is_dynamic() || info.time_manager.is_soft_nodes())
-                    && info.time_manager.is_past_opt_time(info.nodes.get_global())
+                    && !info.analyse_mode
+                    && info
+                        .time_manager
+                        .is_past_soft_limit(info.nodes.get_global())
                 {
                     info.stopped.store(true, Ordering::SeqCst);
                     break 'deepening;
@@ -331,6 +518,8 @@ impl Board {
                     break 'deepening;
                 }
             }
+            t.root_moves.order(t.pv().moves().first().copied());
+
             // aspiration loop:
             // (depth can be dynamically modified in the aspiration loop,
             // so we return out the value of depth to the caller)
@@ -346,11 +535,24 @@ impl Board {
                 aw = AspirationWindow::infinite();
             }
 
+            // keep the session file up to date, so that a crash mid-search loses little.
+            if ThTy::MAIN_THREAD && info.print_to_stdout {
+                uci::session::autosave_progress(|| uci::session::Analysis {
+                    depth: t.completed,
+                    score: t.pv().score().get(),
+                    pv: t
+                        .pv()
+                        .moves()
+                        .iter()
+                        .map(|m| m.display(CHESS960.load(Ordering::Relaxed)).to_string())
+                        .collect(),
+                });
+            }
+
             if ThTy::MAIN_THREAD && depth > TIME_MANAGER_UPDATE_MIN_DEPTH {
                 let bm_frac = if d > 8 {
                     let best_move = pv.moves[0];
-                    let best_move_subtree_size =
-                        info.root_move_nodes[best_move.from()][best_move.to()];
+                    let best_move_subtree_size = t.root_moves.nodes(best_move);
                     let tree_size = info.nodes.get_local();
                     #[allow(clippy::cast_precision_loss)]
                     Some(best_move_subtree_size as f64 / tree_size as f64)
@@ -359,7 +561,7 @@ impl Board {
                 };
                 info.time_manager.report_completed_depth(
                     depth,
-                    pv.score,
+                    pv.score.get(),
                     pv.moves[0],
                     bm_frac,
                     &info.conf,
@@ -384,19 +586,22 @@ impl Board {
         let mut depth = i32::try_from(d).unwrap();
         let min_depth = (depth / 2).max(1);
         loop {
-            pv.score = self.alpha_beta::<Root>(pv, info, t, depth, aw.alpha, aw.beta, false);
+            let score =
+                self.alpha_beta::<Root>(pv, info, t, Depth::new(depth), aw.alpha, aw.beta, false);
+            pv.score = Value::new(score);
             if info.check_up() {
                 return ControlFlow::Break(()); // we've been told to stop searching.
             }
 
-            if aw.alpha != -INFINITY && pv.score <= aw.alpha {
+            if aw.alpha != -INFINITY && score <= aw.alpha {
                 if ThTy::MAIN_THREAD && info.print_to_stdout {
+                    info.flush_counters();
                     let nodes = info.nodes.get_global();
                     let mut apv = t.pv().clone();
                     apv.score = pv.score;
                     readout_info(self, Bound::Upper, &apv, d, info, t.tt, nodes, false);
                 }
-                aw.widen_down(pv.score, depth);
+                aw.widen_down(score, depth);
                 if ThTy::MAIN_THREAD {
                     info.time_manager
                         .report_aspiration_fail(depth, Bound::Upper, &info.conf);
@@ -408,18 +613,19 @@ impl Board {
             }
             // search is either exact or fail-high, so we can update the best line.
             t.update_best_line(pv);
-            if aw.beta != INFINITY && pv.score >= aw.beta {
+            if aw.beta != INFINITY && score >= aw.beta {
                 if ThTy::MAIN_THREAD && info.print_to_stdout {
+                    info.flush_counters();
                     let nodes = info.nodes.get_global();
                     readout_info(self, Bound::Lower, t.pv(), d, info, t.tt, nodes, false);
                 }
-                aw.widen_up(pv.score, depth);
+                aw.widen_up(score, depth);
                 if ThTy::MAIN_THREAD {
                     info.time_manager
                         .report_aspiration_fail(depth, Bound::Lower, &info.conf);
                 }
                 // decrement depth:
-                if !is_game_theoretic_score(pv.score) {
+                if !pv.score.is_game_theoretic() {
                     depth = (depth - 1).max(min_depth);
                 }
 
@@ -432,7 +638,6 @@ impl Board {
             }
 
             // if we've made it here, it means we got an exact score.
-            let score = pv.score;
             let bestmove = t.pvs[t.completed]
                 .moves()
                 .first()
@@ -445,6 +650,7 @@ impl Board {
             };
 
             if ThTy::MAIN_THREAD && info.print_to_stdout {
+                info.flush_counters();
                 let total_nodes = info.nodes.get_global();
                 readout_info(
                     self,
@@ -458,17 +664,19 @@ impl Board {
                 );
             }
 
-            if info.time_manager.solved_breaker::<ThTy>(pv.score, d) == ControlFlow::Break(()) {
+            if info.time_manager.solved_breaker::<ThTy>(score, d) == ControlFlow::Break(()) {
                 info.stopped.store(true, Ordering::SeqCst);
                 return ControlFlow::Break(());
             }
 
-            if info.time_manager.mate_found_breaker::<ThTy>(pv, depth) == ControlFlow::Break(()) {
+            if !info.analyse_mode
+                && info.time_manager.mate_found_breaker::<ThTy>(pv, depth) == ControlFlow::Break(())
+            {
                 info.stopped.store(true, Ordering::SeqCst);
                 return ControlFlow::Break(());
             }
 
-            if ThTy::MAIN_THREAD {
+            if ThTy::MAIN_THREAD && !info.analyse_mode {
                 if let Some(margin) = info.time_manager.check_for_forced_move(depth) {
                     let saved_seldepth = info.seldepth;
                     let forced = self.is_forced(margin, info, t, bestmove, score, (depth - 1) / 2);
@@ -489,25 +697,82 @@ impl Board {
     }
 
     /// Give a legal default move in the case where we don't have enough time to search.
-    fn default_move(&mut self, t: &ThreadData) -> Move {
+    /// The move is one of the root moves, so it respects `searchmoves`.
+    fn default_move(&self, t: &mut ThreadData) -> Move {
         let tt_move =
             t.tt.probe_for_provisional_info(self.zobrist_key())
                 .and_then(|e| e.0);
         let mut mp = MovePicker::new(tt_move, self.get_killer_set(t), t.get_counter_move(self), 0);
         let mut m = None;
-        while let Some(MoveListEntry { mov, .. }) = mp.next(self, t) {
-            if !self.make_move_simple(mov) {
-                continue;
+        while let Some(ScoredMove { mov, .. }) = mp.next(self, t) {
+            // the root moves are all legal.
+            if t.root_moves.contains(mov) {
+                m = Some(mov);
+                break;
             }
-            // if we get here, it means the move is legal.
-            m = Some(mov);
-            self.unmake_move_base();
-            break;
         }
         m.expect("Board::default_move called on a position with no legal moves")
     }
 
-    /// Perform a tactical resolution search, searching only captures and promotions.
+    /// Whether playing `m` lets either side claim a draw: we can claim if `m` repeats the
+    /// position for the third time or completes fifty moves, and the opponent can claim
+    /// if they have a reply that does the same.
+    fn draw_claim_after(&mut self, m: Move) -> Option<DrawClaim> {
+        self.make_move_simple(m);
+        let claim = if self.is_claimable_draw() {
+            Some(DrawClaim::Ours)
+        } else {
+            let theirs = self.legal_moves().into_iter().any(|reply| {
+                self.make_move_simple(reply);
+                let claimable = self.is_claimable_draw();
+                self.unmake_move_base();
+                claimable
+            });
+            theirs.then_some(DrawClaim::Theirs)
+        };
+        self.unmake_move_base();
+        claim
+    }
+
+    /// Under positive contempt, find a move that scores about as well as `best_move`
+    /// without allowing a draw claim. Scores for the alternatives are taken from the
+    /// hash table, and only trusted if they were searched nearly as deeply as the best move.
+    fn draw_avoiding_move(
+        &mut self,
+        best_move: Move,
+        pv: &PVariation,
+        depth: usize,
+        tt: TTView,
+    ) -> Option<Move> {
+        if uci::CONTEMPT.load(Ordering::Relaxed) <= 0 {
+            return None;
+        }
+        let min_depth = i32::try_from(depth).unwrap_or(i32::MAX) - 2;
+        let mut candidates = self
+            .legal_moves()
+            .into_iter()
+            .filter(|&m| m != best_move)
+            .filter_map(|m| {
+                let hit = tt.probe(self.key_after(m), 1)?;
+                // the entry is from the opponent's perspective, so we need an
+                // upper bound on their score to get a lower bound on ours.
+                let bounded_below = matches!(hit.bound, Bound::Exact | Bound::Upper);
+                let score = -hit.value.get();
+                (bounded_below
+                    && hit.depth >= min_depth
+                    && score >= pv.score.get() - DRAW_AVOIDANCE_MARGIN)
+                    .then_some((m, score))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|&(_, score)| -score);
+        candidates
+            .into_iter()
+            .map(|(m, _)| m)
+            .find(|&m| self.draw_claim_after(m).is_none())
+    }
+
+    /// Perform a tactical resolution search, searching only captures and promotions,
+    /// along with quiet checks in the first `QS_CHECK_PLIES` plies.
     #[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
     pub fn quiescence<NT: NodeType>(
         &mut self,
@@ -516,6 +781,7 @@ impl Board {
         t: &mut ThreadData,
         mut alpha: i32,
         beta: i32,
+        qs_ply: usize,
     ) -> i32 {
         #[cfg(debug_assertions)]
         self.check_validity().unwrap();
@@ -524,6 +790,11 @@ impl Board {
             return 0;
         }
 
+        t.stats.qsearch_nodes += 1;
+
+        #[cfg(feature = "movegen-audit")]
+        self.audit_movegen();
+
         let key = self.zobrist_key();
 
         let mut local_pv = PVariation::default();
@@ -546,7 +817,7 @@ impl Board {
             return if in_check {
                 0
             } else {
-                self.evaluate(t, info.nodes.get_local())
+                self.evaluate(t, &info.conf, info.nodes.get_local()).get()
             };
         }
 
@@ -565,10 +836,10 @@ impl Board {
                 && !in_check
                 && !fifty_move_rule_near
                 && (hit.bound == Bound::Exact
-                    || (hit.bound == Bound::Lower && hit.value >= beta)
-                    || (hit.bound == Bound::Upper && hit.value <= alpha))
+                    || (hit.bound == Bound::Lower && hit.value.get() >= beta)
+                    || (hit.bound == Bound::Upper && hit.value.get() <= alpha))
             {
-                return hit.value;
+                return hit.value.get();
             }
 
             Some(hit)
@@ -583,25 +854,25 @@ impl Board {
 
         if in_check {
             // could be being mated!
-            raw_eval = VALUE_NONE;
+            raw_eval = Value::NONE;
             stand_pat = -INFINITY;
         } else if let Some(TTHit { eval: tt_eval, .. }) = &tt_hit {
             // if we have a TT hit, check the cached TT eval.
             let v = *tt_eval;
-            if v == VALUE_NONE {
+            if v.is_none() {
                 // regenerate the static eval if it's VALUE_NONE.
-                raw_eval = self.evaluate(t, info.nodes.get_local());
+                raw_eval = self.evaluate(t, &info.conf, info.nodes.get_local());
             } else {
                 // if the TT eval is not VALUE_NONE, use it.
                 raw_eval = v;
             }
-            let adj_eval = raw_eval + t.correct_evaluation(&info.conf, self);
+            let adj_eval = raw_eval.get() + t.correct_evaluation(&info.conf, self);
 
             // try correcting via search score from TT.
             // notably, this doesn't work for main search for ~reasons.
-            let (tt_flag, tt_value) = tt_hit
-                .as_ref()
-                .map_or((Bound::None, VALUE_NONE), |tte| (tte.bound, tte.value));
+            let (tt_flag, tt_value) = tt_hit.as_ref().map_or((Bound::None, VALUE_NONE), |tte| {
+                (tte.bound, tte.value.get())
+            });
             if tt_flag == Bound::Exact
                 || tt_flag == Bound::Upper && tt_value < adj_eval
                 || tt_flag == Bound::Lower && tt_value > adj_eval
@@ -612,20 +883,20 @@ impl Board {
             }
         } else {
             // otherwise, use the static evaluation.
-            raw_eval = self.evaluate(t, info.nodes.get_local());
+            raw_eval = self.evaluate(t, &info.conf, info.nodes.get_local());
             // store the eval into the TT. We know that we won't overwrite anything,
             // because this branch is one where there wasn't a TT-hit.
             t.tt.store(
                 key,
                 height,
                 None,
-                VALUE_NONE,
+                Value::NONE,
                 raw_eval,
                 Bound::None,
                 0,
                 t.ss[height].ttpv,
             );
-            stand_pat = raw_eval + t.correct_evaluation(&info.conf, self);
+            stand_pat = raw_eval.get() + t.correct_evaluation(&info.conf, self);
         };
 
         if stand_pat >= beta {
@@ -649,10 +920,11 @@ impl Board {
             info.conf.qs_see_bound,
         );
         move_picker.skip_quiets = !in_check;
+        move_picker.with_quiet_checks = !in_check && qs_ply < QS_CHECK_PLIES;
 
         let futility = stand_pat + info.conf.qs_futility;
 
-        while let Some(MoveListEntry { mov: m, .. }) = move_picker.next(self, t) {
+        while let Some(ScoredMove { mov: m, .. }) = move_picker.next(self, t) {
             let is_tactical = self.is_tactical(m);
             if best_score > -MINIMUM_TB_WIN_SCORE
                 && is_tactical
@@ -673,15 +945,23 @@ impl Board {
                 piece: moved,
                 square: m.history_to_square(),
             };
+            // once we know we aren't being mated, evasions that lose material can be skipped.
+            // safe quiet evasions are still searched after the first, as they may be the
+            // only way out of the check that doesn't drop something.
+            if in_check
+                && moves_made > 0
+                && best_score > -MINIMUM_TB_WIN_SCORE
+                && !self.static_exchange_eval(m, 0)
+            {
+                continue;
+            }
             if !self.make_move(m, t) {
                 continue;
             }
-            // move found, we can start skipping quiets again:
-            move_picker.skip_quiets = true;
             info.nodes.increment();
             moves_made += 1;
 
-            let score = -self.quiescence::<NT::Next>(l_pv, info, t, -beta, -alpha);
+            let score = -self.quiescence::<NT::Next>(l_pv, info, t, -beta, -alpha, qs_ply + 1);
             self.unmake_move(t);
 
             if score > best_score {
@@ -694,8 +974,6 @@ impl Board {
                     }
                 }
                 if alpha >= beta {
-                    #[cfg(feature = "stats")]
-                    info.log_fail_high::<true>(moves_made - 1, 0);
                     break; // fail-high
                 }
             }
@@ -719,7 +997,7 @@ impl Board {
             key,
             height,
             best_move,
-            best_score,
+            Value::new(best_score),
             raw_eval,
             flag,
             0,
@@ -732,7 +1010,7 @@ impl Board {
     /// Get the two killer moves for this position.
     pub const fn get_killer_set(&self, t: &ThreadData) -> [Option<Move>; 2] {
         let ply = self.height();
-        t.killer_move_table[ply]
+        t.ss[ply].killers
     }
 
     /// Perform alpha-beta minimax search.
@@ -742,7 +1020,7 @@ impl Board {
         pv: &mut PVariation,
         info: &mut SearchInfo,
         t: &mut ThreadData,
-        mut depth: i32,
+        depth: Depth,
         mut alpha: i32,
         mut beta: i32,
         cut_node: bool,
@@ -756,11 +1034,14 @@ impl Board {
         let key = self.zobrist_key();
 
         let in_check = self.in_check();
-        if depth <= 0 && !in_check {
-            return self.quiescence::<NT::Next>(pv, info, t, alpha, beta);
+        if depth < Depth::ONE_PLY && !in_check {
+            return self.quiescence::<NT::Next>(pv, info, t, alpha, beta, 0);
         }
 
-        depth = depth.max(0);
+        // the children are searched to the full fractional depth, while everything
+        // decided at this node works in whole plies.
+        let mut frac_depth = depth.max(Depth::ZERO);
+        let mut depth = frac_depth.plies();
 
         pv.moves.clear();
 
@@ -768,6 +1049,11 @@ impl Board {
             return 0;
         }
 
+        t.stats.nodes += 1;
+
+        #[cfg(feature = "movegen-audit")]
+        self.audit_movegen();
+
         let height = self.height();
 
         debug_assert_eq!(height == 0, NT::ROOT);
@@ -792,7 +1078,7 @@ impl Board {
                 return if in_check {
                     0
                 } else {
-                    self.evaluate(t, info.nodes.get_local())
+                    self.evaluate(t, &info.conf, info.nodes.get_local()).get()
                 };
             }
 
@@ -816,29 +1102,55 @@ impl Board {
         let fifty_move_rule_near = self.fifty_move_counter() >= 80;
         let tt_hit = if excluded.is_none() {
             if let Some(hit) = t.tt.probe(key, height) {
+                t.stats.tt_hits += 1;
+
+                // TT fast path:
+                // a deep exact entry whose score is well clear of the window would cut off below
+                // anyway, and its move was already credited by the search that stored it, so
+                // return at once. the entry may have been stored with a lower fifty-move counter,
+                // so the path is off if a search of its depth from here could reach the rule.
+                let value = hit.value.get();
+                if !NT::PV
+                    && hit.bound == Bound::Exact
+                    && hit.depth >= depth + info.conf.tt_fast_path_depth_margin
+                    && i32::from(self.fifty_move_counter()) + hit.depth
+                        < info.conf.tt_fast_path_fifty_limit
+                    && (value >= beta + info.conf.tt_fast_path_window_margin
+                        || value <= alpha - info.conf.tt_fast_path_window_margin)
+                    && !is_game_theoretic_score(value)
+                    && hit
+                        .mov
+                        .is_some_and(|m| self.is_pseudo_legal(m) && self.is_legal(m))
+                {
+                    t.stats.tt_fast_paths += 1;
+                    return value;
+                }
+
                 if !NT::PV
                     && hit.depth >= depth
                     && !fifty_move_rule_near
                     && (hit.bound == Bound::Exact
-                        || (hit.bound == Bound::Lower && hit.value >= beta)
-                        || (hit.bound == Bound::Upper && hit.value <= alpha))
+                        || (hit.bound == Bound::Lower && hit.value.get() >= beta)
+                        || (hit.bound == Bound::Upper && hit.value.get() <= alpha))
                 {
                     if let Some(mov) = hit.mov {
                         // add to the history of a quiet move that fails high here.
-                        if hit.value >= beta && !self.is_tactical(mov) && self.is_pseudo_legal(mov)
-                        {
+                        if !self.is_pseudo_legal(mov) {
+                            t.stats.rejected_tt_moves += 1;
+                        } else if hit.value.get() >= beta && !self.is_tactical(mov) {
                             let from = mov.from();
                             let to = mov.history_to_square();
                             let moved = self.piece_at(from).unwrap();
                             let threats = self.threats().all;
                             let delta = history_bonus(&info.conf, depth);
                             self.update_quiet_history_single::<false>(
-                                t, from, to, moved, threats, delta,
+                                &info.conf, t, from, to, moved, threats, delta,
                             );
                         }
                     }
 
-                    return hit.value;
+                    t.stats.tt_cutoffs += 1;
+                    return hit.value.get();
                 }
 
                 Some(hit)
@@ -864,12 +1176,12 @@ impl Board {
             && self.n_men() <= cardinality
         {
             if let Some(wdl) = tablebases::probe::get_wdl(self) {
-                TB_HITS.fetch_add(1, Ordering::Relaxed);
+                info.tbhits.increment();
 
                 let tb_value = match wdl {
-                    WDL::Win => tb_win_in(height),
-                    WDL::Loss => tb_loss_in(height),
-                    WDL::Draw => draw_score(t, info.nodes.get_buffer(), self.turn()),
+                    WDL::Win => Value::tb_win_in(height),
+                    WDL::Loss => Value::tb_loss_in(height),
+                    WDL::Draw => Value::cp(draw_score(t, info.nodes.get_buffer(), self.turn())),
                 };
 
                 let tb_bound = match wdl {
@@ -879,29 +1191,29 @@ impl Board {
                 };
 
                 if tb_bound == Bound::Exact
-                    || (tb_bound == Bound::Lower && tb_value >= beta)
-                    || (tb_bound == Bound::Upper && tb_value <= alpha)
+                    || (tb_bound == Bound::Lower && tb_value.get() >= beta)
+                    || (tb_bound == Bound::Upper && tb_value.get() <= alpha)
                 {
                     t.tt.store(
                         key,
                         height,
                         None,
                         tb_value,
-                        VALUE_NONE,
+                        Value::NONE,
                         tb_bound,
                         depth,
                         t.ss[height].ttpv,
                     );
-                    return tb_value;
+                    return tb_value.get();
                 }
 
                 if NT::PV && tb_bound == Bound::Lower {
-                    alpha = alpha.max(tb_value);
-                    syzygy_min = tb_value;
+                    alpha = alpha.max(tb_value.get());
+                    syzygy_min = tb_value.get();
                 }
 
                 if NT::PV && tb_bound == Bound::Upper {
-                    syzygy_max = tb_value;
+                    syzygy_max = tb_value.get();
                 }
             }
         }
@@ -911,19 +1223,19 @@ impl Board {
 
         if in_check {
             // when we're in check, it could be checkmate, so it's unsound to use evaluate().
-            raw_eval = VALUE_NONE;
+            raw_eval = Value::NONE;
             static_eval = VALUE_NONE;
         } else if excluded.is_some() {
             // if we're in a singular-verification search, we already have the static eval.
             // we can set raw_eval to whatever we like, because we're not going to be saving it.
-            raw_eval = VALUE_NONE;
+            raw_eval = Value::NONE;
             static_eval = t.ss[height].eval;
             t.nnue.hint_common_access(self, t.nnue_params);
         } else if let Some(TTHit { eval: tt_eval, .. }) = &tt_hit {
             let v = *tt_eval; // if we have a TT hit, check the cached TT eval.
-            if v == VALUE_NONE {
+            if v.is_none() {
                 // regenerate the static eval if it's VALUE_NONE.
-                raw_eval = self.evaluate(t, info.nodes.get_local());
+                raw_eval = self.evaluate(t, &info.conf, info.nodes.get_local());
             } else {
                 // if the TT eval is not VALUE_NONE, use it.
                 raw_eval = v;
@@ -931,11 +1243,11 @@ impl Board {
                     t.nnue.hint_common_access(self, t.nnue_params);
                 }
             }
-            static_eval = raw_eval + t.correct_evaluation(&info.conf, self);
+            static_eval = raw_eval.get() + t.correct_evaluation(&info.conf, self);
         } else {
             // otherwise, use the static evaluation.
-            raw_eval = self.evaluate(t, info.nodes.get_local());
-            static_eval = raw_eval + t.correct_evaluation(&info.conf, self);
+            raw_eval = self.evaluate(t, &info.conf, info.nodes.get_local());
+            static_eval = raw_eval.get() + t.correct_evaluation(&info.conf, self);
         };
 
         t.ss[height].eval = static_eval;
@@ -954,6 +1266,18 @@ impl Board {
         } else {
             true
         };
+        // "worsening" is true when the static evaluation has fallen over both of our last two moves,
+        // which suggests that the line is going wrong for us, so we can prune its quiet moves harder
+        // and trust its static evaluation less when it looks good enough for a null-move cutoff.
+        let worsening = !in_check
+            && height >= 4
+            && t.ss[height - 2].eval != VALUE_NONE
+            && t.ss[height - 4].eval != VALUE_NONE
+            && static_eval < t.ss[height - 2].eval
+            && t.ss[height - 2].eval < t.ss[height - 4].eval;
+        // when the opponent attacks much of the area around our king, there may well be a tactic
+        // that the static evaluation can't see, so we don't prune on the strength of it.
+        let king_in_danger = !in_check && self.king_danger() >= info.conf.king_danger_threshold;
 
         t.ss[height].dextensions = if NT::ROOT {
             0
@@ -962,7 +1286,7 @@ impl Board {
         };
 
         // clear out the next set of killer moves.
-        t.killer_move_table[height + 1] = [None; 2];
+        t.ss[height + 1].killers = [None; 2];
 
         let tt_move = tt_hit.and_then(|hit| hit.mov);
         let tt_capture = matches!(tt_move, Some(mv) if self.is_capture(mv));
@@ -975,7 +1299,7 @@ impl Board {
             if static_eval
                 < alpha - info.conf.razoring_coeff_0 - info.conf.razoring_coeff_1 * depth * depth
             {
-                let v = self.quiescence::<OffPV>(pv, info, t, alpha - 1, alpha);
+                let v = self.quiescence::<OffPV>(pv, info, t, alpha - 1, alpha, 0);
                 if v < alpha {
                     return v;
                 }
@@ -1001,20 +1325,24 @@ impl Board {
             // if we can give the opponent a free move while retaining
             // a score above beta, we can prune the node.
             if !last_move_was_null
-                && depth >= 3
+                && depth >= info.conf.nmp_min_depth
                 && static_eval + i32::from(improving) * info.conf.nmp_improving_margin >= beta
                 && !t.nmp_banned_for(self.turn())
+                && !king_in_danger
                 && self.zugzwang_unlikely()
-                && !matches!(tt_hit, Some(TTHit { value: v, bound: Bound::Upper, .. }) if v < beta)
+                && !matches!(tt_hit, Some(TTHit { value: v, bound: Bound::Upper, .. }) if v.get() < beta)
             {
                 t.tt.prefetch(self.key_after_null_move());
-                let r = 4
-                    + depth / 3
+                t.stats.nmp_attempts += 1;
+                // reduce more at higher depths, and when we're further above beta.
+                let r = info.conf.nmp_base_reduction
+                    + depth / info.conf.nmp_reduction_depth_divisor
                     + std::cmp::min(
                         (static_eval - beta) / info.conf.nmp_reduction_eval_divisor,
-                        4,
-                    );
-                let nm_depth = depth - r;
+                        info.conf.nmp_reduction_eval_max,
+                    )
+                    - i32::from(worsening) * info.conf.nmp_worsening_reduction;
+                let nm_depth = frac_depth - Depth::new(r);
                 t.ss[height].searching = None;
                 t.ss[height].searching_tactical = false;
                 t.ss[height].conthist_index = ContHistIndex {
@@ -1034,7 +1362,8 @@ impl Board {
                         null_score = beta;
                     }
                     // unconditionally cutoff if we're just too shallow.
-                    if depth < 12 && !is_game_theoretic_score(beta) {
+                    if depth < info.conf.nmp_verification_depth && !is_game_theoretic_score(beta) {
+                        t.stats.nmp_cutoffs += 1;
                         return null_score;
                     }
                     // verify that it's *actually* fine to prune,
@@ -1047,6 +1376,7 @@ impl Board {
                         self.alpha_beta::<OffPV>(l_pv, info, t, nm_depth, beta - 1, beta, false);
                     t.unban_nmp_for(self.turn());
                     if veri_score >= beta {
+                        t.stats.nmp_cutoffs += 1;
                         return null_score;
                     }
                 }
@@ -1054,16 +1384,19 @@ impl Board {
         }
 
         // TT-reduction (IIR).
-        if NT::PV && tt_hit.map_or(true, |tte| tte.depth + 4 <= depth) {
-            depth -= i32::from(depth >= 4);
+        if NT::PV && tt_hit.map_or(true, |tte| tte.depth + 4 <= depth) && depth >= 4 {
+            frac_depth -= Depth::ONE_PLY;
+            depth = frac_depth.plies();
         }
 
         // cutnode-based TT reduction.
         if cut_node
             && excluded.is_none()
             && (tt_move.is_none() || tt_hit.map_or(true, |tte| tte.depth + 4 <= depth))
+            && depth >= 8
         {
-            depth -= i32::from(depth >= 8);
+            frac_depth -= Depth::ONE_PLY;
+            depth = frac_depth.plies();
         }
 
         // the margins for static-exchange-evaluation pruning for tactical and quiet moves.
@@ -1078,7 +1411,7 @@ impl Board {
                 key,
                 height,
                 None,
-                VALUE_NONE,
+                Value::NONE,
                 raw_eval,
                 Bound::None,
                 0,
@@ -1101,11 +1434,11 @@ impl Board {
             && depth >= 5
             && !is_game_theoretic_score(beta)
             // don't probcut if we have a tthit with value < pcbeta and depth >= depth - 3:
-            && !matches!(tt_hit, Some(TTHit { value: v, depth: d, .. }) if v < pc_beta && d >= depth - 3)
+            && !matches!(tt_hit, Some(TTHit { value: v, depth: d, .. }) if v.get() < pc_beta && d >= depth - 3)
         {
             let mut move_picker = MovePicker::new(tt_move, [None; 2], None, 0);
             move_picker.skip_quiets = true;
-            while let Some(MoveListEntry {
+            while let Some(ScoredMove {
                 mov: m,
                 score: ordering_score,
             }) = move_picker.next(self, t)
@@ -1132,10 +1465,10 @@ impl Board {
                     continue;
                 }
 
-                let mut value = -self.quiescence::<OffPV>(l_pv, info, t, -pc_beta, -pc_beta + 1);
+                let mut value = -self.quiescence::<OffPV>(l_pv, info, t, -pc_beta, -pc_beta + 1, 0);
 
                 if value >= pc_beta {
-                    let pc_depth = depth - 3;
+                    let pc_depth = frac_depth - Depth::new(3);
                     value = -self.alpha_beta::<OffPV>(
                         l_pv,
                         info,
@@ -1154,7 +1487,7 @@ impl Board {
                         key,
                         height,
                         Some(m),
-                        value,
+                        Value::new(value),
                         raw_eval,
                         Bound::Lower,
                         depth - 3,
@@ -1167,28 +1500,97 @@ impl Board {
             t.nnue.hint_common_access(self, t.nnue_params);
         }
 
+        // multi-cut:
+        // at a node we expect to fail high, if several of the first few moves
+        // fail high even at a reduced depth, we assume that the node will too.
+        if !NT::PV
+            && cut_node
+            && !in_check
+            && excluded.is_none()
+            && depth >= info.conf.multi_cut_depth
+            && !is_game_theoretic_score(beta)
+        {
+            let mc_depth = frac_depth - Depth::new(1 + info.conf.multi_cut_reduction);
+            let killers = self.get_killer_set(t);
+            let counter_move = t.get_counter_move(self);
+            let mut move_picker =
+                MovePicker::new(tt_move, killers, counter_move, info.conf.main_see_bound);
+            let mut tried = 0;
+            let mut cutoffs = 0;
+            while let Some(ScoredMove { mov: m, .. }) = move_picker.next(self, t) {
+                if tried >= info.conf.multi_cut_moves {
+                    break;
+                }
+
+                t.tt.prefetch(self.key_after(m));
+                t.ss[height].searching = Some(m);
+                t.ss[height].searching_tactical = self.is_tactical(m);
+                let moved = self.piece_at(m.from()).unwrap();
+                t.ss[height].conthist_index = ContHistIndex {
+                    piece: moved,
+                    square: m.history_to_square(),
+                };
+                if !self.make_move(m, t) {
+                    // illegal move
+                    continue;
+                }
+                tried += 1;
+
+                let value =
+                    -self.alpha_beta::<OffPV>(l_pv, info, t, mc_depth, -beta, -beta + 1, false);
+
+                self.unmake_move(t);
+
+                if info.stopped() {
+                    return 0;
+                }
+
+                if value >= beta {
+                    cutoffs += 1;
+                    if cutoffs >= info.conf.multi_cut_required {
+                        return beta;
+                    }
+                }
+            }
+
+            t.nnue.hint_common_access(self, t.nnue_params);
+        }
+
         let original_alpha = alpha;
         let mut best_move = None;
         let mut best_score = -INFINITY;
         let mut moves_made = 0;
 
         // number of quiet moves to try before we start pruning
-        let lmp_threshold = info.lm_table.lmp_movecount(depth, improving);
+        let lmp_threshold = info
+            .lm_table
+            .lmp_movecount(depth, improving)
+            .saturating_sub(
+                usize::from(worsening)
+                    * usize::try_from(info.conf.lmp_worsening_moves).unwrap_or(0),
+            );
 
         let killers = self.get_killer_set(t);
         let counter_move = t.get_counter_move(self);
-        let mut move_picker =
-            MovePicker::new(tt_move, killers, counter_move, info.conf.main_see_bound);
+        let mut move_picker = if NT::ROOT {
+            MovePicker::root(&t.root_moves)
+        } else {
+            MovePicker::new(tt_move, killers, counter_move, info.conf.main_see_bound)
+        };
 
         let mut quiets_tried = ArrayVec::<_, MAX_POSITION_MOVES>::new();
         let mut tacticals_tried = ArrayVec::<_, MAX_POSITION_MOVES>::new();
 
-        while let Some(MoveListEntry { mov: m, .. }) = move_picker.next(self, t) {
+        let variety = NT::ROOT && !info.analyse_mode && info.variety.applies(self);
+        // the bonus included in best_score, which is taken out again before it's returned.
+        let mut best_bonus = 0;
+
+        while let Some(ScoredMove { mov: m, .. }) = move_picker.next(self, t) {
             if excluded == Some(m) {
                 continue;
             }
 
-            let lmr_reduction = info.lm_table.lm_reduction(depth, moves_made);
+            let lmr_reduction = info.lm_table.lm_reduction(depth, moves_made) / 1024;
             let lmr_depth = std::cmp::max(depth - lmr_reduction, 0);
             let is_quiet = !self.is_tactical(m);
 
@@ -1209,7 +1611,7 @@ impl Board {
             if !NT::ROOT && !NT::PV && !in_check && best_score > -MINIMUM_TB_WIN_SCORE {
                 // late move pruning
                 // if we have made too many moves, we start skipping moves.
-                if lmr_depth <= 8 && moves_made >= lmp_threshold {
+                if lmr_depth <= 8 && moves_made >= lmp_threshold && !king_in_danger {
                     move_picker.skip_quiets = true;
                 }
 
@@ -1226,8 +1628,11 @@ impl Board {
 
                 // futility pruning
                 // if the static eval is too low, we start skipping moves.
-                let fp_margin = lmr_depth * info.conf.futility_coeff_1 + info.conf.futility_coeff_0;
-                if is_quiet && lmr_depth < 6 && static_eval + fp_margin <= alpha {
+                // on a worsening line the margin shrinks, so that more quiet moves are skipped.
+                let fp_margin = lmr_depth * info.conf.futility_coeff_1 + info.conf.futility_coeff_0
+                    - i32::from(worsening) * info.conf.futility_worsening_margin;
+                if is_quiet && lmr_depth < 6 && static_eval + fp_margin <= alpha && !king_in_danger
+                {
                     move_picker.skip_quiets = true;
                 }
             }
@@ -1271,6 +1676,10 @@ impl Board {
             info.nodes.increment();
             moves_made += 1;
 
+            if NT::ROOT && t.thread_id == 0 {
+                readout_current_move(info, depth, m, moves_made);
+            }
+
             let maybe_singular = depth >= 8
                 && excluded.is_none()
                 && matches!(tt_hit, Some(TTHit { mov, depth: tt_depth, bound: Bound::Lower | Bound::Exact, .. }) if mov == Some(m) && tt_depth >= depth - 3);
@@ -1285,8 +1694,9 @@ impl Board {
                 else {
                     unreachable!()
                 };
+                let tt_value = tt_value.get();
                 let r_beta = Self::singularity_margin(tt_value, depth);
-                let r_depth = (depth - 1) / 2;
+                let r_depth = Depth::new((depth - 1) / 2);
                 // undo the singular move so we can search the position that it exists in.
                 self.unmake_move(t);
                 t.ss[self.height()].excluded = Some(m);
@@ -1344,20 +1754,30 @@ impl Board {
             } else {
                 extension = 0;
             }
+            if extension != 0 {
+                t.stats.record_extension(extension);
+            }
             if extension >= 2 {
                 t.ss[height].dextensions += 1;
             }
 
+            // search the move as if its score already included its bonus.
+            let bonus = if variety { info.variety.bonus(self) } else { 0 };
+            let (root_alpha, root_beta) = (alpha, beta);
+            alpha = (alpha - bonus).clamp(-INFINITY, INFINITY);
+            beta = (beta - bonus).clamp(-INFINITY, INFINITY);
+            best_score -= bonus;
+
             let mut score;
             if moves_made == 1 {
                 // first move (presumably the PV-move)
-                let new_depth = depth + extension - 1;
+                let new_depth = frac_depth + Depth::new(extension - 1);
                 score =
                     -self.alpha_beta::<NT::Next>(l_pv, info, t, new_depth, -beta, -alpha, false);
             } else {
                 // calculation of LMR stuff
                 let r = if depth >= 3 && moves_made >= (2 + usize::from(NT::PV)) {
-                    let mut r = info.lm_table.lm_reduction(depth, moves_made) * 1024;
+                    let mut r = info.lm_table.lm_reduction(depth, moves_made);
                     if is_quiet {
                         // extend/reduce using the stat_score of the move
                         r -= stat_score / info.conf.history_lmr_divisor * 1024;
@@ -1373,12 +1793,18 @@ impl Board {
                         // reduce more if the move from the transposition table is tactical
                         r += i32::from(tt_capture) * info.conf.lmr_tt_capture_mul;
                     }
-                    (r / 1024).clamp(1, depth - 1)
+                    // the reduction in depth units, rounded down to the granularity.
+                    let units = r * UNITS_PER_PLY / 1024;
+                    let units = units - units.rem_euclid(info.conf.lmr_granularity);
+                    Depth::from_units(units).clamp(Depth::ONE_PLY, Depth::new(depth - 1))
                 } else {
-                    1
+                    Depth::ONE_PLY
                 };
+                if r > Depth::ONE_PLY {
+                    t.stats.lmr_searches += 1;
+                }
                 // perform a zero-window search
-                let mut new_depth = depth + extension;
+                let mut new_depth = frac_depth + Depth::new(extension);
                 let reduced_depth = new_depth - r;
                 score = -self.alpha_beta::<OffPV>(
                     l_pv,
@@ -1391,23 +1817,25 @@ impl Board {
                 );
                 // if we beat alpha, and reduced more than one ply,
                 // then we do a zero-window search at full depth.
-                if score > alpha && r > 1 {
+                if score > alpha && r > Depth::ONE_PLY {
+                    t.stats.lmr_re_searches += 1;
                     let do_deeper_search = score
                         > (best_score
                             + info.conf.do_deeper_base_margin
-                            + info.conf.do_deeper_depth_margin * r);
-                    let do_shallower_search = score < best_score + new_depth;
+                            + info.conf.do_deeper_depth_margin * r.plies());
+                    let do_shallower_search = score < best_score + new_depth.plies();
                     // depending on the value that the reduced search kicked out,
                     // we might want to do a deeper search, or a shallower search.
-                    new_depth += i32::from(do_deeper_search) - i32::from(do_shallower_search);
+                    new_depth +=
+                        Depth::new(i32::from(do_deeper_search) - i32::from(do_shallower_search));
                     // check if we're actually going to do a deeper search than before
                     // (no point if the re-search is the same as the normal one lol)
-                    if new_depth - 1 > reduced_depth {
+                    if new_depth - Depth::ONE_PLY > reduced_depth {
                         score = -self.alpha_beta::<OffPV>(
                             l_pv,
                             info,
                             t,
-                            new_depth - 1,
+                            new_depth - Depth::ONE_PLY,
                             -alpha - 1,
                             -alpha,
                             !cut_node,
@@ -1421,7 +1849,7 @@ impl Board {
                         l_pv,
                         info,
                         t,
-                        new_depth - 1,
+                        new_depth - Depth::ONE_PLY,
                         -beta,
                         -alpha,
                         false,
@@ -1430,10 +1858,24 @@ impl Board {
             }
             self.unmake_move(t);
 
-            // record subtree size for TimeManager
-            if NT::ROOT && t.thread_id == 0 {
+            (alpha, beta) = (root_alpha, root_beta);
+            best_score += bonus;
+            let bonus = if is_game_theoretic_score(score) {
+                0
+            } else {
+                bonus
+            };
+            score += bonus;
+
+            // record the score and subtree size, for root move ordering and the time manager.
+            if NT::ROOT {
                 let subtree_size = info.nodes.get_local() - nodes_before_search;
-                info.root_move_nodes[m.from()][m.to()] += subtree_size;
+                let root_score = if moves_made == 1 || score > alpha {
+                    score
+                } else {
+                    -INFINITY
+                };
+                t.root_moves.update(m, root_score, subtree_size);
             }
 
             if extension >= 2 {
@@ -1446,6 +1888,7 @@ impl Board {
 
             if score > best_score {
                 best_score = score;
+                best_bonus = bonus;
                 if score > alpha {
                     best_move = Some(m);
                     alpha = score;
@@ -1454,8 +1897,7 @@ impl Board {
                     }
                 }
                 if alpha >= beta {
-                    #[cfg(feature = "stats")]
-                    info.log_fail_high::<false>(moves_made - 1, movepick_score);
+                    t.stats.record_cutoff(moves_made - 1);
                     break;
                 }
             }
@@ -1473,6 +1915,7 @@ impl Board {
             return draw_score(t, info.nodes.get_local(), self.turn());
         }
 
+        best_score -= best_bonus;
         best_score = best_score.clamp(syzygy_min, syzygy_max);
 
         let flag = if best_score >= beta {
@@ -1536,7 +1979,7 @@ impl Board {
                 key,
                 height,
                 best_move,
-                best_score,
+                Value::new(best_score),
                 raw_eval,
                 flag,
                 depth,
@@ -1573,6 +2016,7 @@ impl Board {
     #[allow(clippy::identity_op)]
     fn update_quiet_history_single<const MADE: bool>(
         &self,
+        conf: &Config,
         t: &mut ThreadData,
         from: Square,
         to: Square,
@@ -1580,10 +2024,10 @@ impl Board {
         threats: SquareSet,
         delta: i32,
     ) {
-        t.update_history_single(from, to, moved, threats, delta);
-        t.update_continuation_history_single(self, to, moved, delta, 0 + usize::from(MADE));
-        t.update_continuation_history_single(self, to, moved, delta, 1 + usize::from(MADE));
-        // t.update_continuation_history_single(self, to, moved, delta, 3 + usize::from(MADE));
+        t.update_history_single(conf, from, to, moved, threats, delta);
+        t.update_continuation_history_single(conf, self, to, moved, delta, 0 + usize::from(MADE));
+        t.update_continuation_history_single(conf, self, to, moved, delta, 1 + usize::from(MADE));
+        // t.update_continuation_history_single(conf, self, to, moved, delta, 3 + usize::from(MADE));
     }
 
     /// Update the tactical history table.
@@ -1616,7 +2060,7 @@ impl Board {
         depth: i32,
     ) -> bool {
         let r_beta = (value - margin).max(-MATE_SCORE);
-        let r_depth = (depth - 1) / 2;
+        let r_depth = Depth::new((depth - 1) / 2);
         t.ss[self.height()].excluded = Some(m);
         let pts_prev = info.print_to_stdout;
         info.print_to_stdout = false;
@@ -1671,7 +2115,7 @@ impl Board {
             occupied ^= self.ep_sq().unwrap().as_set();
         }
 
-        let mut attackers = self.pieces.all_attackers_to_sq(to, occupied) & occupied;
+        let mut attackers = self.attackers_to(to, occupied) & occupied;
 
         // after the move, it's the opponent's turn.
         let mut colour = self.turn().flip();
@@ -1732,42 +2176,104 @@ impl Board {
     }
 }
 
-pub fn select_best<'a>(
-    board: &mut Board,
-    thread_headers: &'a [ThreadData],
-    info: &SearchInfo,
-    tt: TTView,
-    total_nodes: u64,
-) -> &'a ThreadData<'a> {
+/// Run the search job of thread `id`, so that a panic in it is reported and stops the
+/// search, rather than taking down the whole process while the GUI waits for a move.
+/// Returns whether the job panicked.
+fn sandboxed(id: usize, stopped: &AtomicBool, job: impl FnOnce()) -> bool {
+    let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) else {
+        return false;
+    };
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("no message");
+    uci::output::send(format!(
+        "info string search thread {id} panicked ({message}), stopping the search"
+    ));
+    stopped.store(true, Ordering::SeqCst);
+    true
+}
+
+/// Choose the thread whose result is played and reported at the end of a search.
+///
+/// While searching, only the main thread reports, so `depth`, `score` and `pv` always
+/// describe one consistent search and don't jump between threads. At the end, the threads
+/// vote for their best moves, each with a weight that grows with its depth and with how
+/// far its score is above the worst, and the deepest thread among those whose move won
+/// the most votes is chosen. Proven wins override the vote, and the final info line is
+/// taken from the chosen thread, so that it agrees with the move played.
+pub fn select_best<'a>(thread_headers: &'a [ThreadData<'a>]) -> &'a ThreadData<'a> {
+    const VOTE_OFFSET: i64 = 14;
     let (mut best_thread, rest) = thread_headers.split_first().unwrap();
+    if rest.is_empty() {
+        return best_thread;
+    }
+
+    // a thread that was stopped before it completed an iteration has nothing to offer.
+    let finished = || thread_headers.iter().filter(|t| !t.pv().moves().is_empty());
+    let min_score = finished().map(|t| t.pv().score().get()).min().unwrap_or(0);
+    let mut votes = Vec::<(Move, i64)>::with_capacity(thread_headers.len());
+    for t in finished() {
+        let m = t.pv().moves()[0];
+        #[allow(clippy::cast_possible_wrap)]
+        let weight =
+            (i64::from(t.pv().score().get() - min_score) + VOTE_OFFSET) * t.completed as i64;
+        match votes.iter_mut().find(|(mv, _)| *mv == m) {
+            Some((_, count)) => *count += weight,
+            None => votes.push((m, weight)),
+        }
+    }
+    let votes_for = |t: &ThreadData| {
+        t.pv()
+            .moves()
+            .first()
+            .and_then(|m| votes.iter().find(|(mv, _)| mv == m))
+            .map_or(0, |&(_, count)| count)
+    };
 
     for thread in rest {
-        let best_depth = best_thread.completed;
-        let best_score = best_thread.pvs[best_depth].score();
-        let this_depth = thread.completed;
-        let this_score = thread.pvs[this_depth].score();
-        if (this_depth == best_depth || this_score >= MINIMUM_TB_WIN_SCORE)
-            && this_score > best_score
-        {
-            best_thread = thread;
+        if thread.pv().moves().is_empty() {
+            continue;
         }
-        if this_depth > best_depth && (this_score > best_score || best_score < MINIMUM_TB_WIN_SCORE)
-        {
+        let best_score = best_thread.pv().score().get();
+        let this_score = thread.pv().score().get();
+        let better = if best_thread.pv().moves().is_empty() {
+            true
+        } else if best_score >= MINIMUM_TB_WIN_SCORE {
+            // among proven wins, take the fastest.
+            this_score > best_score
+        } else if this_score >= MINIMUM_TB_WIN_SCORE {
+            true
+        } else {
+            let (best_votes, this_votes) = (votes_for(best_thread), votes_for(thread));
+            this_score > -MINIMUM_TB_WIN_SCORE
+                && (this_votes > best_votes
+                    || (this_votes == best_votes && thread.completed > best_thread.completed))
+        };
+        if better {
             best_thread = thread;
         }
     }
 
-    // if we aren't using the main thread (thread 0) then we need to do
-    // an extra uci info line to show the best move/score/pv
-    if best_thread.thread_id != 0 && info.print_to_stdout {
-        let pv = &best_thread.pvs[best_thread.completed];
-        let depth = best_thread.completed;
-        readout_info(board, Bound::Exact, pv, depth, info, tt, total_nodes, false);
-    }
-
     best_thread
 }
 
+/// Tell the GUI which root move is being searched, once the search has gone on long enough
+/// for a GUI to want to show it.
+fn readout_current_move(info: &SearchInfo, depth: i32, m: Move, number: usize) {
+    if !info.print_to_stdout
+        || uci::PRETTY_PRINT.load(Ordering::Relaxed)
+        || info.time_manager.time_since_start() < CURRMOVE_REPORT_DELAY
+    {
+        return;
+    }
+    uci::output::info(format!(
+        "info depth {depth} currmove {} currmovenumber {number}",
+        m.display(CHESS960.load(Ordering::Relaxed))
+    ));
+}
+
 /// Print the info about an iteration of the search.
 fn readout_info(
     board: &mut Board,
@@ -1789,7 +2295,7 @@ fn readout_info(
     if info.time_manager.is_dynamic() && info.skip_print() && !force_print {
         return;
     }
-    let sstr = uci::format_score(pv.score);
+    let sstr = uci::format_score(pv.score.get());
     let normal_uci_output = !uci::PRETTY_PRINT.load(Ordering::SeqCst);
     let nps = (nodes as f64 / info.time_manager.elapsed().as_secs_f64()) as u64;
     if board.turn() == Colour::Black {
@@ -1805,16 +2311,22 @@ fn readout_info(
         _ => "",
     };
     if normal_uci_output {
-        println!(
+        let line = format!(
             "info score {sstr}{bound_string} wdl {wdl} depth {depth} seldepth {} nodes {nodes} time {} nps {nps} hashfull {hashfull} tbhits {tbhits} {pv}",
             info.seldepth as usize,
             info.time_manager.elapsed().as_millis(),
             hashfull = tt.hashfull(),
-            tbhits = TB_HITS.load(Ordering::SeqCst),
-            wdl = uci::format_wdl(pv.score, board.ply()),
+            tbhits = info.tbhits.get_global(),
+            wdl = uci::format_wdl(pv.score.get(), board.ply()),
         );
+        // a forced line is the last word on the search, so it mustn't be dropped.
+        if force_print {
+            uci::output::send(line);
+        } else {
+            uci::output::info(line);
+        }
     } else {
-        let value = uci::pretty_format_score(pv.score, board.turn());
+        let value = uci::pretty_format_score(pv.score.get(), board.turn());
         let mut pv_string = board.pv_san(pv).unwrap();
         // truncate the pv string if it's too long
         if pv_string.len() > 130 {
@@ -1837,7 +2349,7 @@ fn readout_info(
             t = uci::format_time(info.time_manager.elapsed().as_millis()),
             knps = nps / 1_000,
             knodes = nodes / 1_000,
-            wdl = uci::pretty_format_wdl(pv.score, board.ply()),
+            wdl = uci::pretty_format_wdl(pv.score.get(), board.ply()),
         );
     }
 }
@@ -1848,7 +2360,12 @@ pub fn draw_score(t: &ThreadData, nodes: u64, stm: Colour) -> i32 {
     // higher contempt means we will play on in drawn positions more often,
     // so if we are to play in a drawn position, then we should return the
     // negative of the contempt score.
-    let contempt = uci::CONTEMPT.load(Ordering::Relaxed);
+    // an analyst wants the objective score, so contempt is left out.
+    let contempt = if t.analyse_mode {
+        0
+    } else {
+        uci::CONTEMPT.load(Ordering::Relaxed)
+    };
     let contempt_component = if stm == t.stm_at_root {
         -contempt
     } else {
@@ -1860,7 +2377,8 @@ pub fn draw_score(t: &ThreadData, nodes: u64, stm: Colour) -> i32 {
 
 #[derive(Clone, Debug)]
 pub struct LMTable {
-    /// The reduction table. rtable\[depth]\[played] is the base LMR reduction for a move
+    /// The reduction table. rtable\[depth]\[played] is the base LMR reduction for a move,
+    /// in 1024ths of a ply, so that tuning the base and divisor moves it smoothly.
     lm_reduction_table: [[i32; 64]; 64],
     /// The movecount table. ptable\[played]\[improving] is the movecount at which LMP is triggered.
     lmp_movecount_table: [[usize; 12]; 2],
@@ -1884,7 +2402,7 @@ impl LMTable {
             cfor!(let mut played = 1; played < 64; played += 1; {
                 let ld = f64::ln(depth as f64);
                 let lp = f64::ln(played as f64);
-                out.lm_reduction_table[depth][played] = (base + ld * lp / division) as i32;
+                out.lm_reduction_table[depth][played] = ((base + ld * lp / division) * 1024.0) as i32;
             });
         });
         cfor!(let mut depth = 1; depth < 12; depth += 1; {
@@ -1980,3 +2498,281 @@ impl AspirationWindow {
         self.beta_fails += 1;
     }
 }
+
+/// Counts the heap allocations made by each thread, so that tests can check that search doesn't allocate.
+#[cfg(test)]
+mod allocations {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    thread_local! {
+        static COUNT: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn record() {
+        // the thread-local may already be gone if the thread is shutting down.
+        let _ = COUNT.try_with(|count| count.set(count.get() + 1));
+    }
+
+    struct Counting;
+
+    // SAFETY: all of the actual work is done by the system allocator.
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record();
+            System.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            record();
+            System.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            record();
+            System.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    /// The number of allocations made by the current thread so far.
+    pub fn count() -> usize {
+        COUNT.with(Cell::get)
+    }
+}
+
+mod tests {
+    #[test]
+    fn panicking_threads_stop_the_search() {
+        use super::sandboxed;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let stopped = AtomicBool::new(false);
+        let mut ran = false;
+        assert!(!sandboxed(0, &stopped, || ran = true));
+        assert!(ran);
+        assert!(!stopped.load(Ordering::SeqCst));
+        assert!(sandboxed(1, &stopped, || panic!("index out of bounds")));
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn threads_vote_for_the_final_move() {
+        use super::select_best;
+        use crate::{
+            chess::{board::Board, chessmove::Move, types::Square},
+            evaluation::Value,
+            nnue::network::NNUEParams,
+            threadlocal::ThreadData,
+            transpositiontable::TT,
+            util::MEGABYTE,
+        };
+
+        let mut board = Board::default();
+        board.set_startpos();
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+        let e4 = Move::new(Square::E2, Square::E4);
+        let d4 = Move::new(Square::D2, Square::D4);
+        let threads = [(e4, 10, 20), (d4, 12, 10), (d4, 12, 15)]
+            .into_iter()
+            .enumerate()
+            .map(|(id, (m, depth, score))| {
+                let mut t = ThreadData::new(id, &board, tt.view(), nnue_params);
+                t.depth = depth;
+                t.pvs[depth].moves.push(m);
+                t.pvs[depth].score = Value::cp(score);
+                t.completed = depth;
+                t
+            })
+            .collect::<Vec<_>>();
+
+        // the main thread has the best score, but the deeper threads outvote it.
+        let best = select_best(&threads);
+        assert_eq!(best.pv().moves()[0], d4);
+        assert_eq!(best.thread_id, 1);
+        assert_eq!(select_best(&threads[..1]).thread_id, 0);
+
+        // a main thread stopped before it completed an iteration defers to any thread that
+        // did, even one that has found itself lost.
+        let mut threads = threads;
+        threads[0].completed = 0;
+        threads[1].pvs[12].score = Value::mated_in(4);
+        threads.truncate(2);
+        assert_eq!(select_best(&threads).thread_id, 1);
+    }
+
+    #[test]
+    fn interrupted_searches_play_searched_moves() {
+        use super::select_best;
+        use crate::{
+            chess::board::Board,
+            nnue::network::NNUEParams,
+            rng::XorShiftState,
+            searchinfo::SearchInfo,
+            threadlocal::ThreadData,
+            timemgmt::{SearchLimit, TimeManager},
+            transpositiontable::TT,
+            util::MEGABYTE,
+        };
+        use std::sync::atomic::{AtomicBool, AtomicU64};
+
+        let positions = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                &[][..],
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                &["a2a3", "h2h3"],
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                &[],
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[]),
+            (
+                "r1b2bkr/ppp3pp/2n5/3qp3/2B5/8/PPPP1PPP/RNB1K2R w KQ - 0 9",
+                &[],
+            ),
+        ];
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+        let mut rng = XorShiftState::new();
+        for (fen, searchmoves) in positions {
+            let mut board = Board::from_fen(fen).unwrap();
+            let legal = board.legal_moves();
+            let searchmoves = searchmoves
+                .iter()
+                .map(|m| board.parse_uci(m).unwrap())
+                .collect::<Vec<_>>();
+            for threads in [1, 3] {
+                // stop before the first iteration completes, and then anywhere from inside
+                // it to a few iterations in.
+                let node_counts = [1, 1 + rng.next() % 4000, 1 + rng.next() % 4000];
+                for node_count in node_counts {
+                    let limit = SearchLimit::Nodes(node_count);
+                    let stopped = AtomicBool::new(false);
+                    let nodes = AtomicU64::new(0);
+                    let mut info = SearchInfo {
+                        print_to_stdout: false,
+                        searchmoves: searchmoves.clone(),
+                        time_manager: TimeManager::default_with_limit(limit.clone()),
+                        ..SearchInfo::new(&stopped, &nodes)
+                    };
+                    let mut thread_data = (0..threads)
+                        .map(|id| ThreadData::new(id, &board, tt.view(), nnue_params))
+                        .collect::<Vec<_>>();
+                    let (score, m) = board.search_position(&mut info, &mut thread_data, tt.view());
+                    let m = m.unwrap();
+                    assert!(legal.contains(&m), "{fen} {limit:?}: illegal move");
+                    assert!(
+                        searchmoves.is_empty() || searchmoves.contains(&m),
+                        "{fen} {limit:?}: move outside searchmoves"
+                    );
+                    // whenever an iteration completed, the move and score are from its line.
+                    let best = select_best(&thread_data);
+                    if let Some(&pv_move) = best.pv().moves().first() {
+                        assert_eq!(m, pv_move, "{fen} {limit:?}: move not from the best line");
+                        let stm_score = if board.turn() == crate::chess::piece::Colour::White {
+                            score
+                        } else {
+                            -score
+                        };
+                        assert_eq!(stm_score, best.pv().score());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn search_does_not_allocate() {
+        use super::{allocations, MainThread};
+        use crate::{
+            chess::board::Board, nnue::network::NNUEParams, searchinfo::SearchInfo,
+            threadlocal::ThreadData, timemgmt::SearchLimit, transpositiontable::TT, util::MEGABYTE,
+        };
+        use std::sync::atomic::{AtomicBool, AtomicU64};
+
+        let mut board = Board::default();
+        board
+            .set_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+            .unwrap();
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+        let mut t = ThreadData::new(0, &board, tt.view(), nnue_params);
+        let stopped = AtomicBool::new(false);
+        let nodes = AtomicU64::new(0);
+        let mut info = SearchInfo {
+            print_to_stdout: false,
+            ..SearchInfo::new(&stopped, &nodes)
+        };
+
+        let mut search = |depth| {
+            info.time_manager.set_limit(SearchLimit::Depth(depth));
+            info.time_manager.start();
+            info.set_up_for_search();
+            t.set_up_for_search(&board, &[], false);
+            board.iterative_deepening::<MainThread>(&mut info, &mut t);
+            stopped.store(false, std::sync::atomic::Ordering::SeqCst);
+        };
+        // the first iteration may set things up.
+        search(1);
+        let before = allocations::count();
+        search(10);
+        assert_eq!(allocations::count(), before, "search allocated");
+    }
+
+    #[test]
+    fn king_danger_limits_pruning() {
+        use super::MainThread;
+        use crate::{
+            chess::board::Board, nnue::network::NNUEParams, searchinfo::SearchInfo,
+            threadlocal::ThreadData, timemgmt::SearchLimit, transpositiontable::TT, util::MEGABYTE,
+        };
+        use std::sync::atomic::{AtomicBool, AtomicU64};
+
+        // five of the six squares around white's king are attacked, but it isn't in check.
+        let board = Board::from_fen("6kr/8/b7/8/8/5q2/8/3Q1RK1 w - - 0 1").unwrap();
+        assert!(!board.in_check());
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+
+        let nmp_attempts = |threshold| {
+            let mut board = board.clone();
+            let mut tt = TT::new();
+            tt.resize(MEGABYTE);
+            let mut t = ThreadData::new(0, &board, tt.view(), nnue_params);
+            let stopped = AtomicBool::new(false);
+            let nodes = AtomicU64::new(0);
+            let mut info = SearchInfo {
+                print_to_stdout: false,
+                ..SearchInfo::new(&stopped, &nodes)
+            };
+            assert!(board.king_danger() >= info.conf.king_danger_threshold);
+            info.conf.king_danger_threshold = threshold;
+            info.time_manager.set_limit(SearchLimit::Depth(8));
+            info.time_manager.start();
+            info.set_up_for_search();
+            t.set_up_for_search(&board, &[], false);
+            board.iterative_deepening::<MainThread>(&mut info, &mut t);
+            t.stats.nmp_attempts
+        };
+
+        // with the guard off, the same search tries null moves in positions it now avoids.
+        let guarded = nmp_attempts(super::KING_DANGER_THRESHOLD);
+        let unguarded = nmp_attempts(u32::MAX);
+        assert!(guarded < unguarded, "{guarded} >= {unguarded}");
+    }
+}
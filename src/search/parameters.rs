@@ -1,20 +1,39 @@
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display, path::Path};
 
-use crate::timemgmt::{
-    DEFAULT_MOVES_TO_GO, FAIL_LOW_TM_BONUS, HARD_WINDOW_FRAC, INCREMENT_FRAC,
-    NODE_TM_SUBTREE_MULTIPLIER, OPTIMAL_WINDOW_FRAC, STRONG_FORCED_TM_FRAC, WEAK_FORCED_TM_FRAC,
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    evaluation::{
+        EVAL_SCALE_BUCKET_0, EVAL_SCALE_BUCKET_1, EVAL_SCALE_BUCKET_2, EVAL_SCALE_BUCKET_3,
+        EVAL_SCALE_BUCKET_4, EVAL_SCALE_BUCKET_5, EVAL_SCALE_BUCKET_6, EVAL_SCALE_BUCKET_7,
+        OCB_SCALE, PAWN_SCALE_BASE, PAWN_SCALE_PER_PAWN, ROOK_ENDING_SCALE, SMALL_NET_THRESHOLD,
+    },
+    timemgmt::{
+        DEFAULT_MOVES_TO_GO, FAIL_LOW_TM_BONUS, HARD_WINDOW_FRAC, INCREMENT_FRAC,
+        NODE_TM_SUBTREE_MULTIPLIER, OPTIMAL_WINDOW_FRAC, PONDER_HIT_TM_MUL, SCORE_TREND_TM_MAX,
+        SCORE_TREND_TM_MIN, SCORE_TREND_TM_MUL, STABILITY_TM_MUL_0, STABILITY_TM_MUL_1,
+        STABILITY_TM_MUL_2, STABILITY_TM_MUL_3, STABILITY_TM_MUL_4, STRONG_FORCED_TM_FRAC,
+        WEAK_FORCED_TM_FRAC,
+    },
+    util::{depth::UNITS_PER_PLY, MAX_DEPTH},
 };
 
 use super::{
     ASPIRATION_WINDOW, DOUBLE_EXTENSION_MARGIN, DO_DEEPER_BASE_MARGIN, DO_DEEPER_DEPTH_MARGIN,
-    FUTILITY_COEFF_0, FUTILITY_COEFF_1, HISTORY_BONUS_MAX, HISTORY_BONUS_MUL, HISTORY_BONUS_OFFSET,
-    HISTORY_LMR_DIVISOR, HISTORY_MALUS_MAX, HISTORY_MALUS_MUL, HISTORY_MALUS_OFFSET,
-    HISTORY_PRUNING_MARGIN, LMR_BASE, LMR_CUT_NODE_MUL, LMR_DIVISION, LMR_NON_IMPROVING_MUL,
-    LMR_NON_PV_MUL, LMR_REFUTATION_MUL, LMR_TTPV_MUL, LMR_TT_CAPTURE_MUL, MAIN_SEE_BOUND,
-    MAJOR_CORRHIST_WEIGHT, MINOR_CORRHIST_WEIGHT, NMP_IMPROVING_MARGIN, NMP_REDUCTION_EVAL_DIVISOR,
+    FUTILITY_COEFF_0, FUTILITY_COEFF_1, FUTILITY_WORSENING_MARGIN, HISTORY_BONUS_MAX,
+    HISTORY_BONUS_MUL, HISTORY_BONUS_OFFSET, HISTORY_LMR_DIVISOR, HISTORY_MALUS_MAX,
+    HISTORY_MALUS_MUL, HISTORY_MALUS_OFFSET, HISTORY_MAX, HISTORY_PRUNING_MARGIN,
+    KING_DANGER_THRESHOLD, LMP_WORSENING_MOVES, LMR_BASE, LMR_CUT_NODE_MUL, LMR_DIVISION,
+    LMR_GRANULARITY, LMR_NON_IMPROVING_MUL, LMR_NON_PV_MUL, LMR_REFUTATION_MUL, LMR_TTPV_MUL,
+    LMR_TT_CAPTURE_MUL, MAIN_SEE_BOUND, MAJOR_CORRHIST_WEIGHT, MINOR_CORRHIST_WEIGHT,
+    MULTI_CUT_DEPTH, MULTI_CUT_MOVES, MULTI_CUT_REDUCTION, MULTI_CUT_REQUIRED, NMP_BASE_REDUCTION,
+    NMP_IMPROVING_MARGIN, NMP_MIN_DEPTH, NMP_REDUCTION_DEPTH_DIVISOR, NMP_REDUCTION_EVAL_DIVISOR,
+    NMP_REDUCTION_EVAL_MAX, NMP_VERIFICATION_DEPTH, NMP_WORSENING_REDUCTION,
     NONPAWN_CORRHIST_WEIGHT, PAWN_CORRHIST_WEIGHT, PROBCUT_IMPROVING_MARGIN, PROBCUT_MARGIN,
     QS_FUTILITY, QS_SEE_BOUND, RAZORING_COEFF_0, RAZORING_COEFF_1, RFP_IMPROVING_MARGIN,
     RFP_MARGIN, SEE_QUIET_MARGIN, SEE_STAT_SCORE_MUL, SEE_TACTICAL_MARGIN,
+    TT_FAST_PATH_DEPTH_MARGIN, TT_FAST_PATH_FIFTY_LIMIT, TT_FAST_PATH_WINDOW_MARGIN,
 };
 
 #[derive(Clone, Debug)]
@@ -24,10 +43,19 @@ pub struct Config {
     pub rfp_improving_margin: i32,
     pub nmp_improving_margin: i32,
     pub nmp_reduction_eval_divisor: i32,
+    pub nmp_min_depth: i32,
+    pub nmp_base_reduction: i32,
+    pub nmp_reduction_depth_divisor: i32,
+    pub nmp_reduction_eval_max: i32,
+    pub nmp_verification_depth: i32,
+    pub nmp_worsening_reduction: i32,
+    pub king_danger_threshold: u32,
     pub see_quiet_margin: i32,
     pub see_tactical_margin: i32,
     pub futility_coeff_0: i32,
     pub futility_coeff_1: i32,
+    pub futility_worsening_margin: i32,
+    pub lmp_worsening_moves: i32,
     pub razoring_coeff_0: i32,
     pub razoring_coeff_1: i32,
     pub dext_margin: i32,
@@ -35,6 +63,13 @@ pub struct Config {
     pub lmr_division: f64,
     pub probcut_margin: i32,
     pub probcut_improving_margin: i32,
+    pub multi_cut_depth: i32,
+    pub multi_cut_reduction: i32,
+    pub multi_cut_moves: i32,
+    pub multi_cut_required: i32,
+    pub tt_fast_path_depth_margin: i32,
+    pub tt_fast_path_window_margin: i32,
+    pub tt_fast_path_fifty_limit: i32,
     pub strong_forced_tm_frac: u32,
     pub weak_forced_tm_frac: u32,
     pub default_moves_to_go: u32,
@@ -43,6 +78,15 @@ pub struct Config {
     pub increment_frac: u32,
     pub node_tm_subtree_multiplier: u32,
     pub fail_low_tm_bonus: u32,
+    pub stability_tm_mul_0: u32,
+    pub stability_tm_mul_1: u32,
+    pub stability_tm_mul_2: u32,
+    pub stability_tm_mul_3: u32,
+    pub stability_tm_mul_4: u32,
+    pub score_trend_tm_mul: u32,
+    pub score_trend_tm_min: u32,
+    pub score_trend_tm_max: u32,
+    pub ponder_hit_tm_mul: u32,
     pub history_lmr_divisor: i32,
     pub qs_see_bound: i32,
     pub main_see_bound: i32,
@@ -57,16 +101,31 @@ pub struct Config {
     pub lmr_cut_node_mul: i32,
     pub lmr_non_improving_mul: i32,
     pub lmr_tt_capture_mul: i32,
+    pub lmr_granularity: i32,
     pub history_bonus_mul: i32,
     pub history_bonus_offset: i32,
     pub history_bonus_max: i32,
     pub history_malus_mul: i32,
     pub history_malus_offset: i32,
     pub history_malus_max: i32,
+    pub history_max: i32,
     pub pawn_corrhist_weight: i32,
     pub major_corrhist_weight: i32,
     pub minor_corrhist_weight: i32,
     pub nonpawn_corrhist_weight: i32,
+    pub eval_scale_bucket_0: i32,
+    pub eval_scale_bucket_1: i32,
+    pub eval_scale_bucket_2: i32,
+    pub eval_scale_bucket_3: i32,
+    pub eval_scale_bucket_4: i32,
+    pub eval_scale_bucket_5: i32,
+    pub eval_scale_bucket_6: i32,
+    pub eval_scale_bucket_7: i32,
+    pub ocb_scale: i32,
+    pub rook_ending_scale: i32,
+    pub pawn_scale_base: i32,
+    pub pawn_scale_per_pawn: i32,
+    pub small_net_threshold: i32,
 }
 
 impl Config {
@@ -77,10 +136,19 @@ impl Config {
             rfp_improving_margin: RFP_IMPROVING_MARGIN,
             nmp_improving_margin: NMP_IMPROVING_MARGIN,
             nmp_reduction_eval_divisor: NMP_REDUCTION_EVAL_DIVISOR,
+            nmp_min_depth: NMP_MIN_DEPTH,
+            nmp_base_reduction: NMP_BASE_REDUCTION,
+            nmp_reduction_depth_divisor: NMP_REDUCTION_DEPTH_DIVISOR,
+            nmp_reduction_eval_max: NMP_REDUCTION_EVAL_MAX,
+            nmp_verification_depth: NMP_VERIFICATION_DEPTH,
+            nmp_worsening_reduction: NMP_WORSENING_REDUCTION,
+            king_danger_threshold: KING_DANGER_THRESHOLD,
             see_quiet_margin: SEE_QUIET_MARGIN,
             see_tactical_margin: SEE_TACTICAL_MARGIN,
             futility_coeff_0: FUTILITY_COEFF_0,
             futility_coeff_1: FUTILITY_COEFF_1,
+            futility_worsening_margin: FUTILITY_WORSENING_MARGIN,
+            lmp_worsening_moves: LMP_WORSENING_MOVES,
             razoring_coeff_0: RAZORING_COEFF_0,
             razoring_coeff_1: RAZORING_COEFF_1,
             dext_margin: DOUBLE_EXTENSION_MARGIN,
@@ -88,6 +156,13 @@ impl Config {
             lmr_division: LMR_DIVISION,
             probcut_margin: PROBCUT_MARGIN,
             probcut_improving_margin: PROBCUT_IMPROVING_MARGIN,
+            multi_cut_depth: MULTI_CUT_DEPTH,
+            multi_cut_reduction: MULTI_CUT_REDUCTION,
+            multi_cut_moves: MULTI_CUT_MOVES,
+            multi_cut_required: MULTI_CUT_REQUIRED,
+            tt_fast_path_depth_margin: TT_FAST_PATH_DEPTH_MARGIN,
+            tt_fast_path_window_margin: TT_FAST_PATH_WINDOW_MARGIN,
+            tt_fast_path_fifty_limit: TT_FAST_PATH_FIFTY_LIMIT,
             strong_forced_tm_frac: STRONG_FORCED_TM_FRAC,
             weak_forced_tm_frac: WEAK_FORCED_TM_FRAC,
             default_moves_to_go: DEFAULT_MOVES_TO_GO,
@@ -96,6 +171,15 @@ impl Config {
             increment_frac: INCREMENT_FRAC,
             node_tm_subtree_multiplier: NODE_TM_SUBTREE_MULTIPLIER,
             fail_low_tm_bonus: FAIL_LOW_TM_BONUS,
+            stability_tm_mul_0: STABILITY_TM_MUL_0,
+            stability_tm_mul_1: STABILITY_TM_MUL_1,
+            stability_tm_mul_2: STABILITY_TM_MUL_2,
+            stability_tm_mul_3: STABILITY_TM_MUL_3,
+            stability_tm_mul_4: STABILITY_TM_MUL_4,
+            score_trend_tm_mul: SCORE_TREND_TM_MUL,
+            score_trend_tm_min: SCORE_TREND_TM_MIN,
+            score_trend_tm_max: SCORE_TREND_TM_MAX,
+            ponder_hit_tm_mul: PONDER_HIT_TM_MUL,
             history_lmr_divisor: HISTORY_LMR_DIVISOR,
             qs_see_bound: QS_SEE_BOUND,
             main_see_bound: MAIN_SEE_BOUND,
@@ -110,16 +194,31 @@ impl Config {
             lmr_cut_node_mul: LMR_CUT_NODE_MUL,
             lmr_non_improving_mul: LMR_NON_IMPROVING_MUL,
             lmr_tt_capture_mul: LMR_TT_CAPTURE_MUL,
+            lmr_granularity: LMR_GRANULARITY,
             history_bonus_mul: HISTORY_BONUS_MUL,
             history_bonus_offset: HISTORY_BONUS_OFFSET,
             history_bonus_max: HISTORY_BONUS_MAX,
             history_malus_mul: HISTORY_MALUS_MUL,
             history_malus_offset: HISTORY_MALUS_OFFSET,
             history_malus_max: HISTORY_MALUS_MAX,
+            history_max: HISTORY_MAX,
             pawn_corrhist_weight: PAWN_CORRHIST_WEIGHT,
             major_corrhist_weight: MAJOR_CORRHIST_WEIGHT,
             minor_corrhist_weight: MINOR_CORRHIST_WEIGHT,
             nonpawn_corrhist_weight: NONPAWN_CORRHIST_WEIGHT,
+            eval_scale_bucket_0: EVAL_SCALE_BUCKET_0,
+            eval_scale_bucket_1: EVAL_SCALE_BUCKET_1,
+            eval_scale_bucket_2: EVAL_SCALE_BUCKET_2,
+            eval_scale_bucket_3: EVAL_SCALE_BUCKET_3,
+            eval_scale_bucket_4: EVAL_SCALE_BUCKET_4,
+            eval_scale_bucket_5: EVAL_SCALE_BUCKET_5,
+            eval_scale_bucket_6: EVAL_SCALE_BUCKET_6,
+            eval_scale_bucket_7: EVAL_SCALE_BUCKET_7,
+            ocb_scale: OCB_SCALE,
+            rook_ending_scale: ROOK_ENDING_SCALE,
+            pawn_scale_base: PAWN_SCALE_BASE,
+            pawn_scale_per_pawn: PAWN_SCALE_PER_PAWN,
+            small_net_threshold: SMALL_NET_THRESHOLD,
         }
     }
 }
@@ -162,6 +261,20 @@ macro_rules! id_value_gen {
 type LazyFieldParser<'a> = Box<dyn FnMut(&str) -> Result<(), Box<dyn std::error::Error>> + 'a>;
 
 impl Config {
+    /// The scale factor (out of 1024) applied to the network's output in the given output bucket.
+    pub const fn eval_scale(&self, bucket: usize) -> i32 {
+        [
+            self.eval_scale_bucket_0,
+            self.eval_scale_bucket_1,
+            self.eval_scale_bucket_2,
+            self.eval_scale_bucket_3,
+            self.eval_scale_bucket_4,
+            self.eval_scale_bucket_5,
+            self.eval_scale_bucket_6,
+            self.eval_scale_bucket_7,
+        ][bucket]
+    }
+
     pub fn ids_with_parsers(&mut self) -> Vec<(&str, LazyFieldParser)> {
         id_parser_gen![
             ASPIRATION_WINDOW = [self.aspiration_window],
@@ -169,10 +282,19 @@ impl Config {
             RFP_IMPROVING_MARGIN = [self.rfp_improving_margin],
             NMP_IMPROVING_MARGIN = [self.nmp_improving_margin],
             NMP_REDUCTION_EVAL_DIVISOR = [self.nmp_reduction_eval_divisor],
+            NMP_MIN_DEPTH = [self.nmp_min_depth],
+            NMP_BASE_REDUCTION = [self.nmp_base_reduction],
+            NMP_REDUCTION_DEPTH_DIVISOR = [self.nmp_reduction_depth_divisor],
+            NMP_REDUCTION_EVAL_MAX = [self.nmp_reduction_eval_max],
+            NMP_VERIFICATION_DEPTH = [self.nmp_verification_depth],
+            NMP_WORSENING_REDUCTION = [self.nmp_worsening_reduction],
+            KING_DANGER_THRESHOLD = [self.king_danger_threshold],
             SEE_QUIET_MARGIN = [self.see_quiet_margin],
             SEE_TACTICAL_MARGIN = [self.see_tactical_margin],
             FUTILITY_COEFF_0 = [self.futility_coeff_0],
             FUTILITY_COEFF_1 = [self.futility_coeff_1],
+            FUTILITY_WORSENING_MARGIN = [self.futility_worsening_margin],
+            LMP_WORSENING_MOVES = [self.lmp_worsening_moves],
             RAZORING_COEFF_0 = [self.razoring_coeff_0],
             RAZORING_COEFF_1 = [self.razoring_coeff_1],
             DOUBLE_EXTENSION_MARGIN = [self.dext_margin],
@@ -180,6 +302,13 @@ impl Config {
             LMR_DIVISION = [self.lmr_division],
             PROBCUT_MARGIN = [self.probcut_margin],
             PROBCUT_IMPROVING_MARGIN = [self.probcut_improving_margin],
+            MULTI_CUT_DEPTH = [self.multi_cut_depth],
+            MULTI_CUT_REDUCTION = [self.multi_cut_reduction],
+            MULTI_CUT_MOVES = [self.multi_cut_moves],
+            MULTI_CUT_REQUIRED = [self.multi_cut_required],
+            TT_FAST_PATH_DEPTH_MARGIN = [self.tt_fast_path_depth_margin],
+            TT_FAST_PATH_WINDOW_MARGIN = [self.tt_fast_path_window_margin],
+            TT_FAST_PATH_FIFTY_LIMIT = [self.tt_fast_path_fifty_limit],
             STRONG_FORCED_TM_FRAC = [self.strong_forced_tm_frac],
             WEAK_FORCED_TM_FRAC = [self.weak_forced_tm_frac],
             DEFAULT_MOVES_TO_GO = [self.default_moves_to_go],
@@ -188,6 +317,15 @@ impl Config {
             INCREMENT_FRAC = [self.increment_frac],
             NODE_TM_SUBTREE_MULTIPLIER = [self.node_tm_subtree_multiplier],
             FAIL_LOW_TM_BONUS = [self.fail_low_tm_bonus],
+            STABILITY_TM_MUL_0 = [self.stability_tm_mul_0],
+            STABILITY_TM_MUL_1 = [self.stability_tm_mul_1],
+            STABILITY_TM_MUL_2 = [self.stability_tm_mul_2],
+            STABILITY_TM_MUL_3 = [self.stability_tm_mul_3],
+            STABILITY_TM_MUL_4 = [self.stability_tm_mul_4],
+            SCORE_TREND_TM_MUL = [self.score_trend_tm_mul],
+            SCORE_TREND_TM_MIN = [self.score_trend_tm_min],
+            SCORE_TREND_TM_MAX = [self.score_trend_tm_max],
+            PONDER_HIT_TM_MUL = [self.ponder_hit_tm_mul],
             HISTORY_LMR_DIVISOR = [self.history_lmr_divisor],
             QS_SEE_BOUND = [self.qs_see_bound],
             MAIN_SEE_BOUND = [self.main_see_bound],
@@ -202,16 +340,31 @@ impl Config {
             LMR_CUT_NODE_MUL = [self.lmr_cut_node_mul],
             LMR_NON_IMPROVING_MUL = [self.lmr_non_improving_mul],
             LMR_TT_CAPTURE_MUL = [self.lmr_tt_capture_mul],
+            LMR_GRANULARITY = [self.lmr_granularity],
             HISTORY_BONUS_MUL = [self.history_bonus_mul],
             HISTORY_BONUS_OFFSET = [self.history_bonus_offset],
             HISTORY_BONUS_MAX = [self.history_bonus_max],
             HISTORY_MALUS_MUL = [self.history_malus_mul],
             HISTORY_MALUS_OFFSET = [self.history_malus_offset],
             HISTORY_MALUS_MAX = [self.history_malus_max],
+            HISTORY_MAX = [self.history_max],
             PAWN_CORRHIST_WEIGHT = [self.pawn_corrhist_weight],
             MAJOR_CORRHIST_WEIGHT = [self.major_corrhist_weight],
             MINOR_CORRHIST_WEIGHT = [self.minor_corrhist_weight],
-            NONPAWN_CORRHIST_WEIGHT = [self.nonpawn_corrhist_weight]
+            NONPAWN_CORRHIST_WEIGHT = [self.nonpawn_corrhist_weight],
+            EVAL_SCALE_BUCKET_0 = [self.eval_scale_bucket_0],
+            EVAL_SCALE_BUCKET_1 = [self.eval_scale_bucket_1],
+            EVAL_SCALE_BUCKET_2 = [self.eval_scale_bucket_2],
+            EVAL_SCALE_BUCKET_3 = [self.eval_scale_bucket_3],
+            EVAL_SCALE_BUCKET_4 = [self.eval_scale_bucket_4],
+            EVAL_SCALE_BUCKET_5 = [self.eval_scale_bucket_5],
+            EVAL_SCALE_BUCKET_6 = [self.eval_scale_bucket_6],
+            EVAL_SCALE_BUCKET_7 = [self.eval_scale_bucket_7],
+            OCB_SCALE = [self.ocb_scale],
+            ROOK_ENDING_SCALE = [self.rook_ending_scale],
+            PAWN_SCALE_BASE = [self.pawn_scale_base],
+            PAWN_SCALE_PER_PAWN = [self.pawn_scale_per_pawn],
+            SMALL_NET_THRESHOLD = [self.small_net_threshold]
         ]
     }
 
@@ -231,10 +384,19 @@ impl Config {
             RFP_IMPROVING_MARGIN = [self.rfp_improving_margin, 30, 150, 10],
             NMP_IMPROVING_MARGIN = [self.nmp_improving_margin, 30, 200, 10],
             NMP_REDUCTION_EVAL_DIVISOR = [self.nmp_reduction_eval_divisor, 100, 400, 20],
+            NMP_MIN_DEPTH = [self.nmp_min_depth, 1, 6, 1],
+            NMP_BASE_REDUCTION = [self.nmp_base_reduction, 2, 6, 1],
+            NMP_REDUCTION_DEPTH_DIVISOR = [self.nmp_reduction_depth_divisor, 2, 6, 1],
+            NMP_REDUCTION_EVAL_MAX = [self.nmp_reduction_eval_max, 1, 8, 1],
+            NMP_VERIFICATION_DEPTH = [self.nmp_verification_depth, 6, 20, 1],
+            NMP_WORSENING_REDUCTION = [self.nmp_worsening_reduction, 0, 2, 1],
+            KING_DANGER_THRESHOLD = [self.king_danger_threshold, 2, 10, 1],
             SEE_QUIET_MARGIN = [self.see_quiet_margin, -150, -20, 5],
             SEE_TACTICAL_MARGIN = [self.see_tactical_margin, -100, -1, 3],
             FUTILITY_COEFF_0 = [self.futility_coeff_0, 40, 200, 10],
             FUTILITY_COEFF_1 = [self.futility_coeff_1, 40, 200, 10],
+            FUTILITY_WORSENING_MARGIN = [self.futility_worsening_margin, 0, 100, 8],
+            LMP_WORSENING_MOVES = [self.lmp_worsening_moves, 0, 4, 1],
             RAZORING_COEFF_0 = [self.razoring_coeff_0, 200, 700, 30],
             RAZORING_COEFF_1 = [self.razoring_coeff_1, 150, 600, 30],
             DOUBLE_EXTENSION_MARGIN = [self.dext_margin, 5, 100, 1],
@@ -242,6 +404,13 @@ impl Config {
             LMR_DIVISION = [self.lmr_division, 150, 500, 15],
             PROBCUT_MARGIN = [self.probcut_margin, 100, 400, 20],
             PROBCUT_IMPROVING_MARGIN = [self.probcut_improving_margin, 20, 150, 10],
+            MULTI_CUT_DEPTH = [self.multi_cut_depth, 4, MAX_DEPTH, 1],
+            MULTI_CUT_REDUCTION = [self.multi_cut_reduction, 2, 8, 1],
+            MULTI_CUT_MOVES = [self.multi_cut_moves, 2, 12, 1],
+            MULTI_CUT_REQUIRED = [self.multi_cut_required, 1, 6, 1],
+            TT_FAST_PATH_DEPTH_MARGIN = [self.tt_fast_path_depth_margin, 1, 10, 1],
+            TT_FAST_PATH_WINDOW_MARGIN = [self.tt_fast_path_window_margin, 0, 200, 10],
+            TT_FAST_PATH_FIFTY_LIMIT = [self.tt_fast_path_fifty_limit, 0, 100, 5],
             STRONG_FORCED_TM_FRAC = [self.strong_forced_tm_frac, 1, 1000, 30],
             WEAK_FORCED_TM_FRAC = [self.weak_forced_tm_frac, 1, 1000, 30],
             DEFAULT_MOVES_TO_GO = [self.default_moves_to_go, 1, 100, 3],
@@ -250,6 +419,15 @@ impl Config {
             INCREMENT_FRAC = [self.increment_frac, 1, 100, 10],
             NODE_TM_SUBTREE_MULTIPLIER = [self.node_tm_subtree_multiplier, 1, 1000, 15],
             FAIL_LOW_TM_BONUS = [self.fail_low_tm_bonus, 1, 1000, 30],
+            STABILITY_TM_MUL_0 = [self.stability_tm_mul_0, 100, 400, 15],
+            STABILITY_TM_MUL_1 = [self.stability_tm_mul_1, 50, 250, 10],
+            STABILITY_TM_MUL_2 = [self.stability_tm_mul_2, 40, 200, 8],
+            STABILITY_TM_MUL_3 = [self.stability_tm_mul_3, 30, 150, 7],
+            STABILITY_TM_MUL_4 = [self.stability_tm_mul_4, 30, 150, 7],
+            SCORE_TREND_TM_MUL = [self.score_trend_tm_mul, 0, 300, 10],
+            SCORE_TREND_TM_MIN = [self.score_trend_tm_min, 50, 100, 4],
+            SCORE_TREND_TM_MAX = [self.score_trend_tm_max, 100, 300, 10],
+            PONDER_HIT_TM_MUL = [self.ponder_hit_tm_mul, 0, 100, 5],
             HISTORY_LMR_DIVISOR = [self.history_lmr_divisor, 1, 16383, 100],
             QS_SEE_BOUND = [self.qs_see_bound, -500, 500, 50],
             MAIN_SEE_BOUND = [self.main_see_bound, -500, 500, 50],
@@ -264,16 +442,31 @@ impl Config {
             LMR_CUT_NODE_MUL = [self.lmr_cut_node_mul, 1, 4096, 96],
             LMR_NON_IMPROVING_MUL = [self.lmr_non_improving_mul, 1, 4096, 96],
             LMR_TT_CAPTURE_MUL = [self.lmr_tt_capture_mul, 1, 4096, 96],
+            LMR_GRANULARITY = [self.lmr_granularity, 1, UNITS_PER_PLY, 10],
             HISTORY_BONUS_MUL = [self.history_bonus_mul, 1, 1500, 32],
             HISTORY_BONUS_OFFSET = [self.history_bonus_offset, -1024, 1024, 64],
             HISTORY_BONUS_MAX = [self.history_bonus_max, 1, 4096, 256],
             HISTORY_MALUS_MUL = [self.history_malus_mul, 1, 1500, 32],
             HISTORY_MALUS_OFFSET = [self.history_malus_offset, -1024, 1024, 64],
             HISTORY_MALUS_MAX = [self.history_malus_max, 1, 4096, 256],
+            HISTORY_MAX = [self.history_max, 2048, 16383, 512],
             PAWN_CORRHIST_WEIGHT = [self.pawn_corrhist_weight, 1, 4096, 144],
             MAJOR_CORRHIST_WEIGHT = [self.major_corrhist_weight, 1, 4096, 144],
             MINOR_CORRHIST_WEIGHT = [self.minor_corrhist_weight, 1, 4096, 144],
-            NONPAWN_CORRHIST_WEIGHT = [self.nonpawn_corrhist_weight, 1, 4096, 144]
+            NONPAWN_CORRHIST_WEIGHT = [self.nonpawn_corrhist_weight, 1, 4096, 144],
+            EVAL_SCALE_BUCKET_0 = [self.eval_scale_bucket_0, 768, 1280, 32],
+            EVAL_SCALE_BUCKET_1 = [self.eval_scale_bucket_1, 768, 1280, 32],
+            EVAL_SCALE_BUCKET_2 = [self.eval_scale_bucket_2, 768, 1280, 32],
+            EVAL_SCALE_BUCKET_3 = [self.eval_scale_bucket_3, 768, 1280, 32],
+            EVAL_SCALE_BUCKET_4 = [self.eval_scale_bucket_4, 768, 1280, 32],
+            EVAL_SCALE_BUCKET_5 = [self.eval_scale_bucket_5, 768, 1280, 32],
+            EVAL_SCALE_BUCKET_6 = [self.eval_scale_bucket_6, 768, 1280, 32],
+            EVAL_SCALE_BUCKET_7 = [self.eval_scale_bucket_7, 768, 1280, 32],
+            OCB_SCALE = [self.ocb_scale, 256, 1024, 48],
+            ROOK_ENDING_SCALE = [self.rook_ending_scale, 512, 1024, 32],
+            PAWN_SCALE_BASE = [self.pawn_scale_base, 256, 1024, 48],
+            PAWN_SCALE_PER_PAWN = [self.pawn_scale_per_pawn, 0, 256, 16],
+            SMALL_NET_THRESHOLD = [self.small_net_threshold, 400, 2000, 50]
         ]
     }
 
@@ -297,6 +490,64 @@ impl Config {
         json
     }
 
+    /// Load a configuration from a parameter file, in JSON or, given a `.toml` extension,
+    /// TOML. Each parameter is given either in the format emitted by `emit_json_for_spsa`
+    /// or as a bare value, as SPSA tuners produce. Parameters not mentioned in the file keep
+    /// their default values.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read parameter file {}", path.display()))?;
+        Self::from_text(&text, ParamFormat::of(path))
+            .with_context(|| format!("failed to load parameter file {}", path.display()))
+    }
+
+    pub fn from_text(text: &str, format: ParamFormat) -> anyhow::Result<Self> {
+        let specs = parse_param_file(text, format)?;
+        Self::from_values(specs.iter().map(|spec| (spec.id.as_str(), spec.value)))
+    }
+
+    /// Build a configuration by overriding the defaults with the given values,
+    /// which are rounded and checked against the parameter ranges.
+    pub fn from_values<'s>(
+        values: impl IntoIterator<Item = (&'s str, f64)>,
+    ) -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        let bounds = config
+            .base_config()
+            .into_iter()
+            .map(|(id, _, min, max, _)| (id.to_string(), (min, max)))
+            .collect::<Vec<_>>();
+        let mut parsers = config.ids_with_parsers();
+        for (id, value) in values {
+            let Some((_, (min, max))) = bounds.iter().find(|(name, _)| name == id) else {
+                bail!("unknown parameter {id}");
+            };
+            // SPSA produces fractional values, and our parameters are all integers.
+            let value = value.round();
+            if !(*min..=*max).contains(&value) {
+                bail!("value {value} for {id} is outside of the range [{min}, {max}]");
+            }
+            let (_, parser) = parsers
+                .iter_mut()
+                .find(|(name, _)| *name == id)
+                .with_context(|| format!("no parser for {id}"))?;
+            parser(&format!("{value}")).map_err(|e| anyhow::anyhow!("{e}"))?;
+        }
+        drop(parsers);
+        Ok(config)
+    }
+
+    /// The parameters that differ from the defaults, as `(id, default, value)`.
+    pub fn diff_from_default(&self) -> Vec<(&str, f64, f64)> {
+        let default = Self::default();
+        self.ids_with_values()
+            .into_iter()
+            .zip(default.ids_with_values())
+            .filter(|((_, value), (_, default))| (value - default).abs() > f64::EPSILON)
+            .map(|((id, value), (_, default))| (id, default, value))
+            .collect()
+    }
+
     pub fn emit_csv_for_spsa(&self) -> String {
         let mut csv = String::new();
         let mut tunegroups = Vec::new();
@@ -310,6 +561,106 @@ impl Config {
     }
 }
 
+/// A parameter entry read from a parameter file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamSpec {
+    pub id: String,
+    pub value: f64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub step: Option<f64>,
+}
+
+/// The format of a parameter file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamFormat {
+    Json,
+    Toml,
+}
+
+impl ParamFormat {
+    /// The format of the file at `path`: TOML for a `.toml` extension, and JSON otherwise.
+    pub fn of(path: &Path) -> Self {
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+        {
+            Self::Toml
+        } else {
+            Self::Json
+        }
+    }
+}
+
+/// How a parameter is written in a parameter file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ParamEntry {
+    Value(f64),
+    Spec {
+        value: f64,
+        min_value: Option<f64>,
+        max_value: Option<f64>,
+        step: Option<f64>,
+    },
+}
+
+/// Read the parameter file at `path`, in the format given by its extension.
+#[cfg(feature = "datagen")]
+pub fn read_param_file(path: &Path) -> anyhow::Result<Vec<ParamSpec>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read parameter file {}", path.display()))?;
+    parse_param_file(&text, ParamFormat::of(path))
+        .with_context(|| format!("failed to parse parameter file {}", path.display()))
+}
+
+/// Parse a table mapping parameter names to either numbers or to tables in the format of
+/// `Config::emit_json_for_spsa`. The parameters are given back in the order that `Config`
+/// lists them, followed by any that it doesn't know, so that callers can reject them.
+pub fn parse_param_file(text: &str, format: ParamFormat) -> anyhow::Result<Vec<ParamSpec>> {
+    let entries: BTreeMap<String, ParamEntry> = match format {
+        ParamFormat::Json => serde_json::from_str(text)?,
+        ParamFormat::Toml => toml::from_str(text)?,
+    };
+    let defaults = Config::default();
+    let order = defaults
+        .base_config()
+        .into_iter()
+        .map(|(id, ..)| id)
+        .collect::<Vec<_>>();
+    let mut specs = entries
+        .into_iter()
+        .map(|(id, entry)| match entry {
+            ParamEntry::Value(value) => ParamSpec {
+                id,
+                value,
+                min: None,
+                max: None,
+                step: None,
+            },
+            ParamEntry::Spec {
+                value,
+                min_value,
+                max_value,
+                step,
+            } => ParamSpec {
+                id,
+                value,
+                min: min_value,
+                max: max_value,
+                step,
+            },
+        })
+        .collect::<Vec<_>>();
+    specs.sort_by_key(|spec| {
+        order
+            .iter()
+            .position(|&id| id == spec.id)
+            .unwrap_or(order.len())
+    });
+    Ok(specs)
+}
+
 mod tests {
     #[test]
     fn macro_hackery_same_length() {
@@ -319,6 +670,22 @@ mod tests {
         assert_eq!(l1, l2);
     }
 
+    #[test]
+    fn every_spsa_parameter_has_a_setoption_handler() {
+        let mut sp = super::Config::default();
+        let spsa_ids = sp
+            .base_config()
+            .into_iter()
+            .map(|(id, ..)| id.to_string())
+            .collect::<Vec<_>>();
+        let parser_ids = sp
+            .ids_with_parsers()
+            .into_iter()
+            .map(|(id, _)| id.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(spsa_ids, parser_ids);
+    }
+
     #[test]
     fn parser_actually_works() {
         let mut sp = super::Config::default();
@@ -345,4 +712,40 @@ mod tests {
             .1;
         assert!((rfp_margin - 10.0).abs() < f64::EPSILON);
     }
+
+    #[test]
+    fn json_round_trip() {
+        use super::{Config, ParamFormat};
+
+        let json = |text: &str| Config::from_text(text, ParamFormat::Json);
+        let mut config = Config::default();
+        config.rfp_margin = 123;
+        let loaded = json(&config.emit_json_for_spsa()).unwrap();
+        assert_eq!(
+            loaded.diff_from_default(),
+            vec![("RFP_MARGIN", f64::from(crate::search::RFP_MARGIN), 123.0)]
+        );
+        let flat = json(r#"{ "RFP_MARGIN": 122.6 }"#).unwrap();
+        assert_eq!(flat.rfp_margin, 123);
+        assert!(json(r#"{ "RFP_MARGIN": 100000 }"#).is_err());
+        assert!(json(r#"{ "NOT_A_PARAM": 1 }"#).is_err());
+        assert!(json(r#"{ "RFP_MARGIN": 1, }"#).is_err());
+    }
+
+    #[test]
+    fn toml_parameter_files() {
+        use super::{parse_param_file, ParamFormat};
+
+        let text = "RFP_MARGIN = 123\n\n[LMR_BASE]\nvalue = 80.5\nmin_value = 50\n";
+        let specs = parse_param_file(text, ParamFormat::Toml).unwrap();
+        // the parameters come back in the order that the configuration lists them.
+        let ids = specs
+            .iter()
+            .map(|spec| spec.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["RFP_MARGIN", "LMR_BASE"]);
+        assert!((specs[1].value - 80.5).abs() < f64::EPSILON);
+        assert_eq!(specs[1].min, Some(50.0));
+        assert!(parse_param_file("LMR_BASE = \"lots\"", ParamFormat::Toml).is_err());
+    }
 }
@@ -4,13 +4,14 @@ use std::sync::atomic::Ordering;
 use arrayvec::ArrayVec;
 
 use crate::chess::CHESS960;
+use crate::evaluation::Value;
 use crate::util::MAX_PLY;
 
 use crate::chess::chessmove::Move;
 
 #[derive(Clone, Debug)]
 pub struct PVariation {
-    pub(crate) score: i32,
+    pub(crate) score: Value,
     pub(crate) moves: ArrayVec<Move, MAX_PLY>,
 }
 
@@ -22,7 +23,7 @@ impl Default for PVariation {
 
 impl PVariation {
     const EMPTY: Self = Self {
-        score: 0,
+        score: Value::DRAW,
         moves: ArrayVec::new_const(),
     };
 
@@ -30,7 +31,7 @@ impl PVariation {
         &self.moves
     }
 
-    pub const fn score(&self) -> i32 {
+    pub const fn score(&self) -> Value {
         self.score
     }
 
@@ -0,0 +1,130 @@
+use arrayvec::ArrayVec;
+
+use crate::{
+    chess::{
+        board::{
+            movegen::{MoveList, MAX_POSITION_MOVES},
+            Board,
+        },
+        chessmove::Move,
+    },
+    util::INFINITY,
+};
+
+/// A legal move at the root, along with what earlier iterations learned about it.
+#[derive(Clone, Copy, Debug)]
+pub struct RootMove {
+    pub mov: Move,
+    /// The score of the move in the current iteration, or `-INFINITY` if it failed low.
+    pub score: i32,
+    /// The score of the move in the previous iteration, or `-INFINITY` if it failed low.
+    pub previous_score: i32,
+    /// The number of nodes searched under this move, across all iterations.
+    pub nodes: u64,
+}
+
+/// The legal moves at the root, in the order they are to be searched.
+#[derive(Clone, Debug)]
+pub struct RootMoves {
+    moves: ArrayVec<RootMove, MAX_POSITION_MOVES>,
+}
+
+impl RootMoves {
+    pub const fn new() -> Self {
+        Self {
+            moves: ArrayVec::new_const(),
+        }
+    }
+
+    /// Start afresh with the legal moves of `board`.
+    pub fn set_up(&mut self, board: &Board) {
+        let mut move_list = MoveList::new();
+        board.generate_legal_moves(&mut move_list);
+        self.moves.clear();
+        self.moves
+            .extend(move_list.iter_moves().map(|&mov| RootMove {
+                mov,
+                score: -INFINITY,
+                previous_score: -INFINITY,
+                nodes: 0,
+            }));
+    }
+
+    /// Search only the moves in `searchmoves`, unless it's empty or none of them are legal.
+    pub fn restrict(&mut self, searchmoves: &[Move]) {
+        if self.moves.iter().any(|rm| searchmoves.contains(&rm.mov)) {
+            self.moves.retain(|rm| searchmoves.contains(&rm.mov));
+        }
+    }
+
+    /// Prepare for a new iteration, putting `best_move` first and then
+    /// the moves that took the most effort to search.
+    pub fn order(&mut self, best_move: Option<Move>) {
+        for rm in &mut self.moves {
+            rm.previous_score = rm.score;
+            rm.score = -INFINITY;
+        }
+        self.moves.sort_unstable_by_key(|rm| {
+            (
+                std::cmp::Reverse(Some(rm.mov) == best_move),
+                std::cmp::Reverse(rm.nodes),
+                std::cmp::Reverse(rm.previous_score),
+            )
+        });
+    }
+
+    /// Record the result of searching `m` in the current iteration.
+    pub fn update(&mut self, m: Move, score: i32, nodes: u64) {
+        if let Some(rm) = self.moves.iter_mut().find(|rm| rm.mov == m) {
+            rm.score = score;
+            rm.nodes += nodes;
+        }
+    }
+
+    /// Whether `m` is one of the moves being searched.
+    pub fn contains(&self, m: Move) -> bool {
+        self.moves.iter().any(|rm| rm.mov == m)
+    }
+
+    /// The number of nodes searched under `m`, across all iterations.
+    pub fn nodes(&self, m: Move) -> u64 {
+        self.moves
+            .iter()
+            .find(|rm| rm.mov == m)
+            .map_or(0, |rm| rm.nodes)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RootMove> {
+        self.moves.iter()
+    }
+}
+
+mod tests {
+    #[test]
+    fn ordering() {
+        use super::RootMoves;
+        use crate::chess::{board::Board, chessmove::Move, types::Square};
+
+        let mut board = Board::default();
+        board.set_startpos();
+        let mut root_moves = RootMoves::new();
+        root_moves.set_up(&board);
+        assert_eq!(root_moves.iter().count(), 20);
+
+        let e4 = Move::new(Square::E2, Square::E4);
+        let d4 = Move::new(Square::D2, Square::D4);
+        let nf3 = Move::new(Square::G1, Square::F3);
+        root_moves.update(e4, 30, 100);
+        root_moves.update(d4, 25, 500);
+        root_moves.update(nf3, 20, 300);
+        root_moves.order(Some(e4));
+        let order = root_moves
+            .iter()
+            .take(3)
+            .map(|rm| rm.mov)
+            .collect::<Vec<_>>();
+        assert_eq!(order, [e4, d4, nf3]);
+        assert_eq!(root_moves.iter().next().unwrap().previous_score, 30);
+        assert_eq!(root_moves.nodes(d4), 500);
+    }
+}
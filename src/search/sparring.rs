@@ -0,0 +1,190 @@
+//! Sparring mode, for training partners: when several root moves score within a small
+//! margin of the best, play the one leading to the sharpest position rather than the
+//! safest, so that more games are decided by tactics. Unlike root variety, the choice is
+//! deterministic: a position is sharp when the opponent's replies lead to very different
+//! scores, each resolved by a quiescence search.
+
+use std::sync::atomic::{AtomicBool, AtomicU64};
+
+use crate::{
+    chess::{board::Board, chessmove::Move, types::ContHistIndex},
+    search::{parameters::Config, pv::PVariation, OffPV},
+    searchinfo::SearchInfo,
+    threadlocal::ThreadData,
+    util::{depth::Depth, INFINITY},
+};
+
+/// Reply scores are capped at this many centipawns in either direction, so that a single
+/// reply that blunders into mate doesn't outweigh how the rest of the replies are spread.
+const SHARPNESS_CAP: i32 = 1000;
+
+/// The mean absolute deviation of `scores` from their mean, after capping each of them.
+fn volatility(scores: &[i32]) -> i32 {
+    #![allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    if scores.is_empty() {
+        return 0;
+    }
+    let len = scores.len() as i32;
+    let capped = scores
+        .iter()
+        .map(|&s| s.clamp(-SHARPNESS_CAP, SHARPNESS_CAP));
+    let mean = capped.clone().sum::<i32>() / len;
+    capped.map(|s| (s - mean).abs()).sum::<i32>() / len
+}
+
+/// Make the move `m`, recording it on the search stack as the search itself would.
+fn play(board: &mut Board, m: Move, t: &mut ThreadData) -> bool {
+    let height = board.height();
+    t.ss[height].searching = Some(m);
+    t.ss[height].searching_tactical = board.is_tactical(m);
+    t.ss[height].conthist_index = ContHistIndex {
+        piece: board.piece_at(m.from()).unwrap(),
+        square: m.history_to_square(),
+    };
+    board.make_move(m, t)
+}
+
+/// Whether `m` scores at least `bound`, according to a null-window search to `depth`.
+fn scores_at_least(
+    board: &mut Board,
+    m: Move,
+    bound: i32,
+    depth: Depth,
+    info: &mut SearchInfo,
+    t: &mut ThreadData,
+) -> bool {
+    if !play(board, m, t) {
+        return false;
+    }
+    let value = -board.alpha_beta::<OffPV>(
+        &mut PVariation::default(),
+        info,
+        t,
+        depth,
+        -bound,
+        -bound + 1,
+        false,
+    );
+    board.unmake_move(t);
+    value >= bound
+}
+
+/// How sharp the position after `m` is: the volatility of the scores of the opponent's
+/// replies, each resolved by a quiescence search.
+fn sharpness(board: &mut Board, m: Move, info: &mut SearchInfo, t: &mut ThreadData) -> i32 {
+    if !play(board, m, t) {
+        return 0;
+    }
+    let mut scores = Vec::new();
+    for reply in board.legal_moves() {
+        if !play(board, reply, t) {
+            continue;
+        }
+        let score =
+            board.quiescence::<OffPV>(&mut PVariation::default(), info, t, -INFINITY, INFINITY, 0);
+        board.unmake_move(t);
+        scores.push(score);
+    }
+    board.unmake_move(t);
+    volatility(&scores)
+}
+
+/// Of `candidates`, which are best first, find the move leading to the sharpest position,
+/// along with its sharpness. Ties go to the better move.
+fn sharpest(
+    board: &mut Board,
+    candidates: &[Move],
+    info: &mut SearchInfo,
+    t: &mut ThreadData,
+) -> (Move, i32) {
+    let mut best = (candidates[0], i32::MIN);
+    for &m in candidates {
+        let sharpness = sharpness(board, m, info, t);
+        if sharpness > best.1 {
+            best = (m, sharpness);
+        }
+    }
+    best
+}
+
+/// Look for a move that scores within `margin` of `best_move`, which the search gave a score
+/// of `score` at `depth`, and that leads to a sharper position than it does. The alternatives
+/// are verified by a null-window search to half the depth, as the search itself only proves
+/// that they are worse than the best move, not by how much.
+pub fn sharper_move(
+    board: &mut Board,
+    best_move: Move,
+    score: i32,
+    depth: usize,
+    margin: i32,
+    conf: &Config,
+    t: &mut ThreadData,
+) -> Option<(Move, i32)> {
+    // the search that chose the best move has stopped, so these searches get a fresh
+    // stop signal and node count of their own.
+    let stopped = AtomicBool::new(false);
+    let nodes = AtomicU64::new(0);
+    let mut info = SearchInfo {
+        print_to_stdout: false,
+        ..SearchInfo::with_search_params(&stopped, &nodes, conf)
+    };
+    t.nnue.reinit_from(board, t.nnue_params);
+
+    let verify_depth = Depth::new(i32::try_from(depth / 2).unwrap_or(i32::MAX).max(1));
+    let mut candidates = vec![best_move];
+    for m in board.legal_moves() {
+        if m != best_move && scores_at_least(board, m, score - margin, verify_depth, &mut info, t) {
+            candidates.push(m);
+        }
+    }
+    if candidates.len() == 1 {
+        return None;
+    }
+    let (sharpest, sharpness) = sharpest(board, &candidates, &mut info, t);
+    (sharpest != best_move).then_some((sharpest, sharpness))
+}
+
+mod tests {
+    #[test]
+    fn volatility() {
+        use super::{volatility, SHARPNESS_CAP};
+        use crate::evaluation::mate_in;
+
+        assert_eq!(volatility(&[]), 0);
+        assert_eq!(volatility(&[30, 30, 30]), 0);
+        assert_eq!(volatility(&[-100, 100]), 100);
+        // a mate among the replies counts for no more than a large advantage.
+        assert_eq!(
+            volatility(&[0, mate_in(3)]),
+            volatility(&[0, SHARPNESS_CAP])
+        );
+    }
+
+    #[test]
+    fn sharper_positions_are_preferred() {
+        use super::sharpest;
+        use crate::{
+            chess::board::Board, nnue::network::NNUEParams, searchinfo::SearchInfo,
+            threadlocal::ThreadData, transpositiontable::TT, util::MEGABYTE,
+        };
+        use std::sync::atomic::{AtomicBool, AtomicU64};
+
+        // after Rb1, the reply Kh8 walks into Rb8#, while behind the a-pawn the rook has
+        // no checks to give, and every reply to Kf1 comes to the same thing.
+        let fen = "6k1/5ppp/8/8/8/P7/5PPP/R5K1 w - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+        let mut t = ThreadData::new(0, &board, tt.view(), nnue_params);
+        let stopped = AtomicBool::new(false);
+        let nodes = AtomicU64::new(0);
+        let mut info = SearchInfo::new(&stopped, &nodes);
+        let quiet = board.parse_uci("g1f1").unwrap();
+        let sharp = board.parse_uci("a1b1").unwrap();
+        let (chosen, sharpness) = sharpest(&mut board, &[quiet, sharp], &mut info, &mut t);
+        assert_eq!(chosen, sharp);
+        assert!(sharpness > 0);
+        assert_eq!(board.to_string(), fen);
+    }
+}
@@ -0,0 +1,177 @@
+//! Short explanations of the move that a search settles on, for tutoring front-ends:
+//! the leading candidate moves, what kind of move was chosen, and, when the search
+//! disagrees sharply with the static evaluation, the tactical motif behind it.
+
+use std::sync::atomic::Ordering;
+
+use crate::{
+    chess::{board::Board, chessmove::Move, piece::PieceType, CHESS960},
+    evaluation::{is_mate_score, Value, MATE_SCORE},
+    search::rootmoves::RootMoves,
+    uci::format_score,
+    util::INFINITY,
+};
+
+/// The number of candidate moves to list.
+const CANDIDATES: usize = 3;
+/// How far the search score must be from the static evaluation before the move is
+/// explained with a tactical motif.
+const SWING_MARGIN: i32 = 150;
+
+/// The name of a piece type, as it reads in a sentence.
+fn piece_name(piece_type: PieceType) -> String {
+    piece_type.to_string().to_lowercase()
+}
+
+/// Describe what kind of move `m` is: a capture, a check, both, or a quiet move.
+fn move_kind(board: &Board, m: Move) -> &'static str {
+    let capture = board.is_tactical(m);
+    let mut after = board.clone();
+    after.make_move_simple(m);
+    match (capture, after.in_check()) {
+        (true, true) => "capture check",
+        (true, false) => "capture",
+        (false, true) => "check",
+        (false, false) => "quiet",
+    }
+}
+
+/// Find the motif that explains a score of `score` for the side to move after `best_move`,
+/// given that the static evaluation was `static_eval`. Both are from the side to move's
+/// perspective.
+fn motif(board: &Board, best_move: Move, score: i32, static_eval: i32) -> Option<String> {
+    if is_mate_score(score) {
+        let moves = (MATE_SCORE - score.abs() + 1) / 2;
+        return Some(if score > 0 {
+            format!("forced mate in {moves}")
+        } else {
+            format!("mated in {moves}")
+        });
+    }
+    let swing = score - static_eval;
+    if swing.abs() < SWING_MARGIN {
+        return None;
+    }
+    let us = board.turn();
+    let their_attacks = board.threats_by(us.flip());
+    let our_attacks = board.threats_by(us);
+    if let Some(victim) = board.captured_piece(best_move) {
+        let to = best_move.to();
+        if !their_attacks.contains_square(to) {
+            return Some(format!(
+                "wins the undefended {} on {to}",
+                piece_name(victim.piece_type())
+            ));
+        }
+        if board.static_exchange_eval(best_move, 1) {
+            return Some(format!("wins material on {to}"));
+        }
+    }
+    // a piece of ours that they attack and that nothing of ours defends.
+    let hanging = (board.pieces.occupied_co(us) & their_attacks & !our_attacks)
+        .iter()
+        .filter_map(|sq| board.piece_at(sq).map(|piece| (sq, piece.piece_type())))
+        .filter(|&(_, piece_type)| piece_type != PieceType::King)
+        .max_by_key(|&(_, piece_type)| piece_type.see_value());
+    if let Some((sq, piece_type)) = hanging {
+        if best_move.from() == sq {
+            return Some(format!(
+                "saves the hanging {} on {sq}",
+                piece_name(piece_type)
+            ));
+        }
+        if swing < 0 {
+            return Some(format!("the {} on {sq} is hanging", piece_name(piece_type)));
+        }
+    }
+    if move_kind(board, best_move).ends_with("check") {
+        return Some("attacks the king".into());
+    }
+    Some(if swing > 0 {
+        "a tactic beyond the static evaluation".into()
+    } else {
+        "a threat beyond the static evaluation".into()
+    })
+}
+
+/// The lines of a teaching explanation of `best_move`, chosen in `board` with a score of
+/// `score` and a static evaluation of `static_eval`, both from the side to move's perspective.
+/// The other candidates are the moves in `root_moves` that led the search at some point in
+/// its last two iterations, with the scores they were given then: moves that never beat the
+/// best move found so far fail low without a score, and aren't listed.
+pub fn explain(
+    board: &Board,
+    best_move: Move,
+    score: Value,
+    static_eval: Value,
+    root_moves: &RootMoves,
+) -> Vec<String> {
+    let (score, static_eval) = (score.get(), static_eval.get());
+    let chess960 = CHESS960.load(Ordering::Relaxed);
+    let mut others = root_moves
+        .iter()
+        .filter(|rm| rm.mov != best_move)
+        .map(|rm| (rm.mov, rm.score.max(rm.previous_score)))
+        .filter(|&(_, score)| score > -INFINITY)
+        .collect::<Vec<_>>();
+    others.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+
+    let mut lines = vec![format!(
+        "teach candidate 1 {} score {}",
+        best_move.display(chess960),
+        format_score(score)
+    )];
+    for (i, (m, score)) in others.into_iter().take(CANDIDATES - 1).enumerate() {
+        lines.push(format!(
+            "teach candidate {} {} score {}",
+            i + 2,
+            m.display(chess960),
+            format_score(score)
+        ));
+    }
+    lines.push(format!(
+        "teach bestmove {} kind {}",
+        best_move.display(chess960),
+        move_kind(board, best_move)
+    ));
+    if let Some(motif) = motif(board, best_move, score, static_eval) {
+        lines.push(format!("teach motif {motif}"));
+    }
+    lines
+}
+
+mod tests {
+    #[test]
+    fn motifs() {
+        use super::{explain, motif};
+        use crate::evaluation::Value;
+        use crate::{chess::board::Board, search::rootmoves::RootMoves};
+
+        // the knight on e5 is free for the taking.
+        let board = Board::from_fen("4k3/8/8/4n3/8/8/8/4RK2 w - - 0 1").unwrap();
+        let capture = board.parse_uci("e1e5").unwrap();
+        assert_eq!(
+            motif(&board, capture, 300, 0).as_deref(),
+            Some("wins the undefended knight on e5")
+        );
+        // no motif is needed when the search agrees with the static evaluation.
+        assert_eq!(motif(&board, capture, 310, 300), None);
+
+        // black's queen is attacked and undefended, and is moved away.
+        let board = Board::from_fen("4k3/8/8/3q4/8/8/8/3RK3 b - - 0 1").unwrap();
+        let retreat = board.parse_uci("d5h5").unwrap();
+        assert_eq!(
+            motif(&board, retreat, 0, -900).as_deref(),
+            Some("saves the hanging queen on d5")
+        );
+
+        let mut root_moves = RootMoves::new();
+        root_moves.set_up(&board);
+        root_moves.update(board.parse_uci("d5d1").unwrap(), -500, 100);
+        let lines = explain(&board, retreat, Value::DRAW, Value::cp(-900), &root_moves);
+        assert_eq!(lines[0], "teach candidate 1 d5h5 score cp 0");
+        assert!(lines[1].starts_with("teach candidate 2 d5d1 score cp -"));
+        assert_eq!(lines[2], "teach bestmove d5h5 kind quiet");
+        assert_eq!(lines[3], "teach motif saves the hanging queen on d5");
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{
+    chess::board::Board,
+    rng::{fresh_seed, mix},
+};
+
+/// Opening variety for self-play exhibitions: during the first few full-moves of a game,
+/// each root move gets a small random bonus, so that near-equal moves are chosen between
+/// differently from game to game. The bonus for a move is fixed for the whole game, so
+/// the search stays consistent from one iteration to the next.
+#[derive(Clone, Debug)]
+pub struct RootVariety {
+    /// The largest bonus, in centipawns, in either direction. Zero disables variety.
+    pub margin: i32,
+    /// The last full-move number in which bonuses are applied.
+    pub moves: usize,
+    /// The seed for the sequence of games, or zero to pick a fresh one for every game.
+    pub seed: u64,
+    /// The number of games started since the seed was set.
+    games: u64,
+    /// The key from which this game's bonuses are derived.
+    game_key: u64,
+}
+
+impl Default for RootVariety {
+    fn default() -> Self {
+        let mut out = Self {
+            margin: 0,
+            moves: 10,
+            seed: 0,
+            games: 0,
+            game_key: 0,
+        };
+        out.rekey();
+        out
+    }
+}
+
+impl RootVariety {
+    fn rekey(&mut self) {
+        self.game_key = if self.seed == 0 {
+            fresh_seed()
+        } else {
+            mix(self.seed.wrapping_add(self.games))
+        };
+    }
+
+    /// Restart the sequence of games from a new seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.games = 0;
+        self.rekey();
+    }
+
+    /// Draw the bonuses for the next game.
+    pub fn new_game(&mut self) {
+        self.games += 1;
+        self.rekey();
+    }
+
+    /// Whether bonuses are applied to the root moves of `board`.
+    pub const fn applies(&self, board: &Board) -> bool {
+        self.margin > 0 && board.full_move_number() <= self.moves
+    }
+
+    /// The bonus for the root move that led to `child`.
+    pub fn bonus(&self, child: &Board) -> i32 {
+        #![allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let span = 2 * self.margin.unsigned_abs() + 1;
+        (mix(self.game_key ^ child.zobrist_key()) % u64::from(span)) as i32 - self.margin
+    }
+}
+
+mod tests {
+    #[test]
+    fn bonuses_are_bounded_and_stable() {
+        use super::RootVariety;
+        use crate::chess::board::Board;
+
+        let mut variety = RootVariety {
+            margin: 5,
+            ..RootVariety::default()
+        };
+        variety.set_seed(42);
+        let mut board = Board::default();
+        board.set_startpos();
+        assert!(variety.applies(&board));
+
+        let bonuses = board
+            .legal_moves()
+            .into_iter()
+            .map(|m| {
+                let mut child = board.clone();
+                child.make_move_simple(m);
+                variety.bonus(&child)
+            })
+            .collect::<Vec<_>>();
+        assert!(bonuses.iter().all(|b| (-5..=5).contains(b)));
+        assert!(bonuses.iter().any(|&b| b != bonuses[0]));
+
+        // the same seed replays the same games.
+        let mut replay = variety.clone();
+        replay.set_seed(42);
+        let mut child = board.clone();
+        child.make_move_simple(board.legal_moves()[0]);
+        assert_eq!(replay.bonus(&child), bonuses[0]);
+
+        variety.moves = 0;
+        assert!(!variety.applies(&board));
+    }
+}
@@ -4,14 +4,18 @@ use std::sync::{
 };
 
 use crate::{
-    search::{parameters::Config, LMTable},
-    timemgmt::TimeManager,
+    adjudication::Adjudicator,
+    chess::{chessmove::Move, piece::Colour},
+    search::{parameters::Config, variety::RootVariety, LMTable},
+    threadlocal::{SearchStats, ThreadData, STATS_CUTOFF_SLOTS},
+    threadpool::ThreadPool,
+    timemgmt::{SearchLimit, SearchLimits, TimeManager},
     uci,
-    util::BatchedAtomicCounter,
+    util::{BatchedAtomicCounter, MAX_PLY},
 };
 
-#[cfg(feature = "stats")]
-use crate::board::movegen::MAX_POSITION_MOVES;
+/// Successful tablebase probes, summed over all threads.
+static TB_HITS: AtomicU64 = AtomicU64::new(0);
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Debug)]
@@ -19,8 +23,9 @@ use crate::board::movegen::MAX_POSITION_MOVES;
 pub struct SearchInfo<'a> {
     /// The number of nodes searched.
     pub nodes: BatchedAtomicCounter<'a>,
-    /// A table storing the number of nodes under the root move(s).
-    pub root_move_nodes: [[u64; 64]; 64], // [from][to]
+    /// The number of successful tablebase probes. These are far rarer than nodes,
+    /// so they're batched more finely.
+    pub tbhits: BatchedAtomicCounter<'a, 16>,
     /// Signal to stop the search.
     pub stopped: &'a AtomicBool,
     /// The highest depth reached (selective depth).
@@ -35,31 +40,25 @@ pub struct SearchInfo<'a> {
     pub lm_table: LMTable,
     /// The time manager.
     pub time_manager: TimeManager,
-
-    /* Conditionally-compiled stat trackers: */
-    /// The number of fail-highs found (beta cutoffs).
-    #[cfg(feature = "stats")]
-    pub failhigh: u64,
-    /// The number of fail-highs that occurred on a given ply.
-    #[cfg(feature = "stats")]
-    pub failhigh_index: [u64; MAX_POSITION_MOVES],
-    /// Tracks fail-highs of different types.
-    #[cfg(feature = "stats")]
-    pub failhigh_types: [u64; 8],
-    /// The number of fail-highs found in quiescence search.
-    #[cfg(feature = "stats")]
-    pub qfailhigh: u64,
-    /// The number of fail-highs that occurred on a given ply in quiescence search.
-    #[cfg(feature = "stats")]
-    pub qfailhigh_index: [u64; MAX_POSITION_MOVES],
+    /// Random bonuses for root moves early in the game.
+    pub variety: RootVariety,
+    /// When to tell the GUI that we would resign or offer a draw.
+    pub adjudicator: Adjudicator,
+    /// Persistent search threads, or `None` to spawn threads for each search.
+    pub pool: Option<&'a ThreadPool>,
+    /// The root moves to search, or empty to search them all.
+    pub searchmoves: Vec<Move>,
+    /// Whether the GUI is analysing rather than playing (`UCI_AnalyseMode`), in which
+    /// case we report the position as it is, and search until we're told to stop.
+    pub analyse_mode: bool,
 }
 
 impl<'a> SearchInfo<'a> {
     pub fn new(stopped: &'a AtomicBool, nodes: &'a AtomicU64) -> Self {
         let out = Self {
             nodes: BatchedAtomicCounter::new(nodes),
+            tbhits: BatchedAtomicCounter::new(&TB_HITS),
             #[allow(clippy::large_stack_arrays)]
-            root_move_nodes: [[0; 64]; 64],
             stopped,
             seldepth: 0,
             stdin_rx: None,
@@ -67,16 +66,11 @@ impl<'a> SearchInfo<'a> {
             conf: Config::default(),
             lm_table: LMTable::default(),
             time_manager: TimeManager::default(),
-            #[cfg(feature = "stats")]
-            failhigh: 0,
-            #[cfg(feature = "stats")]
-            failhigh_index: [0; MAX_POSITION_MOVES],
-            #[cfg(feature = "stats")]
-            failhigh_types: [0; 8],
-            #[cfg(feature = "stats")]
-            qfailhigh: 0,
-            #[cfg(feature = "stats")]
-            qfailhigh_index: [0; MAX_POSITION_MOVES],
+            variety: RootVariety::default(),
+            adjudicator: Adjudicator::default(),
+            pool: None,
+            searchmoves: Vec::new(),
+            analyse_mode: false,
         };
         assert!(!out.stopped.load(Ordering::SeqCst));
         out
@@ -92,21 +86,26 @@ impl<'a> SearchInfo<'a> {
         out
     }
 
+    /// Take the limits of a `go` command for a search with `turn` to move.
+    pub fn set_limits(&mut self, limits: &SearchLimits, turn: Colour) {
+        let mate_ply = limits.mate.map_or(MAX_PLY, |moves| moves * 2);
+        uci::GO_MATE_MAX_DEPTH.store(mate_ply, Ordering::SeqCst);
+        self.time_manager.set_limit(limits.limit(turn));
+        self.searchmoves.clone_from(&limits.searchmoves);
+    }
+
     pub fn set_up_for_search(&mut self) {
         self.stopped.store(false, Ordering::SeqCst);
         self.nodes.reset();
-        for rmnc in self.root_move_nodes.iter_mut().flatten() {
-            *rmnc = 0;
-        }
+        self.tbhits.reset();
         self.time_manager.reset_for_id(&self.conf);
-        #[cfg(feature = "stats")]
-        {
-            self.failhigh = 0;
-            self.failhigh_index = [0; MAX_POSITION_MOVES];
-            self.failhigh_types = [0; 8];
-            self.qfailhigh = 0;
-            self.qfailhigh_index = [0; MAX_POSITION_MOVES];
-        }
+    }
+
+    /// Bring the shared node and tablebase hit totals up to date with this thread's
+    /// counts, before reporting them or when this thread stops searching.
+    pub fn flush_counters(&mut self) {
+        self.nodes.flush();
+        self.tbhits.flush();
     }
 
     pub fn set_stdin(&mut self, stdin_rx: &'a Mutex<mpsc::Receiver<String>>) {
@@ -121,19 +120,39 @@ impl<'a> SearchInfo<'a> {
         let res = self
             .time_manager
             .check_up(self.stopped, self.nodes.get_global());
+        self.heartbeat();
         if let Some(Ok(cmd)) = self.stdin_rx.map(|m| m.lock().unwrap().try_recv()) {
             let cmd = cmd.trim();
+            let pondering = matches!(self.time_manager.limit(), SearchLimit::Pondering { .. });
+            if cmd == "isready" {
+                // the GUI only wants to know we're alive, so the search carries on.
+                uci::output::send("readyok".into());
+                return res;
+            }
             if cmd == "ponderhit" {
-                println!("info string limit was {:?}", self.time_manager.limit());
+                uci::output::send(format!(
+                    "info string limit was {:?}",
+                    self.time_manager.limit()
+                ));
                 let unpondering_limit = self.time_manager.limit().clone().from_pondering();
-                println!("info string unpondering limit is {unpondering_limit:?}");
+                uci::output::send(format!(
+                    "info string unpondering limit is {unpondering_limit:?}"
+                ));
                 self.time_manager.set_limit(unpondering_limit);
                 self.time_manager.start();
+                if pondering {
+                    self.report_ponder_result(true);
+                }
                 return self
                     .time_manager
                     .check_up(self.stopped, self.nodes.get_global());
             }
+            if pondering && cmd == "stop" {
+                // being stopped while pondering means the opponent didn't play our move.
+                self.report_ponder_result(false);
+            }
             self.stopped.store(true, Ordering::SeqCst);
+            self.time_manager.cancel_reply_delay();
             if cmd == "quit" {
                 uci::QUIT.store(true, Ordering::SeqCst);
             }
@@ -143,93 +162,182 @@ impl<'a> SearchInfo<'a> {
         }
     }
 
-    pub fn skip_print(&self) -> bool {
-        self.time_manager.time_since_start().as_millis() < 50
+    /// Let the GUI know we're alive during long iterations, as some GUIs will
+    /// otherwise assume that we have hung and disconnect.
+    fn heartbeat(&self) {
+        #![allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let elapsed = self.time_manager.elapsed();
+        if !self.print_to_stdout
+            || elapsed < uci::output::HEARTBEAT_INTERVAL
+            || uci::PRETTY_PRINT.load(Ordering::Relaxed)
+            || !uci::output::heartbeat_due()
+        {
+            return;
+        }
+        let nodes = self.nodes.get_global();
+        let nps = (nodes as f64 / elapsed.as_secs_f64()) as u64;
+        uci::output::info(format!(
+            "info nodes {nodes} time {} nps {nps}",
+            elapsed.as_millis()
+        ));
     }
 
-    pub fn stopped(&self) -> bool {
-        self.stopped.load(Ordering::SeqCst)
+    /// Hold back our move until the pacing delay has passed, unless the GUI tells us to
+    /// stop. The search is already over, so this only affects when the move is sent.
+    pub fn wait_for_reply_delay(&self) {
+        let Some(reply_after) = self.time_manager.reply_after() else {
+            return;
+        };
+        while let Some(remaining) = reply_after.checked_sub(self.time_manager.elapsed()) {
+            let Some(stdin_rx) = self.stdin_rx else {
+                std::thread::sleep(remaining);
+                return;
+            };
+            let Ok(cmd) = stdin_rx.lock().unwrap().recv_timeout(remaining) else {
+                return;
+            };
+            match cmd.trim() {
+                "isready" => {
+                    uci::output::send("readyok".into());
+                }
+                "quit" => {
+                    uci::QUIT.store(true, Ordering::SeqCst);
+                    return;
+                }
+                // as in search, any other command means we should move now.
+                _ => return,
+            }
+        }
     }
 
-    #[cfg(feature = "stats")]
-    pub fn log_fail_high<const QSEARCH: bool>(&mut self, move_index: usize, ordering_score: i32) {
-        use crate::board::movegen::movepicker::{
-            COUNTER_MOVE_SCORE, FIRST_KILLER_SCORE, SECOND_KILLER_SCORE, TT_MOVE_SCORE,
-            WINNING_CAPTURE_SCORE,
+    /// In infinite mode and while pondering, only the GUI may end the search, so if it runs
+    /// out of depth to search first, hold back the move until we're told to stop.
+    pub fn wait_for_stop(&mut self) {
+        let pondering = matches!(self.time_manager.limit(), SearchLimit::Pondering { .. });
+        if !pondering && self.time_manager.limit() != &SearchLimit::Infinite {
+            return;
+        }
+        let Some(stdin_rx) = self.stdin_rx else {
+            return;
         };
-
-        if QSEARCH {
-            self.qfailhigh += 1;
-            self.qfailhigh_index[move_index] += 1;
-        } else {
-            self.failhigh += 1;
-            self.failhigh_index[move_index] += 1;
-            let fail_type = if ordering_score == TT_MOVE_SCORE {
-                FailHighType::TTMove
-            } else if ordering_score >= WINNING_CAPTURE_SCORE {
-                FailHighType::GoodTactical
-            } else if ordering_score == FIRST_KILLER_SCORE {
-                FailHighType::Killer1
-            } else if ordering_score == SECOND_KILLER_SCORE {
-                FailHighType::Killer2
-            } else if ordering_score == COUNTER_MOVE_SCORE {
-                FailHighType::CounterMove
-            } else if ordering_score > 0 {
-                FailHighType::GoodQuiet
-            } else {
-                FailHighType::BadQuiet
+        loop {
+            let received = stdin_rx.lock().unwrap().recv();
+            let Ok(cmd) = received else {
+                return;
             };
-            self.failhigh_types[fail_type as usize] += 1;
+            match cmd.trim() {
+                "isready" => {
+                    uci::output::send("readyok".into());
+                }
+                "ponderhit" if pondering => {
+                    self.report_ponder_result(true);
+                    return;
+                }
+                "quit" => {
+                    uci::QUIT.store(true, Ordering::SeqCst);
+                    return;
+                }
+                "stop" => {
+                    if pondering {
+                        self.report_ponder_result(false);
+                    }
+                    return;
+                }
+                // as in search, any other command means we should move now.
+                _ => return,
+            }
         }
     }
 
-    #[cfg(feature = "stats")]
-    pub fn print_stats(&self) {
-        #[allow(clippy::cast_precision_loss)]
-        let fail_high_percentages = self
-            .failhigh_index
-            .iter()
-            .map(|&x| (x as f64 * 100.0) / self.failhigh as f64)
-            .take(10)
-            .collect::<Vec<_>>();
-        #[allow(clippy::cast_precision_loss)]
-        let qs_fail_high_percentages = self
-            .qfailhigh_index
-            .iter()
-            .map(|&x| (x as f64 * 100.0) / self.qfailhigh as f64)
-            .take(10)
-            .collect::<Vec<_>>();
-        for ((i1, &x1), (i2, &x2)) in fail_high_percentages
-            .iter()
-            .enumerate()
-            .zip(qs_fail_high_percentages.iter().enumerate())
-        {
-            println!("failhigh {x1:5.2}% at move {i1}     qfailhigh {x2:5.2}% at move {i2}");
+    fn report_ponder_result(&mut self, hit: bool) {
+        self.time_manager.record_ponder_result(hit);
+        if let Some(rate) = self.time_manager.ponder_stats().hit_rate() {
+            if self.print_to_stdout {
+                uci::output::send(format!("info string ponder hitrate {:.0}%", rate * 100.0));
+            }
         }
-        let type_percentages = self
-            .failhigh_types
-            .iter()
-            .map(|&x| (x as f64 * 100.0) / self.failhigh as f64)
-            .collect::<Vec<_>>();
-        println!("failhigh ttmove        {:5.2}%", type_percentages[0]);
-        println!("failhigh good tactical {:5.2}%", type_percentages[1]);
-        println!("failhigh killer1       {:5.2}%", type_percentages[2]);
-        println!("failhigh killer2       {:5.2}%", type_percentages[3]);
-        println!("failhigh countermove   {:5.2}%", type_percentages[4]);
-        println!("failhigh good quiet    {:5.2}%", type_percentages[5]);
-        println!("failhigh bad quiet     {:5.2}%", type_percentages[6]);
     }
-}
 
-#[cfg(feature = "stats")]
-enum FailHighType {
-    TTMove,
-    GoodTactical,
-    Killer1,
-    Killer2,
-    CounterMove,
-    GoodQuiet,
-    BadQuiet,
+    pub fn skip_print(&self) -> bool {
+        self.time_manager.time_since_start().as_millis() < 50
+    }
+
+    /// Whether the search has been stopped. This is polled after every move, so it's
+    /// only a relaxed load: a stop is always noticed by the next `check_up`.
+    pub fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Sum the search statistics of every thread and print them, for the `SearchStats` option.
+    pub fn report_search_stats(&self, thread_data: &[ThreadData]) {
+        #![allow(clippy::cast_precision_loss)]
+        let mut stats = SearchStats::default();
+        for t in thread_data {
+            stats.merge(&t.stats);
+        }
+        let percent = |part: u64, whole: u64| {
+            if whole == 0 {
+                0.0
+            } else {
+                part as f64 * 100.0 / whole as f64
+            }
+        };
+
+        let cutoffs = stats.cutoffs.iter().sum::<u64>();
+        let by_index = stats
+            .cutoffs
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| {
+                let plus = if i == STATS_CUTOFF_SLOTS - 1 { "+" } else { "" };
+                format!("{}{plus}:{:.1}%", i + 1, percent(n, cutoffs))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let nodes = stats.nodes + stats.qsearch_nodes;
+        let depth = thread_data.first().map_or(0, |t| t.completed);
+        let branching_factor = if depth == 0 {
+            0.0
+        } else {
+            (self.nodes.get_global() as f64).powf(1.0 / depth as f64)
+        };
+
+        uci::output::send(format!(
+            "info string stats cutoffs {cutoffs} by move {by_index}"
+        ));
+        uci::output::send(format!(
+            "info string stats tt hits {:.1}% cutoffs {:.1}% fast paths {}",
+            percent(stats.tt_hits, stats.nodes),
+            percent(stats.tt_cutoffs, stats.nodes),
+            stats.tt_fast_paths
+        ));
+        uci::output::send(format!(
+            "info string stats nmp attempts {} successes {:.1}%",
+            stats.nmp_attempts,
+            percent(stats.nmp_cutoffs, stats.nmp_attempts)
+        ));
+        uci::output::send(format!(
+            "info string stats lmr searches {} re-searches {:.1}%",
+            stats.lmr_searches,
+            percent(stats.lmr_re_searches, stats.lmr_searches)
+        ));
+        uci::output::send(format!(
+            "info string stats qsearch nodes {:.1}% branching factor {branching_factor:.2}",
+            percent(stats.qsearch_nodes, nodes)
+        ));
+        uci::output::send(format!(
+            "info string stats extensions +2:{} +1:{} -1:{} -2:{}",
+            stats.extensions[4], stats.extensions[3], stats.extensions[1], stats.extensions[0]
+        ));
+        uci::output::send(format!(
+            "info string stats rejected tt moves {} killers {} counter moves {}",
+            stats.rejected_tt_moves, stats.rejected_killers, stats.rejected_counter_moves
+        ));
+    }
 }
 
 mod tests {
@@ -241,7 +349,7 @@ mod tests {
 
     use crate::{
         chess::board::Board,
-        evaluation::{mate_in, mated_in},
+        evaluation::Value,
         nnue::network::NNUEParams,
         searchinfo::SearchInfo,
         threadlocal::ThreadData,
@@ -276,7 +384,7 @@ mod tests {
             position.san(mov.unwrap()).as_deref(),
             Some("Bxd5+")
         ));
-        assert_eq!(value, mate_in(3)); // 3 ply because we're mating.
+        assert_eq!(value, Value::mate_in(3)); // 3 ply because we're mating.
 
         drop(guard);
     }
@@ -304,7 +412,7 @@ mod tests {
             position.san(mov.unwrap()).as_deref(),
             Some("Qxd5")
         ));
-        assert_eq!(value, mate_in(4)); // 4 ply (and positive) because white mates but it's black's turn.
+        assert_eq!(value, Value::mate_in(4)); // 4 ply (and positive) because white mates but it's black's turn.
 
         drop(guard);
     }
@@ -332,7 +440,7 @@ mod tests {
             position.san(mov.unwrap()).as_deref(),
             Some("Qxd4")
         ));
-        assert_eq!(value, -mate_in(4)); // 4 ply (and negative) because black mates but it's white's turn.
+        assert_eq!(value, -Value::mate_in(4)); // 4 ply (and negative) because black mates but it's white's turn.
 
         drop(guard);
     }
@@ -360,8 +468,82 @@ mod tests {
             position.san(mov.unwrap()).as_deref(),
             Some("Bxd4+")
         ));
-        assert_eq!(value, -mate_in(3)); // 3 ply because we're mating.
+        assert_eq!(value, -Value::mate_in(3)); // 3 ply because we're mating.
+
+        drop(guard);
+    }
+
+    #[test]
+    fn infinite_search_waits_for_stop() {
+        use std::{
+            sync::{atomic::Ordering, mpsc, Mutex},
+            thread,
+            time::Duration,
+        };
+
+        let guard = TEST_LOCK.lock().unwrap();
+
+        // the search runs out of depth almost at once, but must not reply until stopped.
+        let mut position = Board::from_fen("k7/8/1K6/8/8/8/8/1Q6 w - - 0 1").unwrap();
+        let stopped = AtomicBool::new(false);
+        let nodes = AtomicU64::new(0);
+        let (tx, rx) = mpsc::channel();
+        let rx = Mutex::new(rx);
+        let mut info = SearchInfo {
+            time_manager: TimeManager::default_with_limit(SearchLimit::Infinite),
+            ..SearchInfo::new(&stopped, &nodes)
+        };
+        info.set_stdin(&rx);
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let nnue_params = NNUEParams::decompress_and_alloc().unwrap();
+        let mut t = ThreadData::new(0, &position, tt.view(), nnue_params);
+        let finished = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let search = s.spawn(|| {
+                let result =
+                    position.search_position(&mut info, array::from_mut(&mut t), tt.view());
+                finished.store(true, Ordering::SeqCst);
+                result
+            });
+            while !stopped.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            thread::sleep(Duration::from_millis(100));
+            tx.send("isready".into()).unwrap();
+            thread::sleep(Duration::from_millis(100));
+            assert!(!finished.load(Ordering::SeqCst), "replied before stop");
+            tx.send("stop".into()).unwrap();
+            let (value, mov) = search.join().unwrap();
+            assert_eq!(value, Value::mate_in(3));
+            assert!(mov.is_some());
+        });
 
         drop(guard);
     }
+
+    #[test]
+    fn isready_while_pondering_is_not_a_miss() {
+        use std::sync::{mpsc, Mutex};
+
+        let stopped = AtomicBool::new(false);
+        let nodes = AtomicU64::new(0);
+        let (tx, rx) = mpsc::channel();
+        let rx = Mutex::new(rx);
+        let limit = SearchLimit::Infinite.to_pondering();
+        let mut info = SearchInfo {
+            time_manager: TimeManager::default_with_limit(limit),
+            ..SearchInfo::new(&stopped, &nodes)
+        };
+        info.set_stdin(&rx);
+
+        tx.send("isready".into()).unwrap();
+        assert!(!info.check_up(), "isready stopped the search");
+        assert_eq!(info.time_manager.ponder_stats().hit_rate(), None);
+
+        tx.send("stop".into()).unwrap();
+        assert!(info.check_up());
+        assert_eq!(info.time_manager.ponder_stats().hit_rate(), Some(0.0));
+    }
 }
@@ -1,14 +1,99 @@
-use crate::chess::{chessmove::Move, types::ContHistIndex};
+use std::{hint::black_box, time::Instant};
 
+use crate::{
+    chess::{
+        chessmove::Move,
+        types::{ContHistIndex, Square},
+    },
+    rng::XorShiftState,
+    util::MAX_PLY,
+};
+
+/// Everything the search keeps for a single ply. The fields that are touched at every
+/// node live here together, rather than in separate per-ply tables, so that a node's
+/// state and its parent's are found in at most two cache lines. Entries are aligned so
+/// that two fit in a cache line and none straddles a boundary.
 #[derive(Default, Clone)]
+#[repr(C, align(32))]
 #[allow(clippy::module_name_repetitions)]
 pub struct StackEntry {
     pub eval: i32,
+    pub dextensions: i32,
+    pub killers: [Option<Move>; 2],
     pub excluded: Option<Move>,
     pub best_move: Option<Move>,
     pub searching: Option<Move>,
+    pub conthist_index: ContHistIndex,
     pub searching_tactical: bool,
-    pub dextensions: i32,
     pub ttpv: bool,
-    pub conthist_index: ContHistIndex,
+}
+
+const _: () = assert!(std::mem::size_of::<StackEntry>() == 32);
+
+/// The stack as it was laid out before killers moved into the entries, kept so that
+/// `stackbench` can compare the two. Only its layout matters, so most fields go unread.
+#[derive(Default, Clone)]
+#[allow(dead_code)]
+struct SplitEntry {
+    eval: i32,
+    excluded: Option<Move>,
+    best_move: Option<Move>,
+    searching: Option<Move>,
+    searching_tactical: bool,
+    dextensions: i32,
+    ttpv: bool,
+    conthist_index: ContHistIndex,
+}
+
+/// Benchmark the stack traffic of a search, walking up and down the plies and touching
+/// at each node what the search touches: the parent's evaluation and extension count,
+/// this ply's killers, and the next ply's killers, which are cleared.
+pub fn benchmark() {
+    #![allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    const NODES: usize = 50_000_000;
+    let mut rng = XorShiftState::new();
+    let mut height = 1;
+    let heights = (0..NODES)
+        .map(|_| {
+            let r = rng.next();
+            height = if r & 1 == 0 { height + 1 } else { height - 1 }.clamp(1, MAX_PLY - 1);
+            (height, r & 6 == 0)
+        })
+        .collect::<Vec<_>>();
+    let killer = Move::new(Square::E2, Square::E4);
+
+    let mut ss = vec![StackEntry::default(); MAX_PLY + 1];
+    let start = Instant::now();
+    for &(h, cutoff) in &heights {
+        let ss = black_box(&mut ss[..]);
+        ss[h].eval = ss[h - 1].eval ^ 1;
+        ss[h].dextensions = ss[h - 1].dextensions;
+        black_box(ss[h].killers);
+        ss[h + 1].killers = [None; 2];
+        if cutoff {
+            ss[h].killers[1] = ss[h].killers[0];
+            ss[h].killers[0] = Some(killer);
+        }
+    }
+    let together = start.elapsed().as_nanos() as f64 / NODES as f64;
+
+    let mut ss = vec![SplitEntry::default(); MAX_PLY + 1];
+    let mut killers = vec![[None; 2]; MAX_PLY + 1];
+    let start = Instant::now();
+    for &(h, cutoff) in &heights {
+        let ss = black_box(&mut ss[..]);
+        let killers = black_box(&mut killers[..]);
+        ss[h].eval = ss[h - 1].eval ^ 1;
+        ss[h].dextensions = ss[h - 1].dextensions;
+        black_box(killers[h]);
+        killers[h + 1] = [None; 2];
+        if cutoff {
+            killers[h][1] = killers[h][0];
+            killers[h][0] = Some(killer);
+        }
+    }
+    let split = start.elapsed().as_nanos() as f64 / NODES as f64;
+
+    println!("killers in stack entries: {together:.2} ns per node");
+    println!("killers in a separate table: {split:.2} ns per node");
 }
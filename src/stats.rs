@@ -0,0 +1,260 @@
+//! Match statistics: Elo estimates with confidence intervals, and the sequential
+//! probability ratio test that decides whether a change gains Elo.
+
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::bail;
+
+/// The two-sided 95% quantile of the normal distribution.
+const Z_95: f64 = 1.96;
+
+/// The Elo difference implied by an expected score, infinite at either end of the range.
+fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score.clamp(0.0, 1.0) - 1.0).log10()
+}
+
+/// The expected score against an opponent `elo` weaker.
+fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// The Elo difference implied by a `mean` score with the given variance over `n` samples,
+/// and the half-width of its 95% confidence interval.
+fn elo_interval(mean: f64, variance: f64, n: f64) -> (f64, f64) {
+    let margin = Z_95 * (variance / n).sqrt();
+    (
+        elo_from_score(mean),
+        (elo_from_score(mean + margin) - elo_from_score(mean - margin)) / 2.0,
+    )
+}
+
+/// Wins, draws, and losses, from one player's point of view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+}
+
+impl Score {
+    pub const fn games(self) -> u64 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Record a game in which the player scored `half_points`.
+    pub const fn record(&mut self, half_points: usize) {
+        match half_points {
+            0 => self.losses += 1,
+            1 => self.draws += 1,
+            _ => self.wins += 1,
+        }
+    }
+
+    /// The same games, from the opponent's point of view.
+    pub const fn flipped(self) -> Self {
+        Self {
+            wins: self.losses,
+            draws: self.draws,
+            losses: self.wins,
+        }
+    }
+
+    /// The Elo difference that the score implies, and the half-width of its 95% confidence
+    /// interval. Either is infinite when there's a perfect score at an end of the interval.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn elo(self) -> (f64, f64) {
+        let games = self.games() as f64;
+        if games == 0.0 {
+            return (0.0, f64::INFINITY);
+        }
+        let (w, d, l) = (
+            self.wins as f64 / games,
+            self.draws as f64 / games,
+            self.losses as f64 / games,
+        );
+        let score = 0.5f64.mul_add(d, w);
+        let variance = l.mul_add(
+            score.powi(2),
+            w.mul_add((1.0 - score).powi(2), d * (0.5 - score).powi(2)),
+        );
+        elo_interval(score, variance, games)
+    }
+}
+
+/// The number of game pairs in which a player scored 0, 0.5, 1, 1.5, and 2 points.
+/// Playing each opening with both colours makes the two games of a pair correlated, so
+/// pairs, not games, are the independent samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pentanomial(pub [u64; 5]);
+
+impl Pentanomial {
+    /// Record a pair in which the player scored `half_points`, from 0 to 4.
+    pub const fn record(&mut self, half_points: usize) {
+        self.0[half_points] += 1;
+    }
+
+    pub fn pairs(&self) -> u64 {
+        self.0.iter().sum()
+    }
+
+    /// The mean score per game and its variance between pairs, or `None` with no pairs.
+    #[allow(clippy::cast_precision_loss)]
+    fn mean_and_variance(&self) -> Option<(f64, f64)> {
+        let pairs = self.pairs() as f64;
+        if pairs == 0.0 {
+            return None;
+        }
+        // the score per game in each pair, and how often each score happened.
+        let outcomes = || {
+            self.0
+                .iter()
+                .enumerate()
+                .map(|(i, &n)| (i as f64 / 4.0, n as f64 / pairs))
+        };
+        let mean = outcomes().map(|(x, p)| x * p).sum::<f64>();
+        let variance = outcomes().map(|(x, p)| (x - mean).powi(2) * p).sum::<f64>();
+        Some((mean, variance))
+    }
+
+    /// The Elo difference that the pairs imply, and the half-width of its 95% confidence
+    /// interval.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn elo(&self) -> (f64, f64) {
+        self.mean_and_variance()
+            .map_or((0.0, f64::INFINITY), |(mean, variance)| {
+                elo_interval(mean, variance, self.pairs() as f64)
+            })
+    }
+}
+
+impl Display for Pentanomial {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/// The hypothesis that an SPRT accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hypothesis {
+    /// The player is not `elo1` stronger.
+    H0,
+    /// The player is at least `elo0` stronger.
+    H1,
+}
+
+/// A sequential probability ratio test between two Elo hypotheses, run on game pairs
+/// as they finish.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    lower: f64,
+    upper: f64,
+}
+
+impl Sprt {
+    /// A test of `elo0` against `elo1`, with false positive rate `alpha` and false negative
+    /// rate `beta`.
+    pub fn new(elo0: f64, elo1: f64, alpha: f64, beta: f64) -> anyhow::Result<Self> {
+        if elo0 >= elo1 {
+            bail!("elo0 must be less than elo1");
+        }
+        let valid = |rate: f64| rate > 0.0 && rate < 1.0;
+        if !valid(alpha) || !valid(beta) {
+            bail!("alpha and beta must be between 0 and 1");
+        }
+        Ok(Self {
+            elo0,
+            elo1,
+            lower: (beta / (1.0 - alpha)).ln(),
+            upper: ((1.0 - beta) / alpha).ln(),
+        })
+    }
+
+    /// The LLRs below which H0 is accepted and above which H1 is accepted.
+    pub const fn bounds(&self) -> (f64, f64) {
+        (self.lower, self.upper)
+    }
+
+    /// The log-likelihood ratio of `elo1` over `elo0`, using the normal approximation to
+    /// the pentanomial model.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn llr(&self, penta: &Pentanomial) -> f64 {
+        let Some((mean, variance)) = penta.mean_and_variance() else {
+            return 0.0;
+        };
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (score_from_elo(self.elo0), score_from_elo(self.elo1));
+        penta.pairs() as f64 * (s1 - s0) * (2.0f64.mul_add(mean, -s0) - s1) / (2.0 * variance)
+    }
+
+    /// The hypothesis that the pairs so far accept, if the test is over.
+    pub fn verdict(&self, penta: &Pentanomial) -> Option<Hypothesis> {
+        let llr = self.llr(penta);
+        if llr >= self.upper {
+            Some(Hypothesis::H1)
+        } else if llr <= self.lower {
+            Some(Hypothesis::H0)
+        } else {
+            None
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn sprt_llr() {
+        use super::{Hypothesis, Pentanomial, Sprt};
+
+        let sprt = Sprt::new(0.0, 5.0, 0.05, 0.05).unwrap();
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 1e-3);
+        assert!((lower + 2.944).abs() < 1e-3);
+        // all draws carry no information either way.
+        assert!(sprt.llr(&Pentanomial([0, 0, 100, 0, 0])).abs() < f64::EPSILON);
+        let winning = Pentanomial([20, 80, 400, 180, 40]);
+        let losing = Pentanomial([40, 180, 400, 80, 20]);
+        assert_eq!(sprt.verdict(&winning), Some(Hypothesis::H1));
+        assert_eq!(sprt.verdict(&losing), Some(Hypothesis::H0));
+        assert_eq!(sprt.verdict(&Pentanomial([1, 2, 5, 2, 1])), None);
+        // a result halfway between the hypotheses favours neither.
+        let symmetric = Sprt::new(-5.0, 5.0, 0.05, 0.05).unwrap();
+        assert!(symmetric.llr(&Pentanomial([50, 100, 200, 100, 50])).abs() < 1e-9);
+
+        assert!(Sprt::new(5.0, 0.0, 0.05, 0.05).is_err());
+        assert!(Sprt::new(0.0, 5.0, 0.0, 0.05).is_err());
+    }
+
+    #[test]
+    fn elo_estimates() {
+        use super::{Pentanomial, Score};
+
+        let even = Score {
+            wins: 30,
+            draws: 40,
+            losses: 30,
+        };
+        let (elo, error) = even.elo();
+        assert!(elo.abs() < 1e-9);
+        assert!(error > 0.0 && error < 100.0);
+
+        // a 75% score is worth about 191 Elo.
+        let strong = Score {
+            wins: 60,
+            draws: 30,
+            losses: 10,
+        };
+        assert!((strong.elo().0 - 190.85).abs() < 0.1);
+        assert!((strong.flipped().elo().0 + 190.85).abs() < 0.1);
+        assert_eq!(strong.flipped().games(), 100);
+
+        // the same games scored as pairs: the estimate agrees, and correlated pairs
+        // narrow the interval.
+        let penta = Pentanomial([0, 0, 20, 10, 20]);
+        let (elo, error) = penta.elo();
+        assert!((elo - 190.85).abs() < 0.1);
+        assert!(error < strong.elo().1);
+    }
+}
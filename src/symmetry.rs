@@ -0,0 +1,129 @@
+//! A self-test that evaluates positions alongside their colour-flipped mirrors. The
+//! evaluation is from the side to move's point of view, so a position and its mirror
+//! should score exactly the same, and any difference points to a bug, such as a feature
+//! indexed from the wrong side in the network's feature transformer.
+
+use std::path::Path;
+
+use anyhow::{bail, Context};
+
+use crate::{
+    evalfile::{evaluate_fens, fen_from_epd},
+    nnue::network::NNUEParams,
+    search::parameters::Config,
+};
+
+fn swap_case(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_uppercase() {
+                c.to_ascii_lowercase()
+            } else {
+                c.to_ascii_uppercase()
+            }
+        })
+        .collect()
+}
+
+/// Mirror a FEN top to bottom and swap the colours, giving the same position with the
+/// other side to move.
+pub fn flip_fen(fen: &str) -> anyhow::Result<String> {
+    let mut fields = fen.split_whitespace();
+    let board = fields.next().with_context(|| "empty FEN")?;
+    let stm = match fields.next() {
+        Some("w") => "b",
+        Some("b") => "w",
+        other => bail!("bad side to move {other:?} in FEN {fen}"),
+    };
+    let castling = fields.next().unwrap_or("-");
+    let ep = fields.next().unwrap_or("-");
+
+    let board = board.split('/').rev().map(swap_case).collect::<Vec<_>>();
+    // white's rights come first, in both standard and Shredder notation.
+    let mut castling = swap_case(castling).chars().collect::<Vec<_>>();
+    castling.sort_by_key(char::is_ascii_lowercase);
+    let ep = match ep.as_bytes() {
+        [file, b'3'] => format!("{}6", char::from(*file)),
+        [file, b'6'] => format!("{}3", char::from(*file)),
+        _ => ep.to_string(),
+    };
+
+    let mut out = vec![
+        board.join("/"),
+        stm.to_string(),
+        castling.into_iter().collect(),
+        ep,
+    ];
+    out.extend(fields.map(str::to_string));
+    Ok(out.join(" "))
+}
+
+/// Evaluate every position in an EPD or FEN file alongside its mirror, and report any
+/// that differ by more than `tolerance`. Fails if any do, so that it can gate CI.
+pub fn run_symmetry(
+    positions: &Path,
+    threads: usize,
+    tolerance: i32,
+    conf: &Config,
+) -> anyhow::Result<()> {
+    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let text = std::fs::read_to_string(positions)
+        .with_context(|| format!("Failed to read {}", positions.display()))?;
+    let fens = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(fen_from_epd)
+        .collect::<Vec<_>>();
+    let flipped = fens
+        .iter()
+        .map(|fen| flip_fen(fen))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let evaluations = evaluate_fens(&fens, threads, conf, nnue_params)?;
+    let mirrored = evaluate_fens(&flipped, threads, conf, nnue_params)?;
+
+    let mut asymmetric = 0;
+    let (mut worst_raw, mut worst_eval) = (0, 0);
+    for ((fen, ours), theirs) in fens.iter().zip(&evaluations).zip(&mirrored) {
+        let raw_diff = (ours.raw - theirs.raw).abs();
+        let eval_diff = (ours.eval.get() - theirs.eval.get()).abs();
+        worst_raw = worst_raw.max(raw_diff);
+        worst_eval = worst_eval.max(eval_diff);
+        if raw_diff > tolerance || eval_diff > tolerance {
+            asymmetric += 1;
+            println!(
+                "{fen}: raw {} vs {} mirrored, eval {} vs {} mirrored",
+                ours.raw, theirs.raw, ours.eval, theirs.eval
+            );
+        }
+    }
+    println!(
+        "Checked {} positions: {asymmetric} asymmetric, largest difference {worst_raw} raw, {worst_eval} eval",
+        fens.len()
+    );
+
+    if asymmetric > 0 {
+        bail!("{asymmetric} positions evaluate differently when flipped");
+    }
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn fens_flip() {
+        use super::flip_fen;
+
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Kq - 0 1";
+        let flipped = flip_fen(fen).unwrap();
+        assert_eq!(
+            flipped,
+            "r3k2r/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b Qk - 0 1"
+        );
+        assert_eq!(flip_fen(&flipped).unwrap(), fen);
+        assert_eq!(
+            flip_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").unwrap(),
+            "4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 2"
+        );
+        assert!(flip_fen("8/8/8/8/8/8/8/8 x - - 0 1").is_err());
+    }
+}
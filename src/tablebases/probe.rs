@@ -12,7 +12,7 @@ use crate::{
         piece::{Colour, PieceType},
         types::{CastlingRights, Square},
     },
-    evaluation::TB_WIN_SCORE,
+    evaluation::Value,
     tablebases::bindings::{
         tb_init, tb_probe_root, tb_probe_wdl, TB_BLESSED_LOSS, TB_CURSED_WIN, TB_DRAW, TB_LARGEST,
         TB_LOSS, TB_PROMOTES_BISHOP, TB_PROMOTES_KNIGHT, TB_PROMOTES_QUEEN, TB_PROMOTES_ROOK,
@@ -175,7 +175,7 @@ pub fn get_root_wdl_dtz(board: &Board) -> Option<WdlDtzResult> {
 }
 
 /// Checks if there's a tablebase move and returns it as [Some], otherwise [None].
-pub fn get_tablebase_move(board: &Board) -> Option<(Move, i32)> {
+pub fn get_tablebase_move(board: &Board) -> Option<(Move, Value)> {
     if board.n_men() > get_max_pieces_count() {
         return None;
     }
@@ -183,9 +183,9 @@ pub fn get_tablebase_move(board: &Board) -> Option<(Move, i32)> {
     let result = get_root_wdl_dtz(board)?;
 
     let score = match result.wdl {
-        WDL::Win => TB_WIN_SCORE,
-        WDL::Draw => 0,
-        WDL::Loss => -TB_WIN_SCORE,
+        WDL::Win => Value::tb_win_in(0),
+        WDL::Draw => Value::DRAW,
+        WDL::Loss => Value::tb_loss_in(0),
     };
 
     Some((result.best_move, score))
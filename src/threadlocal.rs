@@ -8,13 +8,92 @@ use crate::{
         CaptureHistoryTable, CorrectionHistoryTable, DoubleHistoryTable, MoveTable,
         ThreatsHistoryTable,
     },
-    nnue::{self, network::NNUEParams},
-    search::pv::PVariation,
+    nnue::{
+        self,
+        network::{
+            small::{self, SmallAccumulator, SmallNetwork},
+            NNUEParams,
+        },
+    },
+    search::{pv::PVariation, rootmoves::RootMoves},
     stack::StackEntry,
     transpositiontable::TTView,
     util::MAX_PLY,
 };
 
+/// The number of move indices that beta cutoffs are tracked for. Cutoffs at later moves share the last slot.
+pub const STATS_CUTOFF_SLOTS: usize = 8;
+
+/// Counters describing the shape of a search, for the `SearchStats` option.
+#[derive(Clone, Debug, Default)]
+pub struct SearchStats {
+    /// Main search nodes.
+    pub nodes: u64,
+    /// Quiescence search nodes.
+    pub qsearch_nodes: u64,
+    /// Beta cutoffs in the main search, by the index of the move that caused them.
+    pub cutoffs: [u64; STATS_CUTOFF_SLOTS],
+    /// Main search nodes with a transposition table hit.
+    pub tt_hits: u64,
+    /// Main search nodes cut off by the transposition table.
+    pub tt_cutoffs: u64,
+    /// Non-PV nodes near the fifty-move rule that returned a deep exact score from the
+    /// transposition table.
+    pub tt_fast_paths: u64,
+    /// Null-move searches tried.
+    pub nmp_attempts: u64,
+    /// Null-move searches that failed high and cut the node off.
+    pub nmp_cutoffs: u64,
+    /// Moves searched with a late move reduction of more than one ply.
+    pub lmr_searches: u64,
+    /// Reduced searches that beat alpha and had to be searched again.
+    pub lmr_re_searches: u64,
+    /// Extensions applied to moves, indexed by the extension plus two.
+    pub extensions: [u64; 5],
+    /// Moves from the transposition table that weren't pseudo-legal in the position.
+    pub rejected_tt_moves: u64,
+    /// Killer moves that weren't pseudo-legal in the position.
+    pub rejected_killers: u64,
+    /// Counter-moves that weren't pseudo-legal in the position.
+    pub rejected_counter_moves: u64,
+}
+
+impl SearchStats {
+    pub fn merge(&mut self, other: &Self) {
+        self.nodes += other.nodes;
+        self.qsearch_nodes += other.qsearch_nodes;
+        for (a, b) in self.cutoffs.iter_mut().zip(&other.cutoffs) {
+            *a += b;
+        }
+        self.tt_hits += other.tt_hits;
+        self.tt_cutoffs += other.tt_cutoffs;
+        self.tt_fast_paths += other.tt_fast_paths;
+        self.nmp_attempts += other.nmp_attempts;
+        self.nmp_cutoffs += other.nmp_cutoffs;
+        self.lmr_searches += other.lmr_searches;
+        self.lmr_re_searches += other.lmr_re_searches;
+        for (a, b) in self.extensions.iter_mut().zip(&other.extensions) {
+            *a += b;
+        }
+        self.rejected_tt_moves += other.rejected_tt_moves;
+        self.rejected_killers += other.rejected_killers;
+        self.rejected_counter_moves += other.rejected_counter_moves;
+    }
+
+    pub fn record_cutoff(&mut self, move_index: usize) {
+        self.cutoffs[move_index.min(STATS_CUTOFF_SLOTS - 1)] += 1;
+    }
+
+    pub fn record_extension(&mut self, extension: i32) {
+        if let Some(count) = usize::try_from(extension + 2)
+            .ok()
+            .and_then(|i| self.extensions.get_mut(i))
+        {
+            *count += 1;
+        }
+    }
+}
+
 #[repr(align(64))] // these get stuck in a vec and each thread accesses its own index
 pub struct ThreadData<'a> {
     // stack array is right-padded by one because singular verification
@@ -23,11 +102,13 @@ pub struct ThreadData<'a> {
     pub banned_nmp: u8,
     pub nnue: Box<nnue::network::NNUEState>,
     pub nnue_params: &'a NNUEParams,
+    /// The small network for clearly decided positions, if one is loaded, and its accumulator.
+    pub small_net: Option<&'static SmallNetwork>,
+    pub small_nnue: Box<SmallAccumulator>,
 
     pub main_history: ThreatsHistoryTable,
     pub tactical_history: Box<CaptureHistoryTable>,
     pub continuation_history: Box<DoubleHistoryTable>,
-    pub killer_move_table: [[Option<Move>; 2]; MAX_PLY + 1],
     pub counter_move_table: MoveTable,
     pub pawn_corrhist: Box<CorrectionHistoryTable>,
     pub nonpawn_corrhist: [Box<CorrectionHistoryTable>; 2],
@@ -37,10 +118,16 @@ pub struct ThreadData<'a> {
     pub thread_id: usize,
 
     pub pvs: [PVariation; MAX_PLY],
+    pub root_moves: RootMoves,
     pub completed: usize,
     pub depth: usize,
 
     pub stm_at_root: Colour,
+    /// Whether the search is for analysis, where the engine's playing style and
+    /// contempt are left out of its scores.
+    pub analyse_mode: bool,
+
+    pub stats: SearchStats,
 
     pub tt: TTView<'a>,
 }
@@ -61,10 +148,11 @@ impl<'a> ThreadData<'a> {
             banned_nmp: 0,
             nnue: nnue::network::NNUEState::new(board, nnue_params),
             nnue_params,
+            small_net: small::loaded(),
+            small_nnue: Box::new(SmallAccumulator::new()),
             main_history: ThreatsHistoryTable::new(),
             tactical_history: CaptureHistoryTable::boxed(),
             continuation_history: DoubleHistoryTable::boxed(),
-            killer_move_table: [[None; 2]; MAX_PLY + 1],
             counter_move_table: MoveTable::new(),
             pawn_corrhist: CorrectionHistoryTable::boxed(),
             nonpawn_corrhist: [
@@ -76,9 +164,12 @@ impl<'a> ThreadData<'a> {
             thread_id,
             #[allow(clippy::large_stack_arrays)]
             pvs: [Self::ARRAY_REPEAT_VALUE; MAX_PLY],
+            root_moves: RootMoves::new(),
             completed: 0,
             depth: 0,
             stm_at_root: board.turn(),
+            analyse_mode: false,
+            stats: SearchStats::default(),
             tt,
         };
 
@@ -113,6 +204,12 @@ impl<'a> ThreadData<'a> {
             != 0
     }
 
+    fn clear_killers(&mut self) {
+        for entry in &mut self.ss {
+            entry.killers = [None; 2];
+        }
+    }
+
     pub fn clear_tables(&mut self) {
         self.main_history.clear();
         self.tactical_history.clear();
@@ -122,31 +219,47 @@ impl<'a> ThreadData<'a> {
         self.nonpawn_corrhist[Colour::Black].clear();
         self.major_corrhist.clear();
         self.minor_corrhist.clear();
-        self.killer_move_table.fill([None; 2]);
+        self.clear_killers();
         self.counter_move_table.clear();
         self.depth = 0;
         self.completed = 0;
         self.pvs.fill(Self::ARRAY_REPEAT_VALUE);
     }
 
-    pub fn set_up_for_search(&mut self, board: &Board) {
+    /// Get ready to search `board`, considering only `searchmoves` at the root if any are given.
+    pub fn set_up_for_search(&mut self, board: &Board, searchmoves: &[Move], analyse_mode: bool) {
         self.main_history.age_entries();
         self.tactical_history.age_entries();
         self.continuation_history.age_entries();
-        self.killer_move_table.fill([None; 2]);
+        self.clear_killers();
         self.counter_move_table.clear();
+        self.forget_best_line();
+        self.root_moves.set_up(board);
+        self.root_moves.restrict(searchmoves);
+        self.nnue.refresh_from(board, self.nnue_params);
+        self.small_net = small::loaded();
+        self.stm_at_root = board.turn();
+        self.analyse_mode = analyse_mode;
+        self.stats = SearchStats::default();
+    }
+
+    /// Throw away every line that the search has found, leaving nothing to play.
+    pub fn forget_best_line(&mut self) {
         self.depth = 0;
         self.completed = 0;
         self.pvs.fill(Self::ARRAY_REPEAT_VALUE);
-        self.nnue.reinit_from(board, self.nnue_params);
-        self.stm_at_root = board.turn();
     }
 
+    /// Record `pv` as the best line, after an iteration at the current depth that came back
+    /// exact or failed high. A fail-high line has proved its move better than the last
+    /// completed iteration's, so it's kept even if the iteration never completes.
     pub fn update_best_line(&mut self, pv: &PVariation) {
         self.completed = self.depth;
         self.pvs[self.depth] = pv.clone();
     }
 
+    /// Go back to the line of the last completed iteration, after a fail-low, so that the
+    /// best line is never one that the current iteration has only partly refuted.
     pub fn revert_best_line(&mut self) {
         self.completed = self.depth - 1;
     }
@@ -0,0 +1,292 @@
+//! A pool of search threads that live for the whole UCI session, so that a `go` only has
+//! to wake them rather than spawn them. Each worker sleeps on a condition variable until
+//! it is handed a job, and the pool waits for every job it hands out before returning,
+//! which lets jobs borrow from the caller just as with [`std::thread::scope`].
+
+use std::{
+    any::Any,
+    fmt::{self, Debug},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, PoisonError,
+    },
+    thread::JoinHandle,
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A job for the pool, which may borrow from the scope that runs it.
+pub type ScopedJob<'s> = Box<dyn FnOnce() + Send + 's>;
+
+#[derive(Default)]
+struct Slot {
+    job: Option<Job>,
+    /// Whether the worker should pin itself to a CPU before running the job.
+    pin: bool,
+    quit: bool,
+}
+
+#[derive(Default)]
+struct Mailbox {
+    slot: Mutex<Slot>,
+    wake: Condvar,
+}
+
+struct Worker {
+    mailbox: Arc<Mailbox>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Counts the jobs that are yet to finish, and holds the first panic among them.
+#[derive(Default)]
+struct Latch {
+    state: Mutex<(usize, Option<Box<dyn Any + Send>>)>,
+    done: Condvar,
+}
+
+impl Latch {
+    fn finish(&self, panic: Option<Box<dyn Any + Send>>) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.0 -= 1;
+        if state.1.is_none() {
+            state.1 = panic;
+        }
+        if state.0 == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    fn wait(&self) -> Option<Box<dyn Any + Send>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while state.0 > 0 {
+            state = self
+                .done
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        state.1.take()
+    }
+}
+
+/// Waits for the outstanding jobs when dropped, so that no job can outlive the borrows
+/// it was given, even if handing out the jobs is cut short.
+struct WaitOnDrop<'l>(&'l Latch);
+
+impl Drop for WaitOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.wait();
+    }
+}
+
+/// Pin the calling thread to one CPU, or let it run on any if `cpu` is `None`.
+#[cfg(target_os = "linux")]
+fn set_affinity(cpu: Option<usize>) {
+    let cpus = num_cpus::get();
+    // SAFETY: `cpu_set_t` is a plain bitmask, for which all zeroes is the empty set, and
+    // the CPU_* helpers only touch the set we pass them. The call is checked, and a
+    // failure just leaves the thread wherever the scheduler puts it.
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+        match cpu {
+            Some(cpu) => libc::CPU_SET(cpu % cpus, &mut set),
+            None => (0..cpus).for_each(|cpu| libc::CPU_SET(cpu, &mut set)),
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &raw const set) != 0 {
+            eprintln!("info string failed to set thread affinity");
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+const fn set_affinity(_cpu: Option<usize>) {}
+
+fn worker_loop(id: usize, mailbox: &Mailbox) {
+    let mut pinned = false;
+    loop {
+        let (job, pin) = {
+            let mut slot = mailbox.slot.lock().unwrap_or_else(PoisonError::into_inner);
+            loop {
+                if slot.quit {
+                    return;
+                }
+                if let Some(job) = slot.job.take() {
+                    break (job, slot.pin);
+                }
+                slot = mailbox
+                    .wake
+                    .wait(slot)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        };
+        if pin != pinned {
+            set_affinity(pin.then_some(id));
+            pinned = pin;
+        }
+        job();
+    }
+}
+
+impl Worker {
+    fn new(id: usize) -> Self {
+        let mailbox = Arc::new(Mailbox::default());
+        let theirs = Arc::clone(&mailbox);
+        let handle = std::thread::Builder::new()
+            .name(format!("search-worker-{id}"))
+            .spawn(move || worker_loop(id, &theirs))
+            .expect("failed to spawn search worker");
+        Self {
+            mailbox,
+            handle: Some(handle),
+        }
+    }
+
+    fn give(&self, job: Job, pin: bool) {
+        {
+            let mut slot = self
+                .mailbox
+                .slot
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            slot.job = Some(job);
+            slot.pin = pin;
+        }
+        self.mailbox.wake.notify_one();
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.mailbox
+            .slot
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .quit = true;
+        self.mailbox.wake.notify_one();
+        if let Some(handle) = self.handle.take() {
+            // a worker only panics if a job did, and that has already been reported.
+            let _ = handle.join();
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ThreadPool {
+    workers: Mutex<Vec<Worker>>,
+    /// Whether each worker is pinned to its own CPU.
+    pin: AtomicBool,
+}
+
+impl ThreadPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pin each worker to its own CPU from the next run, or unpin them.
+    pub fn set_pinned(&self, pin: bool) {
+        self.pin.store(pin, Ordering::Relaxed);
+    }
+
+    pub fn pinned(&self) -> bool {
+        self.pin.load(Ordering::Relaxed)
+    }
+
+    /// Run each job on its own worker, starting more workers if there are too few,
+    /// and return once they have all finished. A panic in any job is resumed here.
+    pub fn run(&self, jobs: Vec<ScopedJob<'_>>) {
+        // the workers stay locked until the jobs finish, as each can only take one job.
+        #![allow(clippy::significant_drop_tightening)]
+        let mut workers = self.workers.lock().unwrap_or_else(PoisonError::into_inner);
+        while workers.len() < jobs.len() {
+            let worker = Worker::new(workers.len());
+            workers.push(worker);
+        }
+        let pin = self.pinned();
+
+        let latch = Arc::new(Latch::default());
+        let panic = {
+            let guard = WaitOnDrop(&latch);
+            for (job, worker) in jobs.into_iter().zip(workers.iter()) {
+                // SAFETY: the job only lives as long as the borrows it captured, but it is
+                // guaranteed to have finished before they end: `guard` waits for every job
+                // handed out here before this function returns or unwinds.
+                let job = unsafe { std::mem::transmute::<ScopedJob<'_>, Job>(job) };
+                let done = Arc::clone(&latch);
+                latch.state.lock().unwrap_or_else(PoisonError::into_inner).0 += 1;
+                worker.give(
+                    Box::new(move || {
+                        let result = panic::catch_unwind(AssertUnwindSafe(job));
+                        done.finish(result.err());
+                    }),
+                    pin,
+                );
+            }
+            std::mem::forget(guard);
+            latch.wait()
+        };
+        if let Some(panic) = panic {
+            panic::resume_unwind(panic);
+        }
+    }
+}
+
+impl Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadPool")
+            .field(
+                "workers",
+                &self
+                    .workers
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .len(),
+            )
+            .field("pin", &self.pinned())
+            .finish()
+    }
+}
+
+/// Run the jobs on the pool if there is one, or on freshly spawned threads otherwise.
+pub fn run(pool: Option<&ThreadPool>, jobs: Vec<ScopedJob<'_>>) {
+    if let Some(pool) = pool {
+        pool.run(jobs);
+    } else {
+        std::thread::scope(|s| {
+            for job in jobs {
+                s.spawn(job);
+            }
+        });
+    }
+}
+
+mod tests {
+    #[test]
+    fn workers_persist_between_runs() {
+        use super::{ScopedJob, ThreadPool};
+        use std::{sync::Mutex, thread};
+
+        let pool = ThreadPool::new();
+        let names = Mutex::new(Vec::new());
+        for _ in 0..3 {
+            let mut counts = [0; 4];
+            let jobs = counts
+                .iter_mut()
+                .map(|count| -> ScopedJob<'_> {
+                    let names = &names;
+                    Box::new(move || {
+                        *count += 1;
+                        let name = thread::current().name().unwrap().to_string();
+                        names.lock().unwrap().push(name);
+                    })
+                })
+                .collect();
+            pool.run(jobs);
+            assert_eq!(counts, [1; 4]);
+        }
+        let mut names = names.into_inner().unwrap();
+        assert_eq!(names.len(), 12);
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 4, "each run reuses the same four workers");
+    }
+}
@@ -5,13 +5,16 @@ use std::{
 };
 
 use crate::{
-    chess::chessmove::Move,
-    evaluation::{is_mate_score, mate_in},
+    chess::{chessmove::Move, piece::Colour},
+    evaluation::{is_mate_score, mate_in, MATE_SCORE},
+    rng::XorShiftState,
     search::{parameters::Config, pv::PVariation, SmpThreadType},
     transpositiontable::Bound,
 };
 
 const MOVE_OVERHEAD: u64 = 30;
+/// The largest amount of extra overhead that calibration is allowed to add.
+const MAX_CALIBRATED_OVERHEAD: u64 = 1000;
 
 pub const STRONG_FORCED_TM_FRAC: u32 = 188;
 pub const WEAK_FORCED_TM_FRAC: u32 = 524;
@@ -21,6 +24,17 @@ pub const OPTIMAL_WINDOW_FRAC: u32 = 62;
 pub const INCREMENT_FRAC: u32 = 98;
 pub const NODE_TM_SUBTREE_MULTIPLIER: u32 = 164;
 pub const FAIL_LOW_TM_BONUS: u32 = 245;
+pub const STABILITY_TM_MUL_0: u32 = 250;
+pub const STABILITY_TM_MUL_1: u32 = 120;
+pub const STABILITY_TM_MUL_2: u32 = 90;
+pub const STABILITY_TM_MUL_3: u32 = 80;
+pub const STABILITY_TM_MUL_4: u32 = 75;
+pub const SCORE_TREND_TM_MUL: u32 = 80;
+pub const SCORE_TREND_TM_MIN: u32 = 85;
+pub const SCORE_TREND_TM_MAX: u32 = 160;
+pub const PONDER_HIT_TM_MUL: u32 = 40;
+/// How many iterations a proven mate must hold for before we stop early.
+pub const DEFAULT_MATE_STOP_ITERATIONS: usize = 3;
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum ForcedMoveType {
@@ -73,6 +87,61 @@ impl Default for SearchLimit {
     }
 }
 
+/// Everything a `go` command can say about a search, as the GUI gave it. This is turned
+/// into a [`SearchLimit`] for the side to move once the command has been read in full,
+/// so the order of its parts doesn't matter.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// White's clock, in milliseconds. This may be negative if the GUI is lagging.
+    pub wtime: Option<i64>,
+    pub btime: Option<i64>,
+    pub winc: Option<i64>,
+    pub binc: Option<i64>,
+    pub movestogo: Option<u64>,
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    /// Search for a mate in this many moves.
+    pub mate: Option<usize>,
+    pub movetime: Option<u64>,
+    pub infinite: bool,
+    pub ponder: bool,
+    /// Only these root moves are searched, or all of them if this is empty.
+    pub searchmoves: Vec<Move>,
+}
+
+impl SearchLimits {
+    /// The limit on a search with `turn` to move. Where several limits are given, nodes
+    /// take precedence over clocks, which take precedence over depth, then movetime, then
+    /// mate. A missing opponent's clock is taken to be the same as ours.
+    pub fn limit(&self, turn: Colour) -> SearchLimit {
+        let (our_clock, our_inc, their_clock, their_inc) = match turn {
+            Colour::White => (self.wtime, self.winc, self.btime, self.binc),
+            Colour::Black => (self.btime, self.binc, self.wtime, self.winc),
+        };
+        let millis = |t: Option<i64>| t.map_or(0, |t| u64::try_from(t).unwrap_or(0));
+        let limit = match (self.nodes, our_clock, self.depth, self.movetime, self.mate) {
+            (Some(nodes), ..) => SearchLimit::Nodes(nodes),
+            (None, Some(our_clock), ..) => SearchLimit::Dynamic {
+                our_clock: millis(Some(our_clock)),
+                their_clock: millis(their_clock.or(Some(our_clock))),
+                our_inc: millis(our_inc),
+                their_inc: millis(their_inc),
+                moves_to_go: self.movestogo,
+            },
+            (None, None, Some(depth), ..) => SearchLimit::Depth(depth),
+            (None, None, None, Some(movetime), _) => SearchLimit::Time(movetime),
+            // gives padding when we're giving mate, but whatever
+            (None, None, None, None, Some(mate)) => SearchLimit::Mate { ply: mate * 2 },
+            (None, None, None, None, None) => SearchLimit::Infinite,
+        };
+        if self.ponder {
+            limit.to_pondering()
+        } else {
+            limit
+        }
+    }
+}
+
 impl SearchLimit {
     #[allow(clippy::wrong_self_convention)]
     pub fn to_pondering(self) -> Self {
@@ -100,10 +169,11 @@ impl SearchLimit {
         our_clock: u64,
         moves_to_go: Option<u64>,
         our_inc: u64,
+        move_overhead: u64,
         conf: &Config,
     ) -> (u64, u64, u64) {
         // The absolute maximum time we could spend without losing on the clock:
-        let absolute_maximum = our_clock.saturating_sub(MOVE_OVERHEAD);
+        let absolute_maximum = our_clock.saturating_sub(move_overhead);
 
         // The maximum time we can spend searching before forcibly stopping:
         let hard_time_window =
@@ -121,9 +191,9 @@ impl SearchLimit {
         }
 
         // Otherwise, we use default_moves_to_go.
-        let computed_time_window = our_clock / u64::from(conf.default_moves_to_go)
-            + our_inc * u64::from(conf.increment_frac) / 100
-            - MOVE_OVERHEAD;
+        let computed_time_window = (our_clock / u64::from(conf.default_moves_to_go)
+            + our_inc * u64::from(conf.increment_frac) / 100)
+            .saturating_sub(move_overhead);
         let optimal_time_window = (computed_time_window.min(absolute_maximum)
             * u64::from(conf.optimal_window_frac)
             / 100)
@@ -137,6 +207,119 @@ impl SearchLimit {
     }
 }
 
+/// Tracks the latency that the engine observes between a command arriving on stdin
+/// and being acted upon, and between deciding on a move and the `bestmove` reaching
+/// the GUI. Slow GUIs and overloaded VMs can make these delays large enough that the
+/// fixed move overhead is insufficient, so the observed latency is used to inflate it.
+#[derive(Clone, Debug, Default)]
+pub struct OverheadCalibration {
+    /// A slowly-decaying estimate of the worst latency seen this session, in microseconds.
+    peak_latency_us: u64,
+    /// The overhead value that was last reported to the GUI, in milliseconds.
+    last_reported: u64,
+}
+
+impl OverheadCalibration {
+    /// Incorporate a new latency sample into the estimate.
+    pub fn record(&mut self, latency: Duration) {
+        // this cast is safe to do, because u64::MAX microseconds is 584K years.
+        #[allow(clippy::cast_possible_truncation)]
+        let sample = latency.as_micros() as u64;
+        // older spikes decay away, so a one-off stall doesn't penalise us forever.
+        let decayed = self.peak_latency_us * 15 / 16;
+        self.peak_latency_us = decayed.max(sample);
+    }
+
+    /// The total move overhead to use, in milliseconds.
+    pub fn move_overhead(&self) -> u64 {
+        // double the observed latency as a safety margin, rounding up.
+        let extra = (self.peak_latency_us * 2).div_ceil(1000);
+        MOVE_OVERHEAD + extra.min(MAX_CALIBRATED_OVERHEAD)
+    }
+
+    /// Returns the calibrated overhead if it has changed since it was last reported.
+    pub fn take_report(&mut self) -> Option<u64> {
+        let overhead = self.move_overhead();
+        // nothing is reported until calibration actually moves the overhead off its default.
+        if overhead == self.last_reported.max(MOVE_OVERHEAD) {
+            return None;
+        }
+        self.last_reported = overhead;
+        Some(overhead)
+    }
+}
+
+/// Counts the outcomes of pondering searches over the session.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PonderStats {
+    /// The number of times the opponent played the move we pondered on.
+    hits: u32,
+    /// The number of times the opponent played something else.
+    misses: u32,
+}
+
+impl PonderStats {
+    /// The minimum number of ponder outcomes needed before the hit rate affects time management.
+    const MINIMUM_SAMPLES: u32 = 4;
+
+    pub const fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    /// The fraction of ponder searches that were hits, if any have completed.
+    pub fn hit_rate(self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| f64::from(self.hits) / f64::from(total))
+    }
+
+    /// Scale thinking time up when ponder hits are common, as time spent
+    /// on our move is then likely to be partially repaid on the next one.
+    fn tm_multiplier(self, conf: &Config) -> f64 {
+        if self.hits + self.misses < Self::MINIMUM_SAMPLES {
+            return 1.0;
+        }
+        self.hit_rate().map_or(1.0, |rate| {
+            (rate - 0.5).mul_add(f64::from(conf.ponder_hit_tm_mul) / 100.0, 1.0)
+        })
+    }
+}
+
+/// Human-like pacing for bots on online servers, which should neither play at a
+/// perfectly regular speed nor reply instantly. This never changes how we search,
+/// only how long we aim to search for and when the move is sent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pacing {
+    /// The percentage by which the time we aim to spend on a move is randomly varied.
+    pub jitter: u64,
+    /// The least time, in milliseconds, to wait before replying with a move.
+    pub min_delay: u64,
+    /// The most time, in milliseconds, to wait before replying with a move.
+    pub max_delay: u64,
+}
+
+impl Pacing {
+    /// Pick a random factor by which to scale the soft time limit.
+    fn jitter_factor(self, rng: &mut XorShiftState) -> f64 {
+        #![allow(clippy::cast_precision_loss)]
+        if self.jitter == 0 {
+            return 1.0;
+        }
+        // uniform in [-jitter, +jitter] percent.
+        let offset = (rng.next() % (2 * self.jitter + 1)) as f64 - self.jitter as f64;
+        1.0 + offset / 100.0
+    }
+
+    /// Pick a random delay from the configured band.
+    fn delay(self, rng: &mut XorShiftState) -> Duration {
+        let max_delay = self.max_delay.max(self.min_delay);
+        Duration::from_millis(self.min_delay + rng.next() % (max_delay - self.min_delay + 1))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TimeManager {
     /// The starting time of the search.
@@ -145,10 +328,10 @@ pub struct TimeManager {
     limit: SearchLimit,
     /// The maximum time that the search may last for without losing on the clock.
     max_time: Duration,
-    /// The time after which search will be halted even mid-search.
+    /// The hard limit: the time after which search will be halted even mid-iteration.
     hard_time: Duration,
-    /// The time after which we will stop upon completing a depth.
-    opt_time: Duration,
+    /// The soft limit: the time after which we will stop upon completing an iteration.
+    soft_time: Duration,
     /// The value from the last iteration of search.
     prev_score: i32,
     /// The best move from the last iteration of search.
@@ -157,14 +340,35 @@ pub struct TimeManager {
     stability: usize,
     /// Number of times that we have failed low.
     failed_low: i32,
-    /// Number of ID iterations that a mate score has remained.
+    /// Number of ID iterations that the same mate score has remained.
     mate_counter: usize,
+    /// The mate score that has remained for `mate_counter` iterations.
+    mate_score: i32,
     /// The nature of the forced move (if any)
     found_forced_move: ForcedMoveType,
-    /// The last set of multiplicative factors.
-    last_factors: [f64; 2],
+    /// The multiplier derived from the trend of the score across iterations.
+    score_trend_multiplier: f64,
     /// Fraction of nodes that were underneath the best move.
     best_move_nodes_fraction: Option<f64>,
+    /// Session-long estimate of the I/O latency, used to inflate the move overhead.
+    overhead: OverheadCalibration,
+    /// Nodes per millisecond to count in place of wall-clock time, or zero if disabled.
+    nodestime: u64,
+    /// Our virtual clock, in nodes, when playing in nodestime mode.
+    node_clock: Option<u64>,
+    /// Session-long record of how often the GUI confirmed our predicted move.
+    ponder_stats: PonderStats,
+    /// Settings for varying our thinking time and delaying our replies.
+    pacing: Pacing,
+    /// Source of randomness for pacing.
+    rng: XorShiftState,
+    /// The time since the start of search before which we should not reply, if any.
+    reply_after: Option<Duration>,
+    /// The time, in milliseconds, to spend on a move that needs no thought.
+    instant_move_budget: u64,
+    /// The number of iterations that a proven mate must hold for before we stop, or zero
+    /// to always use the time we planned to.
+    mate_stop_iterations: usize,
 }
 
 impl Default for TimeManager {
@@ -174,28 +378,159 @@ impl Default for TimeManager {
             limit: SearchLimit::Infinite,
             max_time: Duration::from_secs(0),
             hard_time: Duration::from_secs(0),
-            opt_time: Duration::from_secs(0),
+            soft_time: Duration::from_secs(0),
             prev_score: 0,
             prev_move: None,
             stability: 0,
             failed_low: 0,
             mate_counter: 0,
+            mate_score: 0,
             found_forced_move: ForcedMoveType::None,
-            last_factors: [1.0, 1.0],
+            score_trend_multiplier: 1.0,
             best_move_nodes_fraction: None,
+            overhead: OverheadCalibration::default(),
+            nodestime: 0,
+            node_clock: None,
+            ponder_stats: PonderStats::default(),
+            pacing: Pacing::default(),
+            rng: XorShiftState {
+                state: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(1, |t| t.as_nanos() | 1),
+            },
+            reply_after: None,
+            instant_move_budget: 0,
+            mate_stop_iterations: DEFAULT_MATE_STOP_ITERATIONS,
         }
     }
 }
 
 impl TimeManager {
     pub fn set_limit(&mut self, limit: SearchLimit) {
-        self.limit = limit;
+        self.limit = self.convert_for_nodestime(limit);
+    }
+
+    /// Set the number of nodes per millisecond to use in place of wall-clock time.
+    /// Zero disables nodestime mode.
+    pub const fn set_nodestime(&mut self, nodes_per_ms: u64) {
+        self.nodestime = nodes_per_ms;
+        self.node_clock = None;
+    }
+
+    pub const fn nodestime(&self) -> u64 {
+        self.nodestime
+    }
+
+    /// Set the time, in milliseconds, to spend on a move that needs no thought: the only
+    /// legal move, or the best move in a tablebase position. Zero replies at once.
+    pub const fn set_instant_move_budget(&mut self, millis: u64) {
+        self.instant_move_budget = millis;
+    }
+
+    pub const fn instant_move_budget(&self) -> u64 {
+        self.instant_move_budget
+    }
+
+    /// Set the number of iterations that a proven mate must hold for before we stop
+    /// searching and bank the rest of our time. Zero never stops early.
+    pub const fn set_mate_stop_iterations(&mut self, iterations: usize) {
+        self.mate_stop_iterations = iterations;
+    }
+
+    pub const fn mate_stop_iterations(&self) -> usize {
+        self.mate_stop_iterations
+    }
+
+    pub const fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacing = pacing;
+    }
+
+    pub const fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    /// The time since the start of search before which we should hold back our move, if any.
+    pub const fn reply_after(&self) -> Option<Duration> {
+        self.reply_after
+    }
+
+    /// Send our move as soon as the search finishes, as the GUI has asked us to stop.
+    pub const fn cancel_reply_delay(&mut self) {
+        self.reply_after = None;
+    }
+
+    /// Forget the virtual node clock, so that it's re-initialised from the GUI's clock.
+    pub const fn reset_node_clock(&mut self) {
+        self.node_clock = None;
+    }
+
+    /// In nodestime mode, replace our clock with our virtual node clock (measured in
+    /// virtual milliseconds), so that the time windows are computed from node budgets.
+    fn convert_for_nodestime(&mut self, limit: SearchLimit) -> SearchLimit {
+        if self.nodestime == 0 {
+            return limit;
+        }
+        match limit {
+            SearchLimit::Dynamic {
+                our_clock,
+                their_clock,
+                our_inc,
+                their_inc,
+                moves_to_go,
+            } => {
+                let node_clock = *self
+                    .node_clock
+                    .get_or_insert_with(|| our_clock.saturating_mul(self.nodestime));
+                SearchLimit::Dynamic {
+                    our_clock: node_clock / self.nodestime,
+                    their_clock,
+                    our_inc,
+                    their_inc,
+                    moves_to_go,
+                }
+            }
+            SearchLimit::Pondering { saved_limit } => SearchLimit::Pondering {
+                saved_limit: Box::new(self.convert_for_nodestime(*saved_limit)),
+            },
+            other => other,
+        }
+    }
+
+    /// Record whether the opponent played the move we were pondering on.
+    pub const fn record_ponder_result(&mut self, hit: bool) {
+        self.ponder_stats.record(hit);
+    }
+
+    pub const fn ponder_stats(&self) -> PonderStats {
+        self.ponder_stats
+    }
+
+    /// Charge the nodes used by a search against our virtual node clock.
+    pub fn report_search_nodes(&mut self, nodes: u64) {
+        if let (Some(node_clock), SearchLimit::Dynamic { our_inc, .. }) =
+            (self.node_clock.as_mut(), &self.limit)
+        {
+            *node_clock = node_clock.saturating_sub(nodes) + our_inc * self.nodestime;
+        }
+    }
+
+    /// The amount of time that counts as having been used by the search,
+    /// which is derived from the node count in nodestime mode.
+    fn used_time(&self, nodes: u64) -> Duration {
+        nodes
+            .checked_div(self.nodestime)
+            .map_or_else(|| self.time_since_start(), Duration::from_millis)
     }
 
     pub fn start(&mut self) {
         self.start_time = Instant::now();
     }
 
+    /// Start the clock from an instant in the past, such as when the command arrived.
+    pub fn start_at(&mut self, instant: Instant) {
+        self.start_time = instant;
+    }
+
     pub fn elapsed(&self) -> Duration {
         self.start_time.elapsed()
     }
@@ -204,6 +539,21 @@ impl TimeManager {
         &self.limit
     }
 
+    /// Record an observed delay in receiving or responding to a command.
+    pub fn record_latency(&mut self, latency: Duration) {
+        self.overhead.record(latency);
+    }
+
+    /// The move overhead currently in use, in milliseconds.
+    pub fn move_overhead(&self) -> u64 {
+        self.overhead.move_overhead()
+    }
+
+    /// Returns the calibrated move overhead if it has changed since it was last reported.
+    pub fn take_overhead_report(&mut self) -> Option<u64> {
+        self.overhead.take_report()
+    }
+
     #[allow(dead_code)]
     pub fn default_with_limit(limit: SearchLimit) -> Self {
         Self {
@@ -218,9 +568,11 @@ impl TimeManager {
         self.stability = 0;
         self.failed_low = 0;
         self.mate_counter = 0;
+        self.mate_score = 0;
         self.found_forced_move = ForcedMoveType::None;
-        self.last_factors = [1.0, 1.0];
+        self.score_trend_multiplier = 1.0;
         self.best_move_nodes_fraction = None;
+        self.reply_after = None;
 
         if let SearchLimit::Dynamic {
             our_clock,
@@ -229,15 +581,26 @@ impl TimeManager {
             ..
         } = self.limit.clone().from_pondering()
         {
-            let (opt_time, mut hard_time, max_time) =
-                SearchLimit::compute_time_windows(our_clock, moves_to_go, our_inc, conf);
+            let (soft_time, mut hard_time, max_time) = SearchLimit::compute_time_windows(
+                our_clock,
+                moves_to_go,
+                our_inc,
+                self.move_overhead(),
+                conf,
+            );
             // deal with "ponderhit" arriving while we're stuck on a depth:
             if matches!(self.limit, SearchLimit::Pondering { .. }) {
-                hard_time = opt_time;
+                hard_time = soft_time;
             }
             self.max_time = Duration::from_millis(max_time);
             self.hard_time = Duration::from_millis(hard_time);
-            self.opt_time = Duration::from_millis(opt_time);
+            self.soft_time = Duration::from_millis(soft_time)
+                .mul_f64(self.pacing.jitter_factor(&mut self.rng))
+                .min(self.hard_time);
+            // there's no-one to pace ourselves against while pondering.
+            if self.is_dynamic() && self.pacing.max_delay.max(self.pacing.min_delay) > 0 {
+                self.reply_after = Some(self.pacing.delay(&mut self.rng).min(self.hard_time));
+            }
         }
     }
 
@@ -265,7 +628,7 @@ impl TimeManager {
                 past_limit
             }
             SearchLimit::Dynamic { .. } => {
-                let past_limit = self.time_since_start() >= self.hard_time;
+                let past_limit = self.used_time(nodes_so_far) >= self.hard_time;
                 if past_limit {
                     stopped.store(true, Ordering::SeqCst);
                 }
@@ -285,10 +648,9 @@ impl TimeManager {
     }
 
     /// If we have used enough time that stopping after finishing a depth would be good here.
-    #[allow(unused_variables)]
-    pub fn is_past_opt_time(&self, nodes: u64) -> bool {
+    pub fn is_past_soft_limit(&self, nodes: u64) -> bool {
         match self.limit {
-            SearchLimit::Dynamic { .. } => self.time_since_start() >= self.opt_time,
+            SearchLimit::Dynamic { .. } => self.used_time(nodes) >= self.soft_time,
             #[cfg(feature = "datagen")]
             SearchLimit::SoftNodes { soft_limit, .. } => nodes >= soft_limit,
             _ => false,
@@ -335,23 +697,36 @@ impl TimeManager {
         }
     }
 
+    /// Stop once the same mate score has been found for `mate_stop_iterations` iterations in
+    /// a row, each deep enough to see the whole mating line, as searching on can't change the
+    /// result. This only applies to games on the clock, so never while pondering, where we
+    /// must not reply before the opponent has moved.
     pub fn mate_found_breaker<ThTy: SmpThreadType>(
         &mut self,
         pv: &PVariation,
         depth: i32,
     ) -> ControlFlow<()> {
         const MINIMUM_MATE_BREAK_DEPTH: i32 = 10;
-        if ThTy::MAIN_THREAD
-            && self.is_dynamic()
-            && is_mate_score(pv.score())
+        if !ThTy::MAIN_THREAD {
+            return ControlFlow::Continue(());
+        }
+        let score = pv.score().get();
+        let proven = self.is_dynamic()
+            && is_mate_score(score)
             && depth > MINIMUM_MATE_BREAK_DEPTH
-        {
-            self.mate_counter += 1;
-            if self.mate_counter >= 3 {
-                return ControlFlow::Break(());
-            }
-        } else if ThTy::MAIN_THREAD {
+            && MATE_SCORE - score.abs() < depth;
+        if !proven {
             self.mate_counter = 0;
+            return ControlFlow::Continue(());
+        }
+        if score == self.mate_score {
+            self.mate_counter += 1;
+        } else {
+            self.mate_counter = 1;
+            self.mate_score = score;
+        }
+        if self.mate_stop_iterations > 0 && self.mate_counter >= self.mate_stop_iterations {
+            return ControlFlow::Break(());
         }
         ControlFlow::Continue(())
     }
@@ -360,18 +735,14 @@ impl TimeManager {
     const VERY_FORCED: i32 = 8;
     pub fn report_forced_move(&mut self, depth: i32, conf: &Config) {
         assert_eq!(self.found_forced_move, ForcedMoveType::None);
-        if depth >= Self::SLIGHTLY_FORCED {
-            // reduce thinking time by conf.weak_forced_tm_frac
-            self.hard_time = self.hard_time * conf.weak_forced_tm_frac / 1000;
-            self.opt_time = self.opt_time * conf.weak_forced_tm_frac / 1000;
-            self.found_forced_move = ForcedMoveType::Weak;
+        // a singular best move lets us finish early, so this only shortens the soft limit.
+        self.found_forced_move = if depth >= Self::SLIGHTLY_FORCED {
+            ForcedMoveType::Weak
         } else {
             /* depth >= Self::VERY_FORCED */
-            // reduce thinking time by conf.strong_forced_tm_frac
-            self.hard_time = self.hard_time * conf.strong_forced_tm_frac / 1000;
-            self.opt_time = self.opt_time * conf.strong_forced_tm_frac / 1000;
-            self.found_forced_move = ForcedMoveType::Strong;
-        }
+            ForcedMoveType::Strong
+        };
+        self.update_soft_limit(conf);
     }
 
     pub fn check_for_forced_move(&self, depth: i32) -> Option<i32> {
@@ -388,26 +759,93 @@ impl TimeManager {
         }
     }
 
-    pub fn notify_one_legal_move(&mut self) {
-        self.opt_time = Duration::from_millis(0);
+    /// There is only one move to play, so think for no longer than the instant-move budget,
+    /// which gives us a line to ponder on and a score to report.
+    pub fn notify_instant_move(&mut self) {
+        self.soft_time = Duration::from_millis(self.instant_move_budget).min(self.hard_time);
         self.found_forced_move = ForcedMoveType::OneLegal;
     }
 
-    fn best_move_stability_multiplier(stability: usize) -> f64 {
+    fn best_move_stability_multiplier(stability: usize, conf: &Config) -> f64 {
         // approach to this is adapted from Stash.
-        const VALUES: [f64; 5] = [2.50, 1.20, 0.90, 0.80, 0.75];
-
-        // Clamp stability to the range [0, 4], and convert it to a time scale in
-        // the range [0.75, 2.50].
+        let values = [
+            conf.stability_tm_mul_0,
+            conf.stability_tm_mul_1,
+            conf.stability_tm_mul_2,
+            conf.stability_tm_mul_3,
+            conf.stability_tm_mul_4,
+        ];
+
+        // Clamp stability to the range [0, 4], and convert it to a time scale.
         let stability = stability.min(4);
 
-        VALUES[stability]
+        f64::from(values[stability]) / 100.0
     }
 
     fn best_move_subtree_size_multiplier(nodes_fraction: f64, conf: &Config) -> f64 {
         (1.62 - nodes_fraction) * f64::from(conf.node_tm_subtree_multiplier) / 100.0
     }
 
+    /// Scale the soft limit up when the score is dropping between iterations,
+    /// and down slightly when it is rising.
+    fn score_trend_multiplier(prev_score: i32, score: i32, conf: &Config) -> f64 {
+        // game-theoretic scores jump around by huge amounts, so clamp the difference.
+        let drop = (prev_score - score).clamp(-1000, 1000);
+        let multiplier = f64::from(drop).mul_add(f64::from(conf.score_trend_tm_mul) / 10000.0, 1.0);
+        multiplier.clamp(
+            f64::from(conf.score_trend_tm_min) / 100.0,
+            f64::from(conf.score_trend_tm_max) / 100.0,
+        )
+    }
+
+    /// Recompute the soft limit from all of the adjustment factors.
+    /// The hard limit is left untouched, as it exists to keep us from losing on time.
+    fn update_soft_limit(&mut self, conf: &Config) {
+        // the soft limit of a move that needs no thought is the budget, however it goes.
+        if self.found_forced_move == ForcedMoveType::OneLegal {
+            return;
+        }
+        let SearchLimit::Dynamic {
+            our_clock,
+            our_inc,
+            moves_to_go,
+            ..
+        } = self.limit
+        else {
+            return;
+        };
+        let (soft_time, _, _) = SearchLimit::compute_time_windows(
+            our_clock,
+            moves_to_go,
+            our_inc,
+            self.move_overhead(),
+            conf,
+        );
+        let soft_time = Duration::from_millis(soft_time);
+
+        let stability_multiplier = Self::best_move_stability_multiplier(self.stability, conf);
+        // retain time added by windows that failed low
+        let failed_low_multiplier =
+            f64::from(self.failed_low).mul_add(f64::from(conf.fail_low_tm_bonus) / 1000.0, 1.0);
+        let forced_move_multiplier = self.found_forced_move.tm_multiplier(conf);
+        let subtree_size_multiplier = self.best_move_nodes_fraction.map_or(1.0, |frac| {
+            Self::best_move_subtree_size_multiplier(frac, conf)
+        });
+
+        let ponder_multiplier = self.ponder_stats.tm_multiplier(conf);
+
+        let multiplier = stability_multiplier
+            * failed_low_multiplier
+            * forced_move_multiplier
+            * subtree_size_multiplier
+            * self.score_trend_multiplier
+            * ponder_multiplier;
+
+        let soft_time = Duration::from_secs_f64(soft_time.as_secs_f64() * multiplier);
+
+        self.soft_time = soft_time.min(self.hard_time).min(self.max_time);
+    }
+
     pub fn report_completed_depth(
         &mut self,
         _depth: i32,
@@ -416,47 +854,19 @@ impl TimeManager {
         best_move_nodes_fraction: Option<f64>,
         conf: &Config,
     ) {
-        if let SearchLimit::Dynamic {
-            our_clock,
-            our_inc,
-            moves_to_go,
-            ..
-        } = self.limit
-        {
-            let (opt_time, hard_time, max_time) =
-                SearchLimit::compute_time_windows(our_clock, moves_to_go, our_inc, conf);
-            let max_time = Duration::from_millis(max_time);
-            let hard_time = Duration::from_millis(hard_time);
-            let opt_time = Duration::from_millis(opt_time);
-
-            if Some(best_move) == self.prev_move {
-                self.stability += 1;
-            } else {
-                self.stability = 0;
+        if self.is_dynamic() {
+            if let Some(prev_move) = self.prev_move {
+                if best_move == prev_move {
+                    self.stability += 1;
+                } else {
+                    self.stability = 0;
+                }
+                self.score_trend_multiplier =
+                    Self::score_trend_multiplier(self.prev_score, eval, conf);
             }
             self.best_move_nodes_fraction = best_move_nodes_fraction;
 
-            let stability_multiplier = Self::best_move_stability_multiplier(self.stability);
-            // retain time added by windows that failed low
-            let failed_low_multiplier =
-                f64::from(self.failed_low).mul_add(f64::from(conf.fail_low_tm_bonus) / 1000.0, 1.0);
-            let forced_move_multiplier = self.found_forced_move.tm_multiplier(conf);
-            let subtree_size_multiplier = self.best_move_nodes_fraction.map_or(1.0, |frac| {
-                Self::best_move_subtree_size_multiplier(frac, conf)
-            });
-
-            let multiplier = stability_multiplier
-                * failed_low_multiplier
-                * forced_move_multiplier
-                * subtree_size_multiplier;
-
-            let hard_time = Duration::from_secs_f64(hard_time.as_secs_f64() * multiplier);
-            let opt_time = Duration::from_secs_f64(opt_time.as_secs_f64() * multiplier);
-
-            self.hard_time = hard_time.min(max_time);
-            self.opt_time = opt_time.min(max_time);
-
-            self.last_factors = [stability_multiplier, failed_low_multiplier];
+            self.update_soft_limit(conf);
         }
 
         self.prev_move = Some(best_move);
@@ -465,45 +875,141 @@ impl TimeManager {
 
     pub fn report_aspiration_fail(&mut self, depth: i32, bound: Bound, conf: &Config) {
         const FAIL_LOW_UPDATE_THRESHOLD: i32 = 0;
-        let SearchLimit::Dynamic {
-            our_clock,
-            our_inc,
-            moves_to_go,
-            ..
-        } = self.limit
-        else {
-            return;
-        };
-        if depth >= FAIL_LOW_UPDATE_THRESHOLD && bound == Bound::Upper && self.failed_low < 2 {
+        if self.is_dynamic()
+            && depth >= FAIL_LOW_UPDATE_THRESHOLD
+            && bound == Bound::Upper
+            && self.failed_low < 2
+        {
             self.failed_low += 1;
+            self.update_soft_limit(conf);
+        }
+    }
+}
 
-            let (opt_time, hard_time, max_time) =
-                SearchLimit::compute_time_windows(our_clock, moves_to_go, our_inc, conf);
-            let max_time = Duration::from_millis(max_time);
-            let hard_time = Duration::from_millis(hard_time);
-            let opt_time = Duration::from_millis(opt_time);
+mod tests {
+    #[test]
+    fn overhead_calibration_inflates_and_decays() {
+        use super::{OverheadCalibration, MOVE_OVERHEAD};
+        use std::time::Duration;
 
-            let stability_multiplier = self.last_factors[0];
-            // calculate the failed low multiplier
-            let failed_low_multiplier =
-                f64::from(self.failed_low).mul_add(f64::from(conf.fail_low_tm_bonus) / 1000.0, 1.0);
-            let forced_move_multiplier = self.found_forced_move.tm_multiplier(conf);
-            let subtree_size_multiplier = self.best_move_nodes_fraction.map_or(1.0, |frac| {
-                Self::best_move_subtree_size_multiplier(frac, conf)
-            });
+        let mut calibration = OverheadCalibration::default();
+        assert_eq!(calibration.move_overhead(), MOVE_OVERHEAD);
+        assert_eq!(calibration.take_report(), None);
 
-            let multiplier = stability_multiplier
-                * failed_low_multiplier
-                * forced_move_multiplier
-                * subtree_size_multiplier;
+        calibration.record(Duration::from_millis(40));
+        assert_eq!(calibration.move_overhead(), MOVE_OVERHEAD + 80);
+        assert_eq!(calibration.take_report(), Some(MOVE_OVERHEAD + 80));
+        assert_eq!(calibration.take_report(), None);
 
-            let hard_time = Duration::from_secs_f64(hard_time.as_secs_f64() * multiplier);
-            let opt_time = Duration::from_secs_f64(opt_time.as_secs_f64() * multiplier);
+        for _ in 0..200 {
+            calibration.record(Duration::ZERO);
+        }
+        assert_eq!(calibration.move_overhead(), MOVE_OVERHEAD);
+        assert_eq!(calibration.take_report(), Some(MOVE_OVERHEAD));
+    }
 
-            self.hard_time = hard_time.min(max_time);
-            self.opt_time = opt_time.min(max_time);
+    #[test]
+    fn pacing_stays_in_band() {
+        use super::{Pacing, SearchLimit, TimeManager};
+        use crate::search::parameters::Config;
+        use std::time::Duration;
+
+        let conf = Config::default();
+        let limit = SearchLimit::Dynamic {
+            our_clock: 60_000,
+            their_clock: 60_000,
+            our_inc: 0,
+            their_inc: 0,
+            moves_to_go: None,
+        };
+        let mut steady = TimeManager::default_with_limit(limit.clone());
+        steady.reset_for_id(&conf);
+        assert_eq!(steady.reply_after(), None);
+
+        let mut paced = TimeManager::default_with_limit(limit);
+        paced.set_pacing(Pacing {
+            jitter: 20,
+            min_delay: 500,
+            max_delay: 1500,
+        });
+        for _ in 0..100 {
+            paced.reset_for_id(&conf);
+            let ratio = paced.soft_time.as_secs_f64() / steady.soft_time.as_secs_f64();
+            assert!((0.79..=1.21).contains(&ratio), "soft time ratio {ratio}");
+            let delay = paced.reply_after().unwrap();
+            assert!(Duration::from_millis(500) <= delay && delay <= Duration::from_millis(1500));
+        }
+    }
 
-            self.last_factors[1] = failed_low_multiplier;
+    #[test]
+    fn instant_moves_keep_to_the_budget() {
+        use super::{SearchLimit, TimeManager};
+        use crate::{
+            chess::{chessmove::Move, types::Square},
+            search::parameters::Config,
+        };
+        use std::time::Duration;
+
+        let conf = Config::default();
+        let mut tm = TimeManager::default_with_limit(SearchLimit::Dynamic {
+            our_clock: 60_000,
+            their_clock: 60_000,
+            our_inc: 0,
+            their_inc: 0,
+            moves_to_go: None,
+        });
+        tm.reset_for_id(&conf);
+        tm.notify_instant_move();
+        assert!(tm.is_past_soft_limit(0));
+
+        tm.set_instant_move_budget(150);
+        tm.reset_for_id(&conf);
+        tm.notify_instant_move();
+        assert_eq!(tm.soft_time, Duration::from_millis(150));
+        // completed iterations don't stretch the budget.
+        let m = Move::new(Square::E2, Square::E4);
+        for depth in 1..10 {
+            tm.report_completed_depth(depth, 0, m, Some(0.9), &conf);
         }
+        assert_eq!(tm.soft_time, Duration::from_millis(150));
+    }
+
+    #[test]
+    fn stable_mates_stop_the_search() {
+        use super::{SearchLimit, TimeManager};
+        use crate::{
+            evaluation::{mate_in, Value},
+            search::parameters::Config,
+            search::{pv::PVariation, MainThread},
+        };
+        use std::ops::ControlFlow;
+
+        let mut tm = TimeManager::default_with_limit(SearchLimit::Dynamic {
+            our_clock: 60_000,
+            their_clock: 60_000,
+            our_inc: 0,
+            their_inc: 0,
+            moves_to_go: None,
+        });
+        tm.reset_for_id(&Config::default());
+        let iteration = |tm: &mut TimeManager, score, depth| {
+            let pv = PVariation {
+                score: Value::new(score),
+                ..PVariation::default()
+            };
+            tm.mate_found_breaker::<MainThread>(&pv, depth) == ControlFlow::Break(())
+        };
+
+        // a mate longer than the depth isn't proven yet, however long it holds.
+        assert!(!(11..20).any(|depth| iteration(&mut tm, mate_in(25), depth)));
+        // a shorter mate must hold for three iterations, and a change of score starts over.
+        assert!(!iteration(&mut tm, mate_in(7), 20));
+        assert!(!iteration(&mut tm, mate_in(7), 21));
+        assert!(!iteration(&mut tm, -mate_in(8), 22));
+        assert!(!iteration(&mut tm, -mate_in(8), 23));
+        assert!(iteration(&mut tm, -mate_in(8), 24));
+
+        tm.set_mate_stop_iterations(0);
+        assert!(!(25..35).any(|depth| iteration(&mut tm, mate_in(7), depth)));
     }
 }
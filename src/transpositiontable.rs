@@ -1,12 +1,16 @@
 use std::{
+    alloc::{self, Layout},
     mem::size_of,
-    sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering},
+    sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+    time::Duration,
 };
 
+use anyhow::{bail, Context};
+
 use crate::{
     chess::chessmove::Move,
-    evaluation::MINIMUM_TB_WIN_SCORE,
-    util::{self, depth::CompactDepthStorage},
+    evaluation::Value,
+    util::{self, depth::CompactDepthStorage, MEGABYTE},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -31,11 +35,24 @@ macro_rules! impl_from_bound {
 impl_from_bound!(u8);
 impl_from_bound!(i32);
 
+/// Map a key onto `[0, len)` by taking the high half of the 128-bit product of the key
+/// and the length, which spreads keys evenly for any length up to `u64::MAX`, so tables
+/// of terabytes are indexed as well as small ones, and without a division.
+const fn bucket_index(key: u64, len: usize) -> usize {
+    #![allow(clippy::cast_possible_truncation)]
+    ((key as u128 * len as u128) >> 64) as usize
+}
+
 fn divide_into_chunks<T>(slice: &[T], n_chunks: usize) -> impl Iterator<Item = &[T]> {
     let chunk_size = slice.len() / n_chunks + 1; // +1 to avoid 0
     slice.chunks(chunk_size)
 }
 
+/// Tables at least this large report their progress while being cleared.
+const PROGRESS_REPORT_BYTES: usize = 4 << 30;
+/// The number of clusters cleared between updates of the progress count.
+const CLEAR_BLOCK_CLUSTERS: usize = 1 << 16;
+
 const MAX_AGE: i32 = 1 << 5; // must be power of 2
 const AGE_MASK: i32 = MAX_AGE - 1;
 
@@ -95,6 +112,19 @@ impl TTEntry {
 
 const CLUSTER_SIZE: usize = 3;
 
+/// A 16-bit hash of everything in an entry but its key, which is zero for an empty entry.
+///
+/// Entries are written with several atomic stores, so a thread can read part of one write
+/// and part of another. To catch this, the key is stored XOR-ed with this hash of the rest
+/// of the entry: a torn read almost always unseals to the wrong key, and so is treated as
+/// a miss rather than returning a move or score that belongs to a different position.
+const fn seal(bytes: u128) -> u128 {
+    #![allow(clippy::cast_possible_truncation)]
+    let data = (bytes >> 16) as u64;
+    let check = (data.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 48) as u16;
+    bytes ^ check as u128
+}
+
 /// Object representing the backing memory used to store tt entries.
 #[derive(Debug, Default)]
 #[repr(C, align(32))]
@@ -145,6 +175,7 @@ impl TTClusterMemory {
             }
             _ => panic!("Index out of bounds!"),
         };
+        let bytes = seal(bytes);
         // SAFETY: All bitpatterns of TTEntry are valid.
         unsafe { TTEntryReadTarget { bytes }.entry }
     }
@@ -155,7 +186,7 @@ impl TTClusterMemory {
         memory.entry = entry;
         // SAFETY: TTEntry can be safely reinterpreted as bytes, and the
         // whole u128 is initialised.
-        let bytes = unsafe { memory.bytes };
+        let bytes = seal(unsafe { memory.bytes });
         match idx {
             // INTERNAL IMPLEMENTATION DETAIL:
             // We swap the access pattern of the second and third entries, accessing the entry that is third
@@ -218,8 +249,8 @@ pub struct TTHit {
     pub mov: Option<Move>,
     pub depth: i32,
     pub bound: Bound,
-    pub value: i32,
-    pub eval: i32,
+    pub value: Value,
+    pub eval: Value,
     pub was_pv: bool,
 }
 
@@ -232,33 +263,81 @@ impl TT {
     }
 
     pub fn resize(&mut self, bytes: usize) {
-        let new_len = bytes / size_of::<TTClusterMemory>();
-        // dealloc the old table:
+        if let Err(e) = self.try_resize(bytes) {
+            panic!("{e:#}");
+        }
+    }
+
+    /// Resize the table to `bytes`, which may run to terabytes. If that much memory
+    /// can't be had, the table goes back to its old size and the error is returned.
+    pub fn try_resize(&mut self, bytes: usize) -> anyhow::Result<()> {
+        let old_len = self.table.len();
+        // dealloc the old table first, so that the two never need to fit at once:
         self.table = Vec::new();
-        // construct a new vec:
-        // SAFETY: zeroed memory is a legal bitpattern for AtomicUXX.
+        match Self::alloc_table(bytes / size_of::<TTClusterMemory>()) {
+            Ok(table) => {
+                self.table = table;
+                Ok(())
+            }
+            Err(e) => {
+                // we just gave this much back, so it should be there to take again.
+                self.table = Self::alloc_table(old_len)?;
+                Err(e)
+            }
+        }
+    }
+
+    fn alloc_table(len: usize) -> anyhow::Result<Vec<TTClusterMemory>> {
+        let bytes = len * size_of::<TTClusterMemory>();
+        let layout = Layout::array::<TTClusterMemory>(len)
+            .ok()
+            .filter(|layout| layout.size() > 0)
+            .with_context(|| format!("can't make a transposition table of {bytes} bytes"))?;
+        // SAFETY: the layout has a non-zero size, and zeroed memory is a legal
+        // bitpattern for AtomicUXX, so the vec owns `len` valid clusters.
         unsafe {
-            let layout = std::alloc::Layout::array::<TTClusterMemory>(new_len).unwrap();
-            let ptr = std::alloc::alloc_zeroed(layout);
+            let ptr = alloc::alloc_zeroed(layout);
             if ptr.is_null() {
-                std::alloc::handle_alloc_error(layout);
+                bail!(
+                    "failed to allocate {} MB for the transposition table",
+                    bytes / MEGABYTE
+                );
             }
-            self.table = Vec::from_raw_parts(ptr.cast(), new_len, new_len);
+            Ok(Vec::from_raw_parts(ptr.cast(), len, len))
         }
     }
 
+    /// Clear the table, splitting the work between `threads` threads. For very large
+    /// tables, the progress is reported as it goes, so that a GUI can see that we're busy.
     pub fn clear(&self, threads: usize) {
-        #[allow(clippy::collection_is_never_read)]
+        let cleared = AtomicUsize::new(0);
         std::thread::scope(|s| {
-            let mut handles = Vec::with_capacity(threads);
-            for chunk in divide_into_chunks(&self.table, threads) {
-                let handle = s.spawn(move || {
-                    for entry in chunk {
-                        entry.clear();
-                    }
-                });
-                handles.push(handle);
+            let handles = divide_into_chunks(&self.table, threads)
+                .map(|chunk| {
+                    let cleared = &cleared;
+                    s.spawn(move || {
+                        for block in chunk.chunks(CLEAR_BLOCK_CLUSTERS) {
+                            for entry in block {
+                                entry.clear();
+                            }
+                            cleared.fetch_add(block.len(), Ordering::Relaxed);
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            if self.size() < PROGRESS_REPORT_BYTES {
+                return;
             }
+            let mut reported = 0;
+            while handles.iter().any(|h| !h.is_finished()) {
+                std::thread::sleep(Duration::from_millis(50));
+                let percent = cleared.load(Ordering::Relaxed) * 100 / self.table.len();
+                if percent / 10 > reported / 10 && percent < 100 {
+                    crate::uci::output::send(format!("info string hash init {percent}%"));
+                    reported = percent;
+                }
+            }
+            crate::uci::output::send("info string hash init 100%".into());
         });
     }
 
@@ -286,12 +365,8 @@ impl TT {
 }
 
 impl TTView<'_> {
-    fn wrap_key(&self, key: u64) -> usize {
-        #![allow(clippy::cast_possible_truncation)]
-        let key = u128::from(key);
-        let len = self.table.len() as u128;
-        // fixed-point multiplication trick!
-        ((key * len) >> 64) as usize
+    const fn wrap_key(&self, key: u64) -> usize {
+        bucket_index(key, self.table.len())
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -300,8 +375,8 @@ impl TTView<'_> {
         key: u64,
         ply: usize,
         mut best_move: Option<Move>,
-        score: i32,
-        eval: i32,
+        score: Value,
+        eval: Value,
         flag: Bound,
         depth: i32,
         pv: bool,
@@ -345,9 +420,6 @@ impl TTView<'_> {
             best_move = tte.m;
         }
 
-        // normalise mate / TB scores:
-        let score = normalise_gt_truth_score(score, ply);
-
         // give entries a bonus for type:
         // exact = 3, lower = 2, upper = 1
         let insert_flag_bonus = i32::from(flag);
@@ -374,12 +446,10 @@ impl TTView<'_> {
             let write = TTEntry {
                 key,
                 m: best_move,
-                score: score.try_into().expect(
-                    "attempted to store a score with value outwith [i16::MIN, i16::MAX] in the transposition table",
-                ),
+                score: score.to_tt(ply),
                 depth: depth.try_into().unwrap(),
                 info: PackedInfo::new(self.age, flag, pv),
-                evaluation: eval.try_into().expect(
+                evaluation: eval.get().try_into().expect(
                     "attempted to store an eval with value outwith [i16::MIN, i16::MAX] in the transposition table",
                 ),
             };
@@ -405,16 +475,16 @@ impl TTView<'_> {
             let tt_depth = entry.depth.into();
             let tt_bound = entry.info.flag();
 
-            // we can't store the score in a tagged union,
-            // because we need to do mate score preprocessing.
-            let tt_value = reconstruct_gt_truth_score(entry.score.into(), ply);
+            // mate and TB scores are stored relative to the node, so must be made
+            // relative to the root again.
+            let tt_value = Value::from_tt(entry.score, ply);
 
             return Some(TTHit {
                 mov: tt_move,
                 depth: tt_depth,
                 bound: tt_bound,
                 value: tt_value,
-                eval: entry.evaluation.into(),
+                eval: Value::new(entry.evaluation.into()),
                 was_pv: entry.info.pv(),
             });
         }
@@ -441,7 +511,7 @@ impl TTView<'_> {
         }
     }
 
-    pub fn probe_for_provisional_info(&self, key: u64) -> Option<(Option<Move>, i32)> {
+    pub fn probe_for_provisional_info(&self, key: u64) -> Option<(Option<Move>, Value)> {
         self.probe(key, 0)
             .map(|TTHit { mov, value, .. }| (mov, value))
     }
@@ -461,32 +531,23 @@ impl TTView<'_> {
     }
 }
 
-const fn normalise_gt_truth_score(mut score: i32, ply: usize) -> i32 {
-    #![allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
-    if score >= MINIMUM_TB_WIN_SCORE {
-        score += ply as i32;
-    } else if score <= -MINIMUM_TB_WIN_SCORE {
-        score -= ply as i32;
-    }
-    score
-}
-
-const fn reconstruct_gt_truth_score(mut score: i32, ply: usize) -> i32 {
-    #![allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
-    if score >= MINIMUM_TB_WIN_SCORE {
-        score -= ply as i32;
-    } else if score <= -MINIMUM_TB_WIN_SCORE {
-        score += ply as i32;
-    }
-    score
-}
-
 mod tests {
     #![allow(unused_imports)]
     use crate::{chess::piece::PieceType, chess::types::Square};
 
     use super::*;
 
+    #[test]
+    fn buckets_cover_huge_tables() {
+        // a terabyte of 32-byte clusters.
+        let len = (1 << 40) / 32;
+        assert_eq!(bucket_index(0, len), 0);
+        assert_eq!(bucket_index(u64::MAX, len), len - 1);
+        assert_eq!(bucket_index(1 << 63, len), len / 2);
+        // every bit of the key matters, not just the low 32.
+        assert_ne!(bucket_index(1 << 40, len), bucket_index(1 << 41, len));
+    }
+
     #[test]
     fn tt_entry_roundtrip() {
         #![allow(clippy::cast_possible_wrap)]
@@ -520,4 +581,72 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn torn_reads_miss() {
+        let old = TTEntry {
+            key: 0x1234,
+            m: Some(Move::new(Square::E2, Square::E4)),
+            score: 20,
+            depth: 10.try_into().unwrap(),
+            info: PackedInfo::new(0, Bound::Exact, false),
+            evaluation: 15,
+        };
+        let new = TTEntry {
+            m: Some(Move::new(Square::G1, Square::F3)),
+            score: -40,
+            ..old
+        };
+        let memory = TTClusterMemory::default();
+        let other = TTClusterMemory::default();
+        memory.store(2, old);
+        other.store(2, new);
+        // a write of `new` that has stored the move and score, but not yet the key.
+        memory.entry2_block2.store(
+            other.entry2_block2.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        let torn = memory.load(2);
+        assert_ne!(torn.key, old.key);
+        assert_eq!(torn.m, new.m);
+    }
+
+    #[test]
+    fn mate_scores_are_stored_relative_to_the_node() {
+        use crate::{evaluation::Value, util::MEGABYTE};
+
+        let mut tt = TT::new();
+        tt.resize(MEGABYTE);
+        let tt = tt.view();
+        let key = 0xDEAD_BEEF_u64;
+        // mate in ten plies from the root, found four plies in...
+        tt.store(
+            key,
+            4,
+            None,
+            Value::mate_in(10),
+            Value::cp(30),
+            Bound::Exact,
+            5,
+            false,
+        );
+        // ...is mate in twelve when the position is reached at six.
+        let hit = tt.probe(key, 6).unwrap();
+        assert_eq!(hit.value, Value::mate_in(12));
+        assert_eq!(hit.eval, Value::cp(30));
+
+        tt.store(
+            key,
+            4,
+            None,
+            Value::cp(-75),
+            Value::NONE,
+            Bound::Exact,
+            6,
+            false,
+        );
+        let hit = tt.probe(key, 6).unwrap();
+        assert_eq!(hit.value, Value::cp(-75));
+        assert!(hit.eval.is_none());
+    }
 }
@@ -0,0 +1,607 @@
+//! A local SPSA tuner, driven by self-play games between perturbed configurations,
+//! and an SPRT match runner for checking that a configuration is not a regression.
+
+pub mod tournament;
+
+use std::{
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc,
+    },
+    time::Instant,
+};
+
+use anyhow::{bail, Context};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    adjudication::{Adjudicator, DrawRule, ResignRule},
+    chess::{
+        board::{Board, GameOutcome},
+        chessmove::Move,
+    },
+    datagen::uciengine::UciEngine,
+    evaluation::is_game_theoretic_score,
+    nnue::network::NNUEParams,
+    rng::{fresh_seed, seed_for},
+    search::{
+        parameters::{read_param_file, Config},
+        LMTable,
+    },
+    searchinfo::SearchInfo,
+    stats::{Hypothesis, Pentanomial, Sprt},
+    threadlocal::ThreadData,
+    timemgmt::SearchLimit,
+    transpositiontable::TT,
+    util::MEGABYTE,
+};
+
+/// Learning rate at the final iteration, as in the emitted openbench configuration.
+const R_END: f64 = 0.002;
+/// Decay exponent for the learning rate.
+const ALPHA: f64 = 0.602;
+/// Decay exponent for the perturbation size.
+const GAMMA: f64 = 0.101;
+/// Number of random plies played from the start position to make an opening.
+const OPENING_PLIES: usize = 8;
+
+pub struct TuneOptions {
+    /// Path to the parameter file describing what to tune.
+    pub params: PathBuf,
+    /// Total number of games to play.
+    pub games: usize,
+    /// Time control for each game.
+    pub tc: TimeControl,
+    /// Number of game pairs to play concurrently.
+    pub threads: usize,
+    /// When a player resigns.
+    pub resign: ResignRule,
+    /// When the players agree to a draw.
+    pub draw: DrawRule,
+    /// Seed for the perturbations and openings, or `None` for a fresh one.
+    pub seed: Option<u64>,
+    /// Where to write the tuned configuration.
+    pub output: PathBuf,
+}
+
+/// A base + increment time control, written like `5+0.05` (in seconds).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    base_ms: u64,
+    inc_ms: u64,
+}
+
+impl FromStr for TimeControl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (base, inc) = s.split_once('+').unwrap_or((s, "0"));
+        let to_ms = |part: &str| -> anyhow::Result<u64> {
+            let secs: f64 = part
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid time control \"{s}\""))?;
+            if !secs.is_finite() || secs < 0.0 {
+                bail!("invalid time control \"{s}\"");
+            }
+            Ok((secs * 1000.0).round() as u64)
+        };
+        let tc = Self {
+            base_ms: to_ms(base)?,
+            inc_ms: to_ms(inc)?,
+        };
+        if tc.base_ms == 0 && tc.inc_ms == 0 {
+            bail!("time control \"{s}\" leaves no time to think");
+        }
+        Ok(tc)
+    }
+}
+
+/// Something that can play games: our own search, with some configuration and network,
+/// or an external engine.
+#[allow(clippy::large_enum_variant)]
+pub enum Contestant {
+    Internal {
+        conf: Config,
+        nnue_params: &'static NNUEParams,
+    },
+    External {
+        path: PathBuf,
+        options: Vec<(String, String)>,
+    },
+}
+
+/// A finished game: how it ended, and the moves played after the opening.
+pub struct GameRecord {
+    pub outcome: GameOutcome,
+    pub moves: Vec<Move>,
+}
+
+/// A contestant sat down to play one game, with whatever it keeps between moves.
+#[allow(clippy::large_enum_variant)]
+enum Seat<'a> {
+    Internal {
+        info: SearchInfo<'a>,
+        thread_data: ThreadData<'a>,
+        tt: &'a TT,
+    },
+    External(UciEngine),
+}
+
+impl<'a> Seat<'a> {
+    fn new(
+        contestant: &Contestant,
+        board: &Board,
+        tt: &'a TT,
+        stopped: &'a AtomicBool,
+        nodes: &'a AtomicU64,
+    ) -> anyhow::Result<Self> {
+        Ok(match contestant {
+            Contestant::Internal { conf, nnue_params } => Self::Internal {
+                info: SearchInfo {
+                    print_to_stdout: false,
+                    conf: conf.clone(),
+                    lm_table: LMTable::new(conf),
+                    ..SearchInfo::new(stopped, nodes)
+                },
+                thread_data: ThreadData::new(0, board, tt.view(), nnue_params),
+                tt,
+            },
+            Contestant::External { path, options } => {
+                Self::External(UciEngine::spawn(path, options)?)
+            }
+        })
+    }
+
+    /// Search `board`, reached by playing `moves` from `opening`, and give back the
+    /// white-relative score and the best move.
+    fn search(
+        &mut self,
+        opening: &Board,
+        moves: &[Move],
+        board: &mut Board,
+        limit: SearchLimit,
+    ) -> anyhow::Result<(i32, Option<Move>)> {
+        match self {
+            Self::Internal {
+                info,
+                thread_data,
+                tt,
+            } => {
+                info.time_manager.set_limit(limit);
+                info.time_manager.start();
+                tt.increase_age();
+                let (score, best_move) =
+                    board.search_position(info, std::array::from_mut(thread_data), tt.view());
+                Ok((score.get(), best_move))
+            }
+            Self::External(engine) => engine.search_line(opening, moves, board, &limit),
+        }
+    }
+}
+
+/// A parameter under tuning, with SPSA's view of its value as a real number.
+struct TunedParam {
+    id: String,
+    value: f64,
+    min: f64,
+    max: f64,
+    c_end: f64,
+}
+
+fn load_params(path: &Path) -> anyhow::Result<Vec<TunedParam>> {
+    let defaults = Config::default();
+    let base = defaults.base_config();
+    read_param_file(path)?
+        .into_iter()
+        .map(|spec| {
+            let &(_, _, min, max, step) = base
+                .iter()
+                .find(|(id, ..)| *id == spec.id)
+                .with_context(|| format!("unknown parameter {}", spec.id))?;
+            // the file may narrow the range, but never widen it.
+            let min = spec.min.map_or(min, |m| m.max(min));
+            let max = spec.max.map_or(max, |m| m.min(max));
+            if min > max {
+                bail!("empty range for {}", spec.id);
+            }
+            Ok(TunedParam {
+                value: spec.value.clamp(min, max),
+                min,
+                max,
+                c_end: spec.step.unwrap_or(step),
+                id: spec.id,
+            })
+        })
+        .collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn run_tune(options: &TuneOptions) -> anyhow::Result<()> {
+    let mut params = load_params(&options.params)?;
+    if params.is_empty() {
+        bail!("no parameters to tune in {}", options.params.display());
+    }
+    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let threads = options.threads.max(1);
+    let adjudicator = Adjudicator::new(options.resign, options.draw);
+    let iterations = (options.games / (2 * threads)).max(1);
+
+    let n = iterations as f64;
+    let big_a = n / 10.0;
+    let seed = options.seed.unwrap_or_else(fresh_seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    println!(
+        "Tuning {} parameters over {iterations} iterations of {threads} game pairs, with seed {seed}",
+        params.len()
+    );
+    let start = Instant::now();
+    for iteration in 0..iterations {
+        let k = iteration as f64;
+        // (perturbation size, learning rate, direction) for each parameter.
+        let steps = params
+            .iter()
+            .map(|p| {
+                let c = p.c_end * n.powf(GAMMA);
+                let c_k = c / (k + 1.0).powf(GAMMA);
+                let a = R_END * p.c_end.powi(2) * (big_a + n).powf(ALPHA);
+                let a_k = a / (big_a + k + 1.0).powf(ALPHA);
+                let delta = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+                (c_k, a_k / c_k.powi(2), delta)
+            })
+            .collect::<Vec<_>>();
+        let perturbed = |sign: f64| {
+            Config::from_values(params.iter().zip(&steps).map(|(p, &(c_k, _, delta))| {
+                (
+                    p.id.as_str(),
+                    sign.mul_add(c_k * delta, p.value).clamp(p.min, p.max),
+                )
+            }))
+        };
+        let plus = Contestant::Internal {
+            conf: perturbed(1.0)?,
+            nnue_params,
+        };
+        let minus = Contestant::Internal {
+            conf: perturbed(-1.0)?,
+            nnue_params,
+        };
+
+        let result = std::thread::scope(|s| {
+            let handles = (0..threads)
+                .map(|i| {
+                    let pair_seed = seed_for(seed, (iteration * threads + i) as u64);
+                    let (plus, minus, adjudicator) = (&plus, &minus, &adjudicator);
+                    s.spawn(move || play_pair(plus, minus, options.tc, adjudicator, pair_seed))
+                })
+                .collect::<Vec<_>>();
+            let mut result = 0;
+            for handle in handles {
+                result += handle
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("game thread panicked"))??;
+            }
+            anyhow::Ok(result)
+        })?;
+
+        for (p, &(c_k, r_k, delta)) in params.iter_mut().zip(&steps) {
+            p.value = (r_k * c_k * f64::from(result))
+                .mul_add(delta, p.value)
+                .clamp(p.min, p.max);
+        }
+
+        let tuned = Config::from_values(params.iter().map(|p| (p.id.as_str(), p.value)))?;
+        std::fs::write(&options.output, tuned.emit_json_for_spsa()).with_context(|| {
+            format!(
+                "failed to write tuned parameters to {}",
+                options.output.display()
+            )
+        })?;
+        println!(
+            "iteration {}/{iterations}: result {result:+}, {:.1}s elapsed",
+            k + 1.0,
+            start.elapsed().as_secs_f64()
+        );
+        for p in &params {
+            println!("    {}: {:.2}", p.id, p.value);
+        }
+    }
+    println!("Wrote tuned parameters to {}", options.output.display());
+
+    Ok(())
+}
+
+pub struct SprtOptions {
+    /// Parameter file for the configuration under test. Defaults to the built-in parameters.
+    pub dev: Option<PathBuf>,
+    /// Parameter file for the configuration to test against. Defaults to the built-in parameters.
+    pub base: Option<PathBuf>,
+    /// The null hypothesis, in Elo.
+    pub elo0: f64,
+    /// The alternative hypothesis, in Elo.
+    pub elo1: f64,
+    /// The false positive rate.
+    pub alpha: f64,
+    /// The false negative rate.
+    pub beta: f64,
+    /// The most games to play before giving up without a result.
+    pub max_games: usize,
+    /// Time control for each game.
+    pub tc: TimeControl,
+    /// Number of game pairs to play concurrently.
+    pub threads: usize,
+    /// When a player resigns.
+    pub resign: ResignRule,
+    /// When the players agree to a draw.
+    pub draw: DrawRule,
+    /// Seed for the openings, or `None` for a fresh one.
+    pub seed: Option<u64>,
+}
+
+/// Play game pairs numbered from 0 up to `jobs` on `threads` threads with `play`, handing
+/// each result to `record` on this thread as it arrives. Pairs that `skip` rejects when
+/// their turn comes aren't played, and once `record` breaks, no more pairs are started and
+/// the results of those still being played are dropped.
+fn run_pairs<R: Send>(
+    threads: usize,
+    jobs: usize,
+    skip: impl Fn(usize) -> bool + Sync,
+    play: impl Fn(usize) -> anyhow::Result<R> + Sync,
+    mut record: impl FnMut(usize, R) -> anyhow::Result<ControlFlow<()>>,
+) -> anyhow::Result<()> {
+    let next_job = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let (next_job, stop, skip, play) = (&next_job, &stop, &skip, &play);
+            s.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let job = next_job.fetch_add(1, Ordering::Relaxed);
+                    if job >= jobs {
+                        break;
+                    }
+                    if skip(job) {
+                        continue;
+                    }
+                    if sender.send((job, play(job))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for (job, result) in receiver {
+            let flow = result.and_then(|result| record(job, result));
+            if !matches!(flow, Ok(ControlFlow::Continue(()))) {
+                stop.store(true, Ordering::Relaxed);
+                return flow.map(|_| ());
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Play game pairs between two configurations until the SPRT accepts one hypothesis
+/// or the game limit is reached, reporting the LLR as results arrive.
+pub fn run_sprt(options: &SprtOptions) -> anyhow::Result<()> {
+    let load = |path: &Option<PathBuf>| {
+        path.as_deref()
+            .map_or_else(|| Ok(Config::default()), Config::from_file)
+    };
+    let dev = load(&options.dev)?;
+    let base = load(&options.base)?;
+    let sprt = Sprt::new(options.elo0, options.elo1, options.alpha, options.beta)?;
+    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let threads = options.threads.max(1);
+    let adjudicator = Adjudicator::new(options.resign, options.draw);
+    let seed = options.seed.unwrap_or_else(fresh_seed);
+    let (lower, upper) = sprt.bounds();
+
+    println!(
+        "SPRT [{}, {}] with alpha {} and beta {}: accepting below {lower:.2} or above {upper:.2}, with seed {seed}",
+        options.elo0, options.elo1, options.alpha, options.beta
+    );
+    for (id, default, value) in dev.diff_from_default() {
+        println!("    dev {id}: {default} -> {value}");
+    }
+    for (id, default, value) in base.diff_from_default() {
+        println!("    base {id}: {default} -> {value}");
+    }
+    let dev = Contestant::Internal {
+        conf: dev,
+        nnue_params,
+    };
+    let base = Contestant::Internal {
+        conf: base,
+        nnue_params,
+    };
+    let mut penta = Pentanomial::default();
+    let mut verdict = None;
+    let start = Instant::now();
+    run_pairs(
+        threads,
+        options.max_games.div_ceil(2),
+        |_| false,
+        |pair| {
+            let pair_seed = seed_for(seed, pair as u64);
+            play_pair(&dev, &base, options.tc, &adjudicator, pair_seed)
+        },
+        |_, result| {
+            penta.record(usize::try_from(result + 2)?);
+            verdict = sprt.verdict(&penta);
+            // report about as often as a pair finishes on every thread.
+            if verdict.is_some() || penta.pairs() % threads as u64 == 0 {
+                let (elo, error) = penta.elo();
+                println!(
+                    "{} games, pentanomial {penta}, elo {elo:+.1} +/- {error:.1}, LLR {:.2} ({lower:.2}, {upper:.2}), {:.1}s elapsed",
+                    2 * penta.pairs(),
+                    sprt.llr(&penta),
+                    start.elapsed().as_secs_f64()
+                );
+            }
+            Ok(if verdict.is_some() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            })
+        },
+    )?;
+
+    match verdict {
+        Some(Hypothesis::H1) => {
+            println!("H1 accepted: dev is at least {} Elo stronger", options.elo0);
+        }
+        Some(Hypothesis::H0) => {
+            println!("H0 accepted: dev is not {} Elo stronger", options.elo1);
+        }
+        None => println!("No result after {} games", 2 * penta.pairs()),
+    }
+
+    Ok(())
+}
+
+/// Play a random opening, chosen by `seed`, with both colour assignments, returning the
+/// score of `plus` in the pair: +2 for two wins down to -2 for two losses.
+fn play_pair(
+    plus: &Contestant,
+    minus: &Contestant,
+    tc: TimeControl,
+    adjudicator: &Adjudicator,
+    seed: u64,
+) -> anyhow::Result<i32> {
+    let opening = random_opening(&mut StdRng::seed_from_u64(seed));
+    let first = play_game(&opening, [plus, minus], tc, adjudicator)?.outcome;
+    let second = play_game(&opening, [minus, plus], tc, adjudicator)?.outcome;
+    let white_score = |outcome| match outcome {
+        GameOutcome::WhiteWin(_) => 1,
+        GameOutcome::BlackWin(_) => -1,
+        _ => 0,
+    };
+    Ok(white_score(first) - white_score(second))
+}
+
+fn random_opening(rng: &mut impl Rng) -> Board {
+    'retry: loop {
+        let mut board = Board::default();
+        for _ in 0..OPENING_PLIES {
+            let Some(&m) = board.legal_moves().choose(rng) else {
+                continue 'retry;
+            };
+            board.make_move_simple(m);
+        }
+        if board.outcome() == GameOutcome::Ongoing {
+            return board;
+        }
+    }
+}
+
+/// Play out a game from `opening` between `players` (white first), returning the outcome
+/// and the moves played. The game ends early when `adjudicator` says so, as it would
+/// under cutechess.
+fn play_game(
+    opening: &Board,
+    players: [&Contestant; 2],
+    tc: TimeControl,
+    adjudicator: &Adjudicator,
+) -> anyhow::Result<GameRecord> {
+    use crate::chess::board::WinType;
+
+    let mut board = opening.clone();
+    let mut tts = [TT::new(), TT::new()];
+    for (tt, player) in tts.iter_mut().zip(players) {
+        if matches!(player, Contestant::Internal { .. }) {
+            tt.resize(16 * MEGABYTE);
+        }
+    }
+    let stopped = [AtomicBool::new(false), AtomicBool::new(false)];
+    let nodes = [AtomicU64::new(0), AtomicU64::new(0)];
+    let mut seats = (0..2)
+        .map(|i| Seat::new(players[i], &board, &tts[i], &stopped[i], &nodes[i]))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut clocks = [tc.base_ms; 2];
+    let mut adjudicator = adjudicator.clone();
+    adjudicator.new_game();
+    let mut moves = Vec::new();
+
+    loop {
+        let outcome = board.outcome();
+        if outcome != GameOutcome::Ongoing {
+            return Ok(GameRecord { outcome, moves });
+        }
+        let us = board.turn().index();
+        let them = 1 - us;
+        let limit = SearchLimit::Dynamic {
+            our_clock: clocks[us],
+            their_clock: clocks[them],
+            our_inc: tc.inc_ms,
+            their_inc: tc.inc_ms,
+            moves_to_go: None,
+        };
+        let search_start = Instant::now();
+        let (score, best_move) = seats[us].search(opening, &moves, &mut board, limit)?;
+        let elapsed = u64::try_from(search_start.elapsed().as_millis())
+            .with_context(|| "search took an absurd amount of time")?;
+        let loss = if us == 0 {
+            GameOutcome::BlackWin(WinType::Adjudication)
+        } else {
+            GameOutcome::WhiteWin(WinType::Adjudication)
+        };
+        if elapsed > clocks[us] {
+            // lost on time.
+            return Ok(GameRecord {
+                outcome: loss,
+                moves,
+            });
+        }
+        clocks[us] = clocks[us] - elapsed + tc.inc_ms;
+
+        let best_move = best_move.with_context(|| format!("no move returned in {board}"))?;
+
+        if is_game_theoretic_score(score) {
+            let outcome = if score > 0 {
+                GameOutcome::WhiteWin(WinType::Adjudication)
+            } else {
+                GameOutcome::BlackWin(WinType::Adjudication)
+            };
+            return Ok(GameRecord { outcome, moves });
+        }
+        let our_score = if us == 0 { score } else { -score };
+        if let Some(outcome) = adjudicator.adjudicate(&board, our_score) {
+            return Ok(GameRecord { outcome, moves });
+        }
+
+        board.make_move_simple(best_move);
+        moves.push(best_move);
+    }
+}
+
+mod tests {
+    #[test]
+    fn time_control_parsing() {
+        use super::TimeControl;
+        assert_eq!(
+            "5+0.05".parse::<TimeControl>().unwrap(),
+            TimeControl {
+                base_ms: 5000,
+                inc_ms: 50
+            }
+        );
+        assert_eq!(
+            "10".parse::<TimeControl>().unwrap(),
+            TimeControl {
+                base_ms: 10_000,
+                inc_ms: 0
+            }
+        );
+        assert!("0+0".parse::<TimeControl>().is_err());
+        assert!("fast".parse::<TimeControl>().is_err());
+    }
+}
@@ -0,0 +1,441 @@
+//! Round-robin and gauntlet tournaments between configurations of this engine, other
+//! networks, and external UCI engines, reported as a table of Elo estimates, with an
+//! optional SPRT to stop each pairing once it's decided.
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::Write as _,
+    ops::ControlFlow,
+    path::PathBuf,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant,
+};
+
+use anyhow::{bail, Context};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    adjudication::{Adjudicator, DrawRule, ResignRule},
+    chess::board::{Board, GameOutcome},
+    datagen::uciengine::UciEngine,
+    nnue::network::NNUEParams,
+    notation::{move_number, wrap_movetext},
+    rng::{fresh_seed, seed_for},
+    search::parameters::Config,
+    stats::{Pentanomial, Score, Sprt},
+    tune::{play_game, random_opening, run_pairs, Contestant, GameRecord, TimeControl},
+};
+
+/// The number of game pairs between each report of the standings.
+const REPORT_INTERVAL: usize = 20;
+
+/// A player, written as comma-separated `KEY=VALUE` pairs: an optional `name`, and either
+/// `params` and `net` files for a variant of this engine, or the `cmd` to run an external
+/// engine with and any number of its UCI options as `option.NAME`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlayerSpec {
+    pub name: String,
+    pub engine: EngineSpec,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineSpec {
+    /// This engine, with a parameter file and a network in place of the built-in ones.
+    Internal {
+        params: Option<PathBuf>,
+        net: Option<PathBuf>,
+    },
+    /// An external engine, with UCI options as `(name, value)` pairs.
+    External {
+        path: PathBuf,
+        options: Vec<(String, String)>,
+    },
+}
+
+impl FromStr for PlayerSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut name, mut params, mut net, mut path) = (None, None, None, None);
+        let mut options = Vec::new();
+        for pair in s.split(',') {
+            let (key, value) = pair
+                .split_once('=')
+                .with_context(|| format!("expected KEY=VALUE in player \"{s}\", got \"{pair}\""))?;
+            let value = value.trim();
+            match key.trim() {
+                "name" => name = Some(value.to_string()),
+                "params" => params = Some(PathBuf::from(value)),
+                "net" => net = Some(PathBuf::from(value)),
+                "cmd" => path = Some(PathBuf::from(value)),
+                key => match key.strip_prefix("option.") {
+                    Some(option) => options.push((option.to_string(), value.to_string())),
+                    None => bail!(
+                        "unknown key \"{key}\" in player \"{s}\", expected name, params, net, cmd, or option.NAME"
+                    ),
+                },
+            }
+        }
+        // unnamed players go by the file that sets them apart.
+        let stem = |path: &PathBuf| path.file_stem().map(|s| s.to_string_lossy().into_owned());
+        let engine = if let Some(path) = path {
+            if params.is_some() || net.is_some() {
+                bail!("player \"{s}\" is an external engine, so it can't take params or net");
+            }
+            name = name.or_else(|| stem(&path));
+            EngineSpec::External { path, options }
+        } else {
+            if !options.is_empty() {
+                bail!("player \"{s}\" isn't an external engine, so it can't take UCI options");
+            }
+            name = name.or_else(|| params.as_ref().or(net.as_ref()).and_then(stem));
+            EngineSpec::Internal { params, net }
+        };
+        Ok(Self {
+            name: name.unwrap_or_else(|| crate::NAME.to_string()),
+            engine,
+        })
+    }
+}
+
+pub struct TournamentOptions {
+    pub players: Vec<PlayerSpec>,
+    /// Play only the first player against each of the others, rather than everyone against everyone.
+    pub gauntlet: bool,
+    /// The number of game pairs to play in each pairing.
+    pub rounds: usize,
+    /// Time control for each game.
+    pub tc: TimeControl,
+    /// Number of game pairs to play concurrently.
+    pub threads: usize,
+    /// When a player resigns.
+    pub resign: ResignRule,
+    /// When the players agree to a draw.
+    pub draw: DrawRule,
+    /// Stop playing a pairing once an SPRT between its players concludes.
+    pub sprt: Option<Sprt>,
+    /// Seed for the openings, or `None` for a fresh one.
+    pub seed: Option<u64>,
+    /// Where to write the games as PGN.
+    pub pgn: Option<PathBuf>,
+}
+
+/// The results of one pairing, from the point of view of its first player.
+#[derive(Default)]
+struct Results {
+    score: Score,
+    penta: Pentanomial,
+}
+
+/// Half-points for white in a finished game.
+const fn white_half_points(outcome: GameOutcome) -> usize {
+    match outcome {
+        GameOutcome::WhiteWin(_) => 2,
+        GameOutcome::BlackWin(_) => 0,
+        _ => 1,
+    }
+}
+
+const fn result_token(outcome: GameOutcome) -> &'static str {
+    match outcome {
+        GameOutcome::WhiteWin(_) => "1-0",
+        GameOutcome::BlackWin(_) => "0-1",
+        GameOutcome::Draw(_) => "1/2-1/2",
+        GameOutcome::Ongoing => "*",
+    }
+}
+
+/// Write `game`, played from `opening`, as a PGN game followed by a blank line.
+fn write_game(
+    pgn: &mut String,
+    round: usize,
+    players: [&str; 2],
+    tc: TimeControl,
+    opening: &Board,
+    game: &GameRecord,
+) -> anyhow::Result<()> {
+    #![allow(clippy::cast_precision_loss)]
+    let result = result_token(game.outcome);
+    writeln!(pgn, "[Event \"{} tournament\"]", crate::NAME)?;
+    writeln!(pgn, "[Round \"{round}\"]")?;
+    writeln!(pgn, "[White \"{}\"]", players[0])?;
+    writeln!(pgn, "[Black \"{}\"]", players[1])?;
+    writeln!(pgn, "[Result \"{result}\"]")?;
+    writeln!(
+        pgn,
+        "[TimeControl \"{}+{}\"]",
+        tc.base_ms as f64 / 1000.0,
+        tc.inc_ms as f64 / 1000.0
+    )?;
+    writeln!(pgn, "[SetUp \"1\"]")?;
+    writeln!(pgn, "[FEN \"{opening}\"]")?;
+    pgn.push('\n');
+
+    let mut board = opening.clone();
+    let mut words = Vec::new();
+    for (i, &m) in game.moves.iter().enumerate() {
+        let ply = opening.ply() + i;
+        let san = board
+            .san(m)
+            .with_context(|| format!("can't write {} in {board} as SAN", m.display(false)))?;
+        if ply.is_multiple_of(2) || i == 0 {
+            words.push(format!("{} {san}", move_number(ply)));
+        } else {
+            words.push(san);
+        }
+        board.make_move_simple(m);
+    }
+    words.push(result.to_string());
+    wrap_movetext(pgn, &words);
+    pgn.push('\n');
+    Ok(())
+}
+
+fn load_contestant(spec: &PlayerSpec) -> anyhow::Result<Contestant> {
+    Ok(match &spec.engine {
+        EngineSpec::Internal { params, net } => Contestant::Internal {
+            conf: params
+                .as_deref()
+                .map_or_else(|| Ok(Config::default()), Config::from_file)?,
+            nnue_params: match net {
+                Some(path) => NNUEParams::load_quantised(path)?,
+                None => NNUEParams::decompress_and_alloc()?,
+            },
+        },
+        EngineSpec::External { path, options } => {
+            // start the engine once, so that a bad path or option fails before any games.
+            let engine = UciEngine::spawn(path, options)?;
+            println!("    {} runs {}", spec.name, engine.name());
+            Contestant::External {
+                path: path.clone(),
+                options: options.clone(),
+            }
+        }
+    })
+}
+
+/// Print the players in order of their Elo against the field, then each pairing.
+fn report(
+    options: &TournamentOptions,
+    pairings: &[(usize, usize)],
+    results: &[Results],
+    elapsed: f64,
+) {
+    let mut standings = vec![Score::default(); options.players.len()];
+    for (&(a, b), result) in pairings.iter().zip(results) {
+        let s = result.score;
+        for (player, score) in [(a, s), (b, s.flipped())] {
+            standings[player].wins += score.wins;
+            standings[player].draws += score.draws;
+            standings[player].losses += score.losses;
+        }
+    }
+    let mut order = (0..standings.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| standings[b].elo().0.total_cmp(&standings[a].elo().0));
+
+    println!(
+        "{:>4} {:<24} {:>8} {:>7} {:>6} {:>6} {:>6} {:>6}",
+        "Rank", "Name", "Elo", "+/-", "Games", "Wins", "Draws", "Losses"
+    );
+    for (rank, &player) in order.iter().enumerate() {
+        let score = standings[player];
+        let (elo, error) = score.elo();
+        println!(
+            "{:>4} {:<24} {elo:>+8.1} {error:>7.1} {:>6} {:>6} {:>6} {:>6}",
+            rank + 1,
+            options.players[player].name,
+            score.games(),
+            score.wins,
+            score.draws,
+            score.losses
+        );
+    }
+    for (&(a, b), result) in pairings.iter().zip(results) {
+        let (elo, error) = result.penta.elo();
+        let mut line = format!(
+            "    {} vs {}: +{} ={} -{}, elo {elo:+.1} +/- {error:.1}",
+            options.players[a].name,
+            options.players[b].name,
+            result.score.wins,
+            result.score.draws,
+            result.score.losses
+        );
+        if let Some(sprt) = &options.sprt {
+            write!(line, ", LLR {:.2}", sprt.llr(&result.penta)).unwrap();
+        }
+        println!("{line}");
+    }
+    println!("{elapsed:.1}s elapsed");
+}
+
+/// Play every pairing of the tournament, reporting the standings as it goes.
+#[allow(clippy::too_many_lines)]
+pub fn run_tournament(options: &TournamentOptions) -> anyhow::Result<()> {
+    let players = &options.players;
+    if players.len() < 2 {
+        bail!("a tournament needs at least two players");
+    }
+    for (i, player) in players.iter().enumerate() {
+        if players[..i].iter().any(|p| p.name == player.name) {
+            bail!("more than one player is named {}", player.name);
+        }
+    }
+    let n = players.len();
+    let pairings = if options.gauntlet {
+        (1..n).map(|j| (0, j)).collect::<Vec<_>>()
+    } else {
+        (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .collect::<Vec<_>>()
+    };
+    let seed = options.seed.unwrap_or_else(fresh_seed);
+    let adjudicator = Adjudicator::new(options.resign, options.draw);
+
+    println!(
+        "{} of {n} players: {} pairings of {} game pairs, with seed {seed}",
+        if options.gauntlet {
+            "Gauntlet"
+        } else {
+            "Round-robin"
+        },
+        pairings.len(),
+        options.rounds
+    );
+    let contestants = players
+        .iter()
+        .map(load_contestant)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut pgn_file = options
+        .pgn
+        .as_ref()
+        .map(|path| {
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))
+        })
+        .transpose()?;
+
+    let concluded = pairings
+        .iter()
+        .map(|_| AtomicBool::new(false))
+        .collect::<Vec<_>>();
+    let mut results = pairings
+        .iter()
+        .map(|_| Results::default())
+        .collect::<Vec<_>>();
+    let start = Instant::now();
+    // every pairing plays the same opening in each round.
+    let round_and_pairing = |job: usize| (job / pairings.len(), job % pairings.len());
+    let mut finished = 0;
+    run_pairs(
+        options.threads,
+        options.rounds * pairings.len(),
+        |job| concluded[round_and_pairing(job).1].load(Ordering::Relaxed),
+        |job| {
+            let (round, pairing) = round_and_pairing(job);
+            let (a, b) = pairings[pairing];
+            let opening = random_opening(&mut StdRng::seed_from_u64(seed_for(seed, round as u64)));
+            let (a, b) = (&contestants[a], &contestants[b]);
+            let first = play_game(&opening, [a, b], options.tc, &adjudicator)?;
+            let second = play_game(&opening, [b, a], options.tc, &adjudicator)?;
+            Ok((opening, [first, second]))
+        },
+        |job, (opening, games)| {
+            let (round, pairing) = round_and_pairing(job);
+            let (a, b) = pairings[pairing];
+            let first = white_half_points(games[0].outcome);
+            let second = 2 - white_half_points(games[1].outcome);
+            let result = &mut results[pairing];
+            result.score.record(first);
+            result.score.record(second);
+            result.penta.record(first + second);
+
+            if let Some(file) = &mut pgn_file {
+                let names = [players[a].name.as_str(), players[b].name.as_str()];
+                let mut pgn = String::new();
+                write_game(&mut pgn, round + 1, names, options.tc, &opening, &games[0])?;
+                write_game(
+                    &mut pgn,
+                    round + 1,
+                    [names[1], names[0]],
+                    options.tc,
+                    &opening,
+                    &games[1],
+                )?;
+                file.write_all(pgn.as_bytes())
+                    .with_context(|| "Failed to write to the PGN file")?;
+            }
+
+            if let Some(sprt) = &options.sprt {
+                if let Some(verdict) = sprt.verdict(&result.penta) {
+                    if !concluded[pairing].swap(true, Ordering::Relaxed) {
+                        let (elo, error) = result.penta.elo();
+                        println!(
+                            "{} vs {}: {verdict:?} accepted after {} games, elo {elo:+.1} +/- {error:.1}, LLR {:.2}",
+                            players[a].name,
+                            players[b].name,
+                            result.score.games(),
+                            sprt.llr(&result.penta)
+                        );
+                    }
+                }
+            }
+
+            finished += 1;
+            if finished % REPORT_INTERVAL == 0 {
+                report(options, &pairings, &results, start.elapsed().as_secs_f64());
+            }
+            Ok(ControlFlow::Continue(()))
+        },
+    )?;
+
+    println!("Final standings:");
+    report(options, &pairings, &results, start.elapsed().as_secs_f64());
+    if let Some(path) = &options.pgn {
+        println!("Wrote games to {}", path.display());
+    }
+
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn player_specs() {
+        use super::{EngineSpec, PlayerSpec};
+        use std::path::PathBuf;
+
+        let dev = "params=tuned/dev.json".parse::<PlayerSpec>().unwrap();
+        assert_eq!(dev.name, "dev");
+        assert_eq!(
+            dev.engine,
+            EngineSpec::Internal {
+                params: Some(PathBuf::from("tuned/dev.json")),
+                net: None
+            }
+        );
+        assert_eq!(
+            "name=base".parse::<PlayerSpec>().unwrap().name,
+            "base".to_string()
+        );
+
+        let sf = "cmd=/usr/bin/stockfish,option.Threads=1,option.Hash=16"
+            .parse::<PlayerSpec>()
+            .unwrap();
+        assert_eq!(sf.name, "stockfish");
+        assert_eq!(
+            sf.engine,
+            EngineSpec::External {
+                path: PathBuf::from("/usr/bin/stockfish"),
+                options: vec![
+                    ("Threads".to_string(), "1".to_string()),
+                    ("Hash".to_string(), "16".to_string())
+                ]
+            }
+        );
+
+        assert!("cmd=sf,net=big.nnue".parse::<PlayerSpec>().is_err());
+        assert!("option.Hash=16".parse::<PlayerSpec>().is_err());
+        assert!("name=x,colour=red".parse::<PlayerSpec>().is_err());
+        assert!("".parse::<PlayerSpec>().is_err());
+    }
+}
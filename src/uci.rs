@@ -6,59 +6,99 @@
     clippy::unimplemented
 )]
 
+pub mod output;
+mod profiles;
+pub mod session;
+
 use std::{
+    collections::hash_map::RandomState,
     error::Error,
     fmt::{self, Display},
+    hash::{BuildHasher, Hasher},
     io::Write,
     num::{ParseFloatError, ParseIntError},
+    path::{Path, PathBuf},
     str::{FromStr, ParseBoolError},
     sync::{
         atomic::{self, AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
         mpsc, Mutex, Once,
     },
-    time::Instant,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{anyhow, bail, Context};
+use session::{Analysis, Session};
 
 use crate::{
+    adjudication::{Adjudicator, DrawRule, ResignRule},
     bench::BENCH_POSITIONS,
     chess::{
-        board::{movegen::MoveList, Board},
-        piece::Colour,
+        board::{
+            history::HISTORY_KIND,
+            movegen::{
+                bishop_attacks, magic_bishop_attacks, magic_rook_attacks, pext_enabled,
+                rook_attacks, MoveList,
+            },
+            render::BoardStyle,
+            Board,
+        },
+        chessmove::Move,
+        piece::{Colour, Piece, PieceType},
+        squareset::SquareSet,
+        types::Square,
         CHESS960,
     },
-    cuckoo,
+    epd::Epd,
     errors::{FenParseError, MoveParseError},
-    evaluation::{is_game_theoretic_score, is_mate_score, MATE_SCORE, TB_WIN_SCORE},
+    evaluation::{
+        is_game_theoretic_score, is_mate_score, Value, EVAL_BLEND, MATE_SCORE, STYLE, STYLE_MAX,
+        TB_WIN_SCORE,
+    },
+    explain,
+    hardware::{Hardware, Recommendation},
     nnue::{
         self,
-        network::{self, NNUEParams},
+        network::{self, small, NNUEParams},
     },
-    perft,
-    search::{parameters::Config, LMTable},
+    notation, perft,
+    rng::XorShiftState,
+    search::{parameters::Config, pv::PVariation, select_best, variety::RootVariety, LMTable},
     searchinfo::SearchInfo,
-    tablebases, term,
+    stack, tablebases, term,
     threadlocal::ThreadData,
-    timemgmt::SearchLimit,
+    threadpool::ThreadPool,
+    timemgmt::{Pacing, SearchLimits, DEFAULT_MATE_STOP_ITERATIONS},
     transpositiontable::TT,
-    util::{MAX_PLY, MEGABYTE},
+    util::{MAX_DEPTH, MAX_PLY, MEGABYTE},
     NAME, VERSION,
 };
 
 const UCI_DEFAULT_HASH_MEGABYTES: usize = 16;
 const UCI_MAX_HASH_MEGABYTES: usize = 1_048_576;
 const UCI_MAX_THREADS: usize = 512;
+const UCI_MAX_NODESTIME: u64 = 10_000;
+const UCI_MAX_MOVE_TIME_JITTER: u64 = 50;
+const UCI_MAX_REPLY_DELAY: u64 = 60_000;
+const UCI_MAX_INSTANT_MOVE_BUDGET: u64 = 10_000;
+const UCI_MAX_MATE_STOP_ITERATIONS: usize = 100;
+const UCI_MAX_ROOT_VARIETY: i32 = 50;
+const UCI_MAX_ROOT_VARIETY_MOVES: usize = 100;
+const UCI_MAX_SPARRING_MARGIN: i32 = 100;
 
 static STDIN_READER_THREAD_KEEP_RUNNING: AtomicBool = AtomicBool::new(true);
 pub static QUIT: AtomicBool = AtomicBool::new(false);
 pub static GO_MATE_MAX_DEPTH: AtomicUsize = AtomicUsize::new(MAX_PLY);
 pub static PRETTY_PRINT: AtomicBool = AtomicBool::new(true);
+pub static SEARCH_STATS: AtomicBool = AtomicBool::new(false);
+pub static EXPLAIN_MOVES: AtomicBool = AtomicBool::new(false);
 pub static SYZYGY_PROBE_LIMIT: AtomicU8 = AtomicU8::new(6);
 pub static SYZYGY_PROBE_DEPTH: AtomicI32 = AtomicI32::new(1);
 pub static SYZYGY_PATH: Mutex<String> = Mutex::new(String::new());
 pub static SYZYGY_ENABLED: AtomicBool = AtomicBool::new(false);
 pub static CONTEMPT: AtomicI32 = AtomicI32::new(0);
+pub static SPARRING_MARGIN: AtomicI32 = AtomicI32::new(0);
+/// The time at which the stdin reader last received a command.
+static LAST_COMMAND_RECEIVED: Mutex<Option<Instant>> = Mutex::new(None);
 
 #[derive(Debug, PartialEq, Eq)]
 enum UciError {
@@ -119,10 +159,47 @@ impl Display for UciError {
     }
 }
 
+// position epd <record> [moves ...]
+fn parse_position_epd(text: &str, pos: &mut Board) -> anyhow::Result<Option<Epd>> {
+    let record = text
+        .trim_start()
+        .trim_start_matches("position")
+        .trim_start()
+        .trim_start_matches("epd");
+    // moves can only follow the last operation.
+    let ops_end = record.rfind(';').map_or(0, |i| i + 1);
+    let (record, moves) = record[ops_end..].find("moves").map_or((record, ""), |i| {
+        (
+            &record[..ops_end + i],
+            &record[ops_end + i + "moves".len()..],
+        )
+    });
+    let epd = Epd::parse(record.trim())?;
+    pos.set_from_fen(&epd.fen)
+        .with_context(|| format!("Failed to set fen {}", epd.fen))?;
+    // check the moves now, rather than when we come to search.
+    epd.moves(pos, "bm")?;
+    epd.moves(pos, "am")?;
+    let mut played = false;
+    for san in moves.split_ascii_whitespace() {
+        pos.zero_height();
+        let m = pos.parse_uci(san)?;
+        pos.make_move_simple(m);
+        played = true;
+    }
+    pos.zero_height();
+    Ok((!played).then_some(epd))
+}
+
 // position fen
 // position startpos
+// position epd
+// position frc 518
+// position dfrc 518518
+// position random960
 // ... moves e2e4 e7e5 b7b8q
-fn parse_position(text: &str, pos: &mut Board) -> anyhow::Result<()> {
+// Returns the EPD record if one was given and no moves were played from it.
+fn parse_position(text: &str, pos: &mut Board) -> anyhow::Result<Option<Epd>> {
     let mut parts = text.split_ascii_whitespace();
     let command = parts.next().with_context(|| {
         UciError::UnexpectedCommandTermination("No command in parse_position".into())
@@ -133,6 +210,9 @@ fn parse_position(text: &str, pos: &mut Board) -> anyhow::Result<()> {
     let determiner = parts.next().with_context(|| {
         UciError::UnexpectedCommandTermination("No determiner after \"position\"".into())
     })?;
+    if determiner == "epd" {
+        return parse_position_epd(text, pos);
+    }
     if determiner == "startpos" {
         pos.set_startpos();
         let moves = parts.next(); // skip "moves"
@@ -150,6 +230,21 @@ fn parse_position(text: &str, pos: &mut Board) -> anyhow::Result<()> {
             .with_context(|| format!("Failed to parse {index} as FRC index"))?;
         anyhow::ensure!(index < 960, "FRC index can be at most 959 but got {index}");
         pos.set_frc_idx(index);
+        output::send(format!("info string fen {pos}"));
+    } else if determiner == "random960" {
+        // every random960 draws a new start, so moves sent after one would be played
+        // from a different board than the GUI's. the GUI continues the game with the
+        // index we report, via "position frc <index> moves ...".
+        if parts.next().is_some() {
+            bail!(UciError::InvalidFormat(
+                "Nothing may follow \"random960\": continue the game with \"position frc <index> moves ...\"".into()
+            ));
+        }
+        // a fresh hasher is randomly keyed, which is all the randomness we need.
+        let index = RandomState::new().build_hasher().finish() % 960;
+        #[allow(clippy::cast_possible_truncation)]
+        pos.set_frc_idx(index as usize);
+        output::send(format!("info string frc {index} fen {pos}"));
     } else if determiner == "dfrc" {
         let Some(index) = parts.next() else {
             bail!("Expected an index value to follow \"dfrc\"");
@@ -162,6 +257,7 @@ fn parse_position(text: &str, pos: &mut Board) -> anyhow::Result<()> {
             "DFRC index can be at most 921599 but got {index}"
         );
         pos.set_dfrc_idx(index);
+        output::send(format!("info string fen {pos}"));
     } else {
         if determiner != "fen" {
             bail!(UciError::InvalidFormat(format!(
@@ -179,7 +275,23 @@ fn parse_position(text: &str, pos: &mut Board) -> anyhow::Result<()> {
         pos.set_from_fen(&fen)
             .with_context(|| format!("Failed to set fen {fen}"))?;
     }
-    for san in parts {
+    if matches!(determiner, "frc" | "dfrc") {
+        let moves = parts.next(); // skip "moves"
+        if !(matches!(moves, Some("moves") | None)) {
+            bail!(UciError::InvalidFormat(format!(
+                "Expected either \"moves\" or no content to follow the {determiner} position."
+            )));
+        }
+    }
+    play_uci_moves(parts, pos)?;
+    Ok(None)
+}
+
+fn play_uci_moves<'a>(
+    moves: impl IntoIterator<Item = &'a str>,
+    pos: &mut Board,
+) -> anyhow::Result<()> {
+    for san in moves {
         pos.zero_height(); // stuff breaks really hard without this lmao
         let m = pos.parse_uci(san)?;
         pos.make_move_simple(m);
@@ -188,19 +300,162 @@ fn parse_position(text: &str, pos: &mut Board) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn parse_go(text: &str, pos: &Board) -> anyhow::Result<SearchLimit> {
-    #![allow(clippy::too_many_lines)]
+/// The last `position` command and the board it left behind, so that when a GUI sends
+/// the game again with a move or two on the end, only the new moves need playing.
+struct LastPosition {
+    tokens: Vec<String>,
+    key: u64,
+    ply: usize,
+    chess960: bool,
+}
+
+impl LastPosition {
+    fn new(text: &str, pos: &Board) -> Option<Self> {
+        let mut tokens = text
+            .split_ascii_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        // EPD positions carry their record along, and random960 differs every time.
+        if matches!(
+            tokens.get(1).map(String::as_str),
+            Some("epd" | "random960") | None
+        ) {
+            return None;
+        }
+        if !tokens.iter().any(|t| t == "moves") {
+            tokens.push("moves".into());
+        }
+        Some(Self {
+            tokens,
+            key: pos.zobrist_key(),
+            ply: pos.ply(),
+            chess960: CHESS960.load(Ordering::SeqCst),
+        })
+    }
 
-    let mut depth: Option<i32> = None;
-    let mut moves_to_go: Option<u64> = None;
-    let mut movetime: Option<u64> = None;
-    let mut clocks: [Option<i64>; 2] = [None, None];
-    let mut incs: [Option<i64>; 2] = [None, None];
-    let mut nodes: Option<u64> = None;
-    let mut limit = SearchLimit::Infinite;
-    let mut ponder = false;
+    /// The moves that `text` adds on to this position, if that's all it does and the
+    /// board is still where this position left it.
+    fn extra_moves<'a>(&self, text: &'a str, pos: &Board) -> Option<Vec<&'a str>> {
+        if pos.zobrist_key() != self.key
+            || pos.ply() != self.ply
+            || CHESS960.load(Ordering::SeqCst) != self.chess960
+        {
+            return None;
+        }
+        let mut tokens = text.split_ascii_whitespace();
+        for expected in &self.tokens {
+            if tokens.next() != Some(expected.as_str()) {
+                return None;
+            }
+        }
+        Some(tokens.collect())
+    }
+}
 
+// put Q d4
+// remove d4
+// side b
+fn parse_edit(text: &str, pos: &mut Board) -> anyhow::Result<()> {
     let mut parts = text.split_ascii_whitespace();
+    let square = |part: Option<&str>| -> anyhow::Result<Square> {
+        let part = part.with_context(|| "Expected a square")?;
+        part.parse()
+            .map_err(|_| anyhow!("Failed to parse {part} as a square"))
+    };
+    match parts.next() {
+        Some("put") => {
+            let symbol = parts.next().with_context(|| "Expected a piece to put")?;
+            let piece = match *symbol.as_bytes() {
+                [c] => PieceType::from_symbol(c.to_ascii_uppercase()).map(|piece_type| {
+                    let colour = if c.is_ascii_uppercase() {
+                        Colour::White
+                    } else {
+                        Colour::Black
+                    };
+                    Piece::new(colour, piece_type)
+                }),
+                _ => None,
+            }
+            .with_context(|| format!("Failed to parse {symbol} as a piece"))?;
+            pos.put_piece(square(parts.next())?, piece)
+        }
+        Some("remove") => {
+            pos.remove_piece(square(parts.next())?);
+            Ok(())
+        }
+        Some("side") => {
+            let side = match parts.next() {
+                Some("w") => Colour::White,
+                Some("b") => Colour::Black,
+                other => bail!("Expected \"w\" or \"b\" to follow \"side\", got {other:?}"),
+            };
+            pos.set_side_to_move(side);
+            Ok(())
+        }
+        _ => bail!(UciError::InvalidFormat(
+            "Expected put, remove, or side".into()
+        )),
+    }
+}
+
+// explain
+// explain depth 6
+// explain tga heatmap.tga
+fn parse_explain(text: &str) -> anyhow::Result<(i32, Option<PathBuf>)> {
+    let mut parts = text.split_ascii_whitespace().skip(1);
+    let mut depth = 0;
+    let mut tga = None;
+    while let Some(part) = parts.next() {
+        match part {
+            "depth" => {
+                depth = part_parse("depth", parts.next())?;
+                if !(0..=MAX_DEPTH).contains(&depth) {
+                    bail!(UciError::IllegalValue(format!(
+                        "depth must be between 0 and {MAX_DEPTH}"
+                    )));
+                }
+            }
+            "tga" => {
+                let path = parts
+                    .next()
+                    .with_context(|| UciError::InvalidFormat("nothing after \"tga\"".into()))?;
+                tga = Some(PathBuf::from(path));
+            }
+            other => bail!(UciError::InvalidFormat(format!(
+                "unknown term after \"explain\": {other}"
+            ))),
+        }
+    }
+    Ok((depth, tga))
+}
+
+/// Read a `bench` or `benchfull` command, of the form `bench [depth] [hash <MB>] [threads <N>]`,
+/// where the depth may also be given as `depth <N>`.
+fn parse_bench(text: &str) -> anyhow::Result<(&str, BenchOptions)> {
+    let mut parts = text.split_ascii_whitespace();
+    let benchcmd = parts.next().unwrap_or("bench");
+    let mut options = BenchOptions::default();
+    while let Some(part) = parts.next() {
+        match part {
+            "depth" => options.depth = Some(part_parse("depth", parts.next())?),
+            "hash" => options.hash_mb = Some(part_parse("hash", parts.next())?),
+            "threads" => options.threads = Some(part_parse("threads", parts.next())?),
+            depth if options.depth.is_none() && depth.parse::<usize>().is_ok() => {
+                options.depth = Some(part_parse("bench", Some(depth))?);
+            }
+            other => bail!(UciError::InvalidFormat(format!(
+                "unknown term after \"{benchcmd}\": {other}"
+            ))),
+        }
+    }
+    Ok((benchcmd, options))
+}
+
+/// Read a `go` command. The parts may come in any order, and each may be left out.
+fn parse_go(text: &str, pos: &Board) -> anyhow::Result<SearchLimits> {
+    let mut limits = SearchLimits::default();
+
+    let mut parts = text.split_ascii_whitespace().peekable();
     let command = parts
         .next()
         .with_context(|| UciError::UnexpectedCommandTermination("No command in parse_go".into()))?;
@@ -211,68 +466,49 @@ fn parse_go(text: &str, pos: &Board) -> anyhow::Result<SearchLimit> {
     while let Some(part) = parts.next() {
         match part {
             "depth" => {
-                depth = Some(
+                limits.depth = Some(
                     part_parse("depth", parts.next())
                         .with_context(|| "Failed to parse depth part.")?,
                 );
             }
-            "movestogo" => moves_to_go = Some(part_parse("movestogo", parts.next())?),
-            "movetime" => movetime = Some(part_parse("movetime", parts.next())?),
-            "wtime" => clocks[pos.turn()] = Some(part_parse("wtime", parts.next())?),
-            "btime" => clocks[pos.turn().flip()] = Some(part_parse("btime", parts.next())?),
-            "winc" => incs[pos.turn()] = Some(part_parse("winc", parts.next())?),
-            "binc" => incs[pos.turn().flip()] = Some(part_parse("binc", parts.next())?),
-            "infinite" => limit = SearchLimit::Infinite,
-            "mate" => {
-                let mate_distance: usize = part_parse("mate", parts.next())?;
-                let ply = mate_distance * 2; // gives padding when we're giving mate, but whatever
-                GO_MATE_MAX_DEPTH.store(ply, Ordering::SeqCst);
-                limit = SearchLimit::Mate { ply };
+            "movestogo" => limits.movestogo = Some(part_parse("movestogo", parts.next())?),
+            "movetime" => limits.movetime = Some(part_parse("movetime", parts.next())?),
+            "wtime" => limits.wtime = Some(part_parse("wtime", parts.next())?),
+            "btime" => limits.btime = Some(part_parse("btime", parts.next())?),
+            "winc" => limits.winc = Some(part_parse("winc", parts.next())?),
+            "binc" => limits.binc = Some(part_parse("binc", parts.next())?),
+            "infinite" => limits.infinite = true,
+            "mate" => limits.mate = Some(part_parse("mate", parts.next())?),
+            "nodes" => limits.nodes = Some(part_parse("nodes", parts.next())?),
+            "ponder" => limits.ponder = true,
+            "searchmoves" => {
+                // the moves run until the next part that isn't one.
+                limits.searchmoves.clear();
+                while let Some(m) = parts.peek().and_then(|m| pos.parse_uci(m).ok()) {
+                    limits.searchmoves.push(m);
+                    parts.next();
+                }
+                if limits.searchmoves.is_empty() {
+                    bail!(UciError::InvalidFormat(
+                        "no legal moves after \"searchmoves\"".into()
+                    ));
+                }
             }
-            "nodes" => nodes = Some(part_parse("nodes", parts.next())?),
-            "ponder" => ponder = true,
             other => bail!(UciError::InvalidFormat(format!("Unknown term: {other}"))),
         }
     }
-    if !matches!(limit, SearchLimit::Mate { .. }) {
-        GO_MATE_MAX_DEPTH.store(MAX_PLY, Ordering::SeqCst);
-    }
-
-    if let Some(movetime) = movetime {
-        limit = SearchLimit::Time(movetime);
-    }
-    if let Some(depth) = depth {
-        limit = SearchLimit::Depth(depth);
-    }
 
-    if let [Some(our_clock), Some(their_clock)] = clocks {
-        let [our_inc, their_inc] = [incs[0].unwrap_or(0), incs[1].unwrap_or(0)];
-        let our_clock: u64 = our_clock.try_into().unwrap_or(0);
-        let their_clock: u64 = their_clock.try_into().unwrap_or(0);
-        let our_inc: u64 = our_inc.try_into().unwrap_or(0);
-        let their_inc: u64 = their_inc.try_into().unwrap_or(0);
-        limit = SearchLimit::Dynamic {
-            our_clock,
-            their_clock,
-            our_inc,
-            their_inc,
-            moves_to_go,
-        };
-    } else if clocks.iter().chain(incs.iter()).any(Option::is_some) {
+    let (our_clock, their_clock, incs) = match pos.turn() {
+        Colour::White => (limits.wtime, limits.btime, [limits.winc, limits.binc]),
+        Colour::Black => (limits.btime, limits.wtime, [limits.binc, limits.winc]),
+    };
+    if our_clock.is_none() && (their_clock.is_some() || incs.iter().any(Option::is_some)) {
         bail!(UciError::InvalidFormat(
-            "at least one of [wtime, btime, winc, binc] provided, but not all.".into(),
+            "clock information provided, but not the clock of the side to move.".into(),
         ));
     }
 
-    if let Some(nodes) = nodes {
-        limit = SearchLimit::Nodes(nodes);
-    }
-
-    if ponder {
-        limit = limit.to_pondering();
-    }
-
-    Ok(limit)
+    Ok(limits)
 }
 
 fn part_parse<T>(target: &str, next_part: Option<&str>) -> anyhow::Result<T>
@@ -294,6 +530,229 @@ struct SetOptions {
     pub search_config: Config,
     pub hash_mb: usize,
     pub threads: usize,
+    pub nodestime: u64,
+    pub pacing: Pacing,
+    pub instant_move_budget: u64,
+    pub mate_stop_iterations: usize,
+    pub variety: RootVariety,
+    pub adjudicator: Adjudicator,
+    pub pin_threads: bool,
+    pub analyse_mode: bool,
+    pub params_file: Option<ParamsWatch>,
+}
+
+/// The kind of a UCI option, with what a GUI needs to know to build a control for it.
+enum OptionKind {
+    Check {
+        default: bool,
+    },
+    Spin {
+        default: i64,
+        min: i64,
+        max: i64,
+    },
+    String,
+    Combo {
+        default: &'static str,
+        vars: &'static [&'static str],
+    },
+    Button,
+}
+
+/// An option that we advertise in response to `uci`, other than the search parameters.
+struct UciOption {
+    name: &'static str,
+    kind: OptionKind,
+}
+
+const fn check(name: &'static str, default: bool) -> UciOption {
+    UciOption {
+        name,
+        kind: OptionKind::Check { default },
+    }
+}
+
+const fn spin(name: &'static str, default: i64, min: i64, max: i64) -> UciOption {
+    UciOption {
+        name,
+        kind: OptionKind::Spin { default, min, max },
+    }
+}
+
+const fn string(name: &'static str) -> UciOption {
+    UciOption {
+        name,
+        kind: OptionKind::String,
+    }
+}
+
+#[allow(clippy::cast_possible_wrap)]
+const UCI_OPTIONS: [UciOption; 33] = [
+    spin(
+        "Hash",
+        UCI_DEFAULT_HASH_MEGABYTES as i64,
+        1,
+        UCI_MAX_HASH_MEGABYTES as i64,
+    ),
+    spin("Threads", 1, 1, UCI_MAX_THREADS as i64),
+    check("ThreadAffinity", false),
+    check("PrettyPrint", false),
+    check("SearchStats", false),
+    check("ExplainMoves", false),
+    string("SyzygyPath"),
+    spin("SyzygyProbeLimit", 6, 0, 6),
+    spin("SyzygyProbeDepth", 1, 1, 100),
+    spin("Contempt", 0, -10000, 10000),
+    spin("Style", 0, -STYLE_MAX as i64, STYLE_MAX as i64),
+    check("Ponder", false),
+    check("UCI_Chess960", false),
+    check("UCI_AnalyseMode", false),
+    check("EvalBlend", false),
+    string("SmallNetFile"),
+    string("ParamsFile"),
+    spin("nodestime", 0, 0, UCI_MAX_NODESTIME as i64),
+    spin("MoveTimeJitter", 0, 0, UCI_MAX_MOVE_TIME_JITTER as i64),
+    spin("MinReplyDelay", 0, 0, UCI_MAX_REPLY_DELAY as i64),
+    spin("MaxReplyDelay", 0, 0, UCI_MAX_REPLY_DELAY as i64),
+    spin(
+        "InstantMoveBudget",
+        0,
+        0,
+        UCI_MAX_INSTANT_MOVE_BUDGET as i64,
+    ),
+    spin(
+        "MateStopIterations",
+        DEFAULT_MATE_STOP_ITERATIONS as i64,
+        0,
+        UCI_MAX_MATE_STOP_ITERATIONS as i64,
+    ),
+    spin("RootVariety", 0, 0, UCI_MAX_ROOT_VARIETY as i64),
+    spin("RootVarietyMoves", 10, 0, UCI_MAX_ROOT_VARIETY_MOVES as i64),
+    spin("RootVarietySeed", 0, 0, u32::MAX as i64),
+    spin("SparringMargin", 0, 0, UCI_MAX_SPARRING_MARGIN as i64),
+    string("Resign"),
+    string("DrawOffer"),
+    UciOption {
+        name: "Profile",
+        kind: OptionKind::Combo {
+            default: "default",
+            vars: &profiles::BUILTIN_NAMES,
+        },
+    },
+    string("ProfilesFile"),
+    string("SessionFile"),
+    UciOption {
+        name: "AutoConfig",
+        kind: OptionKind::Button,
+    },
+];
+
+impl Display for UciOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+        match self.kind {
+            OptionKind::Check { default } => write!(f, "check default {default}"),
+            OptionKind::Spin { default, min, max } => {
+                write!(f, "spin default {default} min {min} max {max}")
+            }
+            OptionKind::String => write!(f, "string default <empty>"),
+            OptionKind::Combo { default, vars } => {
+                write!(f, "combo default {default}")?;
+                vars.iter().try_for_each(|var| write!(f, " var {var}"))
+            }
+            OptionKind::Button => write!(f, "button"),
+        }
+    }
+}
+
+/// Clamp `value` into `[min, max]`, telling the GUI if it had to.
+fn clamp_option<T: PartialOrd + Display + Copy>(name: &str, value: T, min: T, max: T) -> T {
+    let clamped = if value < min {
+        min
+    } else if value > max {
+        max
+    } else {
+        value
+    };
+    if clamped != value {
+        output::send(format!(
+            "info string {name} value {value} is outside of [{min}, {max}], using {clamped}"
+        ));
+    }
+    clamped
+}
+
+impl UciOption {
+    fn value<'v>(&self, value: Option<&'v str>) -> anyhow::Result<&'v str> {
+        value.with_context(|| {
+            UciError::UnexpectedCommandTermination(format!(
+                "no value given for option {}",
+                self.name
+            ))
+        })
+    }
+
+    fn check_value(&self, value: Option<&str>) -> anyhow::Result<bool> {
+        let value = self.value(value)?;
+        value.parse().with_context(|| {
+            UciError::IllegalValue(format!(
+                "{} value must be true or false, got \"{value}\"",
+                self.name
+            ))
+        })
+    }
+
+    /// The value given for a spin option, clamped into its range.
+    fn spin_value<T: TryFrom<i64>>(&self, value: Option<&str>) -> anyhow::Result<T> {
+        let OptionKind::Spin { min, max, .. } = self.kind else {
+            bail!(UciError::InternalError(format!(
+                "{} is not a spin option",
+                self.name
+            )));
+        };
+        let value = self.value(value)?;
+        let parsed: i64 = value.parse().with_context(|| {
+            UciError::IllegalValue(format!(
+                "{} value must be an integer, got \"{value}\"",
+                self.name
+            ))
+        })?;
+        let clamped = clamp_option(self.name, parsed, min, max);
+        T::try_from(clamped).map_err(|_| {
+            anyhow!(UciError::InternalError(format!(
+                "{} value {clamped} doesn't fit",
+                self.name
+            )))
+        })
+    }
+}
+
+/// Set a search parameter, clamping the value into the parameter's tuning range.
+fn set_search_parameter(
+    config: &mut Config,
+    id: &str,
+    value: Option<&str>,
+    (min, max): (f64, f64),
+) -> anyhow::Result<()> {
+    let value = value.with_context(|| {
+        UciError::UnexpectedCommandTermination(format!("no value given for option {id}"))
+    })?;
+    let parsed: f64 = value.parse().with_context(|| {
+        UciError::IllegalValue(format!("{id} value must be a number, got \"{value}\""))
+    })?;
+    // only reformat the value if we have to clamp it, so that a fractional value
+    // for an integer parameter is still refused rather than silently accepted.
+    let value = if (min..=max).contains(&parsed) {
+        value.to_string()
+    } else {
+        clamp_option(id, parsed, min, max).to_string()
+    };
+    for (param_name, mut parser) in config.ids_with_parsers() {
+        if param_name == id {
+            return parser(&value).map_err(|e| anyhow!(UciError::InvalidFormat(e.to_string())));
+        }
+    }
+    bail!(UciError::InternalError(format!("no parser for {id}")))
 }
 
 #[allow(clippy::too_many_lines)]
@@ -315,71 +774,72 @@ fn parse_setoption(text: &str, pre_config: SetOptions) -> anyhow::Result<SetOpti
             "unexpected character after \"setoption\", expected \"name\", got \"{name_part}\". Did you mean \"setoption name {name_part}\"?"
         )));
     }
-    let opt_name = parts.next().with_context(|| {
-        UnexpectedCommandTermination("no option name given after \"setoption name\"".into())
-    })?;
-    let Some(value_part) = parts.next() else {
-        bail!(UciError::InvalidFormat(
-            "no \"value\" after \"setoption name {opt_name}\"".into()
+    // names and string values may both contain spaces, so the name runs up to "value",
+    // and the value runs to the end of the line.
+    let rest = parts.collect::<Vec<_>>();
+    let (name_parts, value_parts) = rest
+        .iter()
+        .position(|&p| p == "value")
+        .map_or((&rest[..], None), |i| (&rest[..i], Some(&rest[i + 1..])));
+    if name_parts.is_empty() {
+        bail!(UnexpectedCommandTermination(
+            "no option name given after \"setoption name\"".into()
         ));
-    };
-    if value_part != "value" {
-        bail!(UciError::InvalidFormat(format!(
-            "unexpected character after \"setoption name {opt_name}\", expected \"value\", got \"{value_part}\". Did you mean \"setoption name {opt_name} value {value_part}\"?"
-        )));
     }
-    let opt_value = parts.next().with_context(|| {
-        UnexpectedCommandTermination(format!(
-            "no option value given after \"setoption name {opt_name} value\""
-        ))
-    })?;
+    let opt_name = name_parts.join(" ");
+    let opt_value = value_parts.map(|v| v.join(" ")).filter(|v| !v.is_empty());
+    let opt_value = opt_value.as_deref();
+
+    // option names are case-insensitive.
     let mut out = pre_config;
-    let id_parser_pairs = out.search_config.ids_with_parsers();
-    let mut found_match = false;
-    for (param_name, mut parser) in id_parser_pairs {
-        if param_name == opt_name {
-            let res = parser(opt_value);
-            if let Err(e) = res {
-                bail!(UciError::InvalidFormat(e.to_string()));
-            }
-            found_match = true;
-            break;
-        }
-    }
-    if found_match {
+    let parameter = out
+        .search_config
+        .base_config()
+        .into_iter()
+        .find(|(id, ..)| id.eq_ignore_ascii_case(&opt_name))
+        .map(|(id, _, min, max, _)| (id.to_string(), min, max));
+    if let Some((id, min, max)) = parameter {
+        set_search_parameter(&mut out.search_config, &id, opt_value, (min, max))?;
         return Ok(out);
     }
-    match opt_name {
-        "Hash" => {
-            let value: usize = opt_value.parse()?;
-            if !(value > 0 && value <= UCI_MAX_HASH_MEGABYTES) {
-                // "Hash value must be between 1 and {UCI_MAX_HASH_MEGABYTES}"
-                bail!(UciError::IllegalValue(format!(
-                    "Hash value must be between 1 and {UCI_MAX_HASH_MEGABYTES}"
-                )));
-            }
-            out.hash_mb = value;
-        }
-        "Threads" => {
-            let value: usize = opt_value.parse()?;
-            if !(value > 0 && value <= UCI_MAX_THREADS) {
-                // "Threads value must be between 1 and {UCI_MAX_THREADS}"
-                bail!(UciError::IllegalValue(format!(
-                    "Threads value must be between 1 and {UCI_MAX_THREADS}"
-                )));
-            }
-            out.threads = value;
+    let Some(option) = UCI_OPTIONS
+        .iter()
+        .find(|o| o.name.eq_ignore_ascii_case(&opt_name))
+    else {
+        output::send(format!(
+            "info string ignoring unknown option {opt_name}, type \"uci\" for a list of options"
+        ));
+        return Ok(out);
+    };
+
+    match option.name {
+        "AutoConfig" => {
+            let Recommendation { threads, hash_mb } =
+                Hardware::detect().recommend(UCI_MAX_THREADS, UCI_MAX_HASH_MEGABYTES);
+            output::send(format!(
+                "info string autoconfig set Threads {threads} Hash {hash_mb}"
+            ));
+            out.hash_mb = hash_mb;
+            out.threads = threads;
         }
-        "PrettyPrint" => {
-            let value: bool = opt_value.parse()?;
-            PRETTY_PRINT.store(value, Ordering::SeqCst);
+        "Hash" => out.hash_mb = option.spin_value(opt_value)?,
+        "Threads" => out.threads = option.spin_value(opt_value)?,
+        "ThreadAffinity" => out.pin_threads = option.check_value(opt_value)?,
+        "PrettyPrint" => PRETTY_PRINT.store(option.check_value(opt_value)?, Ordering::SeqCst),
+        "SearchStats" => SEARCH_STATS.store(option.check_value(opt_value)?, Ordering::SeqCst),
+        "ExplainMoves" => EXPLAIN_MOVES.store(option.check_value(opt_value)?, Ordering::SeqCst),
+        "Ponder" => {
+            // we can always ponder, so there's nothing to set up.
+            option.check_value(opt_value)?;
         }
         "SyzygyPath" => {
-            let path = opt_value.to_string();
-            tablebases::probe::init(&path);
+            let path = opt_value.filter(|&p| p != "<empty>").unwrap_or_default();
+            if !path.is_empty() {
+                tablebases::probe::init(path);
+            }
             if let Ok(mut lock) = SYZYGY_PATH.lock() {
-                *lock = path;
-                SYZYGY_ENABLED.store(true, Ordering::SeqCst);
+                *lock = path.to_string();
+                SYZYGY_ENABLED.store(!path.is_empty(), Ordering::SeqCst);
             } else {
                 bail!(UciError::InternalError(
                     "failed to take lock on SyzygyPath".into()
@@ -387,43 +847,140 @@ fn parse_setoption(text: &str, pre_config: SetOptions) -> anyhow::Result<SetOpti
             }
         }
         "SyzygyProbeLimit" => {
-            let value: u8 = opt_value.parse()?;
-            if value > 6 {
-                bail!(UciError::IllegalValue(
-                    "SyzygyProbeLimit value must be between 0 and 6".to_string()
-                ));
-            }
-            SYZYGY_PROBE_LIMIT.store(value, Ordering::SeqCst);
+            SYZYGY_PROBE_LIMIT.store(option.spin_value(opt_value)?, Ordering::SeqCst);
         }
         "SyzygyProbeDepth" => {
-            let value: i32 = opt_value.parse()?;
-            if !(1..=100).contains(&value) {
-                bail!(UciError::IllegalValue(
-                    "SyzygyProbeDepth value must be between 0 and 100".to_string()
-                ));
-            }
-            SYZYGY_PROBE_DEPTH.store(value, Ordering::SeqCst);
+            SYZYGY_PROBE_DEPTH.store(option.spin_value(opt_value)?, Ordering::SeqCst);
         }
-        "Contempt" => {
-            let value: i32 = opt_value.parse()?;
-            if !(-10000..=10000).contains(&value) {
-                bail!(UciError::IllegalValue(
-                    "Contempt value must be between -10000 and 10000".to_string()
-                ));
+        "Contempt" => CONTEMPT.store(option.spin_value(opt_value)?, Ordering::SeqCst),
+        "Style" => STYLE.store(option.spin_value(opt_value)?, Ordering::SeqCst),
+        "EvalBlend" => EVAL_BLEND.store(option.check_value(opt_value)?, Ordering::SeqCst),
+        "SmallNetFile" => match opt_value.filter(|&p| p != "<empty>") {
+            Some(path) => small::load(Path::new(path))?,
+            None => small::set_loaded(None),
+        },
+        "UCI_Chess960" => CHESS960.store(option.check_value(opt_value)?, Ordering::SeqCst),
+        "UCI_AnalyseMode" => out.analyse_mode = option.check_value(opt_value)?,
+        "ParamsFile" => {
+            let path = opt_value.filter(|&p| p != "<empty>").map(PathBuf::from);
+            let config = match &path {
+                Some(path) => Config::from_file(path)?,
+                None => Config::default(),
+            };
+            print_config_diff(&config);
+            out.search_config = config;
+            out.params_file = path.map(ParamsWatch::new);
+        }
+        "nodestime" => out.nodestime = option.spin_value(opt_value)?,
+        "MoveTimeJitter" => out.pacing.jitter = option.spin_value(opt_value)?,
+        "MinReplyDelay" => out.pacing.min_delay = option.spin_value(opt_value)?,
+        "MaxReplyDelay" => out.pacing.max_delay = option.spin_value(opt_value)?,
+        "InstantMoveBudget" => out.instant_move_budget = option.spin_value(opt_value)?,
+        "MateStopIterations" => out.mate_stop_iterations = option.spin_value(opt_value)?,
+        "RootVariety" => out.variety.margin = option.spin_value(opt_value)?,
+        "RootVarietyMoves" => out.variety.moves = option.spin_value(opt_value)?,
+        "RootVarietySeed" => out.variety.set_seed(option.spin_value(opt_value)?),
+        "Resign" => {
+            out.adjudicator.resign = opt_value
+                .filter(|&v| v != "<empty>")
+                .map_or(Ok(ResignRule::OFF), str::parse)?;
+        }
+        "DrawOffer" => {
+            out.adjudicator.draw = opt_value
+                .filter(|&v| v != "<empty>")
+                .map_or(Ok(DrawRule::OFF), str::parse)?;
+        }
+        "SparringMargin" => {
+            SPARRING_MARGIN.store(option.spin_value(opt_value)?, Ordering::SeqCst);
+        }
+        "Profile" => {
+            let profile = profiles::find(option.value(opt_value)?)
+                .map_err(|e| anyhow!(UciError::IllegalValue(format!("{e:#}"))))?;
+            for (name, value) in &profile.options {
+                out = parse_setoption(&format!("setoption name {name} value {value}"), out)
+                    .map_err(|e| anyhow!("{e:#} (in profile {})", profile.name))?;
             }
-            CONTEMPT.store(value, Ordering::SeqCst);
+            output::send(format!("info string applied profile {}", profile.name));
         }
-        "UCI_Chess960" => {
-            let val = opt_value.parse()?;
-            CHESS960.store(val, Ordering::SeqCst);
+        "SessionFile" => {
+            let path = opt_value.filter(|&p| p != "<empty>").map(PathBuf::from);
+            session::set_path(path)?;
         }
-        _ => {
-            eprintln!("info string ignoring option {opt_name}, type \"uci\" for a list of options");
+        "ProfilesFile" => {
+            let path = opt_value.filter(|&p| p != "<empty>").map(Path::new);
+            let names = profiles::load_user_profiles(path)?;
+            if !names.is_empty() {
+                output::send(format!("info string loaded profiles {}", names.join(", ")));
+            }
         }
+        _ => bail!(UciError::InternalError(format!(
+            "option {} is advertised but not handled",
+            option.name
+        ))),
     }
     Ok(out)
 }
 
+/// The parameter file given by `ParamsFile` or `--params`, which is loaded again whenever
+/// it changes, so that a tuning run can apply new values without restarting the engine.
+#[derive(Clone, Debug)]
+struct ParamsWatch {
+    path: PathBuf,
+    /// When the file was last modified, as of the last time it was loaded.
+    modified: Option<SystemTime>,
+}
+
+impl ParamsWatch {
+    fn new(path: PathBuf) -> Self {
+        let modified = Self::modified(&path);
+        Self { path, modified }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Load the file again if it has been modified since it was last loaded.
+    fn reload_if_changed(&mut self) -> anyhow::Result<Option<Config>> {
+        let modified = Self::modified(&self.path);
+        if modified == self.modified {
+            return Ok(None);
+        }
+        // a file that fails to load is only tried again once it changes again.
+        self.modified = modified;
+        Config::from_file(&self.path).map(Some)
+    }
+
+    /// Pick up any changes to the file, between searches.
+    fn refresh(&mut self, info: &mut SearchInfo) {
+        match self.reload_if_changed() {
+            Ok(None) => {}
+            Ok(Some(config)) => {
+                output::send(format!("info string reloaded {}", self.path.display()));
+                print_config_diff(&config);
+                info.lm_table = LMTable::new(&config);
+                info.conf = config;
+            }
+            Err(e) => {
+                // parse errors can run over several lines, and an info string can't.
+                let e = format!("{e:#}");
+                let e = e.split_whitespace().collect::<Vec<_>>().join(" ");
+                output::send(format!("info string keeping the current parameters: {e}"));
+            }
+        }
+    }
+}
+
+fn print_config_diff(config: &Config) {
+    let diff = config.diff_from_default();
+    if diff.is_empty() {
+        output::send("info string loaded parameters are identical to the defaults".into());
+    }
+    for (id, default, value) in diff {
+        output::send(format!("info string {id}: {default} -> {value}"));
+    }
+}
+
 fn stdin_reader() -> anyhow::Result<(
     mpsc::Receiver<String>,
     std::thread::JoinHandle<anyhow::Result<()>>,
@@ -452,6 +1009,9 @@ fn stdin_reader_worker(sender: mpsc::Sender<String>) -> anyhow::Result<()> {
             linebuf.clear();
             continue;
         }
+        if let Ok(mut received) = LAST_COMMAND_RECEIVED.lock() {
+            *received = Some(Instant::now());
+        }
         if let Err(e) = sender.send(cmd.to_owned()) {
             bail!("info string error sending command to main thread: {e}");
         }
@@ -574,51 +1134,89 @@ fn print_uci_response(info: &SearchInfo, full: bool) {
     } else {
         "-dev"
     };
-    println!("id name {NAME} {VERSION}{version_extension}");
-    println!("id author Cosmo");
-    println!("option name Hash type spin default {UCI_DEFAULT_HASH_MEGABYTES} min 1 max {UCI_MAX_HASH_MEGABYTES}");
-    println!("option name Threads type spin default 1 min 1 max 512");
-    println!("option name PrettyPrint type check default false");
-    println!("option name SyzygyPath type string default <empty>");
-    println!("option name SyzygyProbeLimit type spin default 6 min 0 max 6");
-    println!("option name SyzygyProbeDepth type spin default 1 min 1 max 100");
-    println!("option name Contempt type spin default 0 min -10000 max 10000");
-    println!("option name Ponder type check default false");
-    println!("option name UCI_Chess960 type check default false");
+    output::send(format!("id name {NAME} {VERSION}{version_extension}"));
+    output::send("id author Cosmo".into());
+    for option in &UCI_OPTIONS {
+        output::send(format!("{option}"));
+    }
     if full {
         for (id, default, min, max, _) in info.conf.base_config() {
-            println!("option name {id} type spin default {default} min {min} max {max}");
+            output::send(format!(
+                "option name {id} type spin default {default} min {min} max {max}"
+            ));
         }
     }
-    println!("uciok");
+    let hardware = Hardware::detect();
+    let Recommendation { threads, hash_mb } =
+        hardware.recommend(UCI_MAX_THREADS, UCI_MAX_HASH_MEGABYTES);
+    output::send(format!(
+        "info string recommended Threads {threads} Hash {hash_mb} ({hardware})"
+    ));
+    output::send("uciok".into());
 }
 
 static SET_TERM: Once = Once::new();
 
 #[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
-pub fn main_loop() -> anyhow::Result<()> {
+pub fn main_loop(params_file: Option<&Path>, resume: Option<&Path>) -> anyhow::Result<()> {
     let mut pos = Board::default();
+    // the EPD record that set up the current position, if any.
+    let mut epd = None;
+    let mut last_position = None::<LastPosition>;
+    // the board, principal variation, and depth of the last search, for `pgn`.
+    let mut last_search = None::<(Board, PVariation, usize)>;
+    // the state that is saved to the session file, once there is a position to save.
+    let mut session = None::<Session>;
 
     let mut tt = TT::new();
     tt.resize(UCI_DEFAULT_HASH_MEGABYTES * MEGABYTE); // default hash size
 
-    let nnue_params = NNUEParams::decompress_and_alloc()?;
+    let nnue_params = NNUEParams::decompress_or_fallback();
 
+    output::install()?;
     let stopped = AtomicBool::new(false);
     let (stdin, stdin_reader_handle) = stdin_reader()?;
     let stdin = Mutex::new(stdin);
     let nodes = AtomicU64::new(0);
+    // search threads are kept between searches, and joined when the pool is dropped on quit.
+    let pool = ThreadPool::new();
     let mut info = SearchInfo::new(&stopped, &nodes);
     info.set_stdin(&stdin);
-
-    let mut thread_data = vec![ThreadData::new(0, &pos, tt.view(), nnue_params)];
+    info.pool = Some(&pool);
+    let mut params_watch = params_file.map(|path| ParamsWatch::new(path.to_path_buf()));
+    if let Some(path) = params_file {
+        info.conf = Config::from_file(path)?;
+        info.lm_table = LMTable::new(&info.conf);
+        print_config_diff(&info.conf);
+    }
 
     let version_extension = if cfg!(feature = "final-release") {
         ""
     } else {
         "-dev"
     };
-    println!("{NAME} {VERSION}{version_extension} by Cosmo");
+    output::send(format!("{NAME} {VERSION}{version_extension} by Cosmo"));
+
+    if let Some(path) = resume {
+        let saved = Session::load(path)?;
+        CHESS960.store(saved.chess960, Ordering::SeqCst);
+        epd = parse_position(&saved.position, &mut pos)?;
+        last_position = LastPosition::new(&saved.position, &pos);
+        match &saved.analysis {
+            Some(analysis) => output::send(format!(
+                "info string resumed {} at depth {} score {} pv {}",
+                saved.position,
+                analysis.depth,
+                analysis.score,
+                analysis.pv.join(" ")
+            )),
+            None => output::send(format!("info string resumed {}", saved.position)),
+        };
+        session::set_path(Some(path.to_path_buf()))?;
+        session = Some(saved);
+    }
+
+    let mut thread_data = vec![ThreadData::new(0, &pos, tt.view(), nnue_params)];
 
     loop {
         std::io::stdout()
@@ -650,52 +1248,98 @@ pub fn main_loop() -> anyhow::Result<()> {
             }
             arg @ ("ucidump" | "ucidumpfull") => {
                 // dump the values of the current UCI options
-                println!("Hash: {}", tt.size() / MEGABYTE);
-                println!("Threads: {}", thread_data.len());
-                println!("PrettyPrint: {}", PRETTY_PRINT.load(Ordering::SeqCst));
-                println!(
+                output::send(format!("Hash: {}", tt.size() / MEGABYTE));
+                output::send(format!("Threads: {}", thread_data.len()));
+                output::send(format!("ThreadAffinity: {}", pool.pinned()));
+                output::send(format!(
+                    "PrettyPrint: {}",
+                    PRETTY_PRINT.load(Ordering::SeqCst)
+                ));
+                output::send(format!(
                     "SyzygyPath: {}",
                     SYZYGY_PATH
                         .lock()
                         .map_err(|_| anyhow!("failed to lock syzygy path"))?
-                );
-                println!(
+                ));
+                output::send(format!(
                     "SyzygyProbeLimit: {}",
                     SYZYGY_PROBE_LIMIT.load(Ordering::SeqCst)
-                );
-                println!(
+                ));
+                output::send(format!(
                     "SyzygyProbeDepth: {}",
                     SYZYGY_PROBE_DEPTH.load(Ordering::SeqCst)
-                );
-                println!("Contempt: {}", CONTEMPT.load(Ordering::SeqCst));
+                ));
+                output::send(format!("Contempt: {}", CONTEMPT.load(Ordering::SeqCst)));
+                output::send(format!("Style: {}", STYLE.load(Ordering::SeqCst)));
+                output::send(format!("nodestime: {}", info.time_manager.nodestime()));
+                let pacing = info.time_manager.pacing();
+                output::send(format!("MoveTimeJitter: {}", pacing.jitter));
+                output::send(format!("MinReplyDelay: {}", pacing.min_delay));
+                output::send(format!("MaxReplyDelay: {}", pacing.max_delay));
+                output::send(format!(
+                    "InstantMoveBudget: {}",
+                    info.time_manager.instant_move_budget()
+                ));
+                output::send(format!(
+                    "MateStopIterations: {}",
+                    info.time_manager.mate_stop_iterations()
+                ));
+                output::send(format!("RootVariety: {}", info.variety.margin));
+                output::send(format!("RootVarietyMoves: {}", info.variety.moves));
+                output::send(format!("RootVarietySeed: {}", info.variety.seed));
+                output::send(format!(
+                    "SparringMargin: {}",
+                    SPARRING_MARGIN.load(Ordering::SeqCst)
+                ));
+                output::send(format!("Resign: {}", info.adjudicator.resign));
+                output::send(format!("DrawOffer: {}", info.adjudicator.draw));
+                output::send(format!("EvalBlend: {}", EVAL_BLEND.load(Ordering::SeqCst)));
+                output::send(format!("UCI_AnalyseMode: {}", info.analyse_mode));
                 if arg == "ucidumpfull" {
                     for (id, default) in Config::default().ids_with_values() {
-                        println!("{id}: {default}");
+                        output::send(format!("{id}: {default}"));
                     }
                 }
                 Ok(())
             }
             "isready" => {
-                println!("readyok");
+                if let Some(watch) = &mut params_watch {
+                    watch.refresh(&mut info);
+                }
+                output::send("readyok".into());
                 Ok(())
             }
             "quit" => {
                 QUIT.store(true, Ordering::SeqCst);
                 break;
             }
-            "ucinewgame" => do_newgame(&mut pos, &tt, &mut thread_data),
+            "ucinewgame" => {
+                info.time_manager.reset_node_clock();
+                info.variety.new_game();
+                info.adjudicator.new_game();
+                epd = None;
+                do_newgame(&mut pos, &tt, &mut thread_data)
+            }
             "eval" => {
                 let eval = if pos.in_check() {
-                    0
+                    Value::DRAW
                 } else {
-                    pos.evaluate(
-                        thread_data
-                            .first_mut()
-                            .with_context(|| "the thread headers are empty.")?,
-                        0,
-                    )
+                    let t = thread_data
+                        .first_mut()
+                        .with_context(|| "the thread headers are empty.")?;
+                    // evaluate as a search from this position would.
+                    t.stm_at_root = pos.turn();
+                    t.analyse_mode = info.analyse_mode;
+                    pos.evaluate(t, &info.conf, 0)
                 };
-                println!("{eval}");
+                output::send(format!("{eval}"));
+                Ok(())
+            }
+            "eval --buckets" => {
+                let t1 = thread_data
+                    .first_mut()
+                    .with_context(|| "the thread headers are empty.")?;
+                print_eval_buckets(&pos, t1, &info.conf);
                 Ok(())
             }
             "raweval" => {
@@ -708,12 +1352,95 @@ pub fn main_loop() -> anyhow::Result<()> {
                     t1.nnue
                         .evaluate(t1.nnue_params, pos.turn(), network::output_bucket(&pos))
                 };
-                println!("{eval}");
+                output::send(format!("{eval}"));
                 Ok(())
             }
-            "show" => {
-                println!("{pos:X}");
-                Ok(())
+            input
+                if input == "show"
+                    || input == "d"
+                    || input.starts_with("show ")
+                    || input.starts_with("d ") =>
+            {
+                // glyphs and colour for a person at a terminal, and plain text for a GUI's log.
+                let style = if PRETTY_PRINT.load(Ordering::SeqCst) {
+                    BoardStyle::FANCY
+                } else {
+                    BoardStyle::PLAIN
+                };
+                style
+                    .with_words(input.split_ascii_whitespace().skip(1))
+                    .map(|style| {
+                        output::send(pos.render(style).to_string().trim_end().to_string());
+                    })
+            }
+            cmd @ ("flip" | "mirror" | "mirror vertical" | "mirror horizontal") => {
+                let res = match cmd {
+                    "flip" => pos.flip_side(),
+                    "mirror horizontal" => Ok(pos.mirror_horizontal()),
+                    _ => Ok(pos.mirror_vertical()),
+                };
+                res.map(|board| {
+                    pos = board;
+                    epd = None;
+                    output::send(format!("info string fen {pos}"));
+                    for t in &mut thread_data {
+                        t.nnue.refresh_from(&pos, t.nnue_params);
+                    }
+                })
+            }
+            input
+                if input.starts_with("put ")
+                    || input.starts_with("remove ")
+                    || input.starts_with("side ") =>
+            {
+                parse_edit(input, &mut pos).map(|()| {
+                    epd = None;
+                    // a position that's still being built may not have its kings yet.
+                    match pos.validate() {
+                        Ok(()) => {
+                            output::send(format!("info string fen {pos}"));
+                            for t in &mut thread_data {
+                                t.nnue.refresh_from(&pos, t.nnue_params);
+                            }
+                        }
+                        Err(e) => {
+                            output::send(format!("info string fen {pos} (not playable: {e})"));
+                        }
+                    }
+                })
+            }
+            "pgn" => match &mut last_search {
+                Some((board, pv, depth)) if board.zobrist_key() == pos.zobrist_key() => {
+                    notation::pv_to_pgn(board, pv, *depth).map(|pgn| {
+                        output::send(pgn.trim_end().to_string());
+                    })
+                }
+                _ => Err(anyhow!("no search from this position to export")),
+            },
+            input if input.starts_with("explain") => {
+                parse_explain(input).and_then(|(depth, tga)| {
+                    let t1 = thread_data
+                        .first_mut()
+                        .with_context(|| "the thread headers are empty.")?;
+                    let explanation = explain::explain(&pos, t1, &info.conf, depth);
+                    SET_TERM.call_once(|| {
+                        term::set_mode_uci();
+                    });
+                    let kind = if depth == 0 {
+                        "static".to_string()
+                    } else {
+                        format!("depth {depth}")
+                    };
+                    output::send(format!(
+                        "eval {:+} (white's perspective, {kind})",
+                        explanation.eval
+                    ));
+                    output::send(explanation.heatmap(&pos).trim_end().to_string());
+                    if let Some(path) = tga {
+                        explanation.save_as_tga(&path);
+                    }
+                    Ok(())
+                })
             }
             "nnuebench" => {
                 nnue::network::inference_benchmark(
@@ -723,40 +1450,107 @@ pub fn main_loop() -> anyhow::Result<()> {
                 Ok(())
             }
             "gobench" => go_benchmark(nnue_params),
-            "initcuckoo" => cuckoo::init(),
+            "makebench" => make_unmake_benchmark(),
+            "attackbench" => {
+                attack_benchmark();
+                Ok(())
+            }
+            "stackbench" => {
+                stack::benchmark();
+                Ok(())
+            }
             input if input.starts_with("setoption") => {
                 let pre_config = SetOptions {
                     search_config: info.conf.clone(),
                     hash_mb: tt.size() / MEGABYTE,
                     threads: thread_data.len(),
+                    nodestime: info.time_manager.nodestime(),
+                    pacing: info.time_manager.pacing(),
+                    instant_move_budget: info.time_manager.instant_move_budget(),
+                    mate_stop_iterations: info.time_manager.mate_stop_iterations(),
+                    variety: info.variety.clone(),
+                    adjudicator: info.adjudicator.clone(),
+                    pin_threads: pool.pinned(),
+                    analyse_mode: info.analyse_mode,
+                    params_file: params_watch.clone(),
                 };
                 let res = parse_setoption(input, pre_config);
                 match res {
                     Ok(conf) => {
                         info.conf = conf.search_config;
+                        if conf.nodestime != info.time_manager.nodestime() {
+                            info.time_manager.set_nodestime(conf.nodestime);
+                        }
+                        info.time_manager.set_pacing(conf.pacing);
+                        info.time_manager
+                            .set_instant_move_budget(conf.instant_move_budget);
+                        info.time_manager
+                            .set_mate_stop_iterations(conf.mate_stop_iterations);
+                        info.variety = conf.variety;
+                        info.adjudicator = conf.adjudicator;
+                        info.analyse_mode = conf.analyse_mode;
+                        params_watch = conf.params_file;
+                        pool.set_pinned(conf.pin_threads);
                         info.lm_table = LMTable::new(&info.conf);
                         let new_size = conf.hash_mb * MEGABYTE;
-                        // drop all the thread_data, as they are borrowing the old tt
-                        std::mem::drop(thread_data);
-                        tt.resize(new_size);
-                        // recreate the thread_data with the new tt
-                        thread_data = (0..conf.threads)
-                            .zip(std::iter::repeat(&pos))
-                            .map(|(i, p)| ThreadData::new(i, p, tt.view(), nnue_params))
-                            .collect();
-                        Ok(())
+                        // only rebuild what has changed, as reallocating a very large
+                        // hash for every option would stall the GUI.
+                        if new_size == tt.size() && conf.threads == thread_data.len() {
+                            Ok(())
+                        } else {
+                            // drop all the thread_data, as they are borrowing the old tt
+                            std::mem::drop(thread_data);
+                            let resized = if new_size == tt.size() {
+                                Ok(())
+                            } else {
+                                tt.try_resize(new_size).map(|()| {
+                                    // the fresh table is zeroed lazily, page by page, so touch
+                                    // it all now with every thread, not in the first search.
+                                    tt.clear(conf.threads);
+                                })
+                            };
+                            // recreate the thread_data with the new tt
+                            thread_data = (0..conf.threads)
+                                .zip(std::iter::repeat(&pos))
+                                .map(|(i, p)| ThreadData::new(i, p, tt.view(), nnue_params))
+                                .collect();
+                            resized
+                        }
                     }
                     Err(err) => Err(err),
                 }
             }
             input if input.starts_with("position") => {
-                let res = parse_position(input, &mut pos);
-                if res.is_ok() {
+                let extra = last_position
+                    .as_ref()
+                    .and_then(|last| last.extra_moves(input, &pos));
+                let res = match extra {
+                    Some(moves) => play_uci_moves(moves, &mut pos).map(|()| None),
+                    None => parse_position(input, &mut pos),
+                };
+                last_position = res
+                    .as_ref()
+                    .ok()
+                    .and_then(|_| LastPosition::new(input, &pos));
+                epd = None;
+                res.map(|record| {
+                    epd = record;
                     for t in &mut thread_data {
-                        t.nnue.reinit_from(&pos, t.nnue_params);
+                        t.nnue.refresh_from(&pos, t.nnue_params);
                     }
-                }
-                res
+                    // a random position can't be set up again, so it is saved as it stands.
+                    let position = if input.contains("random960") {
+                        format!("position fen {pos}")
+                    } else {
+                        input.to_string()
+                    };
+                    let saved = session.insert(Session {
+                        position,
+                        chess960: CHESS960.load(Ordering::SeqCst),
+                        analysis: None,
+                    });
+                    session::autosave(saved);
+                })
             }
             input if input.starts_with("go perft") || input.starts_with("perft") => {
                 let tail = input
@@ -788,8 +1582,13 @@ pub fn main_loop() -> anyhow::Result<()> {
                 }
             }
             input if input.starts_with("go") => {
-                // start the clock *immediately*
-                info.time_manager.start();
+                // start the clock from when the command arrived, not when we got round to it.
+                let received = LAST_COMMAND_RECEIVED.lock().ok().and_then(|mut r| r.take());
+                info.time_manager
+                    .start_at(received.unwrap_or_else(Instant::now));
+                if let Some(overhead) = info.time_manager.take_overhead_report() {
+                    output::send(format!("info string calibrated move overhead {overhead}ms"));
+                }
 
                 // if we're in pretty-printing mode, set the terminal properly:
                 if PRETTY_PRINT.load(Ordering::SeqCst) {
@@ -798,21 +1597,62 @@ pub fn main_loop() -> anyhow::Result<()> {
                     });
                 }
 
-                let res = parse_go(input, &pos);
-                if let Ok(search_limit) = res {
-                    info.time_manager.set_limit(search_limit);
-                    tt.increase_age();
-                    pos.search_position(&mut info, &mut thread_data, tt.view());
-                    Ok(())
-                } else {
-                    res.map(|_| ())
+                if let Some(watch) = &mut params_watch {
+                    watch.refresh(&mut info);
+                }
+
+                match pos.validate().and_then(|()| parse_go(input, &pos)) {
+                    Ok(limits) => {
+                        info.set_limits(&limits, pos.turn());
+                        tt.increase_age();
+                        let (_, best_move) =
+                            pos.search_position(&mut info, &mut thread_data, tt.view());
+                        let best = select_best(&thread_data);
+                        last_search = Some((pos.clone(), best.pv().clone(), best.completed));
+                        if let Some(session) = &mut session {
+                            let chess960 = CHESS960.load(Ordering::SeqCst);
+                            session.analysis = Some(Analysis {
+                                depth: best.completed,
+                                score: best.pv().score().get(),
+                                pv: best
+                                    .pv()
+                                    .moves()
+                                    .iter()
+                                    .map(|m| m.display(chess960).to_string())
+                                    .collect(),
+                            });
+                            session::autosave(session);
+                        }
+                        match (&epd, best_move) {
+                            (Some(epd), Some(best_move)) => {
+                                report_epd_result(epd, &mut pos, best_move)
+                            }
+                            _ => Ok(()),
+                        }
+                    }
+                    Err(e) => {
+                        // the GUI waits on a bestmove after every go, so it gets one even
+                        // when there's nothing we can search.
+                        output::send(format!("info string {e}"));
+                        output::send("bestmove (none)".into());
+                        Ok(())
+                    }
                 }
             }
             "ponderhit" => {
-                println!("info error ponderhit given while not searching.");
+                output::send("info error ponderhit given while not searching.".into());
                 Ok(())
             }
-            benchcmd @ ("bench" | "benchfull") => bench(benchcmd, &info.conf, nnue_params, None),
+            input
+                if matches!(
+                    input.split_ascii_whitespace().next(),
+                    Some("bench" | "benchfull")
+                ) =>
+            {
+                parse_bench(input).and_then(|(benchcmd, options)| {
+                    bench(benchcmd, &info.conf, nnue_params, &options).map(|_| ())
+                })
+            }
             _ => Err(anyhow!(UciError::UnknownCommand(input.to_string()))),
         };
 
@@ -836,21 +1676,69 @@ pub fn main_loop() -> anyhow::Result<()> {
 
 const BENCH_DEPTH: usize = 14;
 const BENCH_THREADS: usize = 1;
+const BENCH_HASH_MEGABYTES: usize = 16;
+
+/// Overrides for the settings that a benchmark runs with.
+#[derive(Default)]
+pub struct BenchOptions {
+    pub depth: Option<usize>,
+    pub hash_mb: Option<usize>,
+    pub threads: Option<usize>,
+}
+
+impl BenchOptions {
+    /// Whether the node count is the bench signature: only the default hash size and a
+    /// single thread give the same count from run to run and machine to machine.
+    pub fn gives_signature(&self) -> bool {
+        self.hash_mb.unwrap_or(BENCH_HASH_MEGABYTES) == BENCH_HASH_MEGABYTES
+            && self.threads.unwrap_or(BENCH_THREADS) == 1
+    }
+
+    /// The depth, hash size and thread count to bench with, after filling in the defaults.
+    fn resolve(&self) -> anyhow::Result<(usize, usize, usize)> {
+        let depth = self.depth.unwrap_or(BENCH_DEPTH);
+        let hash_mb = self.hash_mb.unwrap_or(BENCH_HASH_MEGABYTES);
+        let threads = self.threads.unwrap_or(BENCH_THREADS);
+        if !(1..=MAX_PLY).contains(&depth) {
+            bail!(UciError::IllegalValue(format!(
+                "bench depth must be between 1 and {MAX_PLY}"
+            )));
+        }
+        if !(1..=UCI_MAX_HASH_MEGABYTES).contains(&hash_mb) {
+            bail!(UciError::IllegalValue(format!(
+                "bench hash must be between 1 and {UCI_MAX_HASH_MEGABYTES}"
+            )));
+        }
+        if !(1..=UCI_MAX_THREADS).contains(&threads) {
+            bail!(UciError::IllegalValue(format!(
+                "bench threads must be between 1 and {UCI_MAX_THREADS}"
+            )));
+        }
+        Ok((depth, hash_mb, threads))
+    }
+}
+
+/// Search each of the bench positions, and give back the total number of nodes searched.
+/// The last line printed is always `<nodes> nodes <nps> nps`, as openbench expects.
 pub fn bench(
     benchcmd: &str,
     search_params: &Config,
     nnue_params: &NNUEParams,
-    depth: Option<usize>,
-) -> anyhow::Result<()> {
-    let bench_string = format!("go depth {}\n", depth.unwrap_or(BENCH_DEPTH));
+    options: &BenchOptions,
+) -> anyhow::Result<u64> {
+    let (depth, hash_mb, threads) = options.resolve()?;
+    if !options.gives_signature() {
+        eprintln!("info string warning: bench with non-default hash or threads does not give the bench signature");
+    }
+    let bench_string = format!("go depth {depth}\n");
     let stopped = AtomicBool::new(false);
     let nodes = AtomicU64::new(0);
     let mut info = SearchInfo::with_search_params(&stopped, &nodes, search_params);
     info.print_to_stdout = false;
     let mut pos = Board::default();
     let mut tt = TT::new();
-    tt.resize(16 * MEGABYTE);
-    let mut thread_data = (0..BENCH_THREADS)
+    tt.resize(hash_mb * MEGABYTE);
+    let mut thread_data = (0..threads)
         .zip(std::iter::repeat(&pos))
         .map(|(i, p)| ThreadData::new(i, p, tt.view(), nnue_params))
         .collect::<Vec<_>>();
@@ -878,7 +1766,7 @@ pub fn bench(
         info.time_manager.start();
         let res = parse_go(&bench_string, &pos);
         match res {
-            Ok(limit) => info.time_manager.set_limit(limit),
+            Ok(limits) => info.set_limits(&limits, pos.turn()),
             Err(e) => {
                 info.print_to_stdout = true;
                 return Err(e);
@@ -888,33 +1776,49 @@ pub fn bench(
         pos.search_position(&mut info, &mut thread_data, tt.view());
         node_sum += info.nodes.get_global();
         if matches!(benchcmd, "benchfull" | "openbench") {
-            println!("{fen:<max_fen_len$} | {:>7} nodes", info.nodes.get_global());
+            output::send(format!(
+                "{fen:<max_fen_len$} | {:>7} nodes",
+                info.nodes.get_global()
+            ));
         }
     }
     let time = start.elapsed();
     #[allow(clippy::cast_precision_loss)]
     let nps = node_sum as f64 / time.as_secs_f64();
-    if benchcmd == "openbench" {
-        println!("{node_sum} nodes {nps:.0} nps");
-    } else {
-        println!(
-            "{node_sum} nodes in {time:.3}s ({nps:.0} nps)",
+    if benchcmd != "openbench" {
+        output::send(format!(
+            "{node_sum} nodes in {time:.3}s",
             time = time.as_secs_f64()
-        );
+        ));
+        // a nodestime comfortably below the measured speed avoids time losses.
+        output::send(format!(
+            "nodestime calibration: {:.0} nodes/ms",
+            nps / 1000.0
+        ));
     }
+    output::send(format!("{node_sum} nodes {nps:.0} nps"));
     info.print_to_stdout = true;
 
+    #[cfg(feature = "movegen-audit")]
+    {
+        let (audited, diverged) = crate::chess::board::movegen::audit::summary();
+        let verdict = if diverged { "diverged" } else { "agreed" };
+        output::send(format!(
+            "movegen audit: the generators {verdict} over {audited} nodes"
+        ));
+    }
+
     // logging for permutation
     #[cfg(feature = "nnz-counts")]
-    println!(
+    output::send(format!(
         "NNZ counts: {:?}",
         network::layers::NNZ_COUNTS
             .iter()
             .map(|c| c.load(Ordering::Relaxed))
             .collect::<Vec<u64>>()
-    );
+    ));
 
-    Ok(())
+    Ok(node_sum)
 }
 
 /// Benchmark the go UCI command.
@@ -936,30 +1840,89 @@ pub fn go_benchmark(nnue_params: &NNUEParams) -> anyhow::Result<()> {
     let start = std::time::Instant::now();
     for _ in 0..COUNT {
         info.time_manager.start();
-        let limit = parse_go(
+        let limits = parse_go(
             std::hint::black_box("go wtime 0 btime 0 winc 0 binc 0"),
             &pos,
         )?;
-        info.time_manager.set_limit(limit);
+        info.set_limits(&limits, pos.turn());
         tt.increase_age();
         std::hint::black_box(pos.search_position(&mut info, &mut thread_data, tt.view()));
     }
     let elapsed = start.elapsed();
     let micros = elapsed.as_secs_f64() * (1_000_000.0 / COUNT as f64);
-    println!("{micros} us per parse_go");
+    output::send(format!("{micros} us per parse_go"));
+    Ok(())
+}
+
+/// Benchmark making and unmaking moves, to compare the ways of storing board history.
+pub fn make_unmake_benchmark() -> anyhow::Result<()> {
+    #![allow(clippy::cast_precision_loss)]
+    const REPEATS: usize = 10_000;
+    let mut pos = Board::default();
+    let mut count = 0;
+    let mut elapsed = Duration::ZERO;
+    for fen in BENCH_POSITIONS {
+        pos.set_from_fen(fen)?;
+        let moves = pos.legal_moves();
+        let start = Instant::now();
+        for _ in 0..REPEATS {
+            for &m in &moves {
+                pos.make_move_simple(std::hint::black_box(m));
+                pos.unmake_move_base();
+            }
+        }
+        elapsed += start.elapsed();
+        count += moves.len() * REPEATS;
+    }
+    let nanos = elapsed.as_nanos() as f64 / count as f64;
+    output::send(format!(
+        "{nanos:.1} ns per make/unmake ({HISTORY_KIND} history)"
+    ));
     Ok(())
 }
 
+/// Benchmark sliding piece attack lookups, comparing magic bitboards with
+/// the lookup that was chosen for this machine.
+pub fn attack_benchmark() {
+    #![allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    const LOOKUPS: usize = 20_000_000;
+    let mut rng = XorShiftState::new();
+    let blockers = (0..1024)
+        .map(|_| SquareSet::from_inner(rng.next() & rng.next()))
+        .collect::<Vec<_>>();
+    let time = |bishop: fn(Square, SquareSet) -> SquareSet,
+                rook: fn(Square, SquareSet) -> SquareSet| {
+        let start = Instant::now();
+        for i in 0..LOOKUPS {
+            let sq = Square::new_clamped((i % 64) as u8);
+            let occupied = std::hint::black_box(blockers[i % blockers.len()]);
+            std::hint::black_box(bishop(sq, occupied) | rook(sq, occupied));
+        }
+        start.elapsed().as_nanos() as f64 / LOOKUPS as f64
+    };
+    let magic = time(magic_bishop_attacks, magic_rook_attacks);
+    let chosen = time(bishop_attacks, rook_attacks);
+    output::send(format!("magic: {magic:.2} ns per bishop + rook lookup"));
+    output::send(format!(
+        "{}: {chosen:.2} ns per bishop + rook lookup",
+        if pext_enabled() {
+            "pext"
+        } else {
+            "magic (dispatched)"
+        }
+    ));
+}
+
 fn block_perft(depth: usize, pos: &mut Board) {
     #![allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
     let start_time = Instant::now();
     let nodes = perft::perft(pos, depth);
     let elapsed = start_time.elapsed();
     let nps = nodes as f64 / elapsed.as_secs_f64();
-    println!(
+    output::send(format!(
         "info depth {depth} nodes {nodes} time {elapsed} nps {nps:.0}",
         elapsed = elapsed.as_millis()
-    );
+    ));
 }
 
 fn divide_perft(depth: usize, pos: &mut Board) {
@@ -967,27 +1930,53 @@ fn divide_perft(depth: usize, pos: &mut Board) {
     let start_time = Instant::now();
     let mut nodes = 0;
     let mut ml = MoveList::new();
-    pos.generate_moves(&mut ml);
+    pos.generate_legal_moves(&mut ml);
     for &m in ml.iter_moves() {
-        if !pos.make_move_simple(m) {
-            continue;
-        }
+        pos.make_move_simple(m);
         let arm_nodes = perft::perft(pos, depth - 1);
         nodes += arm_nodes;
-        println!(
+        output::send(format!(
             "{}: {arm_nodes}",
             m.display(CHESS960.load(Ordering::Relaxed))
-        );
+        ));
         pos.unmake_move_base();
     }
     let elapsed = start_time.elapsed();
-    println!(
+    output::send(format!(
         "info depth {depth} nodes {nodes} time {elapsed} nps {nps:.0}",
         elapsed = elapsed.as_millis(),
         nps = nodes as f64 / elapsed.as_secs_f64()
-    );
+    ));
 }
 
+/// Say whether the move we found passes the test set by an EPD record's `bm` and `am` operations.
+fn report_epd_result(epd: &Epd, pos: &mut Board, best_move: Move) -> anyhow::Result<()> {
+    let Some(pass) = epd.judge(pos, best_move)? else {
+        return Ok(());
+    };
+    let san = pos
+        .san(best_move)
+        .with_context(|| "search returned an illegal move.")?;
+    let id = epd
+        .id()
+        .map_or_else(String::new, |id| format!(" id \"{id}\""));
+    let expected = ["bm", "am"]
+        .into_iter()
+        .filter_map(|opcode| {
+            epd.get(opcode)
+                .map(|moves| format!(" {opcode} {}", moves.join(" ")))
+        })
+        .collect::<String>();
+    let verdict = if pass { "pass" } else { "fail" };
+    output::send(format!(
+        "info string epd{id}{expected} found {san} {verdict}"
+    ));
+    Ok(())
+}
+
+/// Go back to the starting position and forget everything learned in the last game:
+/// the transposition table and every thread's histories. A `position` command alone
+/// keeps them, as it's almost always the same game with another move or two played.
 fn do_newgame(pos: &mut Board, tt: &TT, thread_data: &mut [ThreadData]) -> anyhow::Result<()> {
     parse_position("position startpos\n", pos).with_context(|| "Failed to set startpos")?;
     tt.clear(thread_data.len());
@@ -995,13 +1984,38 @@ fn do_newgame(pos: &mut Board, tt: &TT, thread_data: &mut [ThreadData]) -> anyho
     Ok(())
 }
 
+/// Print the raw (pre-scale) network output of every output bucket for the
+/// given position, marking the bucket that the position actually maps to.
+fn print_eval_buckets(pos: &Board, t: &mut ThreadData, conf: &Config) {
+    t.nnue.force(pos, t.nnue_params);
+    let selected = network::output_bucket(pos);
+    output::send(format!(
+        "output bucket: {selected} ({} pieces)",
+        pos.n_men()
+    ));
+    output::send("bucket | raw output | scale".into());
+    for bucket in 0..network::OUTPUT_BUCKETS {
+        let raw = t.nnue.evaluate(t.nnue_params, pos.turn(), bucket);
+        let marker = if bucket == selected { " <-" } else { "" };
+        output::send(format!(
+            "{bucket:>6} | {raw:>10} | {:>5}{marker}",
+            conf.eval_scale(bucket)
+        ));
+    }
+    if pos.in_check() {
+        output::send("in check: the static evaluation is not used".into());
+    } else {
+        output::send(format!("scaled eval: {}", pos.evaluate_nnue(t, conf)));
+    }
+}
+
 /// Normalizes the internal value as reported by evaluate or search
 /// to the UCI centipawn result used in output. This value is derived from
 /// [the WLD model](https://github.com/vondele/WLD_model) such that Viridithas
 /// outputs an advantage of 100 centipawns for a position if the engine has a
 /// 50% probability to win from this position in selfplay at 16s+0.16s time control.
-const NORMALISE_TO_PAWN_VALUE: i32 = 199;
-fn win_rate_model(eval: i32, ply: usize) -> (i32, i32) {
+pub const NORMALISE_TO_PAWN_VALUE: i32 = 199;
+pub fn win_rate_model(eval: i32, ply: usize) -> (i32, i32) {
     #![allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
     const AS: [f64; 4] = [-0.482_975_16, 6.606_540_42, 5.860_087_77, 187.010_789_32];
     const BS: [f64; 4] = [-5.963_499_01, 39.012_824_90, -78.131_169_94, 115.038_711_68];
@@ -1062,3 +2076,242 @@ pub fn format_wdl(eval: i32, ply: usize) -> impl Display {
 pub fn pretty_format_wdl(eval: i32, ply: usize) -> impl Display {
     PrettyUciWdlFormat { eval, ply }
 }
+
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    #[test]
+    fn go_parsing() {
+        use super::parse_go;
+        use crate::{
+            chess::{board::Board, chessmove::Move, piece::Colour, types::Square},
+            timemgmt::{SearchLimit, SearchLimits},
+        };
+
+        let mut white = Board::default();
+        white.set_startpos();
+        let mut black = white.clone();
+        black.make_move_simple(Move::new(Square::E2, Square::E4));
+        let limit = |text: &str, pos: &Board| parse_go(text, pos).unwrap().limit(pos.turn());
+        let dynamic =
+            |our_clock, their_clock, our_inc, their_inc, moves_to_go| SearchLimit::Dynamic {
+                our_clock,
+                their_clock,
+                our_inc,
+                their_inc,
+                moves_to_go,
+            };
+
+        assert_eq!(parse_go("go", &white).unwrap(), SearchLimits::default());
+        assert_eq!(limit("go", &white), SearchLimit::Infinite);
+        assert_eq!(limit("go infinite", &white), SearchLimit::Infinite);
+        assert_eq!(limit("go depth 7", &white), SearchLimit::Depth(7));
+        assert_eq!(limit("go nodes 5000", &white), SearchLimit::Nodes(5000));
+        assert_eq!(limit("go movetime 250", &white), SearchLimit::Time(250));
+        assert_eq!(limit("go mate 3", &white), SearchLimit::Mate { ply: 6 });
+
+        // the clocks in any order, with or without increments.
+        let full = dynamic(1000, 2000, 10, 20, None);
+        assert_eq!(
+            limit("go wtime 1000 btime 2000 winc 10 binc 20", &white),
+            full
+        );
+        assert_eq!(
+            limit("go binc 20 winc 10 btime 2000 wtime 1000", &white),
+            full
+        );
+        assert_eq!(
+            limit("go wtime 1000 btime 2000", &white),
+            dynamic(1000, 2000, 0, 0, None)
+        );
+        assert_eq!(
+            limit("go wtime 1000 btime 2000 winc 10 binc 20", &black),
+            dynamic(2000, 1000, 20, 10, None)
+        );
+        assert_eq!(
+            limit("go movestogo 12 wtime 1000 btime 2000", &white),
+            dynamic(1000, 2000, 0, 0, Some(12))
+        );
+        // only our own clock, and a clock that's run out while the GUI caught up.
+        assert_eq!(
+            limit("go wtime 1000", &white),
+            dynamic(1000, 1000, 0, 0, None)
+        );
+        assert_eq!(
+            limit("go wtime -40 btime 2000", &white),
+            dynamic(0, 2000, 0, 0, None)
+        );
+
+        // limits that override one another, whichever order they come in.
+        assert_eq!(
+            limit("go nodes 100 depth 5", &white),
+            SearchLimit::Nodes(100)
+        );
+        assert_eq!(
+            limit("go depth 5 movetime 100", &white),
+            SearchLimit::Depth(5)
+        );
+        assert_eq!(
+            limit("go wtime 1000 btime 1000 depth 5", &white),
+            dynamic(1000, 1000, 0, 0, None)
+        );
+        assert_eq!(
+            limit("go ponder wtime 1000 btime 1000", &white),
+            dynamic(1000, 1000, 0, 0, None).to_pondering()
+        );
+        assert_eq!(
+            limit("go wtime 1000 btime 1000 ponder", &white),
+            dynamic(1000, 1000, 0, 0, None).to_pondering()
+        );
+
+        // searchmoves, at the end or followed by other parts.
+        let e4 = Move::new(Square::E2, Square::E4);
+        let d4 = Move::new(Square::D2, Square::D4);
+        let limits = parse_go("go searchmoves e2e4 d2d4", &white).unwrap();
+        assert_eq!(limits.searchmoves, [e4, d4]);
+        let limits = parse_go("go searchmoves e2e4 depth 3 infinite", &white).unwrap();
+        assert_eq!(limits.searchmoves, [e4]);
+        assert_eq!(limits.limit(Colour::White), SearchLimit::Depth(3));
+
+        for bad in [
+            "stop",
+            "go depth",
+            "go depth deep",
+            "go nodes -5",
+            "go fast",
+            "go winc 10 binc 10",
+            "go btime 1000",
+            "go searchmoves",
+            "go searchmoves e2e5",
+        ] {
+            assert!(parse_go(bad, &white).is_err(), "{bad} should be rejected");
+        }
+        assert!(parse_go("go btime 1000", &black).is_ok());
+    }
+
+    #[test]
+    fn setoption_parsing() {
+        use super::{parse_setoption, OptionKind, SetOptions, UCI_OPTIONS};
+        use crate::{
+            adjudication::Adjudicator,
+            search::parameters::Config,
+            search::variety::RootVariety,
+            timemgmt::{Pacing, DEFAULT_MATE_STOP_ITERATIONS},
+        };
+
+        let defaults = || SetOptions {
+            search_config: Config::default(),
+            hash_mb: 16,
+            threads: 1,
+            nodestime: 0,
+            pacing: Pacing::default(),
+            instant_move_budget: 0,
+            mate_stop_iterations: DEFAULT_MATE_STOP_ITERATIONS,
+            variety: RootVariety::default(),
+            adjudicator: Adjudicator::default(),
+            pin_threads: false,
+            analyse_mode: false,
+            params_file: None,
+        };
+        let set = |text: &str| parse_setoption(text, defaults());
+
+        // every spin option we advertise can be set to its default.
+        for option in &UCI_OPTIONS {
+            if let OptionKind::Spin { default, .. } = option.kind {
+                let text = format!("setoption name {} value {default}", option.name);
+                assert!(set(&text).is_ok(), "{text} should be accepted");
+            }
+        }
+
+        // out-of-range values are clamped, and names are case-insensitive.
+        assert_eq!(set("setoption name Threads value 0").unwrap().threads, 1);
+        assert_eq!(
+            set("setoption name threads value 100000").unwrap().threads,
+            512
+        );
+        assert_eq!(set("setoption name HASH value 64").unwrap().hash_mb, 64);
+        assert!(
+            set("setoption name UCI_AnalyseMode value true")
+                .unwrap()
+                .analyse_mode
+        );
+        // a profile sets each of its options in turn.
+        let correspondence = set("setoption name Profile value Correspondence").unwrap();
+        assert_eq!(correspondence.mate_stop_iterations, 0);
+        assert!((correspondence.search_config.lmr_division - 230.0).abs() < f64::EPSILON);
+        assert!(set("setoption name Profile value blitz").is_err());
+        let conf = set("setoption name rfp_margin value 100000")
+            .unwrap()
+            .search_config;
+        let max = conf
+            .base_config()
+            .into_iter()
+            .find(|p| p.0 == "RFP_MARGIN")
+            .unwrap()
+            .3;
+        assert_eq!(conf.rfp_margin.to_string(), max.to_string());
+
+        assert!(
+            set("setoption name ThreadAffinity value true")
+                .unwrap()
+                .pin_threads
+        );
+        assert!(set("setoption name NoSuchOption value 1").is_ok());
+        for bad in [
+            "setoption name Hash value lots",
+            "setoption name Hash",
+            "setoption name ThreadAffinity value maybe",
+            "setoption name RFP_MARGIN value 64.5",
+            "setoption name Resign value movecount=three",
+            "setoption name value 5",
+        ] {
+            assert!(set(bad).is_err(), "{bad} should be rejected");
+        }
+    }
+    #[test]
+    fn position_extends_previous_moves() {
+        use super::{parse_position, play_uci_moves, LastPosition};
+        use crate::chess::board::Board;
+
+        let first = "position startpos moves e2e4 e7e5";
+        let next = "position startpos moves e2e4 e7e5 g1f3 b8c6";
+        let mut pos = Board::default();
+        parse_position(first, &mut pos).unwrap();
+        let last = LastPosition::new(first, &pos).unwrap();
+
+        assert_eq!(last.extra_moves(first, &pos).unwrap(), Vec::<&str>::new());
+        assert!(last
+            .extra_moves("position startpos moves e2e4 c7c5", &pos)
+            .is_none());
+        let moves = last.extra_moves(next, &pos).unwrap();
+        assert_eq!(moves, ["g1f3", "b8c6"]);
+        play_uci_moves(moves, &mut pos).unwrap();
+
+        let mut fresh = Board::default();
+        parse_position(next, &mut fresh).unwrap();
+        assert_eq!(pos.to_string(), fresh.to_string());
+        assert_eq!(pos.zobrist_key(), fresh.zobrist_key());
+        // the board has moved on from where the last command left it.
+        assert!(last.extra_moves(next, &pos).is_none());
+        // a bare startpos can be extended too.
+        parse_position("position startpos", &mut pos).unwrap();
+        let last = LastPosition::new("position startpos", &pos).unwrap();
+        assert_eq!(
+            last.extra_moves("position startpos moves e2e4", &pos)
+                .unwrap(),
+            ["e2e4"]
+        );
+    }
+
+    #[test]
+    fn random960_takes_no_moves() {
+        use super::parse_position;
+        use crate::chess::board::Board;
+
+        let mut pos = Board::default();
+        let before = pos.to_string();
+        assert!(parse_position("position random960 moves e2e4", &mut pos).is_err());
+        assert_eq!(pos.to_string(), before);
+        parse_position("position random960", &mut pos).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+//! Output to the GUI goes through a dedicated writer thread, so that a GUI that is slow
+//! to drain our stdout can never stall the search. Search progress is sent with [`info`],
+//! which drops the line if the GUI is too far behind, while lines that the GUI must see,
+//! such as `bestmove`, are sent with [`send`], which waits until they have been flushed.
+
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+        Arc, OnceLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+/// The number of lines that can be waiting to be written before `info` lines are dropped.
+const QUEUE_CAPACITY: usize = 256;
+/// The longest the GUI should go without hearing from us while we're searching.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+enum Message {
+    Line(String),
+    /// A line that must be written, with a channel to report whether it was flushed.
+    Flushed(String, mpsc::Sender<bool>),
+}
+
+pub struct Writer {
+    sender: SyncSender<Message>,
+    /// The time of the last write, in microseconds since `epoch`.
+    last_write: Arc<AtomicU64>,
+    epoch: Instant,
+}
+
+impl Writer {
+    /// Start a writer thread that writes lines to `out`, flushing after each one.
+    pub fn new(
+        mut out: impl Write + Send + 'static,
+        capacity: usize,
+    ) -> anyhow::Result<(Self, JoinHandle<()>)> {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let last_write = Arc::new(AtomicU64::new(0));
+        let epoch = Instant::now();
+        let written = Arc::clone(&last_write);
+        let handle = std::thread::Builder::new()
+            .name("uci-writer".into())
+            .spawn(move || {
+                for message in receiver {
+                    let (line, ack) = match message {
+                        Message::Line(line) => (line, None),
+                        Message::Flushed(line, ack) => (line, Some(ack)),
+                    };
+                    let ok = writeln!(out, "{line}").and_then(|()| out.flush()).is_ok();
+                    #[allow(clippy::cast_possible_truncation)]
+                    written.store(epoch.elapsed().as_micros() as u64, Ordering::Relaxed);
+                    if let Some(ack) = ack {
+                        // the sender may have given up waiting, which is fine.
+                        let _ = ack.send(ok);
+                    }
+                }
+            })
+            .with_context(|| "Couldn't start UCI writer thread")?;
+        Ok((
+            Self {
+                sender,
+                last_write,
+                epoch,
+            },
+            handle,
+        ))
+    }
+
+    /// Queue a line of search progress, dropping it if the GUI is not keeping up.
+    pub fn info(&self, line: String) -> bool {
+        match self.sender.try_send(Message::Line(line)) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    /// Write a line, after everything queued before it, and wait until it has been flushed.
+    pub fn send(&self, line: String) -> bool {
+        let (ack, flushed) = mpsc::channel();
+        self.sender.send(Message::Flushed(line, ack)).is_ok() && flushed.recv().unwrap_or(false)
+    }
+
+    /// Returns true, at most once per `interval`, if nothing has been written for that long.
+    fn claim_heartbeat(&self, interval: Duration) -> bool {
+        #![allow(clippy::cast_possible_truncation)]
+        let now = self.epoch.elapsed().as_micros() as u64;
+        let last = self.last_write.load(Ordering::Relaxed);
+        now.saturating_sub(last) >= interval.as_micros() as u64
+            && self
+                .last_write
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+    }
+}
+
+static WRITER: OnceLock<Writer> = OnceLock::new();
+
+/// Route all UCI output through a writer thread on stdout. Until this is called,
+/// output is written directly.
+pub fn install() -> anyhow::Result<()> {
+    let (writer, _handle) = Writer::new(std::io::stdout(), QUEUE_CAPACITY)?;
+    // installing twice would only leave an idle thread behind.
+    let _ = WRITER.set(writer);
+    Ok(())
+}
+
+/// Print a line of search progress, which may be dropped if the GUI is not keeping up.
+pub fn info(line: String) {
+    if let Some(writer) = WRITER.get() {
+        writer.info(line);
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Print a line that the GUI must receive, returning once it has been flushed.
+pub fn send(line: String) -> bool {
+    if let Some(writer) = WRITER.get() {
+        writer.send(line)
+    } else {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{line}")
+            .and_then(|()| stdout.flush())
+            .is_ok()
+    }
+}
+
+/// Whether it's time to remind the GUI that we're still searching.
+pub fn heartbeat_due() -> bool {
+    WRITER
+        .get()
+        .is_some_and(|writer| writer.claim_heartbeat(HEARTBEAT_INTERVAL))
+}
+
+mod tests {
+    #[test]
+    fn slow_consumer_never_blocks_info() {
+        #![allow(clippy::unwrap_used)]
+        use super::Writer;
+        use std::{
+            io::{self, Write},
+            sync::{Arc, Mutex},
+            time::{Duration, Instant},
+        };
+
+        /// A pipe that the GUI is slow to read from.
+        struct SlowPipe(Arc<Mutex<Vec<String>>>);
+        impl Write for SlowPipe {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                std::thread::sleep(Duration::from_millis(2));
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(buf).into_owned());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let (writer, handle) = Writer::new(SlowPipe(lines.clone()), 8).unwrap();
+        let start = Instant::now();
+        let queued = (0..1000)
+            .filter(|i| writer.info(format!("info nodes {i}")))
+            .count();
+        // writing all of these would take two seconds.
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(queued < 1000);
+
+        assert!(writer.send("bestmove e2e4".into()));
+        let written = lines.lock().unwrap().concat();
+        assert!(written.ends_with("bestmove e2e4\n"));
+        assert_eq!(
+            written.lines().count(),
+            queued + 1,
+            "every line that wasn't dropped is written in full"
+        );
+        std::mem::drop(writer);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+//! Named bundles of options, so that `setoption name Profile value bullet` configures
+//! the engine for a kind of use in one step. The profiles are written in TOML: a
+//! `[name]` table for each profile, holding `Option = value` pairs.
+
+use std::{
+    path::Path,
+    sync::{LazyLock, Mutex},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+/// The profiles that ship with the engine.
+const BUILTIN: &str = include_str!("profiles.toml");
+
+/// The names of the built-in profiles, for the GUI's list.
+pub const BUILTIN_NAMES: [&str; 4] = ["default", "bullet", "correspondence", "analysis"];
+
+/// The built-in profiles, parsed on first use.
+static BUILTIN_PROFILES: LazyLock<anyhow::Result<Vec<Profile>>> = LazyLock::new(|| parse(BUILTIN));
+
+/// Profiles loaded from the user's `ProfilesFile`, which take precedence over ours.
+static USER_PROFILES: Mutex<Vec<Profile>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    /// Option names and values, in the order that they are set.
+    pub options: Vec<(String, String)>,
+}
+
+/// The text that `setoption` would be given for a TOML value.
+fn option_value(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Datetime(_) | toml::Value::Array(_) | toml::Value::Table(_) => None,
+    }
+}
+
+/// Parse a profiles file.
+pub fn parse(text: &str) -> anyhow::Result<Vec<Profile>> {
+    let table = text.parse::<toml::Table>()?;
+    let mut profiles = Vec::with_capacity(table.len());
+    for (name, options) in table {
+        let Some(options) = options.as_table() else {
+            bail!("{name} is set outside of any [profile]");
+        };
+        let mut profile = Profile {
+            name,
+            options: Vec::with_capacity(options.len()),
+        };
+        for (key, value) in options {
+            if key.eq_ignore_ascii_case("Profile") || key.eq_ignore_ascii_case("ProfilesFile") {
+                bail!("profile {}: a profile can't set {key}", profile.name);
+            }
+            let Some(value) = option_value(value) else {
+                bail!(
+                    "profile {}: {key} must be a number, true, false, or a string",
+                    profile.name
+                );
+            };
+            profile.options.push((key.clone(), value));
+        }
+        profiles.push(profile);
+    }
+    Ok(profiles)
+}
+
+/// Load the user's profiles from `path`, or forget them if there is no path.
+pub fn load_user_profiles(path: Option<&Path>) -> anyhow::Result<Vec<String>> {
+    let profiles = match path {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read profiles file {}", path.display()))?;
+            parse(&text)
+                .with_context(|| format!("failed to load profiles file {}", path.display()))?
+        }
+        None => Vec::new(),
+    };
+    let names = profiles.iter().map(|p| p.name.clone()).collect();
+    let Ok(mut lock) = USER_PROFILES.lock() else {
+        bail!("failed to take lock on the user's profiles");
+    };
+    *lock = profiles;
+    Ok(names)
+}
+
+/// Find the profile called `name`, looking first at the user's profiles.
+pub fn find(name: &str) -> anyhow::Result<Profile> {
+    let Ok(user) = USER_PROFILES.lock() else {
+        bail!("failed to take lock on the user's profiles");
+    };
+    let builtin = BUILTIN_PROFILES
+        .as_ref()
+        .map_err(|e| anyhow!("the built-in profiles are malformed: {e:#}"))?;
+    user.iter()
+        .chain(builtin)
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+        .cloned()
+        .with_context(|| format!("no profile called {name}"))
+}
+
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    #[test]
+    fn builtin_profiles() {
+        use super::{parse, BUILTIN, BUILTIN_NAMES};
+
+        let profiles = parse(BUILTIN).unwrap();
+        let names = profiles.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, BUILTIN_NAMES);
+        let bullet = &profiles[1];
+        assert_eq!(bullet.options[1], ("Hash".to_string(), "64".to_string()));
+
+        let text = "[mine] # a comment\nSyzygyPath = \"/tb/#3-4-5 men\"\nEvalBlend = true\n";
+        let mine = parse(text).unwrap();
+        assert_eq!(
+            mine[0].options,
+            [
+                ("SyzygyPath".to_string(), "/tb/#3-4-5 men".to_string()),
+                ("EvalBlend".to_string(), "true".to_string()),
+            ]
+        );
+        assert!(parse("Hash = 16").is_err());
+        assert!(parse("[mine]\nHash = lots").is_err());
+        assert!(parse("[mine]\nProfile = \"bullet\"").is_err());
+        assert!(parse("[mine]\n[mine]").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Saving the state of an analysis session as it goes, so that a session that crashes or
+//! is closed can be picked up again with `--resume`. A session file is plain text, with
+//! the `position` command that set up the board followed by a `key value` line for each
+//! other part of the state:
+//!
+//! ```text
+//! position startpos moves e2e4 e7e5
+//! chess960 false
+//! depth 24
+//! score 31
+//! pv g1f3 b8c6 f1b5
+//! ```
+//!
+//! The last three lines are left out until a search from the position has completed
+//! an iteration. During a search, the session is saved again after each completed
+//! iteration, at most once every [`PROGRESS_SAVE_INTERVAL`].
+
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+
+/// Where the session is being saved, if anywhere.
+static SESSION_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The session as last saved, and when, so that a search can add its progress to it.
+static LAST_SAVE: Mutex<Option<(Session, Instant)>> = Mutex::new(None);
+
+/// The shortest time between two saves of a search's progress, so that the quick
+/// iterations at the start of a search don't each write the file.
+pub const PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The result of the last search from the session's position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Analysis {
+    pub depth: usize,
+    pub score: i32,
+    /// The principal variation, in UCI notation.
+    pub pv: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// The `position` command that set up the board.
+    pub position: String,
+    pub chess960: bool,
+    pub analysis: Option<Analysis>,
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.position)?;
+        writeln!(f, "chess960 {}", self.chess960)?;
+        if let Some(analysis) = &self.analysis {
+            writeln!(f, "depth {}", analysis.depth)?;
+            writeln!(f, "score {}", analysis.score)?;
+            writeln!(f, "pv {}", analysis.pv.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut position, mut chess960) = (None, false);
+        let (mut depth, mut score, mut pv) = (None, None, None);
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "position" => position = Some(line.to_string()),
+                "chess960" => chess960 = value.parse()?,
+                "depth" => depth = Some(value.parse()?),
+                "score" => score = Some(value.parse()?),
+                "pv" => pv = Some(value.split_ascii_whitespace().map(str::to_string).collect()),
+                _ => bail!("unknown key \"{key}\" in session"),
+            }
+        }
+        let position = position.with_context(|| "session has no position")?;
+        let analysis = match (depth, score, pv) {
+            (Some(depth), Some(score), Some(pv)) => Some(Analysis { depth, score, pv }),
+            (None, None, None) => None,
+            _ => bail!("session has only part of an analysis"),
+        };
+        Ok(Self {
+            position,
+            chess960,
+            analysis,
+        })
+    }
+}
+
+impl Session {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read session file {}", path.display()))?;
+        text.parse()
+            .with_context(|| format!("failed to load session file {}", path.display()))
+    }
+
+    /// Write the session to `path`, by way of a temporary file, so that a crash while
+    /// saving leaves the last save whole.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, self.to_string())
+            .with_context(|| format!("failed to write {}", temporary.display()))?;
+        std::fs::rename(&temporary, path)
+            .with_context(|| format!("failed to replace session file {}", path.display()))
+    }
+}
+
+/// Save sessions to `path` from now on, or stop saving them.
+pub fn set_path(path: Option<PathBuf>) -> anyhow::Result<()> {
+    let Ok(mut lock) = SESSION_PATH.lock() else {
+        bail!("failed to take lock on the session path");
+    };
+    *lock = path;
+    if let Ok(mut last) = LAST_SAVE.lock() {
+        *last = None;
+    }
+    Ok(())
+}
+
+/// Save `session` to the session file, if there is one. A session that can't be saved
+/// shouldn't stop the analysis, so failures are only reported.
+pub fn autosave(session: &Session) {
+    let Ok(lock) = SESSION_PATH.lock() else {
+        return;
+    };
+    if let Some(path) = lock.as_deref() {
+        if let Err(e) = session.save(path) {
+            super::output::send(format!("info string couldn't save session: {e:#}"));
+        }
+        if let Ok(mut last) = LAST_SAVE.lock() {
+            *last = Some((session.clone(), Instant::now()));
+        }
+    }
+}
+
+/// Save the analysis from a completed iteration of the search along with the session,
+/// unless the session was saved less than [`PROGRESS_SAVE_INTERVAL`] ago. `analysis`
+/// is only called if the session is going to be saved.
+pub fn autosave_progress(analysis: impl FnOnce() -> Analysis) {
+    let due = LAST_SAVE.lock().ok().and_then(|last| {
+        let (session, saved_at) = last.as_ref()?;
+        (saved_at.elapsed() >= PROGRESS_SAVE_INTERVAL).then(|| session.clone())
+    });
+    if let Some(mut session) = due {
+        session.analysis = Some(analysis());
+        autosave(&session);
+    }
+}
+
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    #[test]
+    fn session_round_trip() {
+        use super::{Analysis, Session};
+
+        let mut session = Session {
+            position: "position startpos moves e2e4 e7e5".into(),
+            chess960: false,
+            analysis: None,
+        };
+        assert_eq!(session.to_string().parse::<Session>().unwrap(), session);
+        session.analysis = Some(Analysis {
+            depth: 24,
+            score: -31,
+            pv: vec!["g1f3".into(), "b8c6".into()],
+        });
+        assert_eq!(session.to_string().parse::<Session>().unwrap(), session);
+
+        assert!("chess960 true".parse::<Session>().is_err());
+        assert!("position startpos\ndepth 3".parse::<Session>().is_err());
+        assert!("position startpos\nhash 64".parse::<Session>().is_err());
+    }
+}
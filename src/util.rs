@@ -11,16 +11,16 @@ pub const INFINITY: i32 = MATE_SCORE + 1;
 pub const VALUE_NONE: i32 = INFINITY + 1;
 pub const MEGABYTE: usize = 1024 * 1024;
 
+/// A counter shared between threads, which each thread adds to in batches of `GRANULARITY`
+/// so that the cache line holding the total isn't fought over at every increment.
 #[derive(Debug, Clone, Copy)]
-pub struct BatchedAtomicCounter<'a> {
+pub struct BatchedAtomicCounter<'a, const GRANULARITY: u64 = 1024> {
     buffer: u64,
     global: &'a AtomicU64,
     local: u64,
 }
 
-impl<'a> BatchedAtomicCounter<'a> {
-    const GRANULARITY: u64 = 1024;
-
+impl<'a, const GRANULARITY: u64> BatchedAtomicCounter<'a, GRANULARITY> {
     pub const fn new(global: &'a AtomicU64) -> Self {
         Self {
             buffer: 0,
@@ -31,13 +31,18 @@ impl<'a> BatchedAtomicCounter<'a> {
 
     pub fn increment(&mut self) {
         self.buffer += 1;
-        if self.buffer >= Self::GRANULARITY {
-            self.global.fetch_add(self.buffer, Ordering::Relaxed);
-            self.local += self.buffer;
-            self.buffer = 0;
+        if self.buffer >= GRANULARITY {
+            self.flush();
         }
     }
 
+    /// Add everything counted so far to the shared total.
+    pub fn flush(&mut self) {
+        self.global.fetch_add(self.buffer, Ordering::Relaxed);
+        self.local += self.buffer;
+        self.buffer = 0;
+    }
+
     pub fn get_global(&self) -> u64 {
         self.global.load(Ordering::Relaxed) + self.buffer
     }
@@ -1,3 +1,5 @@
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct CompactDepthStorage(u8);
 
@@ -24,3 +26,69 @@ impl From<CompactDepthStorage> for i32 {
         Self::from(depth.0)
     }
 }
+
+/// The number of depth units in one ply.
+pub const UNITS_PER_PLY: i32 = 100;
+
+/// A depth to search to, in hundredths of a ply, so that extensions and reductions
+/// needn't be whole plies. The search's heuristics work in whole plies, taken from
+/// [`Depth::plies`], and whatever fraction is left over is passed on to the children.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Depth(i32);
+
+impl Depth {
+    pub const ZERO: Self = Self(0);
+    pub const ONE_PLY: Self = Self(UNITS_PER_PLY);
+
+    pub const fn new(plies: i32) -> Self {
+        Self(plies * UNITS_PER_PLY)
+    }
+
+    pub const fn from_units(units: i32) -> Self {
+        Self(units)
+    }
+
+    /// The whole plies in this depth, rounding down.
+    pub const fn plies(self) -> i32 {
+        self.0.div_euclid(UNITS_PER_PLY)
+    }
+}
+
+impl Add for Depth {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Depth {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl AddAssign for Depth {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Depth {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
+mod tests {
+    #[test]
+    fn fractional_depths_round_down_to_plies() {
+        use super::Depth;
+
+        let half = Depth(50);
+        assert_eq!(half.plies(), 0);
+        assert_eq!((Depth::new(3) + half).plies(), 3);
+        assert_eq!((Depth::ZERO - half).plies(), -1);
+        assert!(half < Depth::ONE_PLY);
+    }
+}